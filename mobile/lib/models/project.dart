@@ -34,7 +34,7 @@ class Project {
   }
 
   /// Check if project is active
-  bool get isActive => status == 'ACTIVE';
+  bool get isActive => status != 'ARCHIVED';
 
   /// Format the creation date for display
   String get formattedDate {
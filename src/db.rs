@@ -46,6 +46,40 @@ pub async fn init_db(database_url: &str) -> DbPool {
     pool
 }
 
+/// Recreate the projects table with `create_sql` (which must create
+/// `projects_rebuilt`), keeping every row
+///
+/// Foreign keys are switched off for the swap so that dropping the old table
+/// doesn't cascade into documents, forum messages and email rules.
+async fn rebuild_projects_table(pool: &DbPool, create_sql: &str) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+
+    let result = async {
+        let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+        sqlx::query(create_sql).execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO projects_rebuilt SELECT * FROM projects")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE projects_rebuilt SET status = 'IN_PRODUCTION' WHERE status = 'ACTIVE'")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DROP TABLE projects").execute(&mut *tx).await?;
+        sqlx::query("ALTER TABLE projects_rebuilt RENAME TO projects")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+    .await;
+
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+    result
+}
+
 /// Execute database schema migrations
 ///
 /// Creates the required tables if they don't exist.
@@ -56,14 +90,14 @@ async fn run_migrations(pool: &DbPool) {
     // Projects table: Represents a work order or "Obra"
     // - id: UUID primary key for global uniqueness
     // - name: Human-readable project name (e.g., "Obra Porto Seg Social")
-    // - status: Workflow state, either "ACTIVE" or "ARCHIVED"
+    // - status: Lifecycle state, one of the codes in `project_states`
     // - created_at: Timestamp for chronological ordering
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS projects (
             id TEXT PRIMARY KEY NOT NULL,
             name TEXT NOT NULL,
-            status TEXT NOT NULL DEFAULT 'ACTIVE' CHECK(status IN ('ACTIVE', 'ARCHIVED')),
+            status TEXT NOT NULL,
             address TEXT,
             client_phone TEXT,
             created_at TEXT NOT NULL DEFAULT (datetime('now'))
@@ -82,10 +116,32 @@ async fn run_migrations(pool: &DbPool) {
         .execute(pool)
         .await;
 
+    // When the project was archived; cold storage counts from this moment
+    let project_columns: Vec<(i32, String, String, i32, Option<String>, i32)> =
+        sqlx::query_as("PRAGMA table_info(projects)")
+            .fetch_all(pool)
+            .await
+            .expect("Failed to query table info");
+    let has_archived_at = project_columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "archived_at");
+    if !has_archived_at {
+        sqlx::query("ALTER TABLE projects ADD COLUMN archived_at TEXT")
+            .execute(pool)
+            .await
+            .expect("Failed to add archived_at column");
+        // Projects archived before this existed start counting now
+        sqlx::query("UPDATE projects SET archived_at = datetime('now') WHERE status = 'ARCHIVED'")
+            .execute(pool)
+            .await
+            .expect("Failed to backfill project archive times");
+        tracing::info!("Added archived_at column to projects table");
+    }
+
     // Documents table: Represents uploaded files (photos, PDFs, etc.)
     // - id: UUID primary key
     // - project_id: Foreign key to projects, NULL means document is in "Inbox"
-    // - file_path: Relative path within the uploads directory
+    // - file_path: Storage key, `ab/cd/<sha256>.<ext>` (older files: flat names, moved at startup)
     // - file_type: MIME type category (image, pdf, etc.)
     // - original_name: Original filename for display purposes
     // - uploaded_at: Upload timestamp
@@ -180,6 +236,331 @@ async fn run_migrations(pool: &DbPool) {
         tracing::info!("Added audio_path column to documents table");
     }
 
+    // Voice memo metadata: detected container format and duration
+    let has_audio_format = columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "audio_format");
+    if !has_audio_format {
+        sqlx::query("ALTER TABLE documents ADD COLUMN audio_format TEXT")
+            .execute(pool)
+            .await
+            .expect("Failed to add audio_format column");
+        tracing::info!("Added audio_format column to documents table");
+    }
+
+    let has_audio_duration = columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "audio_duration_seconds");
+    if !has_audio_duration {
+        sqlx::query("ALTER TABLE documents ADD COLUMN audio_duration_seconds REAL")
+            .execute(pool)
+            .await
+            .expect("Failed to add audio_duration_seconds column");
+        tracing::info!("Added audio_duration_seconds column to documents table");
+    }
+
+    // Content type columns: the MIME type claimed by the client and the one
+    // detected from the file's leading bytes
+    for column in ["mime_type", "detected_mime_type"] {
+        let exists = columns.iter().any(|(_, name, _, _, _, _)| name == column);
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE documents ADD COLUMN {} TEXT", column))
+                .execute(pool)
+                .await
+                .expect("Failed to add content type column");
+            tracing::info!("Added {} column to documents table", column);
+        }
+    }
+
+    let has_scanned_at = columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "scanned_at");
+    if !has_scanned_at {
+        sqlx::query("ALTER TABLE documents ADD COLUMN scanned_at TEXT")
+            .execute(pool)
+            .await
+            .expect("Failed to add scanned_at column");
+        tracing::info!("Added scanned_at column to documents table");
+    }
+
+    // Capture time reported by the uploading device (photo taken vs. uploaded)
+    let has_captured_at = columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "captured_at");
+    if !has_captured_at {
+        sqlx::query("ALTER TABLE documents ADD COLUMN captured_at TEXT")
+            .execute(pool)
+            .await
+            .expect("Failed to add captured_at column");
+        tracing::info!("Added captured_at column to documents table");
+    }
+
+    // SHA-256 of the stored file, verified by the integrity scan
+    let has_checksum = columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "checksum");
+    if !has_checksum {
+        sqlx::query("ALTER TABLE documents ADD COLUMN checksum TEXT")
+            .execute(pool)
+            .await
+            .expect("Failed to add checksum column");
+        tracing::info!("Added checksum column to documents table");
+    }
+
+    // Rendered preview (SVG for DXF drawings)
+    let has_preview_path = columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "preview_path");
+    if !has_preview_path {
+        sqlx::query("ALTER TABLE documents ADD COLUMN preview_path TEXT")
+            .execute(pool)
+            .await
+            .expect("Failed to add preview_path column");
+        tracing::info!("Added preview_path column to documents table");
+    }
+
+    // Uploader identity and provenance columns
+    let has_uploaded_by = columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "uploaded_by");
+    if !has_uploaded_by {
+        sqlx::query("ALTER TABLE documents ADD COLUMN uploaded_by TEXT")
+            .execute(pool)
+            .await
+            .expect("Failed to add uploaded_by column");
+        tracing::info!("Added uploaded_by column to documents table");
+    }
+
+    let has_source = columns.iter().any(|(_, name, _, _, _, _)| name == "source");
+    if !has_source {
+        sqlx::query(
+            "ALTER TABLE documents ADD COLUMN source TEXT NOT NULL DEFAULT 'APP' CHECK(source IN ('APP', 'EMAIL', 'IMPORT', 'API'))",
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to add source column");
+
+        // Older email attachments only carry their provenance inside the notes text
+        sqlx::query("UPDATE documents SET source = 'EMAIL' WHERE notes LIKE '📧 Email de:%'")
+            .execute(pool)
+            .await
+            .expect("Failed to backfill email document sources");
+        tracing::info!("Added source column to documents table");
+    }
+
+    for column in ["email_sender", "email_subject", "email_message_id"] {
+        let exists = columns.iter().any(|(_, name, _, _, _, _)| name == column);
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE documents ADD COLUMN {} TEXT", column))
+                .execute(pool)
+                .await
+                .expect("Failed to add email provenance column");
+            tracing::info!("Added {} column to documents table", column);
+        }
+    }
+
+    // Cold storage: the archive holding the document's files, when they were
+    // moved there and when they were last brought back
+    for column in ["archive_id", "archived_at", "restored_at"] {
+        let exists = columns.iter().any(|(_, name, _, _, _, _)| name == column);
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE documents ADD COLUMN {} TEXT", column))
+                .execute(pool)
+                .await
+                .expect("Failed to add cold storage column");
+            tracing::info!("Added {} column to documents table", column);
+        }
+    }
+
+    // Cold storage archives: ZIPs of archived projects' files kept in the
+    // cold storage location
+    // - storage_key: key of the ZIP in the cold storage backend
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS cold_archives (
+            id TEXT PRIMARY KEY NOT NULL,
+            project_id TEXT,
+            storage_key TEXT NOT NULL,
+            file_count INTEGER NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE SET NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create cold_archives table");
+
+    // Document events table: assignments, status and category changes
+    // - project_id: project the document was in after the change (NULL = inbox)
+    // - from_value/to_value: project IDs for ASSIGNED, otherwise the values
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS document_events (
+            id TEXT PRIMARY KEY NOT NULL,
+            document_id TEXT NOT NULL,
+            event_type TEXT NOT NULL CHECK(event_type IN ('ASSIGNED', 'STATUS', 'CATEGORY')),
+            from_value TEXT,
+            to_value TEXT,
+            project_id TEXT,
+            actor TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create document_events table");
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_document_events_project ON document_events(project_id, created_at)",
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create document_events index");
+
+    // Document tags table: free-form labels for triage and search
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS document_tags (
+            document_id TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            PRIMARY KEY (document_id, tag),
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create document_tags table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_document_tags_tag ON document_tags(tag)")
+        .execute(pool)
+        .await
+        .expect("Failed to create document_tags index");
+
+    // Quarantined files table: uploads and attachments held back by the malware scanner
+    // - signature: clamd signature name, NULL when the scan itself failed
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS quarantined_files (
+            id TEXT PRIMARY KEY NOT NULL,
+            original_name TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            mime_type TEXT,
+            size INTEGER NOT NULL,
+            signature TEXT,
+            reason TEXT NOT NULL,
+            source TEXT NOT NULL CHECK(source IN ('APP', 'EMAIL', 'IMPORT', 'API')),
+            uploaded_by TEXT,
+            email_sender TEXT,
+            email_subject TEXT,
+            email_message_id TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create quarantined_files table");
+
+    // Video metadata table: results of background ffmpeg processing
+    // - status: PENDING, PROCESSING, DONE, FAILED or SKIPPED (ffmpeg unavailable)
+    // - poster_path / transcoded_path: derived files in the uploads directory
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS video_metadata (
+            document_id TEXT PRIMARY KEY NOT NULL,
+            status TEXT NOT NULL DEFAULT 'PENDING' CHECK(status IN ('PENDING', 'PROCESSING', 'DONE', 'FAILED', 'SKIPPED')),
+            duration_seconds REAL,
+            width INTEGER,
+            height INTEGER,
+            codec TEXT,
+            poster_path TEXT,
+            transcoded_path TEXT,
+            error TEXT,
+            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create video_metadata table");
+
+    // Annotation layers table: non-destructive vector drawings over a document
+    // - shapes: JSON array of vector shapes (pen strokes, arrows, text)
+    // The original file is never modified; flattened renders are produced on demand.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS document_annotations (
+            id TEXT PRIMARY KEY NOT NULL,
+            document_id TEXT NOT NULL,
+            name TEXT,
+            author_name TEXT NOT NULL DEFAULT 'Anónimo',
+            shapes TEXT NOT NULL DEFAULT '[]',
+            visible INTEGER NOT NULL DEFAULT 1,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create document_annotations table");
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_document_annotations_document
+        ON document_annotations(document_id, created_at)
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create document_annotations index");
+
+    // Document comments table: discussion threads attached to a document
+    // - parent_id: NULL = thread root, set = reply to a root comment
+    // - x, y: optional pin position normalized to the image/page size (0.0 - 1.0)
+    // - page: optional 1-based page number for PDFs
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS document_comments (
+            id TEXT PRIMARY KEY NOT NULL,
+            document_id TEXT NOT NULL,
+            parent_id TEXT,
+            author_name TEXT NOT NULL DEFAULT 'Anónimo',
+            content TEXT NOT NULL,
+            x REAL,
+            y REAL,
+            page INTEGER,
+            resolved INTEGER NOT NULL DEFAULT 0,
+            resolved_by TEXT,
+            resolved_at TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE,
+            FOREIGN KEY (parent_id) REFERENCES document_comments(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create document_comments table");
+
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_document_comments_document
+        ON document_comments(document_id, created_at)
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create document_comments index");
+
     // Email routing rules table
     // Allows routing emails from specific senders to specific projects
     sqlx::query(
@@ -277,6 +658,24 @@ async fn run_migrations(pool: &DbPool) {
     .await
     .expect("Failed to create forum_messages table");
 
+    // Album documents of a PHOTO message posted for a multi-file upload
+    // (forum_messages.document_id keeps the first one for older clients)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS forum_message_documents (
+            message_id TEXT NOT NULL,
+            document_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (message_id, document_id),
+            FOREIGN KEY (message_id) REFERENCES forum_messages(id) ON DELETE CASCADE,
+            FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create forum_message_documents table");
+
     // Index for efficient per-project forum queries
     sqlx::query(
         r#"
@@ -318,6 +717,50 @@ async fn run_migrations(pool: &DbPool) {
     .await
     .expect("Failed to create task_items table");
 
+    // Task item events - every completion and reopening of a task item
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS task_item_events (
+            id TEXT PRIMARY KEY NOT NULL,
+            item_id TEXT NOT NULL,
+            completed INTEGER NOT NULL,
+            actor TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (item_id) REFERENCES task_items(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create task_item_events table");
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_task_item_events_item ON task_item_events(item_id, created_at)",
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create task_item_events index");
+
+    // Items completed before events were recorded get their completion as history
+    let backfilled = sqlx::query(
+        r#"
+        INSERT INTO task_item_events (id, item_id, completed, actor, created_at)
+        SELECT lower(hex(randomblob(16))), id, 1, completed_by, completed_at
+        FROM task_items t
+        WHERE completed = 1 AND completed_at IS NOT NULL
+          AND NOT EXISTS (SELECT 1 FROM task_item_events e WHERE e.item_id = t.id)
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to backfill task_item_events");
+    if backfilled.rows_affected() > 0 {
+        tracing::info!(
+            "Recorded completion history of {} task item(s)",
+            backfilled.rows_affected()
+        );
+    }
+
     // Ensure the special "Geral" project exists (global forum)
     let geral_exists: (i32,) = sqlx::query_as("SELECT COUNT(*) FROM projects WHERE name = 'Geral'")
         .fetch_one(pool)
@@ -326,7 +769,7 @@ async fn run_migrations(pool: &DbPool) {
 
     if geral_exists.0 == 0 {
         let geral_id = uuid::Uuid::new_v4().to_string();
-        sqlx::query("INSERT INTO projects (id, name, status) VALUES (?, 'Geral', 'ACTIVE')")
+        sqlx::query("INSERT INTO projects (id, name, status) VALUES (?, 'Geral', 'IN_PRODUCTION')")
             .bind(&geral_id)
             .execute(pool)
             .await
@@ -378,5 +821,242 @@ async fn run_migrations(pool: &DbPool) {
     .await
     .expect("Failed to create user_profiles table");
 
+    // Clients table: the person or company projects are done for
+    // - tax_id: NIF, for invoices
+    // Phones and emails live in their own tables; a client can have several
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS clients (
+            id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            tax_id TEXT,
+            address TEXT,
+            notes TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create clients table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS client_phones (
+            client_id TEXT NOT NULL,
+            phone TEXT NOT NULL,
+            PRIMARY KEY (client_id, phone),
+            FOREIGN KEY (client_id) REFERENCES clients(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create client_phones table");
+
+    // Emails are stored lowercase so inbound senders can be matched exactly
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS client_emails (
+            client_id TEXT NOT NULL,
+            email TEXT NOT NULL,
+            PRIMARY KEY (client_id, email),
+            FOREIGN KEY (client_id) REFERENCES clients(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create client_emails table");
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_client_emails_email ON client_emails(email)")
+        .execute(pool)
+        .await
+        .expect("Failed to create client_emails index");
+
+    // Migration: projects belong to a client; documents remember the client
+    // whose email they came from, even while still in the inbox
+    let project_columns: Vec<(i32, String, String, i32, Option<String>, i32)> =
+        sqlx::query_as("PRAGMA table_info(projects)")
+            .fetch_all(pool)
+            .await
+            .expect("Failed to query table info");
+    if !project_columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "client_id")
+    {
+        sqlx::query(
+            "ALTER TABLE projects ADD COLUMN client_id TEXT REFERENCES clients(id) ON DELETE SET NULL",
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to add client_id column to projects");
+        tracing::info!("Added client_id column to projects table");
+    }
+
+    let document_columns: Vec<(i32, String, String, i32, Option<String>, i32)> =
+        sqlx::query_as("PRAGMA table_info(documents)")
+            .fetch_all(pool)
+            .await
+            .expect("Failed to query table info");
+    if !document_columns
+        .iter()
+        .any(|(_, name, _, _, _, _)| name == "client_id")
+    {
+        sqlx::query(
+            "ALTER TABLE documents ADD COLUMN client_id TEXT REFERENCES clients(id) ON DELETE SET NULL",
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to add client_id column to documents");
+        tracing::info!("Added client_id column to documents table");
+    }
+
+    // Project lifecycle: the states a project goes through, in order, and
+    // which state can follow which. ARCHIVED is always present; the rest is
+    // configurable through /api/projects/lifecycle
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_states (
+            code TEXT PRIMARY KEY NOT NULL,
+            label TEXT NOT NULL,
+            position INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create project_states table");
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_state_transitions (
+            from_state TEXT NOT NULL,
+            to_state TEXT NOT NULL,
+            PRIMARY KEY (from_state, to_state),
+            FOREIGN KEY (from_state) REFERENCES project_states(code) ON DELETE CASCADE,
+            FOREIGN KEY (to_state) REFERENCES project_states(code) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create project_state_transitions table");
+
+    let (state_count,): (i32,) = sqlx::query_as("SELECT COUNT(*) FROM project_states")
+        .fetch_one(pool)
+        .await
+        .expect("Failed to count project states");
+    if state_count == 0 {
+        // Default pipeline: quote -> approved -> in production -> installing
+        // -> snagging -> done, one step back where work gets reopened, and
+        // archiving from (and reopening to) any state
+        sqlx::query(
+            r#"
+            INSERT INTO project_states (code, label, position) VALUES
+                ('QUOTE', 'Orçamento', 1),
+                ('APPROVED', 'Aprovada', 2),
+                ('IN_PRODUCTION', 'Em produção', 3),
+                ('INSTALLING', 'Em montagem', 4),
+                ('SNAGGING', 'Correções', 5),
+                ('DONE', 'Concluída', 6),
+                ('ARCHIVED', 'Arquivada', 7)
+            "#,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to seed project states");
+        sqlx::query(
+            r#"
+            INSERT INTO project_state_transitions (from_state, to_state) VALUES
+                ('QUOTE', 'APPROVED'),
+                ('APPROVED', 'IN_PRODUCTION'), ('APPROVED', 'QUOTE'),
+                ('IN_PRODUCTION', 'INSTALLING'), ('IN_PRODUCTION', 'APPROVED'),
+                ('INSTALLING', 'SNAGGING'), ('INSTALLING', 'DONE'), ('INSTALLING', 'IN_PRODUCTION'),
+                ('SNAGGING', 'DONE'), ('SNAGGING', 'INSTALLING'),
+                ('DONE', 'SNAGGING')
+            "#,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to seed project state transitions");
+        sqlx::query(
+            r#"
+            INSERT INTO project_state_transitions (from_state, to_state)
+            SELECT code, 'ARCHIVED' FROM project_states WHERE code != 'ARCHIVED'
+            UNION ALL
+            SELECT 'ARCHIVED', code FROM project_states WHERE code != 'ARCHIVED'
+            "#,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to seed project state transitions");
+        tracing::info!("Created default project lifecycle");
+    }
+
+    // Migration: the projects table used to only allow ACTIVE/ARCHIVED.
+    // SQLite can't drop a CHECK constraint, so the table is rebuilt from its
+    // own schema without it; ongoing (ACTIVE) projects become IN_PRODUCTION
+    let (projects_sql,): (String,) =
+        sqlx::query_as("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'projects'")
+            .fetch_one(pool)
+            .await
+            .expect("Failed to read projects schema");
+    const STATUS_CHECK: &str =
+        "status TEXT NOT NULL DEFAULT 'ACTIVE' CHECK(status IN ('ACTIVE', 'ARCHIVED'))";
+    if projects_sql.contains(STATUS_CHECK) {
+        let rebuilt_sql = projects_sql
+            .replace(STATUS_CHECK, "status TEXT NOT NULL")
+            .replacen("projects", "projects_rebuilt", 1);
+        rebuild_projects_table(pool, &rebuilt_sql)
+            .await
+            .expect("Failed to remove the projects status constraint");
+        tracing::info!("Opened project status to the configurable lifecycle");
+    }
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_projects_client ON projects(client_id)")
+        .execute(pool)
+        .await
+        .expect("Failed to create projects client index");
+
+    // Project status history: every lifecycle transition, with who made it
+    // - from_status: NULL for the state a project was created (or imported) in
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_status_history (
+            id TEXT PRIMARY KEY NOT NULL,
+            project_id TEXT NOT NULL,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            actor TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create project_status_history table");
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_project_status_history_project ON project_status_history(project_id, created_at)",
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to create project_status_history index");
+
+    // Projects from before the history start it with their current state
+    sqlx::query(
+        r#"
+        INSERT INTO project_status_history (id, project_id, from_status, to_status, created_at)
+        SELECT lower(hex(randomblob(16))), id, NULL, status, COALESCE(archived_at, created_at)
+        FROM projects p
+        WHERE NOT EXISTS (SELECT 1 FROM project_status_history h WHERE h.project_id = p.id)
+        "#,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to backfill project_status_history");
+
     tracing::info!("Migrations completed successfully");
 }
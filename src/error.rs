@@ -29,6 +29,22 @@ pub enum AppError {
     #[error("Bad request: {0}")]
     BadRequest(String),
 
+    /// File type not accepted by the upload policy (415)
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    /// File exceeds the size limit for its type (413)
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    /// Request raced with a concurrent change (409)
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// Upload rejected by the malware scanner (422)
+    #[error("Malware detected: {0}")]
+    MalwareDetected(String),
+
     /// Database operation failed (500)
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
@@ -55,6 +71,12 @@ impl IntoResponse for AppError {
         let (status, error_type) = match &self {
             AppError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
             AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            AppError::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
+            AppError::UnsupportedMediaType(_) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_media_type")
+            }
+            AppError::PayloadTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large"),
+            AppError::MalwareDetected(_) => (StatusCode::UNPROCESSABLE_ENTITY, "malware_detected"),
             AppError::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
             AppError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "io_error"),
             AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
@@ -0,0 +1,111 @@
+//! Annotation handlers module
+//!
+//! HTTP handlers for non-destructive annotation layers on image documents
+//! and for rendering flattened previews on demand.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::models::{
+    AnnotationLayerResponse, CreateAnnotationRequest, RenderAnnotationsQuery,
+    UpdateAnnotationRequest,
+};
+use crate::services::annotation_service::RenderFormat;
+use crate::services::AnnotationService;
+
+/// GET /documents/:id/annotations - List annotation layers of a document
+pub async fn list_annotations(
+    State(pool): State<DbPool>,
+    Path(document_id): Path<String>,
+) -> AppResult<Json<Vec<AnnotationLayerResponse>>> {
+    let layers = AnnotationService::list(&pool, &document_id).await?;
+    Ok(Json(
+        layers
+            .into_iter()
+            .map(AnnotationLayerResponse::from_layer)
+            .collect(),
+    ))
+}
+
+/// POST /documents/:id/annotations - Create an annotation layer
+///
+/// # Request Body
+/// ```json
+/// {
+///     "author_name": "Rui",
+///     "shapes": [
+///         { "type": "arrow", "from": [0.1, 0.1], "to": [0.4, 0.3], "color": "#FF0000", "width": 0.005 },
+///         { "type": "text", "position": [0.4, 0.3], "text": "120cm", "color": "#FFFF00", "size": 0.04 }
+///     ]
+/// }
+/// ```
+pub async fn create_annotation(
+    State(pool): State<DbPool>,
+    Path(document_id): Path<String>,
+    Json(payload): Json<CreateAnnotationRequest>,
+) -> AppResult<(StatusCode, Json<AnnotationLayerResponse>)> {
+    let author = payload.author_name.as_deref().unwrap_or("Anónimo");
+    tracing::info!("Creating annotation layer on document {}", document_id);
+
+    let layer = AnnotationService::create(
+        &pool,
+        &document_id,
+        payload.name.as_deref(),
+        author,
+        &payload.shapes,
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(AnnotationLayerResponse::from_layer(layer)),
+    ))
+}
+
+/// PATCH /annotations/:id - Update an annotation layer
+pub async fn update_annotation(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateAnnotationRequest>,
+) -> AppResult<Json<AnnotationLayerResponse>> {
+    let layer = AnnotationService::update(
+        &pool,
+        &id,
+        payload.name.as_deref(),
+        payload.shapes.as_deref(),
+        payload.visible,
+    )
+    .await?;
+
+    Ok(Json(AnnotationLayerResponse::from_layer(layer)))
+}
+
+/// DELETE /annotations/:id - Delete an annotation layer
+pub async fn delete_annotation(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<StatusCode> {
+    AnnotationService::delete(&pool, &id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /documents/:id/annotated - Render the image with visible layers flattened
+///
+/// # Query Parameters
+/// - `format` (optional): "png" (default) or "jpeg"
+pub async fn render_annotated_document(
+    State(pool): State<DbPool>,
+    Path(document_id): Path<String>,
+    Query(params): Query<RenderAnnotationsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let format = RenderFormat::parse(params.format.as_deref())?;
+    let bytes = AnnotationService::render(&pool, &document_id, format).await?;
+
+    Ok(([(header::CONTENT_TYPE, format.content_type())], bytes))
+}
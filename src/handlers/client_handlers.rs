@@ -0,0 +1,127 @@
+//! Client handlers module
+//!
+//! HTTP handlers for clients and the projects and documents they own.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::models::{ClientRequest, ClientResponse, DocumentResponse, ProjectResponse};
+use crate::services::{ClientService, DocumentService, ProjectService};
+
+/// POST /clients - Create a client
+///
+/// # Request Body
+/// ```json
+/// {
+///   "name": "Ana Silva",
+///   "phones": ["912345678"],
+///   "emails": ["ana@example.com"],
+///   "tax_id": "123456789",
+///   "address": "Rua A, Porto",
+///   "notes": "Prefers calls after 18h"
+/// }
+/// ```
+///
+/// # Response
+/// Returns the created client with 201 Created status
+pub async fn create_client(
+    State(pool): State<DbPool>,
+    Json(payload): Json<ClientRequest>,
+) -> AppResult<(StatusCode, Json<ClientResponse>)> {
+    tracing::info!("Creating client: {}", payload.name);
+
+    let client = ClientService::create(&pool, payload).await?;
+
+    Ok((StatusCode::CREATED, Json(client)))
+}
+
+/// GET /clients - List all clients by name
+pub async fn list_clients(State(pool): State<DbPool>) -> AppResult<Json<Vec<ClientResponse>>> {
+    let clients = ClientService::list(&pool).await?;
+
+    Ok(Json(clients))
+}
+
+/// GET /clients/:id - Get a client
+pub async fn get_client(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<Json<ClientResponse>> {
+    tracing::debug!("Getting client: {}", id);
+
+    let client = ClientService::get_response(&pool, &id).await?;
+
+    Ok(Json(client))
+}
+
+/// PATCH /clients/:id - Update a client
+///
+/// Takes the same body as creation and replaces every field,
+/// including the phone and email lists.
+pub async fn update_client(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<ClientRequest>,
+) -> AppResult<Json<ClientResponse>> {
+    tracing::info!("Updating client {}", id);
+
+    let client = ClientService::update(&pool, &id, payload).await?;
+
+    Ok(Json(client))
+}
+
+/// DELETE /clients/:id - Delete a client
+///
+/// The client's projects and documents are kept, without the link.
+pub async fn delete_client(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<StatusCode> {
+    tracing::info!("Deleting client {}", id);
+
+    ClientService::delete(&pool, &id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /clients/:id/projects - List a client's projects
+pub async fn list_client_projects(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<Json<Vec<ProjectResponse>>> {
+    tracing::debug!("Listing projects of client {}", id);
+
+    let projects = ClientService::projects(&pool, &id).await?;
+
+    let mut response = Vec::new();
+    for p in projects {
+        let count = ProjectService::get_document_count(&pool, &p.id)
+            .await
+            .unwrap_or(0);
+        response.push(ProjectResponse::from_project(p, count));
+    }
+
+    Ok(Json(response))
+}
+
+/// GET /clients/:id/documents - List a client's documents
+///
+/// Documents in any of the client's projects, plus documents received by
+/// email from one of the client's addresses (including ones still in the inbox).
+pub async fn list_client_documents(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<Json<Vec<DocumentResponse>>> {
+    tracing::debug!("Listing documents of client {}", id);
+
+    let docs = ClientService::documents(&pool, &id).await?;
+
+    let response = DocumentService::build_responses(&pool, docs).await?;
+
+    Ok(Json(response))
+}
@@ -0,0 +1,137 @@
+//! Comment handlers module
+//!
+//! HTTP handlers for per-document comment threads, optionally pinned to
+//! coordinates on an image or PDF page.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{
+    CreateDocumentCommentRequest, CreateReplyRequest, DocumentComment, DocumentCommentResponse,
+    ResolveDocumentCommentRequest,
+};
+use crate::services::{CommentService, DocumentService, PushService};
+
+/// GET /documents/:id/comments - List comment threads of a document
+pub async fn list_document_comments(
+    State(pool): State<DbPool>,
+    Path(document_id): Path<String>,
+) -> AppResult<Json<Vec<DocumentCommentResponse>>> {
+    let threads = CommentService::list_threads(&pool, &document_id).await?;
+    Ok(Json(threads))
+}
+
+/// POST /documents/:id/comments - Start a comment thread
+///
+/// # Request Body
+/// ```json
+/// { "content": "Esta parede está torta?", "author_name": "Rui", "x": 0.42, "y": 0.77 }
+/// ```
+/// For PDFs, add `"page": 2` to pin the comment to a specific page.
+pub async fn create_document_comment(
+    State(pool): State<DbPool>,
+    Path(document_id): Path<String>,
+    Json(payload): Json<CreateDocumentCommentRequest>,
+) -> AppResult<(StatusCode, Json<DocumentCommentResponse>)> {
+    let author = payload.author_name.as_deref().unwrap_or("Anónimo");
+
+    let position = match (payload.x, payload.y) {
+        (Some(x), Some(y)) => Some((x, y)),
+        (None, None) => None,
+        _ => {
+            return Err(AppError::BadRequest(
+                "Both x and y must be provided to pin a comment".into(),
+            ))
+        }
+    };
+
+    let comment = CommentService::create_thread(
+        &pool,
+        &document_id,
+        &payload.content,
+        author,
+        position,
+        payload.page,
+    )
+    .await?;
+
+    notify_participants(&pool, &comment);
+
+    let response = CommentService::build_response(&pool, comment).await?;
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// POST /comments/:id/replies - Reply to a comment thread
+pub async fn create_comment_reply(
+    State(pool): State<DbPool>,
+    Path(comment_id): Path<String>,
+    Json(payload): Json<CreateReplyRequest>,
+) -> AppResult<(StatusCode, Json<DocumentCommentResponse>)> {
+    let author = payload.author_name.as_deref().unwrap_or("Anónimo");
+
+    let reply = CommentService::create_reply(&pool, &comment_id, &payload.content, author).await?;
+
+    notify_participants(&pool, &reply);
+
+    let response = CommentService::build_response(&pool, reply).await?;
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// PATCH /comments/:id/resolve - Resolve or reopen a comment thread
+///
+/// # Request Body
+/// ```json
+/// { "resolved": true, "resolved_by": "Ana" }
+/// ```
+pub async fn resolve_document_comment(
+    State(pool): State<DbPool>,
+    Path(comment_id): Path<String>,
+    Json(payload): Json<ResolveDocumentCommentRequest>,
+) -> AppResult<Json<DocumentCommentResponse>> {
+    let comment = CommentService::set_resolved(
+        &pool,
+        &comment_id,
+        payload.resolved,
+        payload.resolved_by.as_deref(),
+    )
+    .await?;
+
+    Ok(Json(CommentService::build_response(&pool, comment).await?))
+}
+
+/// DELETE /comments/:id - Delete a comment (and its replies, if a thread root)
+pub async fn delete_document_comment(
+    State(pool): State<DbPool>,
+    Path(comment_id): Path<String>,
+) -> AppResult<StatusCode> {
+    CommentService::delete(&pool, &comment_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send push notifications to the thread participants in background
+fn notify_participants(pool: &DbPool, comment: &DocumentComment) {
+    let pool = pool.clone();
+    let comment = comment.clone();
+    tokio::spawn(async move {
+        let participants = CommentService::thread_participants(&pool, &comment)
+            .await
+            .unwrap_or_default();
+        let topic = DocumentService::get_by_id(&pool, &comment.document_id)
+            .await
+            .map(|d| d.original_name)
+            .unwrap_or_else(|_| "Comentário".to_string());
+        PushService::notify_participants(
+            &pool,
+            &participants,
+            &topic,
+            &comment.author_name,
+            &comment.content,
+        )
+        .await;
+    });
+}
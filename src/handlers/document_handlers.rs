@@ -10,44 +10,65 @@ use axum::{
 };
 
 use crate::db::DbPool;
+use crate::error::AppError;
 use crate::error::AppResult;
 use crate::models::{
-    AssignDocumentRequest, BatchAssignRequest, DocumentResponse, UpdateDocumentCategoryRequest,
-    UpdateDocumentNotesRequest, UpdateDocumentStatusRequest, UploadResponse,
+    AssignDocumentRequest, BatchAssignRequest, BulkDocumentsRequest, BulkDocumentsResponse,
+    DocumentResponse, UpdateDocumentCategoryRequest, UpdateDocumentNotesRequest,
+    UpdateDocumentStatusRequest, UploadResponse,
 };
-use crate::services::DocumentService;
+use crate::services::{DocumentService, VideoService};
 
-/// POST /upload - Upload a new document
+/// POST /upload - Upload one or more documents
 ///
 /// Handles multipart/form-data file uploads. Files are streamed directly
 /// to disk to prevent memory exhaustion with large files.
 ///
-/// The uploaded document is placed in the Inbox (project_id = NULL)
+/// Each file becomes its own document, placed in the Inbox (project_id = NULL)
 /// until manually assigned to a project.
 ///
 /// # Request
-/// Multipart form with a file field
+/// Multipart form with one or more file fields, plus optional `audio`,
+/// `project_id`, `author_name` (recorded as the uploader), `source` ("app" or
+/// "api"). Document fields `notes`, `category`, `status`, `tags` (repeatable
+/// or comma separated) and `captured_at` (RFC 3339 or epoch milliseconds)
+/// apply to every file, or to the i-th file as `notes[i]`, `tags[i]`, ...
 ///
 /// # Response
-/// Returns the created document record with 201 Created status
+/// 201 Created with the first document's fields and a result per file;
+/// rejected files are reported there unless every file was rejected
 pub async fn upload_document(
     State(pool): State<DbPool>,
     multipart: Multipart,
 ) -> AppResult<(StatusCode, Json<UploadResponse>)> {
     tracing::info!("Processing file upload");
 
-    // Pass the entire multipart stream to the service to extract both the file and optional audio
-    let doc = DocumentService::upload(&pool, multipart).await?;
+    // Pass the entire multipart stream to the service to extract the files and optional audio
+    let results = DocumentService::upload(&pool, multipart).await?;
+
+    let first = results
+        .iter()
+        .find_map(|r| r.document.as_ref())
+        .ok_or_else(|| AppError::Internal("Upload created no document".into()))?;
+    let succeeded = results.iter().filter(|r| r.success).count();
 
     let response = UploadResponse {
-        id: doc.id,
-        file_path: doc.file_path.clone(),
-        file_type: doc.file_type,
-        original_name: doc.original_name,
-        file_url: format!("/files/{}", doc.file_path),
+        id: first.id.clone(),
+        file_path: first.file_path.clone(),
+        file_type: first.file_type.clone(),
+        original_name: first.original_name.clone(),
+        file_url: first.file_url.clone(),
+        preview_url: first.preview_url.clone(),
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
     };
 
-    tracing::info!("File uploaded successfully: {}", response.file_path);
+    tracing::info!(
+        "Upload finished: {} file(s) stored, {} rejected",
+        response.succeeded,
+        response.failed
+    );
     Ok((StatusCode::CREATED, Json(response)))
 }
 
@@ -63,10 +84,7 @@ pub async fn list_inbox(State(pool): State<DbPool>) -> AppResult<Json<Vec<Docume
 
     let docs = DocumentService::list_inbox(&pool).await?;
 
-    let response: Vec<DocumentResponse> = docs
-        .into_iter()
-        .map(|d| DocumentResponse::from_document(d))
-        .collect();
+    let response = DocumentService::build_responses(&pool, docs).await?;
 
     Ok(Json(response))
 }
@@ -110,10 +128,11 @@ pub async fn assign_document(
         &id,
         payload.project_id.as_deref(),
         payload.category.as_deref(),
+        payload.changed_by.as_deref(),
     )
     .await?;
 
-    Ok(Json(DocumentResponse::from_document(doc)))
+    Ok(Json(DocumentService::build_response(&pool, doc).await?))
 }
 
 /// GET /projects/:id/documents - List all documents for a project
@@ -133,10 +152,7 @@ pub async fn list_project_documents(
 
     let docs = DocumentService::list_by_project(&pool, &project_id).await?;
 
-    let response: Vec<DocumentResponse> = docs
-        .into_iter()
-        .map(|d| DocumentResponse::from_document(d))
-        .collect();
+    let response = DocumentService::build_responses(&pool, docs).await?;
 
     Ok(Json(response))
 }
@@ -151,7 +167,7 @@ pub async fn get_document(
     tracing::debug!("Fetching document by ID: {}", id);
 
     let doc = DocumentService::get_by_id(&pool, &id).await?;
-    Ok(Json(DocumentResponse::from_document(doc)))
+    Ok(Json(DocumentService::build_response(&pool, doc).await?))
 }
 
 /// DELETE /documents/:id - Delete a document
@@ -197,7 +213,7 @@ pub async fn update_document_notes(
 
     let doc = DocumentService::update_notes(&pool, &id, payload.notes.as_deref()).await?;
 
-    Ok(Json(DocumentResponse::from_document(doc)))
+    Ok(Json(DocumentService::build_response(&pool, doc).await?))
 }
 
 /// PATCH /documents/batch-assign - Batch assign documents to a project
@@ -228,17 +244,57 @@ pub async fn batch_assign_documents(
         &pool,
         &payload.document_ids,
         payload.project_id.as_deref(),
+        payload.changed_by.as_deref(),
     )
     .await?;
 
-    let response: Vec<DocumentResponse> = docs
-        .into_iter()
-        .map(|d| DocumentResponse::from_document(d))
-        .collect();
+    let response = DocumentService::build_responses(&pool, docs).await?;
 
     Ok(Json(response))
 }
 
+/// POST /documents/bulk - Apply one operation to many documents
+///
+/// Runs in a single transaction and reports the outcome per document, so the
+/// inbox can triage dozens of items at once. Unknown IDs are reported as
+/// failures rather than silently ignored.
+///
+/// # Request Body
+/// ```json
+/// {
+///     "document_ids": ["uuid-1", "uuid-2"],
+///     "operation": { "type": "assign", "project_id": "uuid", "category": "Cozinha" }
+/// }
+/// ```
+/// Other operations: `set_status` (`status`), `set_category` (`category`),
+/// `add_tags` / `remove_tags` (`tags`), `delete` and `move_to_inbox`.
+pub async fn bulk_documents(
+    State(pool): State<DbPool>,
+    Json(payload): Json<BulkDocumentsRequest>,
+) -> AppResult<Json<BulkDocumentsResponse>> {
+    tracing::info!(
+        "Bulk {} on {} documents",
+        payload.operation.name(),
+        payload.document_ids.len()
+    );
+
+    let results = DocumentService::bulk_operation(
+        &pool,
+        &payload.document_ids,
+        &payload.operation,
+        payload.changed_by.as_deref(),
+    )
+    .await?;
+
+    let succeeded = results.iter().filter(|r| r.success).count();
+    Ok(Json(BulkDocumentsResponse {
+        operation: payload.operation.name().to_string(),
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    }))
+}
+
 /// PATCH /documents/:id/status - Update document status
 ///
 /// Updates the status of a document (e.g. from Default to Doubt)
@@ -248,7 +304,7 @@ pub async fn batch_assign_documents(
 ///
 /// # Request Body
 /// ```json
-/// { "status": "DOUBT" }
+/// { "status": "DOUBT", "changed_by": "Ana" }
 /// ```
 pub async fn update_document_status(
     State(pool): State<DbPool>,
@@ -261,9 +317,15 @@ pub async fn update_document_status(
         payload.status
     );
 
-    let doc = DocumentService::update_status(&pool, &id, payload.status.as_str()).await?;
+    let doc = DocumentService::update_status(
+        &pool,
+        &id,
+        payload.status.as_str(),
+        payload.changed_by.as_deref(),
+    )
+    .await?;
 
-    Ok(Json(DocumentResponse::from_document(doc)))
+    Ok(Json(DocumentService::build_response(&pool, doc).await?))
 }
 
 /// PATCH /documents/:id/category - Update document category
@@ -275,7 +337,7 @@ pub async fn update_document_status(
 ///
 /// # Request Body
 /// ```json
-/// { "category": "Kitchen" }
+/// { "category": "Kitchen", "changed_by": "Ana" }
 /// ```
 pub async fn update_document_category(
     State(pool): State<DbPool>,
@@ -284,7 +346,72 @@ pub async fn update_document_category(
 ) -> AppResult<Json<DocumentResponse>> {
     tracing::info!("Updating category for document: {}", id);
 
-    let doc = DocumentService::update_category(&pool, &id, payload.category.as_deref()).await?;
+    let doc = DocumentService::update_category(
+        &pool,
+        &id,
+        payload.category.as_deref(),
+        payload.changed_by.as_deref(),
+    )
+    .await?;
+
+    Ok(Json(DocumentService::build_response(&pool, doc).await?))
+}
+
+/// PUT /documents/:id/audio - Attach or replace the voice memo of a document
+///
+/// # Request
+/// Multipart form with an `audio` file field. The format is detected from the
+/// file content (WebM, Ogg, MP3, AAC, M4A, WAV, FLAC or AMR); any previous
+/// memo is deleted.
+///
+/// # Response
+/// Returns the updated document, including audio format and duration
+pub async fn set_document_audio(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    multipart: Multipart,
+) -> AppResult<Json<DocumentResponse>> {
+    tracing::info!("Setting voice memo for document: {}", id);
+
+    let doc = DocumentService::set_voice_memo(&pool, &id, multipart).await?;
+
+    Ok(Json(DocumentService::build_response(&pool, doc).await?))
+}
 
-    Ok(Json(DocumentResponse::from_document(doc)))
+/// DELETE /documents/:id/audio - Remove the voice memo of a document
+///
+/// # Response
+/// Returns the updated document
+pub async fn delete_document_audio(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<Json<DocumentResponse>> {
+    tracing::info!("Removing voice memo from document: {}", id);
+
+    let doc = DocumentService::remove_voice_memo(&pool, &id).await?;
+
+    Ok(Json(DocumentService::build_response(&pool, doc).await?))
+}
+
+/// POST /documents/:id/video/reprocess - Re-run video processing
+///
+/// Useful after installing ffmpeg or when a previous run failed. Processing
+/// happens in background; poll the document to see the new status.
+pub async fn reprocess_video(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<(StatusCode, Json<DocumentResponse>)> {
+    let doc = DocumentService::get_by_id(&pool, &id).await?;
+    if doc.file_type != "video" {
+        return Err(AppError::BadRequest(format!(
+            "Document '{}' is not a video",
+            id
+        )));
+    }
+
+    tracing::info!("Re-queueing video processing for document {}", id);
+    VideoService::enqueue(&pool, &id).await?;
+
+    let response = DocumentService::build_response(&pool, doc).await?;
+    Ok((StatusCode::ACCEPTED, Json(response)))
 }
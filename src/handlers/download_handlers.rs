@@ -0,0 +1,109 @@
+//! Download handlers module
+//!
+//! HTTP handlers for streamed ZIP downloads of a project or a selection
+//! of documents, and for full project export bundles.
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::services::{DownloadService, ExportService};
+
+/// Request payload for downloading a selection of documents
+#[derive(Debug, Deserialize)]
+pub struct DownloadDocumentsRequest {
+    pub document_ids: Vec<String>,
+}
+
+/// GET /projects/:id/download.zip - Download all documents of a project
+///
+/// Files are grouped in one folder per category/room and named after their
+/// original filename. A `manifest.csv` lists notes, status and tags.
+pub async fn download_project_zip(
+    State(pool): State<DbPool>,
+    Path(project_id): Path<String>,
+) -> AppResult<Response> {
+    tracing::info!("Streaming ZIP for project {}", project_id);
+
+    let (filename, body) = DownloadService::project_zip(&pool, &project_id).await?;
+    Ok(zip_response(&filename, body))
+}
+
+/// POST /documents/download.zip - Download a selection of documents
+///
+/// # Request Body
+/// ```json
+/// { "document_ids": ["uuid-1", "uuid-2"] }
+/// ```
+pub async fn download_documents_zip(
+    State(pool): State<DbPool>,
+    Json(payload): Json<DownloadDocumentsRequest>,
+) -> AppResult<Response> {
+    tracing::info!(
+        "Streaming ZIP for {} selected documents",
+        payload.document_ids.len()
+    );
+
+    let (filename, body) = DownloadService::documents_zip(&pool, &payload.document_ids).await?;
+    Ok(zip_response(&filename, body))
+}
+
+/// GET /projects/:id/export - Download a self-contained project bundle
+///
+/// Holds all documents with their metadata JSON, comments and annotations,
+/// voice memos, the forum transcript with replies and task lists with their
+/// completion history, plus an `index.html` to browse it offline.
+pub async fn export_project(
+    State(pool): State<DbPool>,
+    Path(project_id): Path<String>,
+) -> AppResult<Response> {
+    tracing::info!("Streaming export bundle for project {}", project_id);
+
+    let (filename, body) = ExportService::project_bundle(&pool, &project_id).await?;
+    Ok(zip_response(&filename, body))
+}
+
+/// Wrap a streaming ZIP body with download headers
+fn zip_response(filename: &str, body: Body) -> Response {
+    // Plain ASCII fallback plus RFC 5987 UTF-8 name for accented project names
+    let ascii: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-_.".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    let disposition = format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii, encoded
+    );
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
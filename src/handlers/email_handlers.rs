@@ -22,6 +22,10 @@ pub struct EmailWebhookResponse {
     pub message: String,
     pub documents_created: usize,
     pub documents_filtered: usize,
+    pub documents_rejected: usize,
+    pub documents_quarantined: usize,
+    /// "filename: signature" for each quarantined attachment
+    pub threats: Vec<String>,
 }
 
 /// POST /api/email/inbound - Receive inbound email webhook
@@ -56,19 +60,26 @@ pub async fn receive_inbound_email(
     let response = EmailWebhookResponse {
         success: true,
         message: format!(
-            "Email processed: {} document(s) created, {} filtered out.",
+            "Email processed: {} document(s) created, {} filtered out, {} rejected, {} quarantined.",
             result.documents_created,
-            result.documents_filtered
+            result.documents_filtered,
+            result.documents_rejected,
+            result.documents_quarantined
         ),
         documents_created: result.documents_created,
         documents_filtered: result.documents_filtered,
+        documents_rejected: result.documents_rejected,
+        documents_quarantined: result.documents_quarantined,
+        threats: result.threats,
     };
 
     tracing::info!(
-        "Email from '{}' processed: {} attachments saved, {} filtered",
+        "Email from '{}' processed: {} attachments saved, {} filtered, {} rejected, {} quarantined",
         result.sender,
         result.documents_created,
-        result.documents_filtered
+        result.documents_filtered,
+        result.documents_rejected,
+        result.documents_quarantined
     );
 
     Ok((StatusCode::OK, Json(response)))
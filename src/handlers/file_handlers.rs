@@ -0,0 +1,327 @@
+//! File handlers module
+//!
+//! Serves stored files when the storage backend isn't a directory the web
+//! server can serve by itself, and sets the caching headers of every file
+//! response.
+//!
+//! # Architecture Decision
+//! Files are stored under the SHA-256 of their content, so the key itself is
+//! a strong validator: the ETag is the checksum and the response can be
+//! cached forever. Conditional requests are answered from the key without
+//! touching storage.
+//!
+//! Files of long-archived projects may have moved to cold storage; a request
+//! for one brings it back and redirects to the same URL.
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Path, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::services::{ColdStorageService, StorageService};
+
+/// Cache policy of content-addressed files, which never change
+const IMMUTABLE_CACHE: &str = "public, max-age=31536000, immutable";
+
+/// Cache policy of files outside the content-addressed layout
+const REVALIDATE_CACHE: &str = "no-cache";
+
+/// Byte range selected by a `Range` header
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    /// No usable range: send the whole file
+    Full,
+    /// First and last byte, inclusive
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// GET /files/*key - Download a stored file
+///
+/// Redirects to a short-lived presigned URL when the backend hands them out,
+/// otherwise streams the file through the server, honouring single byte
+/// ranges so audio and video can seek.
+pub async fn serve_file(Path(key): Path<String>, headers: HeaderMap) -> AppResult<Response> {
+    if let Some(url) = StorageService::download_url(&key).await? {
+        return Ok(Redirect::temporary(&url).into_response());
+    }
+
+    let backend = StorageService::backend();
+    let size = backend
+        .head(&key)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("File '{}' not found", key)))?
+        .size;
+    let mime = mime_guess::from_path(&key).first_or_octet_stream();
+
+    let (status, object, content_range) = match requested_range(&headers, &key, size) {
+        ByteRange::Full => (StatusCode::OK, backend.get(&key).await?, None),
+        ByteRange::Partial(first, last) => (
+            StatusCode::PARTIAL_CONTENT,
+            backend.get_range(&key, first, last - first + 1).await?,
+            Some(format!("bytes {}-{}/{}", first, last, size)),
+        ),
+        ByteRange::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", size))],
+            )
+                .into_response());
+        }
+    };
+
+    let mut response = (
+        status,
+        [
+            (header::CONTENT_TYPE, mime.to_string()),
+            (header::CONTENT_LENGTH, object.size.to_string()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ],
+        Body::from_stream(ReaderStream::new(object.reader)),
+    )
+        .into_response();
+    if let Some(content_range) = content_range {
+        if let Ok(value) = HeaderValue::from_str(&content_range) {
+            response.headers_mut().insert(header::CONTENT_RANGE, value);
+        }
+    }
+    Ok(response)
+}
+
+/// Middleware adding ETag and Cache-Control to file responses
+///
+/// Answers `If-None-Match` with 304 for content-addressed keys. Redirects and
+/// errors keep the app-wide no-store policy.
+pub async fn file_cache_headers(request: Request, next: Next) -> Response {
+    let key = request.uri().path().trim_start_matches('/');
+    let checksum = StorageService::checksum_of_key(key).map(str::to_string);
+    let is_read = matches!(*request.method(), Method::GET | Method::HEAD);
+
+    if let Some(checksum) = checksum.as_deref() {
+        if is_read && matches_if_none_match(request.headers(), checksum) {
+            let mut response = StatusCode::NOT_MODIFIED.into_response();
+            set_cache_headers(&mut response, Some(checksum));
+            return response;
+        }
+    }
+
+    let mut response = next.run(request).await;
+    if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+        set_cache_headers(&mut response, checksum.as_deref());
+    }
+    response
+}
+
+/// Middleware restoring files moved to cold storage when they're requested
+///
+/// A missing file that belongs to an archived document is extracted back into
+/// storage and the client is redirected to try again.
+pub async fn restore_cold_files(
+    State(pool): State<DbPool>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let key = request.uri().path().trim_start_matches('/').to_string();
+    let uri = match request.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.to_string(),
+        None => request.uri().to_string(),
+    };
+    let is_read = matches!(*request.method(), Method::GET | Method::HEAD);
+
+    let response = next.run(request).await;
+    if !is_read || response.status() != StatusCode::NOT_FOUND || !ColdStorageService::is_enabled() {
+        return response;
+    }
+
+    match ColdStorageService::restore_key(&pool, &key).await {
+        Ok(true) => Redirect::temporary(&uri).into_response(),
+        Ok(false) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
+fn set_cache_headers(response: &mut Response, checksum: Option<&str>) {
+    let Some(checksum) = checksum else {
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(REVALIDATE_CACHE),
+        );
+        return;
+    };
+
+    let etag = format!("\"{}\"", checksum);
+    let headers = response.headers_mut();
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(IMMUTABLE_CACHE),
+    );
+}
+
+/// Entity tags listed in a conditional header, without quotes or weak prefix
+fn entity_tags(headers: &HeaderMap, name: header::HeaderName) -> Vec<&str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| {
+            let tag = tag.trim();
+            tag.strip_prefix("W/").unwrap_or(tag).trim_matches('"')
+        })
+        .collect()
+}
+
+/// Whether the client already holds this content
+fn matches_if_none_match(headers: &HeaderMap, checksum: &str) -> bool {
+    entity_tags(headers, header::IF_NONE_MATCH)
+        .into_iter()
+        .any(|tag| tag == "*" || tag == checksum)
+}
+
+/// Parse a single `bytes=` range against a file of `size` bytes
+///
+/// Multiple ranges, malformed headers and an `If-Range` that doesn't match
+/// the file fall back to sending the whole file.
+fn requested_range(headers: &HeaderMap, key: &str, size: u64) -> ByteRange {
+    let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+        return ByteRange::Full;
+    };
+    if headers.contains_key(header::IF_RANGE) {
+        let current = StorageService::checksum_of_key(key);
+        let tags = entity_tags(headers, header::IF_RANGE);
+        if current.is_none() || tags.len() != 1 || Some(tags[0]) != current {
+            return ByteRange::Full;
+        }
+    }
+
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let (first, last) = match (first.parse::<u64>().ok(), last.parse::<u64>().ok()) {
+        // bytes=first-last, clamped to the end of the file
+        (Some(first), Some(last)) if first <= last => (first, last.min(size.saturating_sub(1))),
+        // bytes=first-
+        (Some(first), None) if last.is_empty() => (first, size.saturating_sub(1)),
+        // bytes=-suffix_length
+        (None, Some(suffix)) if first.is_empty() => {
+            if suffix == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        _ => return ByteRange::Full,
+    };
+    if first >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(first, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUM: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn key() -> String {
+        StorageService::content_key(CHECKSUM, "mp4")
+    }
+
+    fn range(value: &str, size: u64) -> ByteRange {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_str(value).unwrap());
+        requested_range(&headers, &key(), size)
+    }
+
+    #[test]
+    fn no_range_sends_the_whole_file() {
+        assert_eq!(
+            requested_range(&HeaderMap::new(), &key(), 100),
+            ByteRange::Full
+        );
+    }
+
+    #[test]
+    fn ranges_are_inclusive() {
+        assert_eq!(range("bytes=0-0", 100), ByteRange::Partial(0, 0));
+        assert_eq!(range("bytes=10-19", 100), ByteRange::Partial(10, 19));
+        assert_eq!(range("bytes=90-", 100), ByteRange::Partial(90, 99));
+        assert_eq!(range("bytes=-10", 100), ByteRange::Partial(90, 99));
+    }
+
+    #[test]
+    fn ranges_are_clamped_to_the_end_of_the_file() {
+        assert_eq!(range("bytes=50-1000", 100), ByteRange::Partial(50, 99));
+        assert_eq!(range("bytes=-1000", 100), ByteRange::Partial(0, 99));
+        assert_eq!(
+            range(&format!("bytes=0-{}", u64::MAX), 100),
+            ByteRange::Partial(0, 99)
+        );
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_unsatisfiable() {
+        assert_eq!(range("bytes=100-", 100), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=100-200", 100), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=-0", 100), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=0-", 0), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=-5", 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn unusable_ranges_send_the_whole_file() {
+        for value in [
+            "bytes=20-10",
+            "bytes=0-1,5-9",
+            "items=0-9",
+            "bytes=",
+            "bytes=-",
+            "bytes=abc-9",
+            "bytes=-1-2",
+        ] {
+            assert_eq!(range(value, 100), ByteRange::Full, "{}", value);
+        }
+    }
+
+    #[test]
+    fn if_range_must_match_the_content() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=0-9"));
+        headers.insert(
+            header::IF_RANGE,
+            HeaderValue::from_str(&format!("\"{}\"", CHECKSUM)).unwrap(),
+        );
+        assert_eq!(
+            requested_range(&headers, &key(), 100),
+            ByteRange::Partial(0, 9)
+        );
+
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"stale\""));
+        assert_eq!(requested_range(&headers, &key(), 100), ByteRange::Full);
+
+        headers.insert(
+            header::IF_RANGE,
+            HeaderValue::from_str(&format!("\"{}\"", CHECKSUM)).unwrap(),
+        );
+        assert_eq!(
+            requested_range(&headers, "report.mp4", 100),
+            ByteRange::Full
+        );
+    }
+}
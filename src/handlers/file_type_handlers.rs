@@ -0,0 +1,17 @@
+//! File type handlers module
+//!
+//! Exposes the file type registry so clients can show consistent icons and
+//! know which documents they can preview in the browser.
+
+use axum::Json;
+
+use crate::services::file_type_service::FileTypeInfo;
+use crate::services::FileTypeService;
+
+/// GET /file-types - List the file type registry
+///
+/// Each entry has the category stored on documents (`file_type`), an icon
+/// hint, the preview capability and the MIME types/extensions it matches.
+pub async fn list_file_types() -> Json<Vec<&'static FileTypeInfo>> {
+    Json(FileTypeService::all())
+}
@@ -15,13 +15,8 @@ use crate::models::{
     CreateForumMessageRequest, CreateReplyRequest, ForumMessageResponse, TaskItemResponse,
     ToggleTaskItemRequest,
 };
-use crate::services::{ForumService, ProjectService, PushService};
-
-use std::path::Path as StdPath;
-use tokio::io::AsyncWriteExt;
-
-/// Upload directory for audio files
-const UPLOADS_DIR: &str = "./uploads";
+use crate::services::storage_service::KeyPin;
+use crate::services::{DocumentService, ForumService, ProjectService, PushService};
 
 /// POST /projects/:id/forum - Create a forum message
 ///
@@ -81,7 +76,7 @@ pub async fn create_voice_message(
     Path(project_id): Path<String>,
     mut multipart: Multipart,
 ) -> AppResult<(StatusCode, Json<ForumMessageResponse>)> {
-    let mut audio_path: Option<String> = None;
+    let mut audio: Option<KeyPin> = None;
     let mut author_name = "Anónimo".to_string();
     let mut text_content: Option<String> = None;
 
@@ -111,39 +106,23 @@ pub async fn create_voice_message(
         }
 
         if name == "audio" {
-            let original_name = field.file_name().unwrap_or("voice.webm").to_string();
-            let ext = StdPath::new(&original_name)
-                .extension()
-                .and_then(|e| e.to_str())
-                .unwrap_or("webm");
-
-            let timestamp = chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S");
-            let short_id = &uuid::Uuid::new_v4().to_string()[..4];
-            let filename = format!("voice_{}_{}.{}", timestamp, short_id, ext);
-
-            let data = field
-                .bytes()
-                .await
-                .map_err(|e| crate::error::AppError::Internal(format!("Read error: {}", e)))?;
-
-            let file_path = format!("{}/{}", UPLOADS_DIR, filename);
-            let mut file = tokio::fs::File::create(&file_path)
-                .await
-                .map_err(|e| crate::error::AppError::Internal(format!("File error: {}", e)))?;
-            file.write_all(&data)
-                .await
-                .map_err(|e| crate::error::AppError::Internal(format!("Write error: {}", e)))?;
-
-            audio_path = Some(filename);
+            // Checked, scanned and named after its real format, like voice memos
+            let memo = DocumentService::save_voice_memo(&pool, field).await?;
+            audio = Some(memo.file);
         }
     }
 
-    let path = audio_path
+    let audio = audio
         .ok_or_else(|| crate::error::AppError::BadRequest("No audio file provided".to_string()))?;
 
-    let msg =
-        ForumService::create_voice_message(&pool, &project_id, &path, &author_name, text_content)
-            .await?;
+    let msg = ForumService::create_voice_message(
+        &pool,
+        &project_id,
+        audio.key(),
+        &author_name,
+        text_content,
+    )
+    .await?;
     let response = ForumService::build_response(&pool, msg).await?;
 
     // Push notification for voice message
@@ -0,0 +1,46 @@
+//! Integrity handlers module
+//!
+//! Admin endpoints for the storage integrity scan. `GET` only reports;
+//! `POST` can also quarantine orphaned files and repair database rows.
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::models::{IntegrityOptions, IntegrityReport};
+use crate::services::IntegrityService;
+
+/// GET /admin/integrity - Report storage inconsistencies without changing anything
+///
+/// # Query Parameters
+/// - `verify_checksums`: also re-hash document files (slow on large stores)
+pub async fn check_storage_integrity(
+    State(pool): State<DbPool>,
+    Query(options): Query<IntegrityOptions>,
+) -> AppResult<Json<IntegrityReport>> {
+    let options = IntegrityOptions {
+        verify_checksums: options.verify_checksums,
+        ..Default::default()
+    };
+    let report = IntegrityService::check(&pool, &options).await?;
+    Ok(Json(report))
+}
+
+/// POST /admin/integrity - Scan storage and apply the requested fixes
+///
+/// # Request Body
+/// ```json
+/// { "verify_checksums": true, "quarantine_orphans": true, "repair": true }
+/// ```
+pub async fn repair_storage_integrity(
+    State(pool): State<DbPool>,
+    Json(options): Json<IntegrityOptions>,
+) -> AppResult<Json<IntegrityReport>> {
+    tracing::info!("Running storage integrity scan with fixes: {:?}", options);
+
+    let report = IntegrityService::check(&pool, &options).await?;
+    Ok(Json(report))
+}
@@ -3,16 +3,31 @@
 //! This module exposes all HTTP handlers for the API endpoints.
 //! Handlers are organized by domain (projects, documents, email).
 
+pub mod annotation_handlers;
+pub mod client_handlers;
+pub mod comment_handlers;
 pub mod document_handlers;
+pub mod download_handlers;
 pub mod email_handlers;
+pub mod file_handlers;
+pub mod file_type_handlers;
 pub mod forum_handlers;
+pub mod integrity_handlers;
 pub mod project_handlers;
 pub mod push_handlers;
+pub mod quarantine_handlers;
 pub mod user_handlers;
 
+pub use annotation_handlers::*;
+pub use client_handlers::*;
+pub use comment_handlers::*;
 pub use document_handlers::*;
+pub use download_handlers::*;
 pub use email_handlers::*;
+pub use file_handlers::*;
+pub use file_type_handlers::*;
 pub use forum_handlers::*;
+pub use integrity_handlers::*;
 pub use project_handlers::*;
 pub use push_handlers::*;
-pub use user_handlers::*;
+pub use quarantine_handlers::*;
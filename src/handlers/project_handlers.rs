@@ -4,7 +4,7 @@
 //! Handlers are thin wrappers that delegate to the service layer.
 
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Multipart, Path, Query, State},
     http::StatusCode,
     Json,
 };
@@ -12,9 +12,14 @@ use axum::{
 use crate::db::DbPool;
 use crate::error::AppResult;
 use crate::models::{
-    CreateProjectRequest, ListProjectsQuery, ProjectResponse, UpdateProjectStatusRequest,
+    ActivityPage, ActivityQuery, ColdStorageReport, CreateProjectRequest, DeleteProjectQuery,
+    ImportOptions, ImportReport, LifecycleState, ListProjectsQuery, MergeProjectRequest,
+    ProjectDeleteReport, ProjectMergeReport, ProjectResponse, ProjectStatusChange,
+    RenameProjectRequest, SetProjectClientRequest, UpdateProjectStatusRequest,
+};
+use crate::services::{
+    ActivityService, ColdStorageService, ImportService, LifecycleService, ProjectService,
 };
-use crate::services::ProjectService;
 
 /// POST /projects - Create a new project
 ///
@@ -34,8 +39,14 @@ pub async fn create_project(
 ) -> AppResult<(StatusCode, Json<ProjectResponse>)> {
     tracing::info!("Creating new project: {}", payload.name);
 
-    let project =
-        ProjectService::create(&pool, payload.name, payload.address, payload.client_phone).await?;
+    let project = ProjectService::create(
+        &pool,
+        payload.name,
+        payload.address,
+        payload.client_phone,
+        payload.client_id,
+    )
+    .await?;
 
     Ok((StatusCode::CREATED, Json(project.into())))
 }
@@ -92,20 +103,18 @@ pub async fn get_project(
 
 /// PATCH /projects/:id/status - Update project status
 ///
-/// Change a project's status between ACTIVE and ARCHIVED.
-/// Use this to mark a project as complete/done.
+/// Move a project to another lifecycle state (see GET /projects/lifecycle).
+/// Transitions the lifecycle doesn't allow are rejected with 400.
 ///
 /// # Path Parameters
 /// - `id`: Project UUID
 ///
 /// # Request Body
 /// ```json
-/// { "status": "ARCHIVED" }
-/// ```
-/// or
-/// ```json
-/// { "status": "ACTIVE" }
+/// { "status": "INSTALLING", "changed_by": "Rui" }
 /// ```
+/// `{ "status": "ACTIVE" }` reopens an archived project in the state it
+/// was archived from.
 ///
 /// # Response
 /// Returns the updated project
@@ -116,11 +125,75 @@ pub async fn update_project_status(
 ) -> AppResult<Json<ProjectResponse>> {
     tracing::info!("Updating project {} status to {:?}", id, payload.status);
 
-    let project = ProjectService::update_status(&pool, &id, payload.status).await?;
+    let project =
+        ProjectService::update_status(&pool, &id, &payload.status, payload.changed_by.as_deref())
+            .await?;
 
     Ok(Json(project.into()))
 }
 
+/// GET /projects/:id/status-history - List a project's status changes
+///
+/// Oldest first; the first entry is the state the project started in.
+pub async fn get_project_status_history(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<Json<Vec<ProjectStatusChange>>> {
+    let history = ProjectService::status_history(&pool, &id).await?;
+
+    Ok(Json(history))
+}
+
+/// GET /projects/:id/activity - A project's activity feed
+///
+/// Document uploads, moves, status and category changes, forum messages and
+/// replies, task completions and project status changes, newest first.
+///
+/// # Query Parameters
+/// - `limit`: Entries per page (default 50, at most 200)
+/// - `offset`: Entries to skip; pass `next_offset` from the previous page
+pub async fn get_project_activity(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(query): Query<ActivityQuery>,
+) -> AppResult<Json<ActivityPage>> {
+    let page = ActivityService::project_activity(&pool, &id, &query).await?;
+
+    Ok(Json(page))
+}
+
+/// GET /projects/lifecycle - The project lifecycle
+///
+/// States in order, each with the states it can move to.
+pub async fn get_project_lifecycle(
+    State(pool): State<DbPool>,
+) -> AppResult<Json<Vec<LifecycleState>>> {
+    let lifecycle = LifecycleService::get(&pool).await?;
+
+    Ok(Json(lifecycle))
+}
+
+/// PUT /projects/lifecycle - Replace the project lifecycle
+///
+/// # Request Body
+/// ```json
+/// [
+///   { "code": "QUOTE", "label": "Orçamento", "next": ["APPROVED", "ARCHIVED"] },
+///   { "code": "APPROVED", "label": "Aprovada", "next": ["ARCHIVED"] },
+///   { "code": "ARCHIVED", "label": "Arquivada", "next": ["QUOTE"] }
+/// ]
+/// ```
+/// New projects start in the first state. ARCHIVED is required, and states
+/// still used by projects can't be removed.
+pub async fn update_project_lifecycle(
+    State(pool): State<DbPool>,
+    Json(payload): Json<Vec<LifecycleState>>,
+) -> AppResult<Json<Vec<LifecycleState>>> {
+    let lifecycle = LifecycleService::replace(&pool, payload).await?;
+
+    Ok(Json(lifecycle))
+}
+
 /// PATCH /projects/:id/details - Update project details (address, phone)
 pub async fn update_project_details(
     State(pool): State<DbPool>,
@@ -134,3 +207,152 @@ pub async fn update_project_details(
 
     Ok(Json(project.into()))
 }
+
+/// PATCH /projects/:id/client - Link a project to a client
+///
+/// # Request Body
+/// ```json
+/// { "client_id": "client-uuid" }
+/// ```
+/// Send `null` to unlink the project.
+pub async fn set_project_client(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetProjectClientRequest>,
+) -> AppResult<Json<ProjectResponse>> {
+    tracing::info!(
+        "Setting client of project {} to {:?}",
+        id,
+        payload.client_id
+    );
+
+    let project = ProjectService::set_client(&pool, &id, payload.client_id.as_deref()).await?;
+
+    Ok(Json(project.into()))
+}
+
+/// PATCH /projects/:id/name - Rename a project
+///
+/// # Request Body
+/// ```json
+/// { "name": "Obra Porto Seg Social" }
+/// ```
+pub async fn rename_project(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<RenameProjectRequest>,
+) -> AppResult<Json<ProjectResponse>> {
+    tracing::info!("Renaming project {} to {}", id, payload.name);
+
+    let project = ProjectService::rename(&pool, &id, payload.name).await?;
+
+    Ok(Json(project.into()))
+}
+
+/// POST /projects/:id/merge - Merge a project into another
+///
+/// Moves documents, forum messages (with replies and task lists) and email
+/// rules into the target project, then removes this one.
+///
+/// # Request Body
+/// ```json
+/// { "into": "target-project-uuid" }
+/// ```
+///
+/// # Response
+/// Returns the target project and how much was moved
+pub async fn merge_project(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Json(payload): Json<MergeProjectRequest>,
+) -> AppResult<Json<ProjectMergeReport>> {
+    tracing::info!("Merging project {} into {}", id, payload.into);
+
+    let report = ProjectService::merge(&pool, &id, &payload.into).await?;
+
+    Ok(Json(report))
+}
+
+/// DELETE /projects/:id - Delete a project
+///
+/// The project's forum (messages, replies, task lists, voice messages) and
+/// email rules are deleted with it.
+///
+/// # Query Parameters
+/// - `documents`: "inbox" (default) sends documents back to the inbox,
+///   "delete" deletes them and their files permanently
+///
+/// # Response
+/// Returns what was deleted or sent back to the inbox
+pub async fn delete_project(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+    Query(params): Query<DeleteProjectQuery>,
+) -> AppResult<Json<ProjectDeleteReport>> {
+    tracing::info!(
+        "Deleting project {} (documents: {:?})",
+        id,
+        params.documents
+    );
+
+    let report = ProjectService::delete(&pool, &id, params.documents).await?;
+
+    Ok(Json(report))
+}
+
+/// POST /projects/:id/files/archive - Move an archived project's files to cold storage
+///
+/// Does not wait for `COLD_STORAGE_AFTER_DAYS`. Previews and video posters
+/// stay in regular storage.
+///
+/// # Response
+/// Returns how many documents and files were moved and the archive size
+pub async fn archive_project_files(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<Json<ColdStorageReport>> {
+    tracing::info!("Moving files of project {} to cold storage", id);
+
+    let report = ColdStorageService::archive_project(&pool, &id).await?;
+
+    Ok(Json(report))
+}
+
+/// POST /projects/:id/files/restore - Bring a project's files back from cold storage
+///
+/// # Response
+/// Returns how many documents and files were restored
+pub async fn restore_project_files(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<Json<ColdStorageReport>> {
+    tracing::info!("Restoring files of project {} from cold storage", id);
+
+    let report = ColdStorageService::restore_project(&pool, &id).await?;
+
+    Ok(Json(report))
+}
+
+/// POST /projects/import - Recreate a project from an export bundle
+///
+/// # Request
+/// Multipart form with the bundle ZIP as `bundle`.
+///
+/// # Query Parameters
+/// - `dry_run`: only report what would be imported
+/// - `preserve_ids`: keep the bundle's IDs; rows that already exist are skipped
+/// - `project_id`: import into this existing project instead of a new one
+///
+/// # Response
+/// Returns what was (or would be) created, skipped as duplicate, and any problems
+pub async fn import_project(
+    State(pool): State<DbPool>,
+    Query(options): Query<ImportOptions>,
+    multipart: Multipart,
+) -> AppResult<Json<ImportReport>> {
+    tracing::info!("Importing project bundle: {:?}", options);
+
+    let report = ImportService::import_upload(&pool, multipart, &options).await?;
+
+    Ok(Json(report))
+}
@@ -0,0 +1,34 @@
+//! Quarantine handlers module
+//!
+//! HTTP handlers for reviewing and purging files held back by the malware
+//! scanner. Quarantined files are never served; only their records are exposed.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::models::QuarantinedFile;
+use crate::services::ScanService;
+
+/// GET /quarantine - List quarantined files, newest first
+pub async fn list_quarantined_files(
+    State(pool): State<DbPool>,
+) -> AppResult<Json<Vec<QuarantinedFile>>> {
+    let files = ScanService::list_quarantined(&pool).await?;
+    Ok(Json(files))
+}
+
+/// DELETE /quarantine/:id - Permanently delete a quarantined file
+pub async fn delete_quarantined_file(
+    State(pool): State<DbPool>,
+    Path(id): Path<String>,
+) -> AppResult<StatusCode> {
+    tracing::info!("Purging quarantined file: {}", id);
+
+    ScanService::delete_quarantined(&pool, &id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
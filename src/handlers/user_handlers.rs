@@ -2,7 +2,7 @@ use crate::{
     db::DbPool,
     error::AppResult,
     models::{UpdateProfilePhotoRequest, UserProfile},
-    services::user_service::UserService,
+    services::UserService,
 };
 use axum::{
     extract::{Path, State},
@@ -40,7 +40,7 @@ use axum::{
     extract::DefaultBodyLimit,
     http::header,
     middleware,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use std::net::SocketAddr;
@@ -53,18 +53,30 @@ use tower_http::{
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::handlers::{
-    assign_document, batch_assign_documents, create_email_filter, create_email_rule,
-    create_forum_message, create_project, create_reply, create_voice_message, delete_document,
-    delete_email_filter, delete_email_rule, email_webhook_status, get_document, get_project,
-    get_vapid_key, list_email_filters, list_email_rules, list_forum_messages, list_inbox,
-    list_project_documents, list_projects, list_replies, push_subscribe, push_unsubscribe,
-    receive_inbound_email, toggle_task_item, update_document_category, update_document_notes,
-    update_document_status, update_project_details, update_project_status, upload_document,
-    user_handlers,
+    archive_project_files, assign_document, batch_assign_documents, bulk_documents,
+    check_storage_integrity, create_annotation, create_client, create_comment_reply,
+    create_document_comment, create_email_filter, create_email_rule, create_forum_message,
+    create_project, create_reply, create_voice_message, delete_annotation, delete_client,
+    delete_document, delete_document_audio, delete_document_comment, delete_email_filter,
+    delete_email_rule, delete_project, delete_quarantined_file, download_documents_zip,
+    download_project_zip, email_webhook_status, export_project, file_cache_headers, get_client,
+    get_document, get_project, get_project_activity, get_project_lifecycle,
+    get_project_status_history, get_vapid_key, import_project, list_annotations,
+    list_client_documents, list_client_projects, list_clients, list_document_comments,
+    list_email_filters, list_email_rules, list_file_types, list_forum_messages, list_inbox,
+    list_project_documents, list_projects, list_quarantined_files, list_replies, merge_project,
+    push_subscribe, push_unsubscribe, receive_inbound_email, rename_project,
+    render_annotated_document, repair_storage_integrity, reprocess_video, resolve_document_comment,
+    restore_cold_files, restore_project_files, serve_file, set_document_audio, set_project_client,
+    toggle_task_item, update_annotation, update_client, update_document_category,
+    update_document_notes, update_document_status, update_project_details,
+    update_project_lifecycle, update_project_status, upload_document, user_handlers,
+};
+use crate::models::{ImportOptions, IntegrityOptions};
+use crate::services::{
+    ColdStorageService, ContentService, EncryptionService, FileTypeService, ImportService,
+    IntegrityService, PreviewService, PushService, StorageService, VideoService,
 };
-use crate::services::document_service::UPLOADS_DIR;
-use crate::services::email_service::EmailService;
-use crate::services::PushService;
 
 /// Default database URL for SQLite (used if DATABASE_URL env var not set)
 /// The `?mode=rwc` flag creates the database if it doesn't exist
@@ -106,10 +118,23 @@ async fn main() {
     // Initialize database connection pool and run migrations
     let pool = db::init_db(&database_url).await;
 
-    // Ensure uploads directory exists
-    tokio::fs::create_dir_all(UPLOADS_DIR)
-        .await
-        .expect("Failed to create uploads directory");
+    // Set up file storage (creates the uploads directory for local storage)
+    let storage = StorageService::backend();
+    tracing::info!("Storing files in {}", storage.describe());
+
+    // `charta integrity [...]` runs a storage integrity scan instead of the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("integrity") {
+        std::process::exit(run_integrity_command(&pool, &args[1..]).await);
+    }
+    // `charta import <bundle.zip> [...]` imports a project export bundle
+    if args.first().map(String::as_str) == Some("import") {
+        std::process::exit(run_import_command(&pool, &args[1..]).await);
+    }
+    // `charta encryption <command>` manages encryption at rest
+    if args.first().map(String::as_str) == Some("encryption") {
+        std::process::exit(run_encryption_command(&args[1..]).await);
+    }
 
     // Initialize VAPID keys for push notifications
     if let Err(e) = PushService::init_vapid(&pool).await {
@@ -119,6 +144,29 @@ async fn main() {
         );
     }
 
+    // Give drawings stored as "other" before the file type registry their category
+    if let Err(e) = FileTypeService::reclassify_documents(&pool).await {
+        tracing::warn!("Failed to re-categorize documents: {}", e);
+    }
+
+    // Move files of older versions to the content-addressed layout
+    if let Err(e) = StorageService::migrate_layout(&pool).await {
+        tracing::warn!("Failed to migrate stored files: {}", e);
+    }
+
+    // Render previews for drawings uploaded before previews existed
+    if let Err(e) = PreviewService::generate_missing(&pool).await {
+        tracing::warn!("Failed to render drawing previews: {}", e);
+    }
+
+    // Pick up video jobs interrupted by a restart
+    if let Err(e) = VideoService::resume_pending(&pool).await {
+        tracing::warn!("Failed to resume video processing jobs: {}", e);
+    }
+
+    // Move files of long-archived projects to cold storage, if configured
+    ColdStorageService::spawn_scheduler(&pool);
+
     // Configure CORS for cross-origin requests
     // This is necessary for the Tauri desktop app and mobile app
     //
@@ -133,11 +181,41 @@ async fn main() {
     // API routes are prefixed with /api for clean separation from web app
     let api_routes = Router::new()
         // Project endpoints
+        .route("/clients", post(create_client).get(list_clients))
+        .route(
+            "/clients/:id",
+            get(get_client).patch(update_client).delete(delete_client),
+        )
+        .route("/clients/:id/projects", get(list_client_projects))
+        .route("/clients/:id/documents", get(list_client_documents))
         .route("/projects", post(create_project).get(list_projects))
-        .route("/projects/:id", get(get_project))
+        .route("/projects/:id", get(get_project).delete(delete_project))
+        // Bundles are streamed to disk and have their own, larger limit
+        .route(
+            "/projects/import",
+            post(import_project).layer(DefaultBodyLimit::max(
+                ContentService::max_import_size() as usize
+            )),
+        )
         .route("/projects/:id/documents", get(list_project_documents))
+        .route("/projects/:id/download.zip", get(download_project_zip))
+        .route("/projects/:id/export", get(export_project))
         .route("/projects/:id/status", patch(update_project_status))
+        .route(
+            "/projects/:id/status-history",
+            get(get_project_status_history),
+        )
+        .route("/projects/:id/activity", get(get_project_activity))
+        .route(
+            "/projects/lifecycle",
+            get(get_project_lifecycle).put(update_project_lifecycle),
+        )
         .route("/projects/:id/details", patch(update_project_details))
+        .route("/projects/:id/name", patch(rename_project))
+        .route("/projects/:id/client", patch(set_project_client))
+        .route("/projects/:id/merge", post(merge_project))
+        .route("/projects/:id/files/archive", post(archive_project_files))
+        .route("/projects/:id/files/restore", post(restore_project_files))
         // Forum endpoints
         .route(
             "/projects/:id/forum",
@@ -149,15 +227,55 @@ async fn main() {
             get(list_replies).post(create_reply),
         )
         .route("/tasks/:item_id/toggle", patch(toggle_task_item))
-        // Document endpoints - with increased body limit for uploads (100MB)
-        .route("/upload", post(upload_document))
+        // Document endpoints - uploads may carry many files, so their body
+        // limit comes from the upload policy instead of the 100MB default
+        .route(
+            "/upload",
+            post(upload_document).layer(DefaultBodyLimit::max(
+                ContentService::max_request_size() as usize
+            )),
+        )
+        .route("/file-types", get(list_file_types))
         .route("/documents/inbox", get(list_inbox))
         .route("/documents/batch-assign", patch(batch_assign_documents))
+        .route("/documents/bulk", post(bulk_documents))
+        .route("/documents/download.zip", post(download_documents_zip))
         .route("/documents/:id/assign", patch(assign_document))
         .route("/documents/:id/notes", patch(update_document_notes))
         .route("/documents/:id/status", patch(update_document_status))
         .route("/documents/:id/category", patch(update_document_category))
         .route("/documents/:id", get(get_document).delete(delete_document))
+        // Annotation layer endpoints
+        .route(
+            "/documents/:id/annotations",
+            get(list_annotations).post(create_annotation),
+        )
+        .route("/documents/:id/annotated", get(render_annotated_document))
+        .route("/documents/:id/video/reprocess", post(reprocess_video))
+        .route(
+            "/documents/:id/audio",
+            put(set_document_audio).delete(delete_document_audio),
+        )
+        .route(
+            "/annotations/:id",
+            patch(update_annotation).delete(delete_annotation),
+        )
+        // Document comment endpoints
+        .route(
+            "/documents/:id/comments",
+            get(list_document_comments).post(create_document_comment),
+        )
+        .route("/comments/:id/replies", post(create_comment_reply))
+        .route("/comments/:id/resolve", patch(resolve_document_comment))
+        .route("/comments/:id", delete(delete_document_comment))
+        // Malware quarantine endpoints
+        .route("/quarantine", get(list_quarantined_files))
+        .route("/quarantine/:id", delete(delete_quarantined_file))
+        // Storage integrity endpoints
+        .route(
+            "/admin/integrity",
+            get(check_storage_integrity).post(repair_storage_integrity),
+        )
         // Push notification endpoints
         .route("/push/vapid-key", get(get_vapid_key))
         .route("/push/subscribe", post(push_subscribe))
@@ -184,7 +302,7 @@ async fn main() {
             get(list_email_filters).post(create_email_filter),
         )
         .route("/filters/:id", delete(delete_email_filter))
-        .with_state(pool)
+        .with_state(pool.clone())
         .layer(DefaultBodyLimit::max(100 * 1024 * 1024));
 
     // Serve the Flutter web app with no-cache headers
@@ -192,22 +310,28 @@ async fn main() {
     let web_app =
         ServeDir::new(WEB_DIR).not_found_service(ServeFile::new(format!("{}/index.html", WEB_DIR)));
 
+    // Local storage is served straight from disk; other backends redirect
+    // to presigned URLs or stream through the server. Either way, files are
+    // content-addressed, so they get strong ETags and are cached for good.
+    // Files moved to cold storage are brought back on a miss
+    let files = match storage.local_root() {
+        Some(root) => Router::new().fallback_service(ServeDir::new(root)),
+        None => Router::new().route("/*key", get(serve_file)),
+    }
+    .layer(middleware::from_fn_with_state(pool, restore_cold_files))
+    .layer(middleware::from_fn(file_cache_headers));
+
     let app = Router::new()
         // API routes under /api prefix
         .nest("/api", api_routes)
         // Email webhook routes (no auth required)
         .nest("/api/email", email_routes)
-        // Static file serving for uploaded documents (cached 24h — images don't change)
-        .nest_service(
-            "/files",
-            ServeDir::new(UPLOADS_DIR)
-                .precompressed_gzip()
-                .precompressed_br()
-                .precompressed_deflate(),
-        )
+        // Uploaded documents, served from disk or through the storage backend
+        .nest_service("/files", files)
         // Serve web app for all other routes (must be last)
         .fallback_service(web_app)
-        // Add cache-control header to prevent aggressive caching
+        // API responses and the web app must not be cached; file responses
+        // set their own policy
         .layer(SetResponseHeaderLayer::if_not_present(
             header::CACHE_CONTROL,
             header::HeaderValue::from_static("no-cache, no-store, must-revalidate"),
@@ -225,12 +349,40 @@ async fn main() {
     tracing::info!("Web app served from: {}", WEB_DIR);
     tracing::info!("API Documentation:");
     tracing::info!("  POST   /api/projects              - Create project");
-    tracing::info!("  GET    /api/projects              - List projects (?status=active)");
+    tracing::info!("  GET    /api/projects              - List projects (?status=active|<state>)");
+    tracing::info!("  GET    /api/projects/lifecycle    - Project lifecycle (PUT to replace)");
+    tracing::info!("  PATCH  /api/projects/:id/status   - Move project to another state");
+    tracing::info!("  GET    /api/projects/:id/status-history - Project status changes");
+    tracing::info!("  GET    /api/projects/:id/activity - Project activity feed");
     tracing::info!("  GET    /api/projects/:id          - Get project");
+    tracing::info!(
+        "  DELETE /api/projects/:id          - Delete project (?documents=inbox|delete)"
+    );
+    tracing::info!("  PATCH  /api/projects/:id/name     - Rename project");
+    tracing::info!("  POST   /api/projects/:id/merge    - Merge project into another");
+    tracing::info!("  PATCH  /api/projects/:id/client   - Link project to a client");
+    tracing::info!("  POST   /api/clients               - Create client");
+    tracing::info!("  GET    /api/clients               - List clients");
+    tracing::info!("  GET    /api/clients/:id           - Get client (PATCH/DELETE to edit)");
+    tracing::info!("  GET    /api/clients/:id/projects  - List client's projects");
+    tracing::info!("  GET    /api/clients/:id/documents - List client's documents");
     tracing::info!("  GET    /api/projects/:id/documents - List project documents");
+    tracing::info!("  GET    /api/projects/:id/download.zip - Download project as ZIP");
+    tracing::info!("  GET    /api/projects/:id/export   - Export project bundle");
+    tracing::info!("  POST   /api/projects/import       - Import project bundle (?dry_run=true)");
+    tracing::info!("  POST   /api/projects/:id/files/archive - Move files to cold storage");
+    tracing::info!("  POST   /api/projects/:id/files/restore - Restore files from cold storage");
     tracing::info!("  POST   /api/upload                - Upload file (multipart)");
+    tracing::info!("  GET    /api/file-types            - List file type registry");
     tracing::info!("  GET    /api/documents/inbox       - List inbox documents");
     tracing::info!("  PATCH  /api/documents/:id/assign  - Assign document to project");
+    tracing::info!("  POST   /api/documents/bulk        - Bulk document operations");
+    tracing::info!("  PUT    /api/documents/:id/audio   - Attach or replace voice memo");
+    tracing::info!("  GET    /api/documents/:id/annotations - List annotation layers");
+    tracing::info!("  GET    /api/documents/:id/annotated   - Render flattened annotations");
+    tracing::info!("  GET    /api/documents/:id/comments - List document comment threads");
+    tracing::info!("  GET    /api/quarantine            - List quarantined files");
+    tracing::info!("  GET    /api/admin/integrity       - Storage integrity report");
     tracing::info!("  GET    /files/:filename           - Serve uploaded files");
     tracing::info!("  POST   /api/email/inbound         - Email webhook endpoint");
     tracing::info!("  GET    /api/email/rules           - List email routing rules");
@@ -249,3 +401,114 @@ async fn main() {
         .await
         .expect("Server failed to start");
 }
+
+/// Run the `integrity` command, printing the report as JSON
+///
+/// Returns the process exit code: 0 when storage is consistent, 1 when
+/// problems were found, 2 on usage or scan errors.
+async fn run_integrity_command(pool: &db::DbPool, args: &[String]) -> i32 {
+    let mut options = IntegrityOptions::default();
+    for arg in args {
+        match arg.as_str() {
+            "--verify-checksums" => options.verify_checksums = true,
+            "--quarantine" => options.quarantine_orphans = true,
+            "--repair" => options.repair = true,
+            _ => {
+                eprintln!("Unknown option '{}'", arg);
+                eprintln!("Usage: charta integrity [--verify-checksums] [--quarantine] [--repair]");
+                return 2;
+            }
+        }
+    }
+
+    match IntegrityService::check(pool, &options).await {
+        Ok(report) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            );
+            if report.is_clean() {
+                0
+            } else {
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!("Integrity scan failed: {}", e);
+            2
+        }
+    }
+}
+
+/// Run `charta import`, returning the exit code
+async fn run_import_command(pool: &db::DbPool, args: &[String]) -> i32 {
+    const USAGE: &str =
+        "Usage: charta import <bundle.zip> [--dry-run] [--preserve-ids] [--project <id>]";
+
+    let mut options = ImportOptions::default();
+    let mut bundle = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--preserve-ids" => options.preserve_ids = true,
+            "--project" => match args.next() {
+                Some(id) => options.project_id = Some(id.clone()),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            path if !path.starts_with("--") && bundle.is_none() => bundle = Some(path.to_string()),
+            _ => {
+                eprintln!("Unknown option '{}'", arg);
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let Some(bundle) = bundle else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    match ImportService::import(pool, &bundle, &options).await {
+        Ok(report) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Import failed: {}", e);
+            2
+        }
+    }
+}
+
+/// Run `charta encryption rotate-keys|encrypt-existing`, returning the exit code
+async fn run_encryption_command(args: &[String]) -> i32 {
+    let result = match args {
+        [command] if command == "rotate-keys" => EncryptionService::rotate_keys().await,
+        [command] if command == "encrypt-existing" => EncryptionService::encrypt_existing().await,
+        _ => {
+            eprintln!("Usage: charta encryption <rotate-keys|encrypt-existing>");
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(report) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).unwrap_or_default()
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Encryption command failed: {}", e);
+            2
+        }
+    }
+}
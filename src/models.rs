@@ -26,7 +26,7 @@ pub struct Project {
     pub id: String,
     /// Human-readable project name (e.g., "Obra Porto Seg Social")
     pub name: String,
-    /// Workflow status: "ACTIVE" for ongoing, "ARCHIVED" for completed
+    /// Lifecycle state code (e.g. "QUOTE", "IN_PRODUCTION", "ARCHIVED")
     pub status: String,
     /// Job site address (optional)
     pub address: Option<String>,
@@ -34,29 +34,37 @@ pub struct Project {
     pub client_phone: Option<String>,
     /// Timestamp when the project was created
     pub created_at: String,
+    /// When the project was last archived, None while active
+    pub archived_at: Option<String>,
+    /// Client the project is for (optional)
+    pub client_id: Option<String>,
 }
 
-/// Project status enum for type-safe status handling
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "UPPERCASE")]
-pub enum ProjectStatus {
-    Active,
-    Archived,
-}
-
-impl ProjectStatus {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            ProjectStatus::Active => "ACTIVE",
-            ProjectStatus::Archived => "ARCHIVED",
-        }
-    }
+/// One state of the project lifecycle, with the states it can move to
+///
+/// The lifecycle is served and replaced as a list of these, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifecycleState {
+    /// Stored in `projects.status` (e.g. "IN_PRODUCTION")
+    pub code: String,
+    /// Human-readable name (e.g. "Em produção")
+    pub label: String,
+    /// Codes of the states a project in this state can move to
+    #[serde(default)]
+    pub next: Vec<String>,
 }
 
-impl std::fmt::Display for ProjectStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_str())
-    }
+/// A recorded change of a project's lifecycle state
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ProjectStatusChange {
+    pub id: String,
+    pub project_id: String,
+    /// None for the state the project was created or imported in
+    pub from_status: Option<String>,
+    pub to_status: String,
+    /// Who made the change, if known
+    pub actor: Option<String>,
+    pub created_at: String,
 }
 
 /// Document entity representing an uploaded file
@@ -78,6 +86,18 @@ pub struct Document {
     pub original_name: String,
     /// Timestamp when the document was uploaded
     pub uploaded_at: String,
+    /// When the photo/video was taken, as reported by the uploading device (UTC)
+    pub captured_at: Option<String>,
+    /// MIME type claimed by the uploader (multipart or email part header)
+    pub mime_type: Option<String>,
+    /// MIME type detected from the file content, if recognised
+    pub detected_mime_type: Option<String>,
+    /// When the file passed a malware scan, None if it was not scanned
+    pub scanned_at: Option<String>,
+    /// SHA-256 of the stored file (hex), None for files stored before checksums
+    pub checksum: Option<String>,
+    /// Rendered preview path in uploads directory (SVG for DXF drawings)
+    pub preview_path: Option<String>,
     /// User notes/annotations for this document
     pub notes: Option<String>,
     /// Document status (DEFAULT, DOUBT, IN_PROGRESS, COMPLETED)
@@ -86,6 +106,89 @@ pub struct Document {
     pub category: Option<String>,
     /// Optional voice memo audio file path
     pub audio_path: Option<String>,
+    /// Detected voice memo format (webm, ogg, mp3, m4a, ...)
+    pub audio_format: Option<String>,
+    /// Voice memo duration, when it could be determined
+    pub audio_duration_seconds: Option<f64>,
+    /// Name (or email address) of whoever uploaded the file
+    pub uploaded_by: Option<String>,
+    /// How the document entered the system (APP, EMAIL, IMPORT, API)
+    pub source: String,
+    /// Sender address, for documents received by email
+    pub email_sender: Option<String>,
+    /// Subject line, for documents received by email
+    pub email_subject: Option<String>,
+    /// Message-ID header, for documents received by email
+    pub email_message_id: Option<String>,
+    /// Cold storage archive holding the document's files, None while they're hot
+    pub archive_id: Option<String>,
+    /// When the files were moved to cold storage
+    pub archived_at: Option<String>,
+    /// When the files were last brought back from cold storage
+    pub restored_at: Option<String>,
+}
+
+/// Kind of change recorded in a document's history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentEventType {
+    /// Moved to a project or back to the inbox
+    Assigned,
+    /// Workflow status changed
+    Status,
+    /// Category/room changed
+    Category,
+}
+
+impl DocumentEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentEventType::Assigned => "ASSIGNED",
+            DocumentEventType::Status => "STATUS",
+            DocumentEventType::Category => "CATEGORY",
+        }
+    }
+}
+
+/// Document source enum describing the ingestion path of a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DocumentSource {
+    /// Uploaded through the mobile or web app
+    App,
+    /// Extracted from an inbound email attachment
+    Email,
+    /// Imported from another instance or a bulk import
+    Import,
+    /// Uploaded by an external integration through the API
+    Api,
+}
+
+impl DocumentSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentSource::App => "APP",
+            DocumentSource::Email => "EMAIL",
+            DocumentSource::Import => "IMPORT",
+            DocumentSource::Api => "API",
+        }
+    }
+
+    /// Parse a source name case-insensitively (e.g. "api" or "API")
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "APP" => Some(DocumentSource::App),
+            "EMAIL" => Some(DocumentSource::Email),
+            "IMPORT" => Some(DocumentSource::Import),
+            "API" => Some(DocumentSource::Api),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DocumentSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 /// Document status enum for type-safe status handling
@@ -107,6 +210,17 @@ impl DocumentStatus {
             DocumentStatus::Completed => "COMPLETED",
         }
     }
+
+    /// Parse a status name case-insensitively (e.g. "in_progress" or "IN_PROGRESS")
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_uppercase().as_str() {
+            "DEFAULT" => Some(DocumentStatus::Default),
+            "DOUBT" => Some(DocumentStatus::Doubt),
+            "IN_PROGRESS" => Some(DocumentStatus::InProgress),
+            "COMPLETED" => Some(DocumentStatus::Completed),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for DocumentStatus {
@@ -145,6 +259,123 @@ pub struct EmailFilter {
     pub created_at: String,
 }
 
+/// A file held back by the malware scanner instead of becoming a document
+///
+/// The file itself lives in the quarantine directory, outside `/files`.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct QuarantinedFile {
+    pub id: String,
+    pub original_name: String,
+    /// Filename inside the quarantine directory
+    pub file_path: String,
+    pub mime_type: Option<String>,
+    pub size: i64,
+    /// Signature reported by clamd, None when the scan itself failed
+    pub signature: Option<String>,
+    /// Why the file was quarantined
+    pub reason: String,
+    /// Ingestion path (APP, EMAIL, IMPORT, API)
+    pub source: String,
+    pub uploaded_by: Option<String>,
+    pub email_sender: Option<String>,
+    pub email_subject: Option<String>,
+    pub email_message_id: Option<String>,
+    pub created_at: String,
+}
+
+/// Video metadata entity produced by background ffmpeg processing
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct VideoMetadata {
+    pub document_id: String,
+    /// Processing state: PENDING, PROCESSING, DONE, FAILED or SKIPPED
+    pub status: String,
+    pub duration_seconds: Option<f64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Codec of the original video stream (e.g., "hevc", "h264")
+    pub codec: Option<String>,
+    /// Poster frame (JPEG) relative to the uploads directory
+    pub poster_path: Option<String>,
+    /// Browser-friendly H.264 MP4 relative to the uploads directory
+    pub transcoded_path: Option<String>,
+    /// Last processing error, if any
+    pub error: Option<String>,
+    pub updated_at: String,
+}
+
+/// Annotation layer entity: a set of vector shapes drawn over a document
+///
+/// Layers are stored separately from the file so the original photo is
+/// never modified and annotations remain editable.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AnnotationLayer {
+    pub id: String,
+    pub document_id: String,
+    /// Optional label for the layer (e.g., "Medidas")
+    pub name: Option<String>,
+    pub author_name: String,
+    /// JSON-encoded `Vec<AnnotationShape>`
+    pub shapes: String,
+    /// Hidden layers are kept but skipped when rendering
+    pub visible: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A single vector shape inside an annotation layer
+///
+/// Coordinates are normalized to the image size (0.0 - 1.0) so layers
+/// survive resizing and render identically on any screen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AnnotationShape {
+    /// Freehand pen stroke through a list of points
+    Pen {
+        points: Vec<[f32; 2]>,
+        color: String,
+        /// Stroke width relative to the image width
+        width: f32,
+    },
+    /// Straight arrow pointing from `from` to `to`
+    Arrow {
+        from: [f32; 2],
+        to: [f32; 2],
+        color: String,
+        width: f32,
+    },
+    /// Text label anchored at its top-left corner
+    Text {
+        position: [f32; 2],
+        text: String,
+        color: String,
+        /// Font size relative to the image height
+        size: f32,
+    },
+}
+
+/// Document comment entity
+///
+/// Top-level comments (parent_id = None) start a thread and may be pinned to
+/// a position on the image or PDF page; replies only carry text.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DocumentComment {
+    pub id: String,
+    pub document_id: String,
+    pub parent_id: Option<String>,
+    pub author_name: String,
+    pub content: String,
+    /// Horizontal pin position normalized to the width (0.0 - 1.0)
+    pub x: Option<f64>,
+    /// Vertical pin position normalized to the height (0.0 - 1.0)
+    pub y: Option<f64>,
+    /// 1-based page number for PDF documents
+    pub page: Option<i32>,
+    pub resolved: bool,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<String>,
+    pub created_at: String,
+}
+
 /// Global User Profile
 #[derive(Debug, Clone, Serialize, sqlx::FromRow)]
 pub struct UserProfile {
@@ -166,12 +397,14 @@ pub struct CreateProjectRequest {
     pub address: Option<String>,
     /// Client phone number (optional)
     pub client_phone: Option<String>,
+    /// Client the project is for (optional)
+    pub client_id: Option<String>,
 }
 
 /// Query parameters for listing projects
 #[derive(Debug, Deserialize)]
 pub struct ListProjectsQuery {
-    /// Optional status filter (active/archived)
+    /// Optional status filter: a lifecycle state, or "active" for all but archived
     pub status: Option<String>,
 }
 
@@ -182,6 +415,8 @@ pub struct AssignDocumentRequest {
     pub project_id: Option<String>,
     /// Optional category/room assignment at the time of move
     pub category: Option<String>,
+    /// Name of the person making the change
+    pub changed_by: Option<String>,
 }
 
 /// Request payload for batch assigning documents to a project
@@ -191,13 +426,94 @@ pub struct BatchAssignRequest {
     pub document_ids: Vec<String>,
     /// Target project ID (None to move back to Inbox)
     pub project_id: Option<String>,
+    /// Name of the person making the change
+    pub changed_by: Option<String>,
+}
+
+/// Operation applied by the bulk documents endpoint
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkOperation {
+    /// Assign to a project, optionally setting the category
+    Assign {
+        project_id: String,
+        category: Option<String>,
+    },
+    /// Set the workflow status
+    SetStatus { status: DocumentStatus },
+    /// Set or clear the category/room
+    SetCategory { category: Option<String> },
+    /// Add tags (existing tags are kept)
+    AddTags { tags: Vec<String> },
+    /// Remove tags
+    RemoveTags { tags: Vec<String> },
+    /// Permanently delete documents and their files
+    Delete,
+    /// Move documents back to the Inbox
+    MoveToInbox,
+}
+
+impl BulkOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BulkOperation::Assign { .. } => "assign",
+            BulkOperation::SetStatus { .. } => "set_status",
+            BulkOperation::SetCategory { .. } => "set_category",
+            BulkOperation::AddTags { .. } => "add_tags",
+            BulkOperation::RemoveTags { .. } => "remove_tags",
+            BulkOperation::Delete => "delete",
+            BulkOperation::MoveToInbox => "move_to_inbox",
+        }
+    }
+}
+
+/// Request payload for the bulk documents endpoint
+#[derive(Debug, Deserialize)]
+pub struct BulkDocumentsRequest {
+    pub document_ids: Vec<String>,
+    pub operation: BulkOperation,
+    /// Name of the person making the change
+    pub changed_by: Option<String>,
 }
 
 /// Request payload for updating project status
 #[derive(Debug, Deserialize)]
 pub struct UpdateProjectStatusRequest {
-    /// New status: ACTIVE or ARCHIVED
-    pub status: ProjectStatus,
+    /// Lifecycle state code to move to; "ACTIVE" reopens an archived project
+    pub status: String,
+    /// Name of the person making the change
+    pub changed_by: Option<String>,
+}
+
+/// Request payload for renaming a project
+#[derive(Debug, Deserialize)]
+pub struct RenameProjectRequest {
+    pub name: String,
+}
+
+/// Request payload for merging a project into another
+#[derive(Debug, Deserialize)]
+pub struct MergeProjectRequest {
+    /// Project that receives everything; the merged project is removed
+    pub into: String,
+}
+
+/// What happens to a deleted project's documents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletedProjectDocuments {
+    /// Documents go back to the inbox to be assigned again
+    #[default]
+    Inbox,
+    /// Documents and their files are deleted permanently
+    Delete,
+}
+
+/// Query parameters for deleting a project
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteProjectQuery {
+    #[serde(default)]
+    pub documents: DeletedProjectDocuments,
 }
 
 /// Request payload for updating project details
@@ -218,12 +534,60 @@ pub struct UpdateDocumentNotesRequest {
 #[derive(Debug, Deserialize)]
 pub struct UpdateDocumentStatusRequest {
     pub status: DocumentStatus,
+    /// Name of the person making the change
+    pub changed_by: Option<String>,
 }
 
 /// Request payload for updating document category (room)
 #[derive(Debug, Deserialize)]
 pub struct UpdateDocumentCategoryRequest {
     pub category: Option<String>,
+    /// Name of the person making the change
+    pub changed_by: Option<String>,
+}
+
+/// Request payload for creating an annotation layer
+#[derive(Debug, Deserialize)]
+pub struct CreateAnnotationRequest {
+    pub name: Option<String>,
+    pub author_name: Option<String>,
+    pub shapes: Vec<AnnotationShape>,
+}
+
+/// Request payload for updating an annotation layer
+///
+/// Omitted fields are left unchanged; `shapes` replaces the whole list.
+#[derive(Debug, Deserialize)]
+pub struct UpdateAnnotationRequest {
+    pub name: Option<String>,
+    pub shapes: Option<Vec<AnnotationShape>>,
+    pub visible: Option<bool>,
+}
+
+/// Query parameters for rendering a flattened annotated image
+#[derive(Debug, Deserialize)]
+pub struct RenderAnnotationsQuery {
+    /// Output format: "png" (default) or "jpeg"
+    pub format: Option<String>,
+}
+
+/// Request payload for starting a comment thread on a document
+#[derive(Debug, Deserialize)]
+pub struct CreateDocumentCommentRequest {
+    pub content: String,
+    pub author_name: Option<String>,
+    /// Optional pin position (both x and y must be given)
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    /// Optional PDF page number (1-based)
+    pub page: Option<i32>,
+}
+
+/// Request payload for resolving or reopening a comment thread
+#[derive(Debug, Deserialize)]
+pub struct ResolveDocumentCommentRequest {
+    pub resolved: bool,
+    pub resolved_by: Option<String>,
 }
 
 /// Request payload for creating an email routing rule
@@ -259,6 +623,8 @@ pub struct ProjectResponse {
     pub address: Option<String>,
     pub client_phone: Option<String>,
     pub created_at: String,
+    pub archived_at: Option<String>,
+    pub client_id: Option<String>,
     pub document_count: i32,
 }
 
@@ -271,6 +637,8 @@ impl ProjectResponse {
             address: p.address,
             client_phone: p.client_phone,
             created_at: p.created_at,
+            archived_at: p.archived_at,
+            client_id: p.client_id,
             document_count,
         }
     }
@@ -285,6 +653,8 @@ impl From<Project> for ProjectResponse {
             address: p.address,
             client_phone: p.client_phone,
             created_at: p.created_at,
+            archived_at: p.archived_at,
+            client_id: p.client_id,
             document_count: 0,
         }
     }
@@ -299,8 +669,20 @@ pub struct DocumentResponse {
     pub file_type: String,
     pub original_name: String,
     pub uploaded_at: String,
+    /// When the file was captured, as reported by the uploading device
+    pub captured_at: Option<String>,
+    /// MIME type claimed by the uploader
+    pub mime_type: Option<String>,
+    /// MIME type detected from the file content
+    pub detected_mime_type: Option<String>,
+    /// When the file passed a malware scan
+    pub scanned_at: Option<String>,
+    /// SHA-256 of the stored file (hex)
+    pub checksum: Option<String>,
     /// Full URL to access the file
     pub file_url: String,
+    /// URL of a rendered preview, for formats browsers can't display (DXF)
+    pub preview_url: Option<String>,
     /// User notes/annotations
     pub notes: Option<String>,
     /// Current workflow status
@@ -311,16 +693,86 @@ pub struct DocumentResponse {
     pub audio_path: Option<String>,
     /// Voice memo URL
     pub audio_url: Option<String>,
+    /// Detected voice memo format
+    pub audio_format: Option<String>,
+    /// Voice memo duration in seconds
+    pub audio_duration_seconds: Option<f64>,
+    /// Who uploaded the document
+    pub uploaded_by: Option<String>,
+    /// Ingestion path (APP, EMAIL, IMPORT, API)
+    pub source: String,
+    /// Email provenance, for documents received by email
+    pub email: Option<EmailProvenance>,
+    /// Number of comments (threads and replies) on this document
+    pub comment_count: i32,
+    /// Tags attached to this document, alphabetically
+    pub tags: Vec<String>,
+    /// Video metadata and derived files, for video documents
+    pub video: Option<VideoInfoResponse>,
+    /// When the files were moved to cold storage; opening them brings them back
+    pub archived_at: Option<String>,
+}
+
+/// Video information exposed on a document
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoInfoResponse {
+    pub status: String,
+    pub duration_seconds: Option<f64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub codec: Option<String>,
+    /// URL of the poster frame image
+    pub poster_url: Option<String>,
+    /// URL of the web-friendly MP4 (falls back to the original when absent)
+    pub web_url: Option<String>,
+    pub error: Option<String>,
+}
+
+impl From<VideoMetadata> for VideoInfoResponse {
+    fn from(v: VideoMetadata) -> Self {
+        Self {
+            status: v.status,
+            duration_seconds: v.duration_seconds,
+            width: v.width,
+            height: v.height,
+            codec: v.codec,
+            poster_url: v.poster_path.map(|p| format!("/files/{}", p)),
+            web_url: v.transcoded_path.map(|p| format!("/files/{}", p)),
+            error: v.error,
+        }
+    }
+}
+
+/// Structured provenance of a document received by email
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EmailProvenance {
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    pub message_id: Option<String>,
 }
 
 impl DocumentResponse {
     /// Create response from document entity, generating a relative file URL
+    ///
+    /// Counts, tags and video info are left empty; use `DocumentService::build_responses` to fill them.
     pub fn from_document(doc: Document) -> Self {
         let file_url = format!("/files/{}", doc.file_path);
         let audio_url = doc
             .audio_path
             .as_ref()
             .map(|path| format!("/files/{}", path));
+        let email = if doc.email_sender.is_some()
+            || doc.email_subject.is_some()
+            || doc.email_message_id.is_some()
+        {
+            Some(EmailProvenance {
+                sender: doc.email_sender,
+                subject: doc.email_subject,
+                message_id: doc.email_message_id,
+            })
+        } else {
+            None
+        };
         Self {
             id: doc.id,
             project_id: doc.project_id,
@@ -328,17 +780,123 @@ impl DocumentResponse {
             file_type: doc.file_type,
             original_name: doc.original_name,
             uploaded_at: doc.uploaded_at,
+            captured_at: doc.captured_at,
+            mime_type: doc.mime_type,
+            detected_mime_type: doc.detected_mime_type,
+            scanned_at: doc.scanned_at,
+            checksum: doc.checksum,
             file_url,
+            preview_url: doc.preview_path.map(|p| format!("/files/{}", p)),
             notes: doc.notes,
             status: doc.status,
             category: doc.category,
             audio_path: doc.audio_path,
             audio_url,
+            audio_format: doc.audio_format,
+            audio_duration_seconds: doc.audio_duration_seconds,
+            uploaded_by: doc.uploaded_by,
+            source: doc.source,
+            email,
+            comment_count: 0,
+            tags: Vec::new(),
+            video: None,
+            archived_at: doc.archived_at,
         }
     }
 }
 
+/// Response for an annotation layer with decoded shapes
+#[derive(Debug, Serialize)]
+pub struct AnnotationLayerResponse {
+    pub id: String,
+    pub document_id: String,
+    pub name: Option<String>,
+    pub author_name: String,
+    pub shapes: Vec<AnnotationShape>,
+    pub visible: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl AnnotationLayerResponse {
+    /// Decode the stored JSON shapes into a response
+    pub fn from_layer(layer: AnnotationLayer) -> Self {
+        let shapes = serde_json::from_str(&layer.shapes).unwrap_or_default();
+        Self {
+            id: layer.id,
+            document_id: layer.document_id,
+            name: layer.name,
+            author_name: layer.author_name,
+            shapes,
+            visible: layer.visible,
+            created_at: layer.created_at,
+            updated_at: layer.updated_at,
+        }
+    }
+}
+
+/// Response for a comment thread (root comment with its replies)
+#[derive(Debug, Serialize)]
+pub struct DocumentCommentResponse {
+    pub id: String,
+    pub document_id: String,
+    pub parent_id: Option<String>,
+    pub author_name: String,
+    pub content: String,
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub page: Option<i32>,
+    pub resolved: bool,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<String>,
+    pub created_at: String,
+    /// Replies in chronological order (always empty for replies themselves)
+    pub replies: Vec<DocumentCommentResponse>,
+}
+
+impl DocumentCommentResponse {
+    pub fn from_comment(c: DocumentComment, replies: Vec<DocumentCommentResponse>) -> Self {
+        Self {
+            id: c.id,
+            document_id: c.document_id,
+            parent_id: c.parent_id,
+            author_name: c.author_name,
+            content: c.content,
+            x: c.x,
+            y: c.y,
+            page: c.page,
+            resolved: c.resolved,
+            resolved_by: c.resolved_by,
+            resolved_at: c.resolved_at,
+            created_at: c.created_at,
+            replies,
+        }
+    }
+}
+
+/// Outcome of a bulk operation for a single document
+#[derive(Debug, Serialize)]
+pub struct BulkItemResult {
+    pub document_id: String,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Updated document (absent on failure and for deletions)
+    pub document: Option<DocumentResponse>,
+}
+
+/// Response for the bulk documents endpoint
+#[derive(Debug, Serialize)]
+pub struct BulkDocumentsResponse {
+    pub operation: String,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult>,
+}
+
 /// Response for file upload operations
+///
+/// The top-level document fields describe the first document created, for
+/// clients that upload a single file; `results` has one entry per file.
 #[derive(Debug, Serialize)]
 pub struct UploadResponse {
     pub id: String,
@@ -346,6 +904,24 @@ pub struct UploadResponse {
     pub file_type: String,
     pub original_name: String,
     pub file_url: String,
+    pub preview_url: Option<String>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<UploadFileResult>,
+}
+
+/// Outcome of one file of an upload request
+#[derive(Debug, Serialize)]
+pub struct UploadFileResult {
+    /// Position of the file among the request's files (0-based)
+    pub index: usize,
+    /// Filename as sent by the client
+    pub original_name: String,
+    pub success: bool,
+    /// Why the file failed, or why a created document is missing
+    pub error: Option<String>,
+    /// Created document (absent on failure, or if it couldn't be loaded afterwards)
+    pub document: Option<DocumentResponse>,
 }
 
 /// Generic success message response
@@ -413,13 +989,6 @@ pub struct ToggleTaskItemRequest {
     pub completed_by: Option<String>,
 }
 
-/// Request for updating a forum message
-#[derive(Debug, Deserialize)]
-pub struct UpdateForumMessageRequest {
-    /// Message text filtering
-    pub content_filter: Option<String>,
-}
-
 /// Request to update a user's profile photo
 #[derive(Debug, Deserialize)]
 pub struct UpdateProfilePhotoRequest {
@@ -447,6 +1016,8 @@ pub struct ForumMessageResponse {
     pub reply_count: i32,
     /// For PHOTO messages, include the document info
     pub document: Option<DocumentResponse>,
+    /// For album PHOTO messages (several files uploaded at once), all documents in order
+    pub documents: Option<Vec<DocumentResponse>>,
     /// For TASK_LIST messages, include the items
     pub items: Option<Vec<TaskItemResponse>>,
 }
@@ -472,3 +1043,425 @@ impl From<TaskItem> for TaskItemResponse {
         }
     }
 }
+
+// =============================================================================
+// Storage Integrity
+// =============================================================================
+
+/// Options for a storage integrity scan
+///
+/// Without `quarantine_orphans` or `repair` the scan only reports.
+#[derive(Debug, Default, Deserialize)]
+pub struct IntegrityOptions {
+    /// Re-hash document files and compare them with their stored checksums
+    #[serde(default)]
+    pub verify_checksums: bool,
+    /// Move orphaned files to the quarantine directory (empty ones are deleted)
+    #[serde(default)]
+    pub quarantine_orphans: bool,
+    /// Fix database rows: clear or regenerate missing derived files and
+    /// record checksums for documents that have none
+    #[serde(default)]
+    pub repair: bool,
+}
+
+/// Result of a storage integrity scan
+#[derive(Debug, Default, Serialize)]
+pub struct IntegrityReport {
+    /// Files found in the uploads directory
+    pub files_scanned: usize,
+    /// File references found in the database
+    pub references_checked: usize,
+    /// Unreferenced files skipped because they may belong to an upload in progress
+    pub recent_files_skipped: usize,
+    /// Files no database row points to
+    pub orphaned_files: Vec<OrphanedFile>,
+    /// Database rows pointing to files that don't exist
+    pub missing_files: Vec<MissingFile>,
+    /// Zero-byte files, referenced or not
+    pub empty_files: Vec<String>,
+    /// Document files whose checksum was compared
+    pub checksums_verified: usize,
+    pub checksum_mismatches: Vec<ChecksumMismatch>,
+    /// What quarantine/repair changed, one line per action
+    pub actions: Vec<String>,
+}
+
+impl IntegrityReport {
+    /// Whether the scan found nothing wrong
+    pub fn is_clean(&self) -> bool {
+        self.orphaned_files.is_empty()
+            && self.missing_files.is_empty()
+            && self.empty_files.is_empty()
+            && self.checksum_mismatches.is_empty()
+    }
+}
+
+/// A file in the uploads directory without a database reference
+#[derive(Debug, Serialize)]
+pub struct OrphanedFile {
+    /// Path relative to the uploads directory
+    pub path: String,
+    pub size: u64,
+    pub modified_at: Option<String>,
+    /// Left over from an interrupted upload (`.part` file)
+    pub partial: bool,
+}
+
+/// A database reference to a file that doesn't exist
+#[derive(Debug, Serialize)]
+pub struct MissingFile {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub row_id: String,
+    pub path: String,
+}
+
+/// A document file whose content no longer matches its checksum
+#[derive(Debug, Serialize)]
+pub struct ChecksumMismatch {
+    pub document_id: String,
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+// =============================================================================
+// Encryption at rest
+// =============================================================================
+
+/// Result of `charta encryption rotate-keys` or `encrypt-existing`
+#[derive(Debug, Default, Serialize)]
+pub struct EncryptionReport {
+    /// Files found in storage
+    pub files_scanned: usize,
+    /// Data keys re-wrapped with the current master key
+    pub keys_rewrapped: usize,
+    /// Data keys already wrapped with the current master key
+    pub keys_current: usize,
+    /// Files that were stored in plain text and have been encrypted
+    pub files_encrypted: usize,
+    /// Files still stored in plain text
+    pub plaintext_files: usize,
+}
+
+// =============================================================================
+// Project Activity
+// =============================================================================
+
+/// Query parameters for the project activity feed
+#[derive(Debug, Default, Deserialize)]
+pub struct ActivityQuery {
+    /// Entries per page (default 50, at most 200)
+    pub limit: Option<i64>,
+    /// Entries to skip, from `next_offset` of the previous page
+    pub offset: Option<i64>,
+}
+
+/// One entry of a project's activity feed
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ActivityEntry {
+    /// document_uploaded, document_assigned, document_unassigned,
+    /// document_status_changed, document_category_changed, forum_message,
+    /// forum_reply, task_completed, task_reopened or project_status_changed
+    pub kind: String,
+    pub at: String,
+    /// Who did it, if known
+    pub actor: Option<String>,
+    pub document_id: Option<String>,
+    /// Forum message (for task events, the task list message)
+    pub message_id: Option<String>,
+    /// Forum message type (TEXT, PHOTO, VOICE, TASK_LIST)
+    pub message_type: Option<String>,
+    /// Previous value: status, category or project name (None for the inbox)
+    #[serde(rename = "from")]
+    pub from_value: Option<String>,
+    /// New value: status, category or project name (None for the inbox)
+    #[serde(rename = "to")]
+    pub to_value: Option<String>,
+    /// Document name, message text or task text
+    pub text: Option<String>,
+}
+
+/// A page of a project's activity feed, newest first
+#[derive(Debug, Serialize)]
+pub struct ActivityPage {
+    pub entries: Vec<ActivityEntry>,
+    /// Offset of the next page, None on the last page
+    pub next_offset: Option<i64>,
+}
+
+// =============================================================================
+// Clients
+// =============================================================================
+
+/// Client entity: the person or company projects are done for
+///
+/// A client can own several projects, e.g. a kitchen now and a bathroom
+/// years later. Phones and emails are stored separately.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Client {
+    pub id: String,
+    pub name: String,
+    /// Tax ID (NIF)
+    pub tax_id: Option<String>,
+    pub address: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: String,
+}
+
+/// Request payload for creating or updating a client
+///
+/// Updates replace every field, including the phone and email lists.
+#[derive(Debug, Deserialize)]
+pub struct ClientRequest {
+    pub name: String,
+    #[serde(default)]
+    pub phones: Vec<String>,
+    /// Addresses inbound emails are matched against
+    #[serde(default)]
+    pub emails: Vec<String>,
+    pub tax_id: Option<String>,
+    pub address: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// Request payload for linking a project to a client (None unlinks it)
+#[derive(Debug, Deserialize)]
+pub struct SetProjectClientRequest {
+    pub client_id: Option<String>,
+}
+
+/// Response for client endpoints
+#[derive(Debug, Serialize)]
+pub struct ClientResponse {
+    pub id: String,
+    pub name: String,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub tax_id: Option<String>,
+    pub address: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub project_count: i32,
+}
+
+// =============================================================================
+// Project Merge and Delete
+// =============================================================================
+
+/// Result of merging one project into another
+#[derive(Debug, Serialize)]
+pub struct ProjectMergeReport {
+    /// The project everything was moved into
+    pub project: ProjectResponse,
+    /// ID of the merged project, which no longer exists
+    pub merged_project_id: String,
+    pub documents_moved: u64,
+    /// Forum messages moved, replies included
+    pub forum_messages_moved: u64,
+    /// Task list items moved along with their messages
+    pub task_items_moved: u64,
+    pub email_rules_moved: u64,
+}
+
+/// Result of deleting a project
+#[derive(Debug, Default, Serialize)]
+pub struct ProjectDeleteReport {
+    pub project_id: String,
+    /// Documents sent back to the inbox
+    pub documents_to_inbox: u64,
+    /// Documents deleted along with their files
+    pub documents_deleted: u64,
+    /// Forum messages deleted, replies included
+    pub forum_messages_deleted: u64,
+    /// Task list items deleted with their messages
+    pub task_items_deleted: u64,
+    pub email_rules_deleted: u64,
+}
+
+// =============================================================================
+// Cold Storage
+// =============================================================================
+
+/// Result of moving a project's files to or back from cold storage
+#[derive(Debug, Default, Serialize)]
+pub struct ColdStorageReport {
+    pub project_id: String,
+    /// Documents whose files were moved
+    pub documents: usize,
+    /// Distinct files packed into or extracted from archives
+    pub files: usize,
+    /// Size of the archive written, when files were moved to cold storage
+    pub archive_size_bytes: Option<u64>,
+}
+
+// =============================================================================
+// Project Export
+// =============================================================================
+
+/// Contents of `project.json` in a project export bundle
+///
+/// File fields are paths inside the bundle, None when the file was missing
+/// at export time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectExport {
+    /// Always "charta-project-export"
+    pub format: String,
+    /// Bumped on incompatible changes to the bundle layout
+    pub version: u32,
+    pub exported_at: String,
+    pub project: ExportedProject,
+    pub documents: Vec<ExportedDocument>,
+    /// Top-level messages and replies, oldest first
+    pub forum: Vec<ExportedForumMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedProject {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+    pub address: Option<String>,
+    pub client_phone: Option<String>,
+    pub created_at: String,
+    pub archived_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedDocument {
+    pub id: String,
+    pub file: Option<String>,
+    /// SHA-256 of the file (hex)
+    pub checksum: Option<String>,
+    pub original_name: String,
+    pub file_type: String,
+    pub mime_type: Option<String>,
+    pub detected_mime_type: Option<String>,
+    pub uploaded_at: String,
+    pub captured_at: Option<String>,
+    pub uploaded_by: Option<String>,
+    pub source: String,
+    pub status: String,
+    pub category: Option<String>,
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+    pub email_sender: Option<String>,
+    pub email_subject: Option<String>,
+    pub email_message_id: Option<String>,
+    pub voice_memo: Option<ExportedVoiceMemo>,
+    /// Comment threads, roots and replies oldest first
+    pub comments: Vec<ExportedComment>,
+    pub annotations: Vec<ExportedAnnotation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedVoiceMemo {
+    pub file: String,
+    pub format: Option<String>,
+    pub duration_seconds: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedComment {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub author_name: String,
+    pub content: String,
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub page: Option<i32>,
+    pub resolved: bool,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedAnnotation {
+    pub id: String,
+    pub name: Option<String>,
+    pub author_name: String,
+    pub shapes: Vec<AnnotationShape>,
+    pub visible: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedForumMessage {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub message_type: String,
+    pub content: Option<String>,
+    pub author_name: String,
+    pub created_at: String,
+    /// Documents of PHOTO messages, in album order
+    pub document_ids: Vec<String>,
+    pub voice_memo: Option<String>,
+    /// Checklist of TASK_LIST messages
+    pub items: Vec<ExportedTaskItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedTaskItem {
+    pub id: String,
+    pub text: String,
+    pub completed: bool,
+    pub completed_by: Option<String>,
+    pub completed_at: Option<String>,
+    pub created_at: String,
+    /// Every completion and reopening, oldest first
+    pub history: Vec<ExportedTaskEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedTaskEvent {
+    pub completed: bool,
+    pub actor: Option<String>,
+    pub at: String,
+}
+
+// =============================================================================
+// Project Import
+// =============================================================================
+
+/// Options of a project bundle import
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportOptions {
+    /// Only report what would be imported
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Keep the bundle's IDs instead of generating new ones; rows whose ID
+    /// already exists are left as they are
+    #[serde(default)]
+    pub preserve_ids: bool,
+    /// Import into this existing project instead of creating one
+    pub project_id: Option<String>,
+}
+
+/// What a project bundle import did, or would do on a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Project the bundle is imported into
+    pub project_id: String,
+    pub project_name: String,
+    /// Whether the project is created by the import
+    pub project_created: bool,
+    pub documents_created: usize,
+    /// Documents whose content is already in the target project
+    pub documents_duplicate: usize,
+    /// Documents whose ID already exists (with `preserve_ids`)
+    pub documents_existing: usize,
+    /// Files whose content isn't stored on this instance yet
+    pub files_new: usize,
+    pub comments_created: usize,
+    pub annotations_created: usize,
+    pub forum_messages_created: usize,
+    /// Forum messages whose ID already exists (with `preserve_ids`)
+    pub forum_messages_existing: usize,
+    pub task_items_created: usize,
+    /// Entries that are not imported, or imported only in part, and why
+    pub problems: Vec<String>,
+}
@@ -0,0 +1,124 @@
+//! Project activity service
+//!
+//! Builds a project's activity feed: one time-ordered list with document
+//! uploads, moves and status/category changes, forum messages and replies,
+//! task completions and lifecycle changes, each with who did it.
+//!
+//! Nothing is stored for the feed itself; it is read from the tables that
+//! already keep each kind of history.
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::models::{ActivityEntry, ActivityPage, ActivityQuery};
+use crate::services::ProjectService;
+
+/// Entries per page when the query doesn't say
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// Largest page a client can ask for
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// Project activity service
+pub struct ActivityService;
+
+impl ActivityService {
+    /// One page of a project's activity, newest first
+    ///
+    /// Moves are listed in both projects: as `document_assigned` in the one
+    /// the document went to and as `document_unassigned` in the one it left.
+    pub async fn project_activity(
+        pool: &DbPool,
+        project_id: &str,
+        query: &ActivityQuery,
+    ) -> AppResult<ActivityPage> {
+        ProjectService::get_by_id(pool, project_id).await?;
+
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let offset = query.offset.unwrap_or(0).max(0);
+
+        // Timestamps have one-second resolution. Entries of the same second
+        // are ordered by the table they come from (`source`), then by rowid
+        // within it, so the order is total and pages neither repeat nor skip
+        // entries. That is not necessarily the order they happened in.
+        // One row more than the page tells whether there is a next page.
+        let mut entries = sqlx::query_as::<_, ActivityEntry>(
+            r#"
+            SELECT kind, at, actor, document_id, message_id, message_type,
+                   from_value, to_value, text
+            FROM (
+                SELECT 'document_uploaded' AS kind, d.uploaded_at AS at, d.uploaded_by AS actor,
+                       d.id AS document_id, NULL AS message_id, NULL AS message_type,
+                       NULL AS from_value, NULL AS to_value, d.original_name AS text,
+                       1 AS source, d.rowid AS seq
+                FROM documents d
+                WHERE d.project_id = ?1
+
+                UNION ALL
+
+                SELECT CASE
+                           WHEN e.event_type = 'ASSIGNED' AND e.project_id IS ?1 THEN 'document_assigned'
+                           WHEN e.event_type = 'ASSIGNED' THEN 'document_unassigned'
+                           WHEN e.event_type = 'STATUS' THEN 'document_status_changed'
+                           ELSE 'document_category_changed'
+                       END,
+                       e.created_at, e.actor, e.document_id, NULL, NULL,
+                       CASE WHEN e.event_type = 'ASSIGNED' THEN fp.name ELSE e.from_value END,
+                       CASE WHEN e.event_type = 'ASSIGNED' THEN tp.name ELSE e.to_value END,
+                       d.original_name, 2, e.rowid
+                FROM document_events e
+                JOIN documents d ON d.id = e.document_id
+                LEFT JOIN projects fp ON fp.id = e.from_value
+                LEFT JOIN projects tp ON tp.id = e.to_value
+                WHERE e.project_id = ?1 OR (e.event_type = 'ASSIGNED' AND e.from_value = ?1)
+
+                UNION ALL
+
+                SELECT CASE WHEN m.parent_id IS NULL THEN 'forum_message' ELSE 'forum_reply' END,
+                       m.created_at, m.author_name, m.document_id, m.id, m.message_type,
+                       NULL, NULL, m.content, 3, m.rowid
+                FROM forum_messages m
+                WHERE m.project_id = ?1
+
+                UNION ALL
+
+                SELECT CASE WHEN te.completed THEN 'task_completed' ELSE 'task_reopened' END,
+                       te.created_at, te.actor, NULL, m.id, m.message_type,
+                       NULL, NULL, t.text, 4, te.rowid
+                FROM task_item_events te
+                JOIN task_items t ON t.id = te.item_id
+                JOIN forum_messages m ON m.id = t.message_id
+                WHERE m.project_id = ?1
+
+                UNION ALL
+
+                SELECT 'project_status_changed', h.created_at, h.actor, NULL, NULL, NULL,
+                       h.from_status, h.to_status, NULL, 5, h.rowid
+                FROM project_status_history h
+                WHERE h.project_id = ?1
+            )
+            ORDER BY at DESC, source DESC, seq DESC
+            LIMIT ?2 OFFSET ?3
+            "#,
+        )
+        .bind(project_id)
+        .bind(limit + 1)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+
+        let next_offset = if entries.len() as i64 > limit {
+            entries.truncate(limit as usize);
+            Some(offset + limit)
+        } else {
+            None
+        };
+
+        Ok(ActivityPage {
+            entries,
+            next_offset,
+        })
+    }
+}
@@ -0,0 +1,764 @@
+//! Annotation service module
+//!
+//! Business logic for non-destructive image annotations. Drawings are stored
+//! as JSON vector layers per document and flattened into a PNG/JPEG only when
+//! requested, so the original upload is never touched.
+//!
+//! # Architecture Decision
+//! Shapes use coordinates normalized to the image size (0.0 - 1.0). Rendering
+//! scales them to the actual pixel dimensions, so the same layer works for
+//! thumbnails, full-size photos and any client canvas size.
+//!
+//! Drawing cost grows with the area strokes and text cover, not just with
+//! the number of points, so besides per-shape limits every document has a
+//! budget of layers, points and "ink" (covered area, in image areas) that
+//! writes are checked against and that renders never exceed.
+
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+use ab_glyph::{FontVec, PxScale};
+use image::{DynamicImage, GrayImage, ImageFormat, Luma, Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_circle_mut, draw_text_mut, text_size};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{AnnotationLayer, AnnotationShape};
+use crate::services::{ColdStorageService, DocumentService, StorageService};
+
+/// Default font used to render text shapes (overridable via ANNOTATION_FONT_PATH)
+const DEFAULT_FONT_PATH: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+/// Most points a layer may hold (pen points, arrow ends and text anchors)
+const MAX_POINTS_PER_LAYER: usize = 10_000;
+
+/// Longest text label, in characters
+const MAX_TEXT_LENGTH: usize = 500;
+
+/// Widest stroke, relative to the image width
+const MAX_STROKE_WIDTH: f32 = 0.1;
+
+/// Largest text, relative to the image height
+const MAX_TEXT_SIZE: f32 = 0.1;
+
+/// Most layers a document may have
+const MAX_LAYERS_PER_DOCUMENT: usize = 50;
+
+/// Most points all layers of a document may hold together
+const MAX_POINTS_PER_DOCUMENT: usize = 50_000;
+
+/// Most area all layers of a document may cover together, in image areas
+///
+/// Overlapping strokes count every time, as each one is drawn.
+const MAX_INK_PER_DOCUMENT: f32 = 20.0;
+
+/// Thinnest stroke counted against the ink budget: strokes are never drawn
+/// thinner than a couple of pixels, however small their width
+const MIN_INK_WIDTH: f32 = 0.002;
+
+/// Output format for flattened renders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    Png,
+    Jpeg,
+}
+
+impl RenderFormat {
+    /// Parse the `?format=` query value, defaulting to PNG
+    pub fn parse(value: Option<&str>) -> AppResult<Self> {
+        match value.map(|v| v.to_lowercase()).as_deref() {
+            None | Some("png") => Ok(RenderFormat::Png),
+            Some("jpeg") | Some("jpg") => Ok(RenderFormat::Jpeg),
+            Some(other) => Err(AppError::BadRequest(format!(
+                "Invalid format '{}'. Use 'png' or 'jpeg'",
+                other
+            ))),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            RenderFormat::Png => "image/png",
+            RenderFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+/// Running totals of a document's layers, checked against the document limits
+#[derive(Debug, Default)]
+pub struct LayerBudget {
+    layers: usize,
+    points: usize,
+    ink: f32,
+}
+
+impl LayerBudget {
+    /// Count a layer, or fail without counting it when it would exceed a limit
+    pub fn add(&mut self, shapes: &[AnnotationShape]) -> AppResult<()> {
+        if self.layers >= MAX_LAYERS_PER_DOCUMENT {
+            return Err(AppError::BadRequest(format!(
+                "A document can have at most {} annotation layers",
+                MAX_LAYERS_PER_DOCUMENT
+            )));
+        }
+        let points = self.points + AnnotationService::point_count(shapes);
+        if points > MAX_POINTS_PER_DOCUMENT {
+            return Err(AppError::BadRequest(format!(
+                "The annotation layers of a document can have at most {} points together",
+                MAX_POINTS_PER_DOCUMENT
+            )));
+        }
+        let ink = self.ink + shapes.iter().map(AnnotationService::ink).sum::<f32>();
+        if ink > MAX_INK_PER_DOCUMENT {
+            return Err(AppError::BadRequest(
+                "The annotation layers of a document cover too much of the image; use thinner strokes, smaller text or fewer shapes".into(),
+            ));
+        }
+
+        self.layers += 1;
+        self.points = points;
+        self.ink = ink;
+        Ok(())
+    }
+}
+
+/// Annotation service handling vector layers and flattened rendering
+pub struct AnnotationService;
+
+impl AnnotationService {
+    /// List all annotation layers of a document, oldest first (render order)
+    pub async fn list(pool: &DbPool, document_id: &str) -> AppResult<Vec<AnnotationLayer>> {
+        // Ensure the document exists so a typo gives 404 instead of an empty list
+        DocumentService::get_by_id(pool, document_id).await?;
+
+        let layers = sqlx::query_as::<_, AnnotationLayer>(
+            "SELECT * FROM document_annotations WHERE document_id = ? ORDER BY created_at ASC",
+        )
+        .bind(document_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(layers)
+    }
+
+    /// Get a single annotation layer by ID
+    pub async fn get_by_id(pool: &DbPool, id: &str) -> AppResult<AnnotationLayer> {
+        sqlx::query_as::<_, AnnotationLayer>("SELECT * FROM document_annotations WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Annotation layer '{}' not found", id)))
+    }
+
+    /// Create a new annotation layer on an image document
+    pub async fn create(
+        pool: &DbPool,
+        document_id: &str,
+        name: Option<&str>,
+        author_name: &str,
+        shapes: &[AnnotationShape],
+    ) -> AppResult<AnnotationLayer> {
+        let doc = DocumentService::get_by_id(pool, document_id).await?;
+        if doc.file_type != "image" {
+            return Err(AppError::BadRequest(
+                "Annotations are only supported on image documents".into(),
+            ));
+        }
+
+        Self::validate_shapes(shapes)?;
+        Self::budget_of(pool, document_id, None)
+            .await?
+            .add(shapes)?;
+        let shapes_json = serde_json::to_string(shapes)
+            .map_err(|e| AppError::Internal(format!("Failed to encode shapes: {}", e)))?;
+
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO document_annotations (id, document_id, name, author_name, shapes) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(document_id)
+        .bind(name)
+        .bind(author_name)
+        .bind(&shapes_json)
+        .execute(pool)
+        .await?;
+
+        Self::get_by_id(pool, &id).await
+    }
+
+    /// Update a layer's name, shapes or visibility
+    pub async fn update(
+        pool: &DbPool,
+        id: &str,
+        name: Option<&str>,
+        shapes: Option<&[AnnotationShape]>,
+        visible: Option<bool>,
+    ) -> AppResult<AnnotationLayer> {
+        let layer = Self::get_by_id(pool, id).await?;
+
+        let shapes_json = match shapes {
+            Some(shapes) => {
+                Self::validate_shapes(shapes)?;
+                Self::budget_of(pool, &layer.document_id, Some(id))
+                    .await?
+                    .add(shapes)?;
+                serde_json::to_string(shapes)
+                    .map_err(|e| AppError::Internal(format!("Failed to encode shapes: {}", e)))?
+            }
+            None => layer.shapes,
+        };
+
+        sqlx::query(
+            "UPDATE document_annotations SET name = ?, shapes = ?, visible = ?, updated_at = datetime('now') WHERE id = ?",
+        )
+        .bind(name.or(layer.name.as_deref()))
+        .bind(&shapes_json)
+        .bind(visible.unwrap_or(layer.visible))
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Self::get_by_id(pool, id).await
+    }
+
+    /// Budget used by the layers of a document, leaving out `except`
+    async fn budget_of(
+        pool: &DbPool,
+        document_id: &str,
+        except: Option<&str>,
+    ) -> AppResult<LayerBudget> {
+        let layers: Vec<(String,)> = sqlx::query_as(
+            "SELECT shapes FROM document_annotations WHERE document_id = ? AND id IS NOT ?",
+        )
+        .bind(document_id)
+        .bind(except)
+        .fetch_all(pool)
+        .await?;
+
+        let mut budget = LayerBudget::default();
+        for (shapes,) in layers {
+            let shapes: Vec<AnnotationShape> = serde_json::from_str(&shapes).unwrap_or_default();
+            budget.add(&shapes)?;
+        }
+        Ok(budget)
+    }
+
+    /// Delete an annotation layer
+    pub async fn delete(pool: &DbPool, id: &str) -> AppResult<()> {
+        let result = sqlx::query("DELETE FROM document_annotations WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!(
+                "Annotation layer '{}' not found",
+                id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Render the original image with all visible layers flattened on top
+    ///
+    /// Returns the encoded image bytes. The original file is only read.
+    pub async fn render(
+        pool: &DbPool,
+        document_id: &str,
+        format: RenderFormat,
+    ) -> AppResult<Vec<u8>> {
+        let doc = DocumentService::get_by_id(pool, document_id).await?;
+        if doc.file_type != "image" {
+            return Err(AppError::BadRequest(
+                "Only image documents can be rendered with annotations".into(),
+            ));
+        }
+
+        // Writes keep every document within its budget; this only guards
+        // the renderer against rows that were changed by other means
+        let mut budget = LayerBudget::default();
+        let mut shapes = Vec::new();
+        for layer in Self::list(pool, document_id).await? {
+            if !layer.visible {
+                continue;
+            }
+            let layer_shapes =
+                serde_json::from_str::<Vec<AnnotationShape>>(&layer.shapes).unwrap_or_default();
+            if let Err(e) =
+                Self::validate_shapes(&layer_shapes).and_then(|_| budget.add(&layer_shapes))
+            {
+                tracing::warn!("Skipping annotation layer {}: {}", layer.id, e);
+                continue;
+            }
+            shapes.extend(layer_shapes);
+        }
+
+        ColdStorageService::ensure_restored(pool, &doc).await?;
+        let data = StorageService::read(&doc.file_path).await?;
+
+        // Decoding and drawing are CPU-bound, keep them off the async workers
+        tokio::task::spawn_blocking(move || Self::flatten(&data, &shapes, format))
+            .await
+            .map_err(|e| AppError::Internal(format!("Render task failed: {}", e)))?
+    }
+
+    /// Decode, draw all shapes and re-encode an image
+    fn flatten(
+        data: &[u8],
+        shapes: &[AnnotationShape],
+        format: RenderFormat,
+    ) -> AppResult<Vec<u8>> {
+        let mut canvas = image::load_from_memory(data)
+            .map_err(|e| AppError::BadRequest(format!("Unsupported image file: {}", e)))?
+            .to_rgba8();
+
+        let mut mask = Mask::new(canvas.width(), canvas.height());
+        for shape in shapes {
+            Self::draw_shape(&mut mask, shape);
+            mask.apply(&mut canvas);
+        }
+
+        let mut out = Cursor::new(Vec::new());
+        let result = match format {
+            RenderFormat::Png => {
+                DynamicImage::ImageRgba8(canvas).write_to(&mut out, ImageFormat::Png)
+            }
+            // JPEG has no alpha channel
+            RenderFormat::Jpeg => {
+                DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+                    .write_to(&mut out, ImageFormat::Jpeg)
+            }
+        };
+        result.map_err(|e| AppError::Internal(format!("Failed to encode image: {}", e)))?;
+
+        Ok(out.into_inner())
+    }
+
+    /// Mark a single shape, scaled to the canvas size, on the mask
+    fn draw_shape(mask: &mut Mask, shape: &AnnotationShape) {
+        let (w, h) = (mask.width() as f32, mask.height() as f32);
+        let to_px = |p: &[f32; 2]| (p[0] * w, p[1] * h);
+
+        match shape {
+            AnnotationShape::Pen {
+                points,
+                color,
+                width,
+            } => {
+                mask.color = Self::parse_color(color).unwrap_or(Rgba([255, 0, 0, 255]));
+                let radius = (width * w / 2.0).max(1.0);
+                let px: Vec<(f32, f32)> = points.iter().map(to_px).collect();
+                if px.len() == 1 {
+                    Self::stroke(mask, px[0], px[0], radius);
+                }
+                for pair in px.windows(2) {
+                    Self::stroke(mask, pair[0], pair[1], radius);
+                }
+            }
+            AnnotationShape::Arrow {
+                from,
+                to,
+                color,
+                width,
+            } => {
+                mask.color = Self::parse_color(color).unwrap_or(Rgba([255, 0, 0, 255]));
+                let radius = (width * w / 2.0).max(1.0);
+                let (start, end) = (to_px(from), to_px(to));
+                Self::stroke(mask, start, end, radius);
+
+                // Arrow head: two short strokes at ±30° from the shaft
+                let angle = (start.1 - end.1).atan2(start.0 - end.0);
+                let head_len = (radius * 6.0).max(0.03 * (w * w + h * h).sqrt());
+                for offset in [-std::f32::consts::FRAC_PI_6, std::f32::consts::FRAC_PI_6] {
+                    let tip = (
+                        end.0 + head_len * (angle + offset).cos(),
+                        end.1 + head_len * (angle + offset).sin(),
+                    );
+                    Self::stroke(mask, end, tip, radius);
+                }
+            }
+            AnnotationShape::Text {
+                position,
+                text,
+                color,
+                size,
+            } => {
+                let Some(font) = Self::font() else {
+                    tracing::warn!("No font available, skipping text annotation");
+                    return;
+                };
+                mask.color = Self::parse_color(color).unwrap_or(Rgba([255, 0, 0, 255]));
+                let (x, y) = to_px(position);
+                let scale = PxScale::from((size * h).max(8.0));
+                mask.text(x as i32, y as i32, scale, font, text);
+            }
+        }
+    }
+
+    /// Mark a thick line segment by stamping filled circles along it
+    fn stroke(mask: &mut Mask, start: (f32, f32), end: (f32, f32), radius: f32) {
+        let length = ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)).sqrt();
+        let steps = (length / (radius / 2.0).max(0.5)).ceil().max(1.0) as usize;
+        for i in 0..=steps {
+            let t = i as f32 / steps as f32;
+            let x = start.0 + (end.0 - start.0) * t;
+            let y = start.1 + (end.1 - start.1) * t;
+            mask.circle((x as i32, y as i32), radius as i32);
+        }
+    }
+
+    /// Number of points in a list of shapes, as counted against the layer limit
+    fn point_count(shapes: &[AnnotationShape]) -> usize {
+        shapes
+            .iter()
+            .map(|shape| match shape {
+                AnnotationShape::Pen { points, .. } => points.len(),
+                AnnotationShape::Arrow { .. } => 2,
+                AnnotationShape::Text { .. } => 1,
+            })
+            .sum()
+    }
+
+    /// Area a shape covers when drawn, in image areas
+    ///
+    /// An estimate of the drawing cost: strokes count their full length
+    /// however much they overlap, and text its whole glyph boxes.
+    fn ink(shape: &AnnotationShape) -> f32 {
+        let distance = |a: &[f32; 2], b: &[f32; 2]| (a[0] - b[0]).hypot(a[1] - b[1]);
+        match shape {
+            AnnotationShape::Pen { points, width, .. } => {
+                let width = width.max(MIN_INK_WIDTH);
+                let length: f32 = points.windows(2).map(|p| distance(&p[0], &p[1])).sum();
+                width * (length + width)
+            }
+            AnnotationShape::Arrow {
+                from, to, width, ..
+            } => {
+                let width = width.max(MIN_INK_WIDTH);
+                // Two head strokes, as long as `draw_shape` makes them
+                let head = (width * 3.0).max(0.03 * std::f32::consts::SQRT_2);
+                width * (distance(from, to) + 2.0 * head + width)
+            }
+            AnnotationShape::Text { text, size, .. } => text.chars().count() as f32 * size * size,
+        }
+    }
+
+    /// Lazily load the font used for text shapes
+    fn font() -> Option<&'static FontVec> {
+        static FONT: OnceLock<Option<FontVec>> = OnceLock::new();
+        FONT.get_or_init(|| {
+            let path = std::env::var("ANNOTATION_FONT_PATH")
+                .unwrap_or_else(|_| DEFAULT_FONT_PATH.to_string());
+            let data = std::fs::read(&path)
+                .map_err(|e| tracing::warn!("Failed to read annotation font {}: {}", path, e))
+                .ok()?;
+            FontVec::try_from_vec(data)
+                .map_err(|e| tracing::warn!("Invalid annotation font {}: {}", path, e))
+                .ok()
+        })
+        .as_ref()
+    }
+
+    /// Parse "#RRGGBB" or "#RRGGBBAA" into an RGBA color
+    fn parse_color(value: &str) -> Option<Rgba<u8>> {
+        let hex = value.strip_prefix('#')?;
+        if !hex.is_ascii() {
+            return None;
+        }
+        let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        match hex.len() {
+            6 => Some(Rgba([byte(0)?, byte(2)?, byte(4)?, 255])),
+            8 => Some(Rgba([byte(0)?, byte(2)?, byte(4)?, byte(6)?])),
+            _ => None,
+        }
+    }
+
+    /// Validate the shapes of one layer before storing them
+    pub fn validate_shapes(shapes: &[AnnotationShape]) -> AppResult<()> {
+        if Self::point_count(shapes) > MAX_POINTS_PER_LAYER {
+            return Err(AppError::BadRequest(format!(
+                "A layer can have at most {} points",
+                MAX_POINTS_PER_LAYER
+            )));
+        }
+
+        let in_range = |p: &[f32; 2]| p.iter().all(|v| v.is_finite() && (0.0..=1.0).contains(v));
+        let stroke = |v: f32| v.is_finite() && v > 0.0 && v <= MAX_STROKE_WIDTH;
+        let text_size = |v: f32| v.is_finite() && v > 0.0 && v <= MAX_TEXT_SIZE;
+
+        for shape in shapes {
+            let (color, valid) = match shape {
+                AnnotationShape::Pen {
+                    points,
+                    color,
+                    width,
+                } => (
+                    color,
+                    !points.is_empty() && points.iter().all(in_range) && stroke(*width),
+                ),
+                AnnotationShape::Arrow {
+                    from,
+                    to,
+                    color,
+                    width,
+                } => (color, in_range(from) && in_range(to) && stroke(*width)),
+                AnnotationShape::Text {
+                    position,
+                    text,
+                    color,
+                    size,
+                } => (
+                    color,
+                    in_range(position)
+                        && !text.trim().is_empty()
+                        && text.chars().count() <= MAX_TEXT_LENGTH
+                        && text_size(*size),
+                ),
+            };
+
+            if Self::parse_color(color).is_none() {
+                return Err(AppError::BadRequest(format!(
+                    "Invalid color '{}'. Use #RRGGBB or #RRGGBBAA",
+                    color
+                )));
+            }
+            if !valid {
+                return Err(AppError::BadRequest(format!(
+                    "Invalid shape: coordinates must be normalized between 0 and 1, stroke widths and text sizes at most {}",
+                    MAX_STROKE_WIDTH.max(MAX_TEXT_SIZE)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Coverage of the shape being drawn, blended onto the canvas in one pass
+///
+/// Stamps overlap, so painting them straight onto the image would darken
+/// semi-transparent strokes wherever they cross. Marking coverage first and
+/// blending once gives every shape a uniform opacity. Marked pixels are
+/// tracked as one span per row, so blending a thin diagonal stroke doesn't
+/// visit its whole bounding box.
+struct Mask {
+    coverage: GrayImage,
+    color: Rgba<u8>,
+    /// Marked columns of each row as (min x, max x)
+    spans: Vec<Option<(u32, u32)>>,
+    /// First and last row with a span
+    rows: Option<(u32, u32)>,
+}
+
+impl Mask {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            coverage: GrayImage::new(width, height),
+            color: Rgba([255, 0, 0, 255]),
+            spans: vec![None; height as usize],
+            rows: None,
+        }
+    }
+
+    fn width(&self) -> u32 {
+        self.coverage.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.coverage.height()
+    }
+
+    fn circle(&mut self, center: (i32, i32), radius: i32) {
+        draw_filled_circle_mut(&mut self.coverage, center, radius, Luma([255]));
+        self.include(
+            (center.0 - radius, center.1 - radius),
+            (center.0 + radius, center.1 + radius),
+        );
+    }
+
+    fn text(&mut self, x: i32, y: i32, scale: PxScale, font: &FontVec, text: &str) {
+        draw_text_mut(&mut self.coverage, Luma([255]), x, y, scale, font, text);
+        // Glyphs can reach a little past the measured box
+        let (tw, th) = text_size(scale, font, text);
+        let slack = scale.y.ceil() as i32;
+        self.include(
+            (x - slack, y - slack),
+            (x + tw as i32 + slack, y + th as i32 + slack),
+        );
+    }
+
+    /// Grow the row spans to cover a box, clamped to the canvas
+    fn include(&mut self, min: (i32, i32), max: (i32, i32)) {
+        let (w, h) = (self.width() as i32, self.height() as i32);
+        if max.0 < 0 || max.1 < 0 || min.0 >= w || min.1 >= h {
+            return;
+        }
+        let clamp = |v: i32, limit: i32| v.clamp(0, limit - 1) as u32;
+        let (x0, y0) = (clamp(min.0, w), clamp(min.1, h));
+        let (x1, y1) = (clamp(max.0, w), clamp(max.1, h));
+        for span in &mut self.spans[y0 as usize..=y1 as usize] {
+            *span = Some(match *span {
+                Some((a, b)) => (a.min(x0), b.max(x1)),
+                None => (x0, x1),
+            });
+        }
+        self.rows = Some(match self.rows {
+            Some((a, b)) => (a.min(y0), b.max(y1)),
+            None => (y0, y1),
+        });
+    }
+
+    /// Blend the marked pixels onto the canvas in the mask colour and clear them
+    fn apply(&mut self, canvas: &mut RgbaImage) {
+        let Some((y0, y1)) = self.rows.take() else {
+            return;
+        };
+        for y in y0..=y1 {
+            let Some((x0, x1)) = self.spans[y as usize].take() else {
+                continue;
+            };
+            for x in x0..=x1 {
+                let Luma([coverage]) = *self.coverage.get_pixel(x, y);
+                if coverage == 0 {
+                    continue;
+                }
+                let pixel = canvas.get_pixel_mut(x, y);
+                *pixel = blend(*pixel, self.color, coverage);
+                self.coverage.put_pixel(x, y, Luma([0]));
+            }
+        }
+    }
+}
+
+/// Composite `color` over `dst` ("source over"), scaled by `coverage`
+fn blend(dst: Rgba<u8>, color: Rgba<u8>, coverage: u8) -> Rgba<u8> {
+    let src_a = color[3] as f32 / 255.0 * coverage as f32 / 255.0;
+    let dst_a = dst[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a <= 0.0 {
+        return dst;
+    }
+
+    let channel = |i: usize| {
+        let value = (color[i] as f32 * src_a + dst[i] as f32 * dst_a * (1.0 - src_a)) / out_a;
+        value.round().clamp(0.0, 255.0) as u8
+    };
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        (out_a * 255.0).round() as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pen(points: Vec<[f32; 2]>, width: f32) -> AnnotationShape {
+        AnnotationShape::Pen {
+            points,
+            color: "#ff0000".into(),
+            width,
+        }
+    }
+
+    fn text(text: &str, size: f32) -> AnnotationShape {
+        AnnotationShape::Text {
+            position: [0.1, 0.1],
+            text: text.into(),
+            color: "#00ff0080".into(),
+            size,
+        }
+    }
+
+    #[test]
+    fn strokes_and_text_are_capped() {
+        assert!(
+            AnnotationService::validate_shapes(&[pen(vec![[0.0, 0.0]], MAX_STROKE_WIDTH)]).is_ok()
+        );
+        assert!(AnnotationService::validate_shapes(&[pen(vec![[0.0, 0.0]], 0.5)]).is_err());
+        assert!(AnnotationService::validate_shapes(&[pen(vec![[0.0, 0.0]], 0.0)]).is_err());
+        assert!(AnnotationService::validate_shapes(&[text("Fix", MAX_TEXT_SIZE)]).is_ok());
+        assert!(AnnotationService::validate_shapes(&[text("Fix", 1.0)]).is_err());
+        assert!(AnnotationService::validate_shapes(&[text(&"x".repeat(501), 0.05)]).is_err());
+    }
+
+    #[test]
+    fn coordinates_and_colours_are_checked() {
+        assert!(AnnotationService::validate_shapes(&[pen(vec![[1.5, 0.0]], 0.01)]).is_err());
+        assert!(AnnotationService::validate_shapes(&[pen(vec![[f32::NAN, 0.0]], 0.01)]).is_err());
+        assert!(AnnotationService::validate_shapes(&[pen(Vec::new(), 0.01)]).is_err());
+        let shape = AnnotationShape::Arrow {
+            from: [0.0, 0.0],
+            to: [1.0, 1.0],
+            color: "red".into(),
+            width: 0.01,
+        };
+        assert!(AnnotationService::validate_shapes(&[shape]).is_err());
+    }
+
+    #[test]
+    fn layers_per_document_are_capped() {
+        let mut budget = LayerBudget::default();
+        for _ in 0..MAX_LAYERS_PER_DOCUMENT {
+            budget.add(&[pen(vec![[0.5, 0.5]], 0.01)]).unwrap();
+        }
+        assert!(budget.add(&[]).is_err());
+    }
+
+    #[test]
+    fn points_per_document_are_capped() {
+        let layer = vec![pen(vec![[0.5, 0.5]; MAX_POINTS_PER_LAYER], 0.001)];
+        let mut budget = LayerBudget::default();
+        for _ in 0..MAX_POINTS_PER_DOCUMENT / MAX_POINTS_PER_LAYER {
+            budget.add(&layer).unwrap();
+        }
+        assert!(budget.add(&[text("one more", 0.01)]).is_err());
+    }
+
+    #[test]
+    fn ink_per_document_is_capped() {
+        // Back and forth across the image with the widest stroke
+        let points: Vec<[f32; 2]> = (0..1_000).map(|i| [(i % 2) as f32, 0.5]).collect();
+        let layer = vec![pen(points, MAX_STROKE_WIDTH)];
+        assert!(AnnotationService::validate_shapes(&layer).is_ok());
+        assert!(AnnotationService::ink(&layer[0]) > MAX_INK_PER_DOCUMENT);
+        let mut budget = LayerBudget::default();
+        assert!(budget.add(&layer).is_err());
+        // A rejected layer doesn't use up the budget
+        budget
+            .add(&[pen(vec![[0.0, 0.0], [1.0, 1.0]], 0.01)])
+            .unwrap();
+    }
+
+    #[test]
+    fn thin_strokes_count_as_drawn() {
+        let thin = pen(vec![[0.0, 0.5], [1.0, 0.5]], 0.000_001);
+        assert!(AnnotationService::ink(&thin) >= MIN_INK_WIDTH);
+    }
+
+    #[test]
+    fn mask_blends_only_marked_pixels() {
+        let mut canvas = RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]));
+        let mut mask = Mask::new(20, 20);
+        mask.color = Rgba([0, 0, 0, 255]);
+        mask.circle((2, 2), 1);
+        mask.circle((17, 17), 1);
+        mask.apply(&mut canvas);
+
+        assert_eq!(*canvas.get_pixel(2, 2), Rgba([0, 0, 0, 255]));
+        assert_eq!(*canvas.get_pixel(17, 17), Rgba([0, 0, 0, 255]));
+        assert_eq!(*canvas.get_pixel(10, 10), Rgba([255, 255, 255, 255]));
+        assert!(mask.rows.is_none() && mask.spans.iter().all(Option::is_none));
+        assert!(mask.coverage.pixels().all(|p| p.0 == [0]));
+    }
+
+    #[test]
+    fn translucent_colour_blends_over_the_image() {
+        let blended = blend(Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 128]), 255);
+        assert_eq!(blended[3], 255);
+        assert!((126..=128).contains(&blended[0]));
+    }
+}
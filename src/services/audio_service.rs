@@ -0,0 +1,176 @@
+//! Audio service module
+//!
+//! Inspection of voice memo files: the real container format is detected
+//! from the file's leading bytes (browsers record WebM/Ogg, iPhones M4A,
+//! some Android apps AMR or 3GP) and the duration is read with `ffprobe`
+//! when available. WAV durations are computed directly from the header.
+//!
+//! # Configuration
+//! - `FFPROBE_PATH`: ffprobe binary to use (default: from PATH)
+
+use std::io::SeekFrom;
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::process::Command;
+
+use crate::error::{AppError, AppResult};
+
+/// Number of leading bytes read for format detection
+const SNIFF_LEN: usize = 64;
+
+/// RIFF chunks walked looking for `data` before giving up on a WAV file
+const MAX_WAV_CHUNKS: usize = 64;
+
+/// Voice memo container formats we accept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Webm,
+    Ogg,
+    Mp3,
+    Aac,
+    M4a,
+    Wav,
+    Flac,
+    Amr,
+}
+
+impl AudioFormat {
+    /// Format name, also used as the stored file extension
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioFormat::Webm => "webm",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Aac => "aac",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Amr => "amr",
+        }
+    }
+
+    /// Detect the format from the first bytes of a file
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        match header {
+            [0x1A, 0x45, 0xDF, 0xA3, ..] => Some(AudioFormat::Webm),
+            [b'O', b'g', b'g', b'S', ..] => Some(AudioFormat::Ogg),
+            [b'f', b'L', b'a', b'C', ..] => Some(AudioFormat::Flac),
+            [b'#', b'!', b'A', b'M', b'R', ..] => Some(AudioFormat::Amr),
+            [b'I', b'D', b'3', ..] => Some(AudioFormat::Mp3),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => {
+                Some(AudioFormat::Wav)
+            }
+            // ISO base media (M4A, 3GP): size box followed by "ftyp"
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some(AudioFormat::M4a),
+            // ADTS AAC: 12-bit sync word with layer bits set to 00
+            [0xFF, b, ..] if b & 0xF6 == 0xF0 => Some(AudioFormat::Aac),
+            // MPEG audio frame: 11-bit sync word with a valid layer
+            [0xFF, b, ..] if b & 0xE0 == 0xE0 && b & 0x06 != 0 => Some(AudioFormat::Mp3),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Audio service with static methods for voice memo inspection
+pub struct AudioService;
+
+impl AudioService {
+    /// Detect the format and duration of an audio file on disk
+    ///
+    /// Fails with BadRequest when the content is not a supported audio format.
+    pub async fn inspect(path: &str) -> AppResult<(AudioFormat, Option<f64>)> {
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        tokio::fs::File::open(path)
+            .await?
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut header)
+            .await?;
+
+        let format = AudioFormat::detect(&header).ok_or_else(|| {
+            AppError::BadRequest(
+                "Unsupported voice memo format. Use WebM, Ogg, MP3, AAC, M4A, WAV, FLAC or AMR"
+                    .into(),
+            )
+        })?;
+
+        let duration = match Self::probe_duration(path).await {
+            Some(duration) => Some(duration),
+            None if format == AudioFormat::Wav => Self::wav_duration(path).await,
+            None => None,
+        };
+
+        Ok((format, duration))
+    }
+
+    /// Read the duration with ffprobe; None if ffprobe is missing or fails
+    async fn probe_duration(path: &str) -> Option<f64> {
+        let ffprobe = std::env::var("FFPROBE_PATH").unwrap_or_else(|_| "ffprobe".to_string());
+        let output = Command::new(ffprobe)
+            .args([
+                "-v",
+                "error",
+                "-show_entries",
+                "format=duration",
+                "-of",
+                "default=noprint_wrappers=1:nokey=1",
+                path,
+            ])
+            .output()
+            .await
+            .map_err(|e| tracing::debug!("ffprobe unavailable for voice memo: {}", e))
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        // Browser recordings often lack a duration header, reported as "N/A"
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .ok()
+            .filter(|d: &f64| d.is_finite() && *d >= 0.0)
+    }
+
+    /// Compute the duration of a PCM WAV file from its `fmt ` and `data` chunks
+    ///
+    /// Only the chunk headers are read; other chunks are skipped by seeking.
+    async fn wav_duration(path: &str) -> Option<f64> {
+        let mut file = tokio::fs::File::open(path).await.ok()?;
+        let len = file.metadata().await.ok()?.len();
+
+        let mut byte_rate = None;
+        let mut pos: u64 = 12;
+        for _ in 0..MAX_WAV_CHUNKS {
+            if pos + 8 > len {
+                break;
+            }
+            let mut header = [0u8; 8];
+            file.seek(SeekFrom::Start(pos)).await.ok()?;
+            file.read_exact(&mut header).await.ok()?;
+            let size = u32::from_le_bytes(header[4..8].try_into().ok()?) as u64;
+            let body = pos + 8;
+
+            if &header[..4] == b"fmt " && body + 12 <= len {
+                // Byte rate sits after the format tag, channels and sample rate
+                let mut fmt = [0u8; 12];
+                file.read_exact(&mut fmt).await.ok()?;
+                byte_rate = Some(u32::from_le_bytes(fmt[8..12].try_into().ok()?));
+            } else if &header[..4] == b"data" {
+                let rate = byte_rate.filter(|r| *r > 0)?;
+                let size = size.min(len - body);
+                return Some(size as f64 / rate as f64);
+            }
+
+            // Chunks are padded to an even size
+            pos = body + size + (size & 1);
+        }
+        None
+    }
+}
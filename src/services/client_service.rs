@@ -0,0 +1,322 @@
+//! Client service module
+//!
+//! Business logic for clients, the people or companies projects are done
+//! for. A client owns any number of projects, and inbound emails are matched
+//! to clients by the sender's address.
+
+use sqlx::{Sqlite, Transaction};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{Client, ClientRequest, ClientResponse, Document, Project};
+use crate::services::lifecycle_service::ARCHIVED_STATUS;
+
+/// Client service with static methods for client operations
+pub struct ClientService;
+
+impl ClientService {
+    /// Create a new client
+    pub async fn create(pool: &DbPool, request: ClientRequest) -> AppResult<ClientResponse> {
+        let (name, phones, emails) = Self::validate(&request)?;
+
+        let id = Uuid::new_v4().to_string();
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO clients (id, name, tax_id, address, notes) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(&name)
+        .bind(&request.tax_id)
+        .bind(&request.address)
+        .bind(&request.notes)
+        .execute(&mut *tx)
+        .await?;
+        Self::replace_contacts(&mut tx, &id, &phones, &emails).await?;
+
+        tx.commit().await?;
+
+        Self::get_response(pool, &id).await
+    }
+
+    /// Get a client by ID
+    pub async fn get_by_id(pool: &DbPool, id: &str) -> AppResult<Client> {
+        sqlx::query_as::<_, Client>("SELECT * FROM clients WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Client with id '{}' not found", id)))
+    }
+
+    /// Get a client with its phones, emails and project count
+    pub async fn get_response(pool: &DbPool, id: &str) -> AppResult<ClientResponse> {
+        let client = Self::get_by_id(pool, id).await?;
+        Self::build_response(pool, client).await
+    }
+
+    /// List all clients by name
+    pub async fn list(pool: &DbPool) -> AppResult<Vec<ClientResponse>> {
+        let clients =
+            sqlx::query_as::<_, Client>("SELECT * FROM clients ORDER BY name COLLATE NOCASE")
+                .fetch_all(pool)
+                .await?;
+
+        let mut responses = Vec::with_capacity(clients.len());
+        for client in clients {
+            responses.push(Self::build_response(pool, client).await?);
+        }
+        Ok(responses)
+    }
+
+    /// Replace a client's details, phones and emails
+    pub async fn update(
+        pool: &DbPool,
+        id: &str,
+        request: ClientRequest,
+    ) -> AppResult<ClientResponse> {
+        let (name, phones, emails) = Self::validate(&request)?;
+        Self::get_by_id(pool, id).await?;
+
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("UPDATE clients SET name = ?, tax_id = ?, address = ?, notes = ? WHERE id = ?")
+            .bind(&name)
+            .bind(&request.tax_id)
+            .bind(&request.address)
+            .bind(&request.notes)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        Self::replace_contacts(&mut tx, id, &phones, &emails).await?;
+
+        tx.commit().await?;
+
+        Self::get_response(pool, id).await
+    }
+
+    /// Delete a client
+    ///
+    /// Its projects and documents are kept and just lose the link.
+    pub async fn delete(pool: &DbPool, id: &str) -> AppResult<()> {
+        Self::get_by_id(pool, id).await?;
+
+        let mut tx = pool.begin().await?;
+        sqlx::query("UPDATE projects SET client_id = NULL WHERE client_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE documents SET client_id = NULL WHERE client_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM clients WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// List a client's projects, newest first
+    pub async fn projects(pool: &DbPool, id: &str) -> AppResult<Vec<Project>> {
+        Self::get_by_id(pool, id).await?;
+
+        let projects = sqlx::query_as::<_, Project>(
+            "SELECT * FROM projects WHERE client_id = ? ORDER BY created_at DESC",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(projects)
+    }
+
+    /// List a client's documents, newest first
+    ///
+    /// Includes every document in the client's projects, plus documents that
+    /// came in by email from the client, wherever they are (inbox included).
+    pub async fn documents(pool: &DbPool, id: &str) -> AppResult<Vec<Document>> {
+        Self::get_by_id(pool, id).await?;
+
+        let docs = sqlx::query_as::<_, Document>(
+            r#"
+            SELECT d.* FROM documents d
+            LEFT JOIN projects p ON p.id = d.project_id
+            WHERE p.client_id = ?1 OR d.client_id = ?1
+            ORDER BY d.uploaded_at DESC
+            "#,
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(docs)
+    }
+
+    /// Find the client an email sender belongs to
+    ///
+    /// Accepts a bare address or a `From` header like `Ana <ana@example.com>`.
+    /// Returns None when no client, or more than one, has the address.
+    pub async fn match_sender(pool: &DbPool, sender: &str) -> AppResult<Option<String>> {
+        let address = Self::email_address(sender);
+        if address.is_empty() {
+            return Ok(None);
+        }
+
+        let matches: Vec<(String,)> =
+            sqlx::query_as("SELECT DISTINCT client_id FROM client_emails WHERE email = ?")
+                .bind(&address)
+                .fetch_all(pool)
+                .await?;
+
+        match matches.as_slice() {
+            [(client_id,)] => Ok(Some(client_id.clone())),
+            [] => Ok(None),
+            _ => {
+                tracing::warn!(
+                    "Email address '{}' belongs to {} clients, not matching any",
+                    address,
+                    matches.len()
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// The client's only active (not archived) project, if it has exactly one
+    pub async fn sole_active_project(pool: &DbPool, id: &str) -> AppResult<Option<String>> {
+        let projects: Vec<(String,)> =
+            sqlx::query_as("SELECT id FROM projects WHERE client_id = ? AND status != ? LIMIT 2")
+                .bind(id)
+                .bind(ARCHIVED_STATUS)
+                .fetch_all(pool)
+                .await?;
+
+        Ok(match projects.as_slice() {
+            [(project_id,)] => Some(project_id.clone()),
+            _ => None,
+        })
+    }
+
+    /// Add a phone number to a client if it isn't there yet
+    pub async fn add_phone(pool: &DbPool, id: &str, phone: &str) -> AppResult<()> {
+        let phone = phone.trim();
+        if !phone.is_empty() {
+            sqlx::query("INSERT OR IGNORE INTO client_phones (client_id, phone) VALUES (?, ?)")
+                .bind(id)
+                .bind(phone)
+                .execute(pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Check a request and return the trimmed name, phones and lowercase emails
+    fn validate(request: &ClientRequest) -> AppResult<(String, Vec<String>, Vec<String>)> {
+        let name = request.name.trim().to_string();
+        if name.is_empty() {
+            return Err(AppError::BadRequest("Client name cannot be empty".into()));
+        }
+
+        let mut phones: Vec<String> = Vec::new();
+        for phone in &request.phones {
+            let phone = phone.trim().to_string();
+            if !phone.is_empty() && !phones.contains(&phone) {
+                phones.push(phone);
+            }
+        }
+
+        let mut emails: Vec<String> = Vec::new();
+        for email in &request.emails {
+            let email = Self::email_address(email);
+            if email.is_empty() {
+                continue;
+            }
+            if !email.contains('@') {
+                return Err(AppError::BadRequest(format!(
+                    "'{}' is not an email address",
+                    email
+                )));
+            }
+            if !emails.contains(&email) {
+                emails.push(email);
+            }
+        }
+
+        Ok((name, phones, emails))
+    }
+
+    /// Extract the lowercase address from `Name <address>` or a bare address
+    fn email_address(value: &str) -> String {
+        let value = value.trim();
+        let address = match (value.rfind('<'), value.rfind('>')) {
+            (Some(start), Some(end)) if start < end => &value[start + 1..end],
+            _ => value,
+        };
+        address.trim().to_lowercase()
+    }
+
+    async fn replace_contacts(
+        tx: &mut Transaction<'_, Sqlite>,
+        id: &str,
+        phones: &[String],
+        emails: &[String],
+    ) -> AppResult<()> {
+        sqlx::query("DELETE FROM client_phones WHERE client_id = ?")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query("DELETE FROM client_emails WHERE client_id = ?")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+
+        for phone in phones {
+            sqlx::query("INSERT INTO client_phones (client_id, phone) VALUES (?, ?)")
+                .bind(id)
+                .bind(phone)
+                .execute(&mut **tx)
+                .await?;
+        }
+        for email in emails {
+            sqlx::query("INSERT INTO client_emails (client_id, email) VALUES (?, ?)")
+                .bind(id)
+                .bind(email)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn build_response(pool: &DbPool, client: Client) -> AppResult<ClientResponse> {
+        let phones: Vec<(String,)> =
+            sqlx::query_as("SELECT phone FROM client_phones WHERE client_id = ? ORDER BY rowid")
+                .bind(&client.id)
+                .fetch_all(pool)
+                .await?;
+        let emails: Vec<(String,)> =
+            sqlx::query_as("SELECT email FROM client_emails WHERE client_id = ? ORDER BY rowid")
+                .bind(&client.id)
+                .fetch_all(pool)
+                .await?;
+        let (project_count,): (i32,) =
+            sqlx::query_as("SELECT COUNT(*) FROM projects WHERE client_id = ?")
+                .bind(&client.id)
+                .fetch_one(pool)
+                .await?;
+
+        Ok(ClientResponse {
+            id: client.id,
+            name: client.name,
+            phones: phones.into_iter().map(|(phone,)| phone).collect(),
+            emails: emails.into_iter().map(|(email,)| email).collect(),
+            tax_id: client.tax_id,
+            address: client.address,
+            notes: client.notes,
+            created_at: client.created_at,
+            project_count,
+        })
+    }
+}
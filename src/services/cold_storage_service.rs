@@ -0,0 +1,668 @@
+//! Cold storage service module
+//!
+//! Moves the full-size files of long-archived projects out of the hot
+//! storage backend into compressed ZIP archives in a separate location, and
+//! brings them back when they're needed again.
+//!
+//! A project qualifies once it has been ARCHIVED for
+//! `COLD_STORAGE_AFTER_DAYS`. Its documents' originals, voice memos and video
+//! transcodes are packed into one archive per run; previews and video
+//! posters stay hot so the project can still be browsed. Packed documents
+//! point at their archive through `documents.archive_id`.
+//!
+//! Files come back per document when one is opened through `/files`,
+//! exported, annotated or processed, and for the whole project when it is
+//! reactivated or on request.
+//!
+//! # Architecture Decision
+//! Hot copies are only deleted after the archive has been read back from
+//! cold storage with every file verified, and only when no document outside
+//! cold storage still uses the same content. Archives are built and verified
+//! without holding the lock restores wait on; only switching the documents
+//! over and deleting their hot copies is, after checking under it that the
+//! documents still point at the files that were packed. The cold location
+//! is a storage backend of its own (a directory, typically a NAS or USB disk
+//! mount), encrypted like the hot one when `ENCRYPTION_KEY` is set. Archives
+//! no document points to any more are deleted by the periodic job.
+//!
+//! # Configuration
+//! - `COLD_STORAGE_DIR`: where archives are kept; cold storage is off when unset
+//! - `COLD_STORAGE_AFTER_DAYS`: time a project spends ARCHIVED before its
+//!   files move (default: 90)
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use async_zip::tokio::read::seek::ZipFileReader;
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio_util::compat::{FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{ColdStorageReport, Document};
+use crate::services::encryption_service::{EncryptedStorage, Keyring};
+use crate::services::lifecycle_service::ARCHIVED_STATUS;
+use crate::services::storage_service::{LocalStorage, StorageBackend};
+use crate::services::{ProjectService, StorageService};
+
+/// Default time a project spends ARCHIVED before its files move
+const DEFAULT_AFTER_DAYS: i64 = 90;
+
+/// How often the archival job looks for due projects
+const JOB_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Name of the manifest entry describing an archive's documents
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// The cold storage backend, None when not configured
+static COLD: OnceLock<Option<Box<dyn StorageBackend>>> = OnceLock::new();
+
+/// Serializes switching documents to and from archives and cleanup, so a
+/// restore never races the deletion of the files it brings back
+static LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Cold storage service with static methods for archiving project files
+pub struct ColdStorageService;
+
+impl ColdStorageService {
+    /// Whether `COLD_STORAGE_DIR` is set
+    pub fn is_enabled() -> bool {
+        Self::cold().is_some()
+    }
+
+    /// The cold storage backend, set up from the environment on first use
+    ///
+    /// Panics on an invalid configuration, like the hot backend.
+    fn cold() -> Option<&'static dyn StorageBackend> {
+        COLD.get_or_init(|| {
+            Self::from_env().unwrap_or_else(|e| panic!("Invalid cold storage configuration: {}", e))
+        })
+        .as_deref()
+    }
+
+    fn backend() -> AppResult<&'static dyn StorageBackend> {
+        Self::cold().ok_or_else(|| {
+            AppError::BadRequest("Cold storage is not configured; set COLD_STORAGE_DIR".into())
+        })
+    }
+
+    fn from_env() -> AppResult<Option<Box<dyn StorageBackend>>> {
+        let dir = match std::env::var("COLD_STORAGE_DIR") {
+            Ok(dir) if !dir.trim().is_empty() => dir,
+            _ => return Ok(None),
+        };
+        std::fs::create_dir_all(&dir)?;
+        let backend: Box<dyn StorageBackend> = Box::new(LocalStorage::new(dir));
+        match Keyring::from_env()? {
+            Some(keyring) => Ok(Some(Box::new(EncryptedStorage::new(backend, keyring)))),
+            None => Ok(Some(backend)),
+        }
+    }
+
+    fn after_days() -> i64 {
+        std::env::var("COLD_STORAGE_AFTER_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_AFTER_DAYS)
+    }
+
+    /// Start the periodic archival job, when cold storage is configured
+    pub fn spawn_scheduler(pool: &DbPool) {
+        let Some(cold) = Self::cold() else {
+            return;
+        };
+        tracing::info!(
+            "Moving files of projects archived for {} days to {}",
+            Self::after_days(),
+            cold.describe()
+        );
+
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = Self::run(&pool).await {
+                    tracing::error!("Cold storage job failed: {}", e);
+                }
+                tokio::time::sleep(JOB_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Move the files of every due project, then drop archives nothing uses
+    pub async fn run(pool: &DbPool) -> AppResult<()> {
+        let cutoff = (Utc::now() - chrono::Duration::days(Self::after_days()))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let due: Vec<(String,)> = sqlx::query_as(
+            "SELECT id FROM projects WHERE status = 'ARCHIVED' AND archived_at <= ? ORDER BY archived_at",
+        )
+        .bind(&cutoff)
+        .fetch_all(pool)
+        .await?;
+
+        for (project_id,) in due {
+            match Self::pack_project(pool, &project_id, &cutoff).await {
+                Ok(report) if report.documents > 0 => tracing::info!(
+                    "Moved {} file(s) of {} document(s) of project {} to cold storage",
+                    report.files,
+                    report.documents,
+                    project_id
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::warn!(
+                    "Failed to move files of project {} to cold storage: {}",
+                    project_id,
+                    e
+                ),
+            }
+        }
+
+        Self::collect_garbage(pool).await
+    }
+
+    /// Move an archived project's files to cold storage now, however long
+    /// it has been archived
+    pub async fn archive_project(pool: &DbPool, project_id: &str) -> AppResult<ColdStorageReport> {
+        // Documents restored at any time are packed again
+        Self::pack_project(pool, project_id, "9999-12-31").await
+    }
+
+    /// Pack the hot documents of an archived project not restored since `cutoff`
+    async fn pack_project(
+        pool: &DbPool,
+        project_id: &str,
+        cutoff: &str,
+    ) -> AppResult<ColdStorageReport> {
+        let cold = Self::backend()?;
+        let project = ProjectService::get_by_id(pool, project_id).await?;
+        if project.status != ARCHIVED_STATUS {
+            return Err(AppError::BadRequest(
+                "Only archived projects can be moved to cold storage".into(),
+            ));
+        }
+
+        let docs = sqlx::query_as::<_, Document>(
+            "SELECT * FROM documents WHERE project_id = ? AND archive_id IS NULL AND (restored_at IS NULL OR restored_at <= ?)",
+        )
+        .bind(project_id)
+        .bind(cutoff)
+        .fetch_all(pool)
+        .await?;
+
+        let mut report = ColdStorageReport {
+            project_id: project_id.to_string(),
+            ..Default::default()
+        };
+        if docs.is_empty() {
+            return Ok(report);
+        }
+
+        // What the documents point at, checked again before switching them
+        let mut packed = Vec::with_capacity(docs.len());
+        for doc in &docs {
+            if let Some(files) = Self::hot_files(pool, &doc.id).await? {
+                packed.push((doc, files));
+            }
+        }
+        if packed.is_empty() {
+            return Ok(report);
+        }
+
+        let hot = StorageService::backend();
+        let mut keys = BTreeSet::new();
+        let all_keys = packed.iter().flat_map(|(_, (file, audio, transcoded))| {
+            std::iter::once(file).chain(audio).chain(transcoded)
+        });
+        for key in all_keys
+            .filter(|key| !key.is_empty())
+            .cloned()
+            .collect::<BTreeSet<_>>()
+        {
+            if hot.head(&key).await?.is_some() {
+                keys.insert(key);
+            } else {
+                tracing::warn!("'{}' is missing, not moving it to cold storage", key);
+            }
+        }
+
+        let manifest = serde_json::json!({
+            "project": { "id": project.id, "name": project.name },
+            "documents": packed.iter().map(|(doc, _)| serde_json::json!({
+                "id": doc.id,
+                "original_name": doc.original_name,
+                "file_path": doc.file_path,
+                "audio_path": doc.audio_path,
+            })).collect::<Vec<_>>(),
+        });
+
+        let staged = StorageService::staging_path("zip");
+        let written = Self::write_archive(&staged, &keys, &manifest.to_string()).await;
+        let size = match written {
+            Ok(()) => tokio::fs::metadata(&staged).await?.len(),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&staged).await;
+                return Err(e);
+            }
+        };
+
+        let archive_id = Uuid::new_v4().to_string();
+        let archive_key = format!("{}/{}.zip", project_id, archive_id);
+        if let Err(e) = cold.put_file(&archive_key, Path::new(&staged)).await {
+            let _ = tokio::fs::remove_file(&staged).await;
+            return Err(e);
+        }
+        if let Err(e) = Self::verify_archive(&archive_key, &keys).await {
+            let _ = cold.delete(&archive_key).await;
+            return Err(e);
+        }
+
+        let _lock = LOCK.lock().await;
+        let switched = async {
+            let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+            let (status,): (String,) = sqlx::query_as("SELECT status FROM projects WHERE id = ?")
+                .bind(project_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .unwrap_or_default();
+            let changed = || {
+                AppError::Conflict(format!(
+                    "Project {} changed while its files were being packed; try again",
+                    project_id
+                ))
+            };
+            if status != ARCHIVED_STATUS {
+                return Err(changed());
+            }
+            for (doc, files) in &packed {
+                if Self::hot_files(&mut *tx, &doc.id).await?.as_ref() != Some(files) {
+                    return Err(changed());
+                }
+            }
+
+            sqlx::query(
+                "INSERT INTO cold_archives (id, project_id, storage_key, file_count, size_bytes) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&archive_id)
+            .bind(project_id)
+            .bind(&archive_key)
+            .bind(keys.len() as i64)
+            .bind(size as i64)
+            .execute(&mut *tx)
+            .await?;
+            for (doc, _) in &packed {
+                sqlx::query(
+                    "UPDATE documents SET archive_id = ?, archived_at = datetime('now'), restored_at = NULL WHERE id = ?",
+                )
+                .bind(&archive_id)
+                .bind(&doc.id)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = switched {
+            let _ = cold.delete(&archive_key).await;
+            return Err(e);
+        }
+
+        // Identical content may still be used by documents that stay hot,
+        // or be about to be by an upload that found it stored
+        for key in &keys {
+            if let Err(e) = StorageService::delete_unused(key, Self::needed_hot(pool, key)).await {
+                tracing::warn!("Failed to delete '{}' after archiving: {}", key, e);
+            }
+        }
+
+        report.documents = packed.len();
+        report.files = keys.len();
+        report.archive_size_bytes = Some(size);
+        Ok(report)
+    }
+
+    /// Keys of the files that move to cold storage with these documents
+    async fn cold_keys(pool: &DbPool, docs: &[Document]) -> AppResult<BTreeSet<String>> {
+        let mut keys = BTreeSet::new();
+        for doc in docs {
+            keys.insert(doc.file_path.clone());
+            keys.extend(doc.audio_path.clone());
+            let transcoded: Option<(Option<String>,)> =
+                sqlx::query_as("SELECT transcoded_path FROM video_metadata WHERE document_id = ?")
+                    .bind(&doc.id)
+                    .fetch_optional(pool)
+                    .await?;
+            keys.extend(transcoded.and_then(|(path,)| path));
+        }
+        keys.retain(|key| !key.is_empty());
+        Ok(keys)
+    }
+
+    /// File, voice memo and video transcode of a document, None once it is
+    /// gone or archived
+    async fn hot_files<'e, E>(
+        executor: E,
+        document_id: &str,
+    ) -> AppResult<Option<(String, Option<String>, Option<String>)>>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        let files = sqlx::query_as(
+            r#"
+            SELECT d.file_path, d.audio_path, v.transcoded_path FROM documents d
+            LEFT JOIN video_metadata v ON v.document_id = d.id
+            WHERE d.id = ? AND d.archive_id IS NULL
+            "#,
+        )
+        .bind(document_id)
+        .fetch_optional(executor)
+        .await?;
+        Ok(files)
+    }
+
+    /// Whether a row outside cold storage still points at this key
+    async fn needed_hot(pool: &DbPool, key: &str) -> AppResult<bool> {
+        let row: Option<(i32,)> = sqlx::query_as(
+            r#"
+            SELECT 1 FROM documents WHERE archive_id IS NULL AND (file_path = ?1 OR audio_path = ?1)
+            UNION ALL SELECT 1 FROM documents WHERE preview_path = ?1
+            UNION ALL SELECT 1 FROM video_metadata v JOIN documents d ON d.id = v.document_id
+                WHERE v.poster_path = ?1 OR (d.archive_id IS NULL AND v.transcoded_path = ?1)
+            UNION ALL SELECT 1 FROM forum_messages WHERE audio_path = ?1
+            LIMIT 1
+            "#,
+        )
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    /// Write the files and manifest into a ZIP at `path`
+    async fn write_archive(path: &str, keys: &BTreeSet<String>, manifest: &str) -> AppResult<()> {
+        let file = tokio::fs::File::create(path).await?;
+        let mut zip = ZipFileWriter::with_tokio(file);
+        let zip_error = |e: async_zip::error::ZipError| AppError::Internal(e.to_string());
+
+        for key in keys {
+            let object = StorageService::backend().get(key).await?;
+            let builder = ZipEntryBuilder::new(key.clone().into(), Compression::Deflate);
+            let mut entry = zip.write_entry_stream(builder).await.map_err(zip_error)?;
+            futures::io::copy(object.reader.compat(), &mut entry).await?;
+            entry.close().await.map_err(zip_error)?;
+        }
+
+        let builder = ZipEntryBuilder::new(MANIFEST_NAME.into(), Compression::Deflate);
+        zip.write_entry_whole(builder, manifest.as_bytes())
+            .await
+            .map_err(zip_error)?;
+        let mut file = zip.close().await.map_err(zip_error)?.into_inner();
+        file.shutdown().await?;
+        Ok(())
+    }
+
+    /// Read an archive back from cold storage and check it holds every file intact
+    async fn verify_archive(archive_key: &str, keys: &BTreeSet<String>) -> AppResult<()> {
+        let found = Self::read_entries(archive_key, keys, false).await?;
+        if found.len() != keys.len() {
+            return Err(AppError::Internal(format!(
+                "Cold storage archive {} is missing files",
+                archive_key
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the entries of an archive named in `keys` against their
+    /// checksum and recorded size, returning the keys found
+    ///
+    /// With `restore`, intact entries are also put back into hot storage.
+    async fn read_entries(
+        archive_key: &str,
+        keys: &BTreeSet<String>,
+        restore: bool,
+    ) -> AppResult<Vec<String>> {
+        let zip_error = |e: async_zip::error::ZipError| AppError::Internal(e.to_string());
+        let copy = Self::backend()?.local_copy(archive_key).await?;
+        let file = BufReader::new(tokio::fs::File::open(copy.path()).await?);
+        let mut zip = ZipFileReader::with_tokio(file).await.map_err(zip_error)?;
+
+        let mut found = Vec::new();
+        for index in 0..zip.file().entries().len() {
+            let entry = &zip.file().entries()[index];
+            let key = entry.filename().as_str().map_err(zip_error)?.to_string();
+            let expected_size = entry.uncompressed_size();
+            if !keys.contains(&key) {
+                continue;
+            }
+
+            let reader = zip
+                .reader_without_entry(index)
+                .await
+                .map_err(zip_error)?
+                .compat();
+            let staged = restore.then(|| {
+                let extension = Path::new(&key)
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .unwrap_or("");
+                StorageService::staging_path(extension)
+            });
+            let hashed = match &staged {
+                Some(staged) => {
+                    let mut file = tokio::fs::File::create(staged).await?;
+                    Self::hash(reader, &mut file).await
+                }
+                None => Self::hash(reader, &mut tokio::io::sink()).await,
+            };
+
+            let intact = hashed.as_ref().is_ok_and(|(checksum, size)| {
+                *size == expected_size
+                    && StorageService::checksum_of_key(&key)
+                        .is_none_or(|expected| expected == checksum)
+            });
+            if !intact {
+                if let Some(staged) = &staged {
+                    let _ = tokio::fs::remove_file(staged).await;
+                }
+                hashed?;
+                return Err(AppError::Internal(format!(
+                    "'{}' is damaged in cold storage archive {}",
+                    key, archive_key
+                )));
+            }
+            if let Some(staged) = &staged {
+                // The archived documents' rows already refer to the key
+                drop(StorageService::store_staged(staged, &key).await?);
+            }
+            found.push(key);
+        }
+        Ok(found)
+    }
+
+    /// Copy `reader` into `writer`, returning its SHA-256 and length
+    async fn hash(
+        mut reader: impl AsyncRead + Unpin,
+        writer: &mut (impl tokio::io::AsyncWrite + Unpin),
+    ) -> AppResult<(String, u64)> {
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut size = 0;
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            writer.write_all(&buf[..n]).await?;
+            size += n as u64;
+        }
+        writer.flush().await?;
+        Ok((format!("{:x}", hasher.finalize()), size))
+    }
+
+    /// Bring back the files of an archived document, if it is one
+    pub async fn ensure_restored(pool: &DbPool, doc: &Document) -> AppResult<()> {
+        if doc.archive_id.is_some() {
+            Self::restore_documents(pool, std::slice::from_ref(doc)).await?;
+        }
+        Ok(())
+    }
+
+    /// Bring back the files of archived documents pointing at `key`
+    ///
+    /// Returns whether any were restored.
+    pub async fn restore_key(pool: &DbPool, key: &str) -> AppResult<bool> {
+        let docs = sqlx::query_as::<_, Document>(
+            r#"
+            SELECT * FROM documents WHERE archive_id IS NOT NULL
+                AND (file_path = ?1 OR audio_path = ?1
+                    OR id IN (SELECT document_id FROM video_metadata WHERE transcoded_path = ?1))
+            "#,
+        )
+        .bind(key)
+        .fetch_all(pool)
+        .await?;
+        Ok(Self::restore_documents(pool, &docs).await?.documents > 0)
+    }
+
+    /// Bring back all files of a project from cold storage
+    pub async fn restore_project(pool: &DbPool, project_id: &str) -> AppResult<ColdStorageReport> {
+        ProjectService::get_by_id(pool, project_id).await?;
+        let docs = sqlx::query_as::<_, Document>(
+            "SELECT * FROM documents WHERE project_id = ? AND archive_id IS NOT NULL",
+        )
+        .bind(project_id)
+        .fetch_all(pool)
+        .await?;
+
+        let mut report = Self::restore_documents(pool, &docs).await?;
+        report.project_id = project_id.to_string();
+        Ok(report)
+    }
+
+    /// Restore a reactivated project's files in the background
+    pub fn spawn_restore_project(pool: &DbPool, project_id: &str) {
+        let pool = pool.clone();
+        let project_id = project_id.to_string();
+        tokio::spawn(async move {
+            match Self::restore_project(&pool, &project_id).await {
+                Ok(report) if report.documents > 0 => tracing::info!(
+                    "Restored {} document(s) of project {} from cold storage",
+                    report.documents,
+                    project_id
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::error!(
+                    "Failed to restore project {} from cold storage: {}",
+                    project_id,
+                    e
+                ),
+            }
+        });
+    }
+
+    /// Extract the files of archived documents back into hot storage
+    pub async fn restore_documents(
+        pool: &DbPool,
+        docs: &[Document],
+    ) -> AppResult<ColdStorageReport> {
+        let mut report = ColdStorageReport::default();
+        let mut by_archive: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for doc in docs {
+            if let Some(archive_id) = &doc.archive_id {
+                by_archive.entry(archive_id).or_default().push(&doc.id);
+            }
+        }
+        if by_archive.is_empty() {
+            return Ok(report);
+        }
+
+        let _lock = LOCK.lock().await;
+        for (archive_id, doc_ids) in by_archive {
+            // Restored by someone else while waiting for the lock?
+            let placeholders = vec!["?"; doc_ids.len()].join(", ");
+            let query = format!(
+                "SELECT * FROM documents WHERE archive_id = ? AND id IN ({})",
+                placeholders
+            );
+            let mut q = sqlx::query_as::<_, Document>(&query).bind(archive_id);
+            for id in &doc_ids {
+                q = q.bind(*id);
+            }
+            let docs = q.fetch_all(pool).await?;
+            if docs.is_empty() {
+                continue;
+            }
+
+            let mut missing = BTreeSet::new();
+            for key in Self::cold_keys(pool, &docs).await? {
+                if !StorageService::exists(&key).await? {
+                    missing.insert(key);
+                }
+            }
+            if !missing.is_empty() {
+                report.files += Self::extract(pool, archive_id, &missing).await?;
+            }
+
+            for doc in &docs {
+                sqlx::query(
+                    "UPDATE documents SET archive_id = NULL, archived_at = NULL, restored_at = datetime('now') WHERE id = ?",
+                )
+                .bind(&doc.id)
+                .execute(pool)
+                .await?;
+            }
+            report.documents += docs.len();
+        }
+        Ok(report)
+    }
+
+    /// Copy files out of an archive into hot storage, returning how many
+    async fn extract(pool: &DbPool, archive_id: &str, keys: &BTreeSet<String>) -> AppResult<usize> {
+        let (archive_key,): (String,) =
+            sqlx::query_as("SELECT storage_key FROM cold_archives WHERE id = ?")
+                .bind(archive_id)
+                .fetch_optional(pool)
+                .await?
+                .ok_or_else(|| {
+                    AppError::Internal(format!("Cold storage archive {} not found", archive_id))
+                })?;
+
+        let restored = Self::read_entries(&archive_key, keys, true).await?;
+
+        for key in keys.iter().filter(|key| !restored.contains(key)) {
+            tracing::error!("'{}' is not in cold storage archive {}", key, archive_id);
+        }
+        Ok(restored.len())
+    }
+
+    /// Delete archives no document points to any more
+    async fn collect_garbage(pool: &DbPool) -> AppResult<()> {
+        let cold = Self::backend()?;
+        let _lock = LOCK.lock().await;
+        let unused: Vec<(String, String)> = sqlx::query_as(
+            "SELECT id, storage_key FROM cold_archives WHERE id NOT IN (SELECT archive_id FROM documents WHERE archive_id IS NOT NULL)",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for (id, key) in unused {
+            cold.delete(&key).await?;
+            sqlx::query("DELETE FROM cold_archives WHERE id = ?")
+                .bind(&id)
+                .execute(pool)
+                .await?;
+            tracing::info!("Deleted unused cold storage archive {}", key);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Comment service module
+//!
+//! Business logic for per-document comment threads. A thread starts with a
+//! root comment that may be pinned to normalized coordinates on the image
+//! (plus a page number for PDFs); replies hang off the root and the whole
+//! thread can be marked as resolved.
+
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{DocumentComment, DocumentCommentResponse};
+use crate::services::DocumentService;
+
+/// Comment service with static methods for document comment operations
+pub struct CommentService;
+
+impl CommentService {
+    /// Start a new comment thread on a document
+    pub async fn create_thread(
+        pool: &DbPool,
+        document_id: &str,
+        content: &str,
+        author_name: &str,
+        position: Option<(f64, f64)>,
+        page: Option<i32>,
+    ) -> AppResult<DocumentComment> {
+        let doc = DocumentService::get_by_id(pool, document_id).await?;
+
+        let content = content.trim();
+        if content.is_empty() {
+            return Err(AppError::BadRequest("Comment cannot be empty".into()));
+        }
+
+        Self::validate_anchor(&doc.file_type, position, page)?;
+
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO document_comments (id, document_id, author_name, content, x, y, page) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(document_id)
+        .bind(author_name)
+        .bind(content)
+        .bind(position.map(|(x, _)| x))
+        .bind(position.map(|(_, y)| y))
+        .bind(page)
+        .execute(pool)
+        .await?;
+
+        Self::get_by_id(pool, &id).await
+    }
+
+    /// Check where a thread is pinned on a document of `file_type`
+    pub fn validate_anchor(
+        file_type: &str,
+        position: Option<(f64, f64)>,
+        page: Option<i32>,
+    ) -> AppResult<()> {
+        if let Some((x, y)) = position {
+            if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+                return Err(AppError::BadRequest(
+                    "Comment coordinates must be normalized between 0 and 1".into(),
+                ));
+            }
+        }
+
+        if let Some(page) = page {
+            if file_type != "pdf" {
+                return Err(AppError::BadRequest(
+                    "Page numbers are only valid for PDF documents".into(),
+                ));
+            }
+            if page < 1 {
+                return Err(AppError::BadRequest("Page numbers start at 1".into()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reply to a comment thread
+    ///
+    /// Replies to a reply are attached to the thread root, keeping threads flat.
+    pub async fn create_reply(
+        pool: &DbPool,
+        parent_id: &str,
+        content: &str,
+        author_name: &str,
+    ) -> AppResult<DocumentComment> {
+        let parent = Self::get_by_id(pool, parent_id).await?;
+        let root_id = parent.parent_id.unwrap_or(parent.id);
+
+        let content = content.trim();
+        if content.is_empty() {
+            return Err(AppError::BadRequest("Comment cannot be empty".into()));
+        }
+
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO document_comments (id, document_id, parent_id, author_name, content) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&id)
+        .bind(&parent.document_id)
+        .bind(&root_id)
+        .bind(author_name)
+        .bind(content)
+        .execute(pool)
+        .await?;
+
+        Self::get_by_id(pool, &id).await
+    }
+
+    /// Get a single comment by ID
+    pub async fn get_by_id(pool: &DbPool, id: &str) -> AppResult<DocumentComment> {
+        sqlx::query_as::<_, DocumentComment>("SELECT * FROM document_comments WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Comment '{}' not found", id)))
+    }
+
+    /// List all threads of a document with their replies, oldest first
+    pub async fn list_threads(
+        pool: &DbPool,
+        document_id: &str,
+    ) -> AppResult<Vec<DocumentCommentResponse>> {
+        DocumentService::get_by_id(pool, document_id).await?;
+
+        let comments = sqlx::query_as::<_, DocumentComment>(
+            "SELECT * FROM document_comments WHERE document_id = ? ORDER BY created_at ASC",
+        )
+        .bind(document_id)
+        .fetch_all(pool)
+        .await?;
+
+        let (roots, replies): (Vec<_>, Vec<_>) =
+            comments.into_iter().partition(|c| c.parent_id.is_none());
+
+        Ok(roots
+            .into_iter()
+            .map(|root| {
+                let thread_replies = replies
+                    .iter()
+                    .filter(|r| r.parent_id.as_deref() == Some(root.id.as_str()))
+                    .cloned()
+                    .map(|r| DocumentCommentResponse::from_comment(r, Vec::new()))
+                    .collect();
+                DocumentCommentResponse::from_comment(root, thread_replies)
+            })
+            .collect())
+    }
+
+    /// Build a response for a single thread root (or a lone reply)
+    pub async fn build_response(
+        pool: &DbPool,
+        comment: DocumentComment,
+    ) -> AppResult<DocumentCommentResponse> {
+        if comment.parent_id.is_some() {
+            return Ok(DocumentCommentResponse::from_comment(comment, Vec::new()));
+        }
+
+        let replies = sqlx::query_as::<_, DocumentComment>(
+            "SELECT * FROM document_comments WHERE parent_id = ? ORDER BY created_at ASC",
+        )
+        .bind(&comment.id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|r| DocumentCommentResponse::from_comment(r, Vec::new()))
+        .collect();
+
+        Ok(DocumentCommentResponse::from_comment(comment, replies))
+    }
+
+    /// Mark a thread as resolved or reopen it
+    pub async fn set_resolved(
+        pool: &DbPool,
+        id: &str,
+        resolved: bool,
+        resolved_by: Option<&str>,
+    ) -> AppResult<DocumentComment> {
+        let comment = Self::get_by_id(pool, id).await?;
+        if comment.parent_id.is_some() {
+            return Err(AppError::BadRequest(
+                "Only thread root comments can be resolved".into(),
+            ));
+        }
+
+        let resolved_at =
+            resolved.then(|| chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string());
+        let resolved_by = if resolved { resolved_by } else { None };
+
+        sqlx::query(
+            "UPDATE document_comments SET resolved = ?, resolved_by = ?, resolved_at = ? WHERE id = ?",
+        )
+        .bind(resolved)
+        .bind(resolved_by)
+        .bind(&resolved_at)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Self::get_by_id(pool, id).await
+    }
+
+    /// Delete a comment (deleting a thread root also deletes its replies)
+    pub async fn delete(pool: &DbPool, id: &str) -> AppResult<()> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("DELETE FROM document_comments WHERE parent_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM document_comments WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Comment '{}' not found", id)));
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Names of everyone involved in a thread: its authors and the document uploader
+    pub async fn thread_participants(
+        pool: &DbPool,
+        comment: &DocumentComment,
+    ) -> AppResult<Vec<String>> {
+        let root_id = comment.parent_id.as_deref().unwrap_or(&comment.id);
+
+        let mut participants: Vec<String> = sqlx::query_as::<_, (String,)>(
+            "SELECT DISTINCT author_name FROM document_comments WHERE id = ? OR parent_id = ?",
+        )
+        .bind(root_id)
+        .bind(root_id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(name,)| name)
+        .collect();
+
+        let doc = DocumentService::get_by_id(pool, &comment.document_id).await?;
+        if let Some(uploader) = doc.uploaded_by {
+            participants.push(uploader);
+        }
+
+        Ok(participants)
+    }
+}
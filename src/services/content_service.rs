@@ -0,0 +1,300 @@
+//! Content service module
+//!
+//! Verifies what an uploaded file really is before it is accepted. Clients
+//! and mail servers only *claim* a MIME type (and the filename extension is
+//! whatever the sender typed), so the leading bytes of every file are
+//! sniffed and the detected type is checked against the upload policy.
+//!
+//! # Architecture Decision
+//! The detected type wins whenever the bytes are recognised. Types that
+//! always carry a signature (images, PDFs, video, audio) are rejected when no
+//! signature is found, while signature-less formats (CSV, TXT, DXF) fall back
+//! to the claimed type. Office files are ZIP or OLE containers and SVG is XML,
+//! so a generic container detection keeps the more specific claimed type.
+//!
+//! # Configuration
+//! Comma separated MIME patterns (`image/*`, `application/pdf`):
+//! - `UPLOAD_ALLOWED_TYPES`: accepted types (default: everything not denied)
+//! - `UPLOAD_DENIED_TYPES`: rejected types (default: executables, scripts and HTML)
+//! - `UPLOAD_MAX_SIZES`: per-type limits such as `image/*=25MB,application/pdf=50MB`
+//! - `UPLOAD_MAX_SIZE`: limit for every other type (default: 100MB)
+//! - `UPLOAD_MAX_REQUEST_SIZE`: limit for a whole upload request, which may
+//!   carry many files (default: 500MB)
+//! - `IMPORT_MAX_SIZE`: limit for a project import bundle, and for what its
+//!   files unpack to (default: 2GB)
+
+use std::sync::OnceLock;
+
+use tokio::io::AsyncReadExt;
+
+use crate::error::{AppError, AppResult};
+
+/// Number of leading bytes read for content sniffing
+///
+/// Large enough for the OOXML/ODF checks, which look for entry names inside the ZIP.
+const SNIFF_LEN: usize = 8192;
+
+/// Denied unless `UPLOAD_DENIED_TYPES` says otherwise
+const DEFAULT_DENIED_TYPES: &str = "application/vnd.microsoft.portable-executable,\
+application/x-msdownload,application/x-msi,application/x-executable,application/x-mach-binary,\
+application/vnd.android.dex,application/vnd.android.dey,application/vnd.android.package-archive,\
+application/java,application/java-archive,application/wasm,application/x-sh,\
+application/x-shellscript,text/x-shellscript,application/x-bat,text/html";
+
+/// Per-type limits unless `UPLOAD_MAX_SIZES` says otherwise
+const DEFAULT_MAX_SIZES: &str = "image/*=25MB,application/pdf=50MB,video/*=100MB";
+
+/// Limit for types without a specific entry unless `UPLOAD_MAX_SIZE` says otherwise
+const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Limit for a whole upload request unless `UPLOAD_MAX_REQUEST_SIZE` says otherwise
+const DEFAULT_MAX_REQUEST_SIZE: u64 = 500 * 1024 * 1024;
+
+/// Limit for an import bundle unless `IMPORT_MAX_SIZE` says otherwise
+const DEFAULT_MAX_IMPORT_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Types that always start with a recognisable signature
+const SIGNED_PREFIXES: [&str; 4] = ["image/", "video/", "audio/", "application/pdf"];
+
+/// Types under a signed prefix that have no signature the sniffer knows
+/// (text-based SVG/DXF, and DWG drawings)
+const UNSIGNED_TYPES: [&str; 5] = [
+    "image/svg+xml",
+    "image/vnd.dxf",
+    "image/x-dxf",
+    "image/vnd.dwg",
+    "image/x-dwg",
+];
+
+/// Generic containers that more specific formats are built on
+/// (office files on ZIP/OLE, SVG on XML)
+const CONTAINER_TYPES: [&str; 3] = ["application/zip", "application/x-ole-storage", "text/xml"];
+
+/// Upload acceptance policy, read once from the environment
+struct UploadPolicy {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+    max_sizes: Vec<(String, u64)>,
+    default_max_size: u64,
+    max_request_size: u64,
+    max_import_size: u64,
+}
+
+/// Result of checking an uploaded file
+#[derive(Debug, Clone)]
+pub struct ContentCheck {
+    /// MIME type claimed by the client, without parameters
+    pub claimed_mime: String,
+    /// MIME type detected from the file content, if recognised
+    pub detected_mime: Option<String>,
+    /// Type the document is treated as: detected when available, claimed otherwise
+    pub effective_mime: String,
+}
+
+/// Content service with static methods for sniffing and policy checks
+pub struct ContentService;
+
+impl ContentService {
+    /// Sniff the MIME type of a file from its leading bytes
+    pub fn sniff(header: &[u8]) -> Option<&'static str> {
+        infer::get(header).map(|kind| kind.mime_type())
+    }
+
+    /// Check in-memory content (e.g. an email attachment) against the policy
+    ///
+    /// Fails with 415 for denied or mismatching types and 413 when too large.
+    pub fn check(claimed_mime: &str, data: &[u8]) -> AppResult<ContentCheck> {
+        let header = &data[..data.len().min(SNIFF_LEN)];
+        Self::check_parts(claimed_mime, header, data.len() as u64)
+    }
+
+    /// Check a file already written to disk against the policy
+    pub async fn check_file(path: &str, claimed_mime: &str) -> AppResult<ContentCheck> {
+        let size = tokio::fs::metadata(path).await?.len();
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        tokio::fs::File::open(path)
+            .await?
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut header)
+            .await?;
+
+        Self::check_parts(claimed_mime, &header, size)
+    }
+
+    /// Largest size any upload may have, used to abort streams early
+    pub fn max_upload_size() -> u64 {
+        let policy = Self::policy();
+        policy
+            .max_sizes
+            .iter()
+            .map(|(_, size)| *size)
+            .fold(policy.default_max_size, u64::max)
+    }
+
+    /// Largest body an upload request may have, across all its files
+    pub fn max_request_size() -> u64 {
+        let policy = Self::policy();
+        policy.max_request_size.max(Self::max_upload_size())
+    }
+
+    /// Largest project import bundle, and the most its files may unpack to
+    pub fn max_import_size() -> u64 {
+        Self::policy().max_import_size
+    }
+
+    fn check_parts(claimed_mime: &str, header: &[u8], size: u64) -> AppResult<ContentCheck> {
+        let claimed = Self::normalize(claimed_mime);
+        let detected = Self::sniff(header).map(str::to_string);
+
+        let effective = match &detected {
+            Some(d) if CONTAINER_TYPES.contains(&d.as_str()) && !Self::is_signed(&claimed) => {
+                if claimed == "application/octet-stream" {
+                    d.clone()
+                } else {
+                    claimed.clone()
+                }
+            }
+            Some(d) => d.clone(),
+            None if Self::is_signed(&claimed) => {
+                return Err(AppError::UnsupportedMediaType(format!(
+                    "File content does not match its declared type '{}'",
+                    claimed
+                )));
+            }
+            None => claimed.clone(),
+        };
+
+        let policy = Self::policy();
+        let is_denied = policy.denied.iter().any(|p| Self::matches(p, &effective));
+        let is_allowed = policy.allowed.is_empty()
+            || policy.allowed.iter().any(|p| Self::matches(p, &effective));
+        if is_denied || !is_allowed {
+            return Err(AppError::UnsupportedMediaType(format!(
+                "Files of type '{}' are not accepted",
+                effective
+            )));
+        }
+
+        let limit = Self::max_size_for(policy, &effective);
+        if size > limit {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Files of type '{}' are limited to {} MB",
+                effective,
+                limit / (1024 * 1024)
+            )));
+        }
+
+        Ok(ContentCheck {
+            claimed_mime: claimed,
+            detected_mime: detected,
+            effective_mime: effective,
+        })
+    }
+
+    /// Size limit for a type: exact entries win over wildcards
+    fn max_size_for(policy: &UploadPolicy, mime: &str) -> u64 {
+        policy
+            .max_sizes
+            .iter()
+            .find(|(pattern, _)| pattern == mime)
+            .or_else(|| {
+                policy
+                    .max_sizes
+                    .iter()
+                    .find(|(pattern, _)| Self::matches(pattern, mime))
+            })
+            .map(|(_, size)| *size)
+            .unwrap_or(policy.default_max_size)
+    }
+
+    fn is_signed(mime: &str) -> bool {
+        SIGNED_PREFIXES
+            .iter()
+            .any(|prefix| mime.starts_with(prefix))
+            && !UNSIGNED_TYPES.contains(&mime)
+    }
+
+    /// Match a MIME type against a pattern such as `image/*` or `*`
+    fn matches(pattern: &str, mime: &str) -> bool {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => mime.starts_with(prefix),
+            None => pattern == mime,
+        }
+    }
+
+    /// Lowercase and strip parameters (`text/csv; charset=utf-8` -> `text/csv`)
+    fn normalize(mime: &str) -> String {
+        let mime = mime.split(';').next().unwrap_or("").trim().to_lowercase();
+        if mime.is_empty() {
+            "application/octet-stream".to_string()
+        } else {
+            mime
+        }
+    }
+
+    fn policy() -> &'static UploadPolicy {
+        static POLICY: OnceLock<UploadPolicy> = OnceLock::new();
+        POLICY.get_or_init(|| {
+            let list = |var: &str, default: &str| -> Vec<String> {
+                std::env::var(var)
+                    .unwrap_or_else(|_| default.to_string())
+                    .split(',')
+                    .map(|p| p.trim().to_lowercase())
+                    .filter(|p| !p.is_empty() && p != "*")
+                    .collect()
+            };
+
+            let max_sizes = std::env::var("UPLOAD_MAX_SIZES")
+                .unwrap_or_else(|_| DEFAULT_MAX_SIZES.to_string())
+                .split(',')
+                .filter_map(|entry| {
+                    let (pattern, size) = entry.split_once('=')?;
+                    let size = Self::parse_size(size);
+                    if size.is_none() {
+                        tracing::warn!("Ignoring invalid UPLOAD_MAX_SIZES entry '{}'", entry);
+                    }
+                    Some((pattern.trim().to_lowercase(), size?))
+                })
+                .collect();
+
+            let default_max_size = std::env::var("UPLOAD_MAX_SIZE")
+                .ok()
+                .and_then(|s| Self::parse_size(&s))
+                .unwrap_or(DEFAULT_MAX_SIZE);
+
+            let max_request_size = std::env::var("UPLOAD_MAX_REQUEST_SIZE")
+                .ok()
+                .and_then(|s| Self::parse_size(&s))
+                .unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+
+            let max_import_size = std::env::var("IMPORT_MAX_SIZE")
+                .ok()
+                .and_then(|s| Self::parse_size(&s))
+                .unwrap_or(DEFAULT_MAX_IMPORT_SIZE);
+
+            UploadPolicy {
+                allowed: list("UPLOAD_ALLOWED_TYPES", ""),
+                denied: list("UPLOAD_DENIED_TYPES", DEFAULT_DENIED_TYPES),
+                max_sizes,
+                default_max_size,
+                max_request_size,
+                max_import_size,
+            }
+        })
+    }
+
+    /// Parse a size such as `25MB`, `512KB`, `1GB` or a plain byte count
+    fn parse_size(value: &str) -> Option<u64> {
+        let value = value.trim().to_uppercase();
+        let (number, multiplier) = if let Some(n) = value.strip_suffix("GB") {
+            (n, 1024 * 1024 * 1024)
+        } else if let Some(n) = value.strip_suffix("MB") {
+            (n, 1024 * 1024)
+        } else if let Some(n) = value.strip_suffix("KB") {
+            (n, 1024)
+        } else {
+            (value.strip_suffix('B').unwrap_or(&value), 1)
+        };
+        number.trim().parse::<u64>().ok().map(|n| n * multiplier)
+    }
+}
@@ -8,153 +8,661 @@
 //! # Architecture Decision
 //! File uploads are streamed directly to disk rather than buffered in memory.
 //! This prevents memory exhaustion when handling large files and allows
-//! the server to handle multiple concurrent uploads efficiently.
+//! the server to handle multiple concurrent uploads efficiently. Files are
+//! vetted in the staging directory and only then handed to the storage backend.
 
 use crate::db::DbPool;
 use crate::error::{AppError, AppResult};
-use crate::models::Document;
+use crate::models::{
+    BulkItemResult, BulkOperation, Document, DocumentEventType, DocumentResponse, DocumentSource,
+    DocumentStatus, UploadFileResult, VideoInfoResponse, VideoMetadata,
+};
+use crate::services::audio_service::AudioFormat;
+use crate::services::content_service::ContentCheck;
+use crate::services::scan_service::QuarantineOrigin;
+use crate::services::storage_service::{KeyPin, STAGING_DIR};
+use crate::services::{AudioService, ContentService, FileTypeService, ScanService, StorageService};
 use axum::extract::multipart::Field;
-use chrono::Local;
+use chrono::{DateTime, Local, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{Sqlite, Transaction};
+use std::collections::HashMap;
 use std::path::Path;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 
-/// Upload directory path
-pub const UPLOADS_DIR: &str = "./uploads";
+/// Maximum length of a single document tag
+const MAX_TAG_LENGTH: usize = 50;
+
+/// A voice memo saved to storage
+pub struct VoiceMemo {
+    /// Storage key, pinned until a row refers to it
+    pub file: KeyPin,
+    pub format: AudioFormat,
+    pub duration_seconds: Option<f64>,
+}
+
+/// Text fields that apply to uploaded files
+///
+/// Sent as `name` for every file of the request or `name[i]` for the i-th
+/// file. Per-file values replace request-wide ones, except tags, which add up.
+#[derive(Debug, Clone, Default)]
+struct UploadFields {
+    notes: Option<String>,
+    category: Option<String>,
+    status: Option<DocumentStatus>,
+    tags: Vec<String>,
+    /// Capture time in UTC, formatted like SQLite's `datetime()`
+    captured_at: Option<String>,
+}
+
+impl UploadFields {
+    /// Per-file values, falling back to the request-wide ones
+    fn or(self, defaults: &UploadFields) -> UploadFields {
+        let mut tags = defaults.tags.clone();
+        for tag in self.tags {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        UploadFields {
+            notes: self.notes.or_else(|| defaults.notes.clone()),
+            category: self.category.or_else(|| defaults.category.clone()),
+            status: self.status.or(defaults.status),
+            tags,
+            captured_at: self.captured_at.or_else(|| defaults.captured_at.clone()),
+        }
+    }
+}
+
+/// An uploaded file vetted and stored, not yet recorded
+struct StoredUpload {
+    /// Storage key, pinned until the document row refers to it
+    file: KeyPin,
+    check: ContentCheck,
+    scanned: bool,
+    checksum: String,
+}
+
+/// Everything needed to record one uploaded file as a document
+struct NewUpload<'a> {
+    stored: StoredUpload,
+    original_name: String,
+    project_id: Option<&'a str>,
+    fields: UploadFields,
+    voice_memo: Option<VoiceMemo>,
+    uploaded_by: &'a str,
+    source: DocumentSource,
+}
+
+/// Outcome of a bulk operation on one document, before building the response
+enum BulkOutcome {
+    Updated,
+    /// Deleted; carries the files to remove once the transaction is committed
+    Deleted(Vec<String>),
+    Failed(String),
+}
 
 /// Document service handling all document-related business logic
 pub struct DocumentService;
 
 impl DocumentService {
-    /// Process and save an uploaded file
+    /// Process and save the files of an upload request
+    ///
+    /// Every file field becomes its own document. Text fields: `project_id`,
+    /// `author_name` and `source` ("app" by default, or "api" for external
+    /// integrations) apply to the whole request; `notes` and `category` set a
+    /// value for every file and `notes[i]` / `category[i]` override it for the
+    /// i-th file (0-based). A voice memo in `audio` (or `audio[i]`) is attached
+    /// to the first (or i-th) file.
     ///
-    /// Extracts the file, optional audio, project_id, and author_name from multipart.
-    /// If project_id is provided, assigns the document directly and auto-posts
-    /// a PHOTO message to the project's forum.
+    /// A file rejected by the content policy or the malware scanner doesn't
+    /// fail the others; its result carries the error. Only when no file could
+    /// be stored is the first error returned. If project_id is provided, the
+    /// documents are assigned directly and a single PHOTO (album) message is
+    /// posted to the project's forum.
     pub async fn upload(
         pool: &DbPool,
         mut multipart: axum::extract::Multipart,
-    ) -> AppResult<Document> {
+    ) -> AppResult<Vec<UploadFileResult>> {
         let now = Local::now();
-        let date_part = now.format("%Y-%m-%d_%H-%M-%S");
-        let random_suffix = &Uuid::new_v4().to_string()[..4];
 
-        tokio::fs::create_dir_all(UPLOADS_DIR).await?;
+        tokio::fs::create_dir_all(STAGING_DIR).await?;
 
-        let mut main_unique_filename = None;
-        let mut main_raw_name = None;
-        let mut main_content_type = None;
-        let mut audio_file_path = None;
+        let mut files: Vec<(String, AppResult<StoredUpload>)> = Vec::new();
+        let mut voice_memos: HashMap<usize, VoiceMemo> = HashMap::new();
+        let mut defaults = UploadFields::default();
+        let mut per_file: HashMap<usize, UploadFields> = HashMap::new();
         let mut project_id: Option<String> = None;
         let mut author_name = "Anónimo".to_string();
+        let mut source = DocumentSource::App;
 
-        while let Some(field) = multipart
-            .next_field()
-            .await
-            .map_err(|e| crate::error::AppError::BadRequest(e.to_string()))?
-        {
-            let field_name = field.name().unwrap_or("").to_string();
+        let parsed: AppResult<()> = async {
+            while let Some(field) = multipart
+                .next_field()
+                .await
+                .map_err(|e| AppError::BadRequest(e.to_string()))?
+            {
+                let field_name = field.name().unwrap_or("").to_string();
+                let (name, index) = Self::split_field_index(&field_name);
 
-            if field_name == "project_id" {
-                if let Ok(text) = field.text().await {
-                    if !text.is_empty() {
-                        project_id = Some(text);
+                match name {
+                    "project_id" => {
+                        if let Ok(text) = field.text().await {
+                            if !text.is_empty() {
+                                project_id = Some(text);
+                            }
+                        }
+                    }
+                    "author_name" => {
+                        if let Ok(text) = field.text().await {
+                            if !text.is_empty() {
+                                author_name = text;
+                            }
+                        }
+                    }
+                    "source" => {
+                        if let Ok(text) = field.text().await {
+                            source = match DocumentSource::parse(&text) {
+                                Some(s @ (DocumentSource::App | DocumentSource::Api)) => s,
+                                _ => {
+                                    return Err(AppError::BadRequest(format!(
+                                        "Invalid source '{}'. Use 'app' or 'api'",
+                                        text
+                                    )))
+                                }
+                            };
+                        }
                     }
+                    "notes" | "category" | "status" | "tags" | "captured_at" => {
+                        let text = field.text().await.unwrap_or_default();
+                        let target = match index {
+                            Some(i) => per_file.entry(i).or_default(),
+                            None => &mut defaults,
+                        };
+                        Self::set_upload_field(target, name, &text)?;
+                    }
+                    "audio" => {
+                        let memo = Self::save_voice_memo(pool, field).await?;
+                        if let Some(replaced) = voice_memos.insert(index.unwrap_or(0), memo) {
+                            StorageService::remove_unreferenced(pool, &[replaced.file.release()])
+                                .await;
+                        }
+                    }
+                    _ if field.file_name().is_some() => {
+                        let raw_name = field.file_name().unwrap_or("unknown").to_string();
+                        let stored = Self::store_upload(pool, field, source, &author_name).await;
+                        files.push((raw_name, stored));
+                    }
+                    _ => {}
                 }
-                continue;
             }
+            Ok(())
+        }
+        .await;
 
-            if field_name == "author_name" {
-                if let Ok(text) = field.text().await {
-                    if !text.is_empty() {
-                        author_name = text;
+        if let Err(e) = parsed {
+            let written: Vec<String> = files
+                .into_iter()
+                .filter_map(|(_, stored)| stored.ok())
+                .map(|stored| stored.file.release())
+                .chain(voice_memos.into_values().map(|memo| memo.file.release()))
+                .collect();
+            StorageService::remove_unreferenced(pool, &written).await;
+            return Err(e);
+        }
+
+        if files.is_empty() {
+            let memos: Vec<String> = voice_memos
+                .into_values()
+                .map(|m| m.file.release())
+                .collect();
+            StorageService::remove_unreferenced(pool, &memos).await;
+            return Err(AppError::BadRequest("No document file provided".into()));
+        }
+
+        let total = files.len();
+        let mut results = Vec::with_capacity(total);
+        let mut created_ids = Vec::new();
+        let mut first_error = None;
+        for (index, (raw_name, stored)) in files.into_iter().enumerate() {
+            let voice_memo = voice_memos.remove(&index);
+            let outcome = match stored {
+                Ok(stored) => {
+                    let original_name = if !Self::is_generic_filename(&raw_name) {
+                        raw_name.clone()
+                    } else if total > 1 {
+                        format!("Foto {} ({})", now.format("%d-%m-%Y %H:%M"), index + 1)
+                    } else {
+                        format!("Foto {}", now.format("%d-%m-%Y %H:%M"))
+                    };
+                    let fields = per_file.remove(&index).unwrap_or_default().or(&defaults);
+                    Self::record_upload(
+                        pool,
+                        NewUpload {
+                            stored,
+                            original_name,
+                            project_id: project_id.as_deref(),
+                            fields,
+                            voice_memo,
+                            uploaded_by: &author_name,
+                            source,
+                        },
+                    )
+                    .await
+                }
+                Err(e) => {
+                    if let Some(memo) = voice_memo {
+                        StorageService::remove_unreferenced(pool, &[memo.file.release()]).await;
                     }
+                    Err(e)
+                }
+            };
+
+            match outcome {
+                Ok(doc) => {
+                    created_ids.push(doc.id.clone());
+                    // The document is committed: failing the request now would
+                    // only make the client upload it again
+                    let id = doc.id.clone();
+                    let (document, error) = match Self::build_response(pool, doc).await {
+                        Ok(response) => (Some(response), None),
+                        Err(e) => {
+                            tracing::warn!("Failed to load uploaded document {}: {}", id, e);
+                            let error =
+                                format!("Document {} was created but can't be shown: {}", id, e);
+                            (None, Some(error))
+                        }
+                    };
+                    results.push(UploadFileResult {
+                        index,
+                        original_name: raw_name,
+                        success: true,
+                        error,
+                        document,
+                    });
+                }
+                Err(e) => {
+                    results.push(UploadFileResult {
+                        index,
+                        original_name: raw_name,
+                        success: false,
+                        error: Some(e.to_string()),
+                        document: None,
+                    });
+                    first_error.get_or_insert(e);
                 }
-                continue;
             }
+        }
+
+        // Voice memos sent for a file index that doesn't exist
+        let unused: Vec<String> = voice_memos
+            .into_values()
+            .map(|m| m.file.release())
+            .collect();
+        StorageService::remove_unreferenced(pool, &unused).await;
 
-            if field_name == "audio" {
-                let unique_filename = format!("{}_{}_audio.webm", date_part, random_suffix);
-                let file_path = format!("{}/{}", UPLOADS_DIR, unique_filename);
-                Self::stream_to_file(field, &file_path).await?;
-                audio_file_path = Some(unique_filename);
-            } else if field.file_name().is_some() {
-                let raw_name = field
-                    .file_name()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| "unknown".to_string());
-                let content_type = field
-                    .content_type()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| "application/octet-stream".to_string());
-
-                let extension = Path::new(&raw_name)
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .unwrap_or("bin");
-                let unique_filename = format!("{}_{}.{}", date_part, random_suffix, extension);
-                let file_path = format!("{}/{}", UPLOADS_DIR, unique_filename);
-
-                Self::stream_to_file(field, &file_path).await?;
-
-                main_unique_filename = Some(unique_filename);
-                main_raw_name = Some(raw_name);
-                main_content_type = Some(content_type);
+        if created_ids.is_empty() {
+            return Err(first_error
+                .unwrap_or_else(|| AppError::BadRequest("No document file provided".into())));
+        }
+
+        // Auto-post one PHOTO message (an album when several files) to the project's forum
+        if let Some(ref pid) = project_id {
+            if let Err(e) = crate::services::ForumService::create_album_message(
+                pool,
+                pid,
+                &created_ids,
+                &author_name,
+            )
+            .await
+            {
+                tracing::warn!("Failed to post upload to forum of project {}: {}", pid, e);
             }
         }
 
-        let unique_filename = main_unique_filename.ok_or_else(|| {
-            crate::error::AppError::BadRequest("No document file provided".into())
-        })?;
-        let raw_name = main_raw_name.unwrap();
-        let content_type = main_content_type.unwrap();
+        Ok(results)
+    }
+
+    /// Stream one uploaded file to staging, verify its content, scan it and store it
+    ///
+    /// A rejected file is no longer in the staging directory when this fails.
+    async fn store_upload(
+        pool: &DbPool,
+        field: Field<'_>,
+        source: DocumentSource,
+        author_name: &str,
+    ) -> AppResult<StoredUpload> {
+        let raw_name = field.file_name().unwrap_or("unknown").to_string();
+        let content_type = field
+            .content_type()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
 
-        let file_type = Self::categorize_mime_type(&content_type);
+        let extension = Path::new(&raw_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .or_else(|| FileTypeService::extension_for(&content_type))
+            .unwrap_or("bin");
+        let staged = StorageService::staging_path(extension);
 
-        let original_name = if Self::is_generic_filename(&raw_name) {
-            format!("Foto {}", now.format("%d-%m-%Y %H:%M"))
-        } else {
-            raw_name
+        let checksum = Self::stream_to_file(field, &staged).await?;
+
+        // Verify the real content and scan it before accepting the file
+        let check = match ContentService::check_file(&staged, &content_type).await {
+            Ok(check) => check,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&staged).await;
+                return Err(e);
+            }
         };
+        let origin = QuarantineOrigin {
+            original_name: &raw_name,
+            mime_type: Some(&content_type),
+            source,
+            uploaded_by: Some(author_name),
+            email: None,
+        };
+        let scanned = ScanService::vet_file(pool, &staged, &origin).await?;
+        let file_path = StorageService::content_key(&checksum, extension);
+        let file = StorageService::store_staged(&staged, &file_path).await?;
 
+        Ok(StoredUpload {
+            file,
+            check,
+            scanned,
+            checksum,
+        })
+    }
+
+    /// Create the document row for a stored upload and start its processing
+    ///
+    /// The row, its tags and its scan stamp are written in one transaction;
+    /// the stored files are only given up when that fails.
+    async fn record_upload(pool: &DbPool, upload: NewUpload<'_>) -> AppResult<Document> {
+        let NewUpload {
+            stored,
+            original_name,
+            project_id,
+            fields,
+            voice_memo,
+            uploaded_by,
+            source,
+        } = upload;
+        let file_type = FileTypeService::categorize(&stored.check.effective_mime, &original_name);
         let doc_id = Uuid::new_v4().to_string();
 
-        sqlx::query(
-            r#"
-            INSERT INTO documents (id, project_id, file_path, file_type, original_name, uploaded_at, audio_path)
-            VALUES (?, ?, ?, ?, ?, datetime('now'), ?)
-            "#
+        let inserted = async {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                r#"
+                INSERT INTO documents (id, project_id, file_path, file_type, original_name, uploaded_at, captured_at,
+                                       mime_type, detected_mime_type, checksum, notes, status, category,
+                                       audio_path, audio_format, audio_duration_seconds, uploaded_by, source)
+                VALUES (?, ?, ?, ?, ?, datetime('now'), ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#
+            )
+            .bind(&doc_id)
+            .bind(project_id)
+            .bind(stored.file.key())
+            .bind(&file_type)
+            .bind(&original_name)
+            .bind(&fields.captured_at)
+            .bind(&stored.check.claimed_mime)
+            .bind(&stored.check.detected_mime)
+            .bind(&stored.checksum)
+            .bind(&fields.notes)
+            .bind(fields.status.unwrap_or(DocumentStatus::Default).as_str())
+            .bind(&fields.category)
+            .bind(voice_memo.as_ref().map(|m| m.file.key()))
+            .bind(voice_memo.as_ref().map(|m| m.format.as_str()))
+            .bind(voice_memo.as_ref().and_then(|m| m.duration_seconds))
+            .bind(uploaded_by)
+            .bind(source.as_str())
+            .execute(&mut *tx)
+            .await?;
+
+            for tag in &fields.tags {
+                sqlx::query("INSERT OR IGNORE INTO document_tags (document_id, tag) VALUES (?, ?)")
+                    .bind(&doc_id)
+                    .bind(tag)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            if stored.scanned {
+                ScanService::mark_clean(&mut *tx, &doc_id).await?;
+            }
+            tx.commit().await?;
+            Ok::<_, AppError>(())
+        }
+        .await;
+
+        if let Err(e) = inserted {
+            let mut files = vec![stored.file.release()];
+            files.extend(voice_memo.map(|m| m.file.release()));
+            StorageService::remove_unreferenced(pool, &files).await;
+            return Err(e);
+        }
+        // The row refers to the files now
+        drop(stored);
+        drop(voice_memo);
+
+        // The document exists from here on; a job that can't be queued is
+        // retried with the reprocess endpoint instead of failing the upload
+        if file_type == "video" {
+            if let Err(e) = crate::services::VideoService::enqueue(pool, &doc_id).await {
+                tracing::warn!("Failed to queue video processing of {}: {}", doc_id, e);
+            }
+        }
+
+        let doc = Self::get_by_id(pool, &doc_id).await?;
+        crate::services::PreviewService::enqueue(pool, &doc);
+
+        Ok(doc)
+    }
+
+    /// Validate and store one document field sent with an upload
+    ///
+    /// Status and tags follow the same rules as their PATCH endpoints; tags may
+    /// be repeated or comma separated.
+    fn set_upload_field(fields: &mut UploadFields, name: &str, value: &str) -> AppResult<()> {
+        let value = value.trim();
+        let text = Some(value.to_string()).filter(|v| !v.is_empty());
+        match name {
+            "notes" => fields.notes = text,
+            "category" => fields.category = text,
+            "status" if value.is_empty() => fields.status = None,
+            "status" => {
+                fields.status = Some(DocumentStatus::parse(value).ok_or_else(|| {
+                    AppError::BadRequest(format!(
+                        "Invalid status '{}'. Use DEFAULT, DOUBT, IN_PROGRESS or COMPLETED",
+                        value
+                    ))
+                })?);
+            }
+            "tags" => {
+                let tags: Vec<String> = value.split(',').map(str::to_string).collect();
+                for tag in Self::normalize_tags(&tags)? {
+                    if !fields.tags.contains(&tag) {
+                        fields.tags.push(tag);
+                    }
+                }
+            }
+            "captured_at" if value.is_empty() => fields.captured_at = None,
+            "captured_at" => fields.captured_at = Some(Self::parse_capture_time(value)?),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Parse a device capture time: RFC 3339 or milliseconds since the epoch
+    ///
+    /// Returns it in UTC, in the same format as the other timestamps.
+    fn parse_capture_time(value: &str) -> AppResult<String> {
+        let time = match value.parse::<i64>() {
+            Ok(millis) => DateTime::<Utc>::from_timestamp_millis(millis),
+            Err(_) => DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|t| t.with_timezone(&Utc)),
+        }
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Invalid captured_at '{}'. Use an RFC 3339 timestamp or milliseconds since the epoch",
+                value
+            ))
+        })?;
+
+        // Allow for device clocks that are off, but not for nonsense
+        if time > Utc::now() + chrono::Duration::days(1) {
+            return Err(AppError::BadRequest(format!(
+                "captured_at '{}' is in the future",
+                value
+            )));
+        }
+        Ok(time.format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+
+    /// Split a multipart field name such as `notes[2]` into its name and index
+    fn split_field_index(field_name: &str) -> (&str, Option<usize>) {
+        field_name
+            .strip_suffix(']')
+            .and_then(|rest| rest.split_once('['))
+            .and_then(|(name, index)| Some((name, Some(index.parse().ok()?))))
+            .unwrap_or((field_name, None))
+    }
+
+    /// Attach a voice memo to an existing document, replacing any previous one
+    ///
+    /// Expects a multipart form with an `audio` file field. The old audio
+    /// file is removed from storage once the document points at the new one.
+    pub async fn set_voice_memo(
+        pool: &DbPool,
+        document_id: &str,
+        mut multipart: axum::extract::Multipart,
+    ) -> AppResult<Document> {
+        let doc = Self::get_by_id(pool, document_id).await?;
+
+        tokio::fs::create_dir_all(STAGING_DIR).await?;
+
+        let mut voice_memo = None;
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| AppError::BadRequest(e.to_string()))?
+        {
+            if field.name() == Some("audio") {
+                voice_memo = Some(Self::save_voice_memo(pool, field).await?);
+                break;
+            }
+        }
+        let memo =
+            voice_memo.ok_or_else(|| AppError::BadRequest("No audio file provided".into()))?;
+
+        let result = sqlx::query(
+            "UPDATE documents SET audio_path = ?, audio_format = ?, audio_duration_seconds = ? WHERE id = ?",
         )
-        .bind(&doc_id)
-        .bind(&project_id)
-        .bind(&unique_filename)
-        .bind(&file_type)
-        .bind(&original_name)
-        .bind(&audio_file_path)
+        .bind(memo.file.key())
+        .bind(memo.format.as_str())
+        .bind(memo.duration_seconds)
+        .bind(document_id)
         .execute(pool)
         .await?;
 
-        // Auto-post a PHOTO message to the project's forum
-        if let Some(ref pid) = project_id {
-            let _ = crate::services::ForumService::create_photo_message(
-                pool,
-                pid,
-                &doc_id,
-                &author_name,
-            )
-            .await;
+        if result.rows_affected() == 0 {
+            // Deleted while the upload was streaming
+            StorageService::remove_unreferenced(pool, &[memo.file.release()]).await;
+            return Err(AppError::NotFound(format!(
+                "Document with id '{}' not found",
+                document_id
+            )));
         }
 
-        Self::get_by_id(pool, &doc_id).await
+        if let Some(old) = doc.audio_path {
+            StorageService::remove_unreferenced(pool, &[old]).await;
+        }
+
+        Self::get_by_id(pool, document_id).await
+    }
+
+    /// Remove the voice memo of a document and delete its file
+    pub async fn remove_voice_memo(pool: &DbPool, document_id: &str) -> AppResult<Document> {
+        let doc = Self::get_by_id(pool, document_id).await?;
+        let old = doc.audio_path.ok_or_else(|| {
+            AppError::NotFound(format!("Document '{}' has no voice memo", document_id))
+        })?;
+
+        sqlx::query(
+            "UPDATE documents SET audio_path = NULL, audio_format = NULL, audio_duration_seconds = NULL WHERE id = ?",
+        )
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+
+        StorageService::remove_unreferenced(pool, &[old]).await;
+
+        Self::get_by_id(pool, document_id).await
+    }
+
+    /// Stream a voice memo field to storage, keyed with its detected format
+    ///
+    /// Voice memos and forum voice messages go through the same checks as
+    /// uploads: the file is removed again if the upload policy rejects it or
+    /// its content isn't a supported audio format, and quarantined if the
+    /// malware scanner flags it.
+    pub async fn save_voice_memo(pool: &DbPool, field: Field<'_>) -> AppResult<VoiceMemo> {
+        let original_name = field.file_name().unwrap_or("voice memo").to_string();
+        let content_type = field
+            .content_type()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        tokio::fs::create_dir_all(STAGING_DIR).await?;
+        let temp_path = StorageService::staging_path("part");
+        let checksum = Self::stream_to_file(field, &temp_path).await?;
+
+        let inspected = match ContentService::check_file(&temp_path, &content_type).await {
+            Ok(_) => AudioService::inspect(&temp_path).await,
+            Err(e) => Err(e),
+        };
+        let (format, duration_seconds) = match inspected {
+            Ok(inspected) => inspected,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                return Err(e);
+            }
+        };
+
+        let origin = QuarantineOrigin {
+            original_name: &original_name,
+            mime_type: Some(&content_type),
+            source: DocumentSource::App,
+            uploaded_by: None,
+            email: None,
+        };
+        ScanService::vet_file(pool, &temp_path, &origin).await?;
+
+        let file_path = StorageService::content_key(&checksum, format.as_str());
+        let file = StorageService::store_staged(&temp_path, &file_path).await?;
+
+        Ok(VoiceMemo {
+            file,
+            format,
+            duration_seconds,
+        })
     }
 
     /// Stream multipart field data directly to a file
     ///
     /// This function reads the upload in chunks and writes directly to disk,
-    /// preventing memory exhaustion for large files.
-    async fn stream_to_file(mut field: Field<'_>, path: &str) -> AppResult<()> {
+    /// preventing memory exhaustion for large files. Streams larger than the
+    /// biggest size allowed by the upload policy are aborted and removed.
+    /// Returns the SHA-256 checksum of the written content.
+    async fn stream_to_file(mut field: Field<'_>, path: &str) -> AppResult<String> {
         let mut file = File::create(path).await?;
+        let mut hasher = Sha256::new();
+        let max_size = ContentService::max_upload_size();
+        let mut written = 0u64;
 
         // Read and write in chunks (Axum's multipart handles chunking internally)
         while let Some(chunk) = field
@@ -162,6 +670,16 @@ impl DocumentService {
             .await
             .map_err(|e| AppError::Internal(format!("Failed to read upload chunk: {}", e)))?
         {
+            written += chunk.len() as u64;
+            if written > max_size {
+                drop(file);
+                let _ = tokio::fs::remove_file(path).await;
+                return Err(AppError::PayloadTooLarge(format!(
+                    "Uploads are limited to {} MB",
+                    max_size / (1024 * 1024)
+                )));
+            }
+            hasher.update(&chunk);
             file.write_all(&chunk).await?;
         }
 
@@ -169,29 +687,7 @@ impl DocumentService {
         file.flush().await?;
 
         tracing::debug!("File written to: {}", path);
-        Ok(())
-    }
-
-    /// Categorize MIME type into a simple file type category
-    fn categorize_mime_type(mime: &str) -> String {
-        if mime.starts_with("image/") {
-            "image".to_string()
-        } else if mime == "application/pdf" {
-            "pdf".to_string()
-        } else if mime.starts_with("video/") {
-            "video".to_string()
-        } else if mime == "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
-            || mime == "application/vnd.ms-excel"
-            || mime == "text/csv"
-        {
-            "excel".to_string()
-        } else if mime == "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
-            || mime == "application/msword"
-        {
-            "word".to_string()
-        } else {
-            "other".to_string()
-        }
+        Ok(format!("{:x}", hasher.finalize()))
     }
 
     /// Check if a filename is generic (from camera or unknown source)
@@ -219,6 +715,77 @@ impl DocumentService {
             .ok_or_else(|| AppError::NotFound(format!("Document with id '{}' not found", id)))
     }
 
+    /// Build a full response for a document, including comment count, tags and video info
+    pub async fn build_response(pool: &DbPool, doc: Document) -> AppResult<DocumentResponse> {
+        let mut responses = Self::build_responses(pool, vec![doc]).await?;
+        Ok(responses.remove(0))
+    }
+
+    /// Build full responses for a list of documents
+    ///
+    /// Counts are fetched with a single grouped query rather than one per document.
+    pub async fn build_responses(
+        pool: &DbPool,
+        docs: Vec<Document>,
+    ) -> AppResult<Vec<DocumentResponse>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; docs.len()].join(", ");
+        let query = format!(
+            "SELECT document_id, COUNT(*) FROM document_comments WHERE document_id IN ({}) GROUP BY document_id",
+            placeholders
+        );
+        let mut q = sqlx::query_as::<_, (String, i32)>(&query);
+        for doc in &docs {
+            q = q.bind(&doc.id);
+        }
+        let comment_counts: HashMap<String, i32> = q.fetch_all(pool).await?.into_iter().collect();
+
+        let query = format!(
+            "SELECT document_id, tag FROM document_tags WHERE document_id IN ({}) ORDER BY tag ASC",
+            placeholders
+        );
+        let mut q = sqlx::query_as::<_, (String, String)>(&query);
+        for doc in &docs {
+            q = q.bind(&doc.id);
+        }
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for (document_id, tag) in q.fetch_all(pool).await? {
+            tags.entry(document_id).or_default().push(tag);
+        }
+
+        let query = format!(
+            "SELECT * FROM video_metadata WHERE document_id IN ({})",
+            placeholders
+        );
+        let mut q = sqlx::query_as::<_, VideoMetadata>(&query);
+        for doc in &docs {
+            q = q.bind(&doc.id);
+        }
+        let mut videos: HashMap<String, VideoMetadata> = q
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|v| (v.document_id.clone(), v))
+            .collect();
+
+        Ok(docs
+            .into_iter()
+            .map(|doc| {
+                let comment_count = comment_counts.get(&doc.id).copied().unwrap_or(0);
+                let doc_tags = tags.remove(&doc.id).unwrap_or_default();
+                let video = videos.remove(&doc.id).map(VideoInfoResponse::from);
+                let mut response = DocumentResponse::from_document(doc);
+                response.comment_count = comment_count;
+                response.tags = doc_tags;
+                response.video = video;
+                response
+            })
+            .collect())
+    }
+
     /// List all documents in the Inbox (unassigned to any project)
     ///
     /// Documents in the Inbox are those with NULL project_id.
@@ -256,37 +823,26 @@ impl DocumentService {
         document_id: &str,
         project_id: Option<&str>,
         category: Option<&str>,
+        actor: Option<&str>,
     ) -> AppResult<Document> {
         // If assigning to a project, verify the project exists
         if let Some(pid) = project_id {
-            let project_exists: Option<(i32,)> =
-                sqlx::query_as("SELECT 1 FROM projects WHERE id = ?")
-                    .bind(pid)
-                    .fetch_optional(pool)
-                    .await?;
-
-            if project_exists.is_none() {
-                return Err(AppError::NotFound(format!(
-                    "Project with id '{}' not found",
-                    pid
-                )));
-            }
+            Self::ensure_project_exists(pool, pid).await?;
         }
 
+        let mut tx = pool.begin().await?;
+        let before = Self::fetch_for_update(&mut tx, document_id).await?;
+
         // Update the document's project assignment and category
-        let result = sqlx::query("UPDATE documents SET project_id = ?, category = ? WHERE id = ?")
+        sqlx::query("UPDATE documents SET project_id = ?, category = ? WHERE id = ?")
             .bind(project_id)
             .bind(category)
             .bind(document_id)
-            .execute(pool)
+            .execute(&mut *tx)
             .await?;
+        Self::record_changes(&mut tx, &before, actor).await?;
 
-        if result.rows_affected() == 0 {
-            return Err(AppError::NotFound(format!(
-                "Document with id '{}' not found",
-                document_id
-            )));
-        }
+        tx.commit().await?;
 
         // Fetch and return the updated document
         Self::get_by_id(pool, document_id).await
@@ -295,10 +851,11 @@ impl DocumentService {
     /// Delete a document and its associated file
     ///
     /// This permanently removes the document from the database
-    /// and deletes the file from disk.
+    /// and deletes its files from storage.
     pub async fn delete(pool: &DbPool, document_id: &str) -> AppResult<()> {
-        // First get the document to find the file path
+        // First get the document to find the file paths
         let doc = Self::get_by_id(pool, document_id).await?;
+        let files = Self::stored_files(pool, &doc).await?;
 
         // Delete from database
         let result = sqlx::query("DELETE FROM documents WHERE id = ?")
@@ -313,17 +870,35 @@ impl DocumentService {
             )));
         }
 
-        // Delete file from disk (best effort - don't fail if file is missing)
-        let file_path = format!("{}/{}", UPLOADS_DIR, doc.file_path);
-        if let Err(e) = tokio::fs::remove_file(&file_path).await {
-            tracing::warn!("Failed to delete file {}: {}", file_path, e);
-        } else {
-            tracing::debug!("Deleted file: {}", file_path);
-        }
+        StorageService::remove_unreferenced(pool, &files).await;
 
         Ok(())
     }
 
+    /// List every stored file that belongs to a document:
+    /// the original, its voice memo and derived video files
+    pub async fn stored_files<'e, E>(executor: E, doc: &Document) -> AppResult<Vec<String>>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        let mut files = vec![doc.file_path.clone()];
+        files.extend(doc.audio_path.clone());
+        files.extend(doc.preview_path.clone());
+
+        let derived: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT poster_path, transcoded_path FROM video_metadata WHERE document_id = ?",
+        )
+        .bind(&doc.id)
+        .fetch_optional(executor)
+        .await?;
+        if let Some((poster, transcoded)) = derived {
+            files.extend(poster);
+            files.extend(transcoded);
+        }
+
+        Ok(files)
+    }
+
     /// Update the notes for a document
     ///
     /// Notes allow users to annotate documents with context or reminders.
@@ -353,19 +928,19 @@ impl DocumentService {
         pool: &DbPool,
         document_id: &str,
         status: &str,
+        actor: Option<&str>,
     ) -> AppResult<Document> {
-        let result = sqlx::query("UPDATE documents SET status = ? WHERE id = ?")
+        let mut tx = pool.begin().await?;
+        let before = Self::fetch_for_update(&mut tx, document_id).await?;
+
+        sqlx::query("UPDATE documents SET status = ? WHERE id = ?")
             .bind(status)
             .bind(document_id)
-            .execute(pool)
+            .execute(&mut *tx)
             .await?;
+        Self::record_changes(&mut tx, &before, actor).await?;
 
-        if result.rows_affected() == 0 {
-            return Err(AppError::NotFound(format!(
-                "Document with id '{}' not found",
-                document_id
-            )));
-        }
+        tx.commit().await?;
 
         Self::get_by_id(pool, document_id).await
     }
@@ -375,19 +950,19 @@ impl DocumentService {
         pool: &DbPool,
         document_id: &str,
         category: Option<&str>,
+        actor: Option<&str>,
     ) -> AppResult<Document> {
-        let result = sqlx::query("UPDATE documents SET category = ? WHERE id = ?")
+        let mut tx = pool.begin().await?;
+        let before = Self::fetch_for_update(&mut tx, document_id).await?;
+
+        sqlx::query("UPDATE documents SET category = ? WHERE id = ?")
             .bind(category)
             .bind(document_id)
-            .execute(pool)
+            .execute(&mut *tx)
             .await?;
+        Self::record_changes(&mut tx, &before, actor).await?;
 
-        if result.rows_affected() == 0 {
-            return Err(AppError::NotFound(format!(
-                "Document with id '{}' not found",
-                document_id
-            )));
-        }
+        tx.commit().await?;
 
         Self::get_by_id(pool, document_id).await
     }
@@ -397,6 +972,7 @@ impl DocumentService {
         pool: &DbPool,
         document_ids: &[String],
         project_id: Option<&str>,
+        actor: Option<&str>,
     ) -> AppResult<Vec<Document>> {
         if document_ids.is_empty() {
             return Ok(Vec::new());
@@ -404,28 +980,26 @@ impl DocumentService {
 
         // Verify project exists if assigning
         if let Some(pid) = project_id {
-            let project_exists: Option<(i32,)> =
-                sqlx::query_as("SELECT 1 FROM projects WHERE id = ?")
-                    .bind(pid)
-                    .fetch_optional(pool)
-                    .await?;
-
-            if project_exists.is_none() {
-                return Err(AppError::NotFound(format!(
-                    "Project with id '{}' not found",
-                    pid
-                )));
-            }
+            Self::ensure_project_exists(pool, pid).await?;
         }
 
         let mut tx = pool.begin().await?;
 
         for doc_id in document_ids {
+            let before = sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id = ?")
+                .bind(doc_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let Some(before) = before else {
+                continue;
+            };
+
             sqlx::query("UPDATE documents SET project_id = ? WHERE id = ?")
                 .bind(project_id)
                 .bind(doc_id)
                 .execute(&mut *tx)
                 .await?;
+            Self::record_changes(&mut tx, &before, actor).await?;
         }
 
         tx.commit().await?;
@@ -443,4 +1017,261 @@ impl DocumentService {
 
         Ok(docs)
     }
+
+    /// Apply one operation to many documents in a single transaction
+    ///
+    /// Unlike `batch_assign_to_project`, every ID gets an explicit result:
+    /// unknown documents are reported as failures instead of being skipped.
+    /// Successful changes are committed together; database errors roll back
+    /// the whole batch.
+    pub async fn bulk_operation(
+        pool: &DbPool,
+        document_ids: &[String],
+        operation: &BulkOperation,
+        actor: Option<&str>,
+    ) -> AppResult<Vec<BulkItemResult>> {
+        // Validate the operation once, before touching any document
+        let tags = match operation {
+            BulkOperation::Assign { project_id, .. } => {
+                Self::ensure_project_exists(pool, project_id).await?;
+                Vec::new()
+            }
+            BulkOperation::AddTags { tags } | BulkOperation::RemoveTags { tags } => {
+                let tags = Self::normalize_tags(tags)?;
+                if tags.is_empty() {
+                    return Err(AppError::BadRequest("No tags provided".into()));
+                }
+                tags
+            }
+            _ => Vec::new(),
+        };
+
+        // Keep the first occurrence of each ID, preserving request order
+        let mut seen = std::collections::HashSet::new();
+        let ids: Vec<&String> = document_ids.iter().filter(|id| seen.insert(*id)).collect();
+
+        let mut tx = pool.begin().await?;
+        let mut outcomes: Vec<(String, BulkOutcome)> = Vec::new();
+
+        for id in ids {
+            let existing = sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            let Some(doc) = existing else {
+                outcomes.push((id.clone(), BulkOutcome::Failed("Document not found".into())));
+                continue;
+            };
+
+            match operation {
+                BulkOperation::Assign {
+                    project_id,
+                    category,
+                } => {
+                    sqlx::query("UPDATE documents SET project_id = ?, category = COALESCE(?, category) WHERE id = ?")
+                        .bind(project_id)
+                        .bind(category)
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                BulkOperation::SetStatus { status } => {
+                    sqlx::query("UPDATE documents SET status = ? WHERE id = ?")
+                        .bind(status.as_str())
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                BulkOperation::SetCategory { category } => {
+                    sqlx::query("UPDATE documents SET category = ? WHERE id = ?")
+                        .bind(category)
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                BulkOperation::AddTags { .. } => {
+                    for tag in &tags {
+                        sqlx::query(
+                            "INSERT OR IGNORE INTO document_tags (document_id, tag) VALUES (?, ?)",
+                        )
+                        .bind(id)
+                        .bind(tag)
+                        .execute(&mut *tx)
+                        .await?;
+                    }
+                }
+                BulkOperation::RemoveTags { .. } => {
+                    for tag in &tags {
+                        sqlx::query("DELETE FROM document_tags WHERE document_id = ? AND tag = ?")
+                            .bind(id)
+                            .bind(tag)
+                            .execute(&mut *tx)
+                            .await?;
+                    }
+                }
+                BulkOperation::Delete => {
+                    let files = Self::stored_files(&mut *tx, &doc).await?;
+                    sqlx::query("DELETE FROM documents WHERE id = ?")
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                    outcomes.push((id.clone(), BulkOutcome::Deleted(files)));
+                    continue;
+                }
+                BulkOperation::MoveToInbox => {
+                    sqlx::query("UPDATE documents SET project_id = NULL WHERE id = ?")
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+            Self::record_changes(&mut tx, &doc, actor).await?;
+
+            outcomes.push((id.clone(), BulkOutcome::Updated));
+        }
+
+        tx.commit().await?;
+
+        let mut results = Vec::with_capacity(outcomes.len());
+        for (document_id, outcome) in outcomes {
+            let result = match outcome {
+                // Deleted: files are only removed once the rows are gone for good
+                BulkOutcome::Deleted(files) => {
+                    StorageService::remove_unreferenced(pool, &files).await;
+                    BulkItemResult {
+                        document_id,
+                        success: true,
+                        error: None,
+                        document: None,
+                    }
+                }
+                BulkOutcome::Updated => {
+                    let doc = Self::get_by_id(pool, &document_id).await?;
+                    BulkItemResult {
+                        document_id,
+                        success: true,
+                        error: None,
+                        document: Some(Self::build_response(pool, doc).await?),
+                    }
+                }
+                BulkOutcome::Failed(error) => BulkItemResult {
+                    document_id,
+                    success: false,
+                    error: Some(error),
+                    document: None,
+                },
+            };
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Load a document inside a transaction, NotFound if it doesn't exist
+    async fn fetch_for_update(
+        tx: &mut Transaction<'_, Sqlite>,
+        document_id: &str,
+    ) -> AppResult<Document> {
+        sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id = ?")
+            .bind(document_id)
+            .fetch_optional(&mut **tx)
+            .await?
+            .ok_or_else(|| {
+                AppError::NotFound(format!("Document with id '{}' not found", document_id))
+            })
+    }
+
+    /// Record the assignment, status and category changes made to a document
+    ///
+    /// Compares `before` with the row as it is now; unchanged values are not
+    /// recorded. Events carry the project the document ended up in, so moves
+    /// show up in the destination project's activity.
+    async fn record_changes(
+        tx: &mut Transaction<'_, Sqlite>,
+        before: &Document,
+        actor: Option<&str>,
+    ) -> AppResult<()> {
+        let after = Self::fetch_for_update(tx, &before.id).await?;
+        let actor = actor.map(str::trim).filter(|a| !a.is_empty());
+
+        let changes = [
+            (
+                DocumentEventType::Assigned,
+                before.project_id.as_deref(),
+                after.project_id.as_deref(),
+            ),
+            (
+                DocumentEventType::Status,
+                Some(before.status.as_str()),
+                Some(after.status.as_str()),
+            ),
+            (
+                DocumentEventType::Category,
+                before.category.as_deref(),
+                after.category.as_deref(),
+            ),
+        ];
+
+        for (event_type, from, to) in changes {
+            if from == to {
+                continue;
+            }
+            sqlx::query(
+                r#"
+                INSERT INTO document_events
+                    (id, document_id, event_type, from_value, to_value, project_id, actor)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&before.id)
+            .bind(event_type.as_str())
+            .bind(from)
+            .bind(to)
+            .bind(&after.project_id)
+            .bind(actor)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Trim, deduplicate and validate a list of tags
+    pub fn normalize_tags(tags: &[String]) -> AppResult<Vec<String>> {
+        let mut normalized: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = tag.trim();
+            if tag.is_empty() {
+                continue;
+            }
+            if tag.chars().count() > MAX_TAG_LENGTH {
+                return Err(AppError::BadRequest(format!(
+                    "Tag '{}' is longer than {} characters",
+                    tag, MAX_TAG_LENGTH
+                )));
+            }
+            if !normalized.iter().any(|t| t == tag) {
+                normalized.push(tag.to_string());
+            }
+        }
+        Ok(normalized)
+    }
+
+    /// Return NotFound unless the project exists
+    async fn ensure_project_exists(pool: &DbPool, project_id: &str) -> AppResult<()> {
+        let project_exists: Option<(i32,)> = sqlx::query_as("SELECT 1 FROM projects WHERE id = ?")
+            .bind(project_id)
+            .fetch_optional(pool)
+            .await?;
+
+        if project_exists.is_none() {
+            return Err(AppError::NotFound(format!(
+                "Project with id '{}' not found",
+                project_id
+            )));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,293 @@
+//! Download service module
+//!
+//! Builds ZIP archives of documents for handing an obra's files to an
+//! architect or accountant in one go.
+//!
+//! # Architecture Decision
+//! Archives are streamed: a background task writes the ZIP into one end of an
+//! in-memory pipe while the HTTP response body reads from the other end, so
+//! only a small buffer is held in memory regardless of archive size. Photos
+//! and PDFs are already compressed, so files are stored as-is; only the CSV
+//! manifest is deflated.
+
+use std::collections::HashSet;
+
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::body::Body;
+use futures::AsyncWriteExt as _;
+use tokio::io::AsyncWrite;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::Document;
+use crate::services::{ColdStorageService, DocumentService, ProjectService, StorageService};
+
+/// Size of the in-memory pipe between the ZIP writer and the response body
+const PIPE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Folder used for documents without a category/room
+const UNCATEGORIZED_FOLDER: &str = "Sem categoria";
+
+/// Folder used for documents still in the Inbox
+const INBOX_FOLDER: &str = "Inbox";
+
+/// A file to be placed in the archive
+struct ArchiveEntry {
+    /// Path inside the ZIP (folder/filename)
+    zip_path: String,
+    /// Storage key
+    key: String,
+}
+
+/// Download service building streamed ZIP archives
+pub struct DownloadService;
+
+impl DownloadService {
+    /// Build a ZIP of all documents of a project, one folder per category/room
+    ///
+    /// Returns the suggested archive filename and a streaming body.
+    pub async fn project_zip(pool: &DbPool, project_id: &str) -> AppResult<(String, Body)> {
+        let project = ProjectService::get_by_id(pool, project_id).await?;
+        let docs = DocumentService::list_by_project(pool, project_id).await?;
+
+        let folders = docs.iter().map(Self::category_folder).collect();
+        let body = Self::stream(pool, docs, folders).await?;
+
+        Ok((format!("{}.zip", Self::sanitize(&project.name)), body))
+    }
+
+    /// Build a ZIP of a selection of documents, one folder per project/category
+    pub async fn documents_zip(
+        pool: &DbPool,
+        document_ids: &[String],
+    ) -> AppResult<(String, Body)> {
+        if document_ids.is_empty() {
+            return Err(AppError::BadRequest("No documents selected".into()));
+        }
+
+        let mut docs = Vec::new();
+        let mut seen = HashSet::new();
+        for id in document_ids {
+            if seen.insert(id) {
+                docs.push(DocumentService::get_by_id(pool, id).await?);
+            }
+        }
+
+        let mut folders = Vec::with_capacity(docs.len());
+        for doc in &docs {
+            let project_folder = match &doc.project_id {
+                Some(pid) => ProjectService::get_by_id(pool, pid)
+                    .await
+                    .map(|p| Self::sanitize(&p.name))
+                    .unwrap_or_else(|_| INBOX_FOLDER.to_string()),
+                None => INBOX_FOLDER.to_string(),
+            };
+            folders.push(format!("{}/{}", project_folder, Self::category_folder(doc)));
+        }
+
+        let filename = format!(
+            "documentos_{}.zip",
+            chrono::Local::now().format("%Y-%m-%d_%H-%M")
+        );
+        Ok((filename, Self::stream(pool, docs, folders).await?))
+    }
+
+    /// Plan the archive layout and start streaming it
+    ///
+    /// `folders[i]` is the archive folder for `docs[i]`.
+    async fn stream(pool: &DbPool, docs: Vec<Document>, folders: Vec<String>) -> AppResult<Body> {
+        let mut used_paths = HashSet::new();
+        let mut entries = Vec::new();
+        let mut manifest = String::from(
+            "file,original_name,category,status,uploaded_at,uploaded_by,tags,notes,voice_memo\n",
+        );
+
+        ColdStorageService::restore_documents(pool, &docs).await?;
+        let responses = DocumentService::build_responses(pool, docs).await?;
+
+        for (doc, folder) in responses.into_iter().zip(folders) {
+            let file_name = Self::display_filename(&doc.original_name, &doc.file_path);
+            let zip_path = Self::unique_path(&mut used_paths, &folder, &file_name);
+
+            let file_cell = if StorageService::exists(&doc.file_path).await? {
+                entries.push(ArchiveEntry {
+                    zip_path: zip_path.clone(),
+                    key: doc.file_path.clone(),
+                });
+                zip_path.clone()
+            } else {
+                tracing::warn!("Skipping missing file for document {}", doc.id);
+                format!("(missing) {}", zip_path)
+            };
+
+            // Voice memo goes next to its document
+            let mut memo_cell = String::new();
+            if let Some(audio) = &doc.audio_path {
+                if StorageService::exists(audio).await? {
+                    let stem = file_name
+                        .rsplit_once('.')
+                        .map(|(stem, _)| stem)
+                        .unwrap_or(&file_name);
+                    let ext = audio.rsplit_once('.').map(|(_, e)| e).unwrap_or("webm");
+                    let memo_name = format!("{} - memo.{}", stem, ext);
+                    let memo_path = Self::unique_path(&mut used_paths, &folder, &memo_name);
+                    memo_cell = memo_path.clone();
+                    entries.push(ArchiveEntry {
+                        zip_path: memo_path,
+                        key: audio.clone(),
+                    });
+                }
+            }
+
+            let row = [
+                file_cell.as_str(),
+                doc.original_name.as_str(),
+                doc.category.as_deref().unwrap_or(""),
+                doc.status.as_str(),
+                doc.uploaded_at.as_str(),
+                doc.uploaded_by.as_deref().unwrap_or(""),
+                &doc.tags.join("; "),
+                doc.notes.as_deref().unwrap_or(""),
+                memo_cell.as_str(),
+            ]
+            .iter()
+            .map(|field| Self::csv_field(field))
+            .collect::<Vec<_>>()
+            .join(",");
+            manifest.push_str(&row);
+            manifest.push('\n');
+        }
+
+        let (writer, reader) = tokio::io::duplex(PIPE_BUFFER_SIZE);
+        tokio::spawn(async move {
+            if let Err(e) = Self::write_zip(writer, entries, manifest).await {
+                // The client sees a truncated download; nothing else we can do mid-stream
+                tracing::error!("Failed to stream ZIP archive: {}", e);
+            }
+        });
+
+        Ok(Body::from_stream(ReaderStream::new(reader)))
+    }
+
+    /// Write all entries and the manifest into a ZIP stream
+    async fn write_zip<W: AsyncWrite + Unpin>(
+        writer: W,
+        entries: Vec<ArchiveEntry>,
+        manifest: String,
+    ) -> Result<(), String> {
+        let mut zip = ZipFileWriter::with_tokio(writer);
+
+        for entry in entries {
+            let object = StorageService::backend()
+                .get(&entry.key)
+                .await
+                .map_err(|e| format!("open {}: {}", entry.key, e))?;
+
+            let builder = ZipEntryBuilder::new(entry.zip_path.into(), Compression::Stored);
+            let mut entry_writer = zip
+                .write_entry_stream(builder)
+                .await
+                .map_err(|e| e.to_string())?;
+            futures::io::copy(object.reader.compat(), &mut entry_writer)
+                .await
+                .map_err(|e| format!("copy {}: {}", entry.key, e))?;
+            entry_writer.close().await.map_err(|e| e.to_string())?;
+        }
+
+        let builder = ZipEntryBuilder::new("manifest.csv".into(), Compression::Deflate);
+        let mut entry_writer = zip
+            .write_entry_stream(builder)
+            .await
+            .map_err(|e| e.to_string())?;
+        // UTF-8 BOM so spreadsheet apps pick up accents correctly
+        entry_writer
+            .write_all(b"\xEF\xBB\xBF")
+            .await
+            .map_err(|e| e.to_string())?;
+        entry_writer
+            .write_all(manifest.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        entry_writer.close().await.map_err(|e| e.to_string())?;
+
+        zip.close().await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Archive folder for a document's category/room
+    pub fn category_folder(doc: &Document) -> String {
+        doc.category
+            .as_deref()
+            .map(Self::sanitize)
+            .filter(|c| !c.is_empty())
+            .unwrap_or_else(|| UNCATEGORIZED_FOLDER.to_string())
+    }
+
+    /// Human-readable filename, keeping the stored extension if the original lacks one
+    pub fn display_filename(original_name: &str, file_path: &str) -> String {
+        let name = Self::sanitize(original_name);
+        let name = if name.is_empty() {
+            "documento".to_string()
+        } else {
+            name
+        };
+
+        let has_extension = name
+            .rsplit_once('.')
+            .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty() && ext.len() <= 5);
+        match file_path.rsplit_once('.') {
+            Some((_, ext)) if !has_extension => format!("{}.{}", name, ext),
+            _ => name,
+        }
+    }
+
+    /// Make `folder/name` unique within the archive by appending " (2)", " (3)", ...
+    pub fn unique_path(used: &mut HashSet<String>, folder: &str, name: &str) -> String {
+        let candidate = format!("{}/{}", folder, name);
+        if used.insert(candidate.to_lowercase()) {
+            return candidate;
+        }
+
+        let (stem, ext) = match name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+            _ => (name, String::new()),
+        };
+        (2..)
+            .map(|n| format!("{}/{} ({}){}", folder, stem, n, ext))
+            .find(|candidate| used.insert(candidate.to_lowercase()))
+            .expect("unbounded range always yields a free name")
+    }
+
+    /// Strip path separators and control characters from a name
+    pub fn sanitize(name: &str) -> String {
+        let cleaned: String = name
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        cleaned.trim().trim_matches('.').trim().to_string()
+    }
+
+    /// Quote a CSV field when it contains separators, quotes or newlines
+    ///
+    /// Cells a spreadsheet would read as a formula get a leading `'`, since
+    /// names and notes come from users.
+    fn csv_field(value: &str) -> String {
+        let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+            format!("'{}", value)
+        } else {
+            value.to_string()
+        };
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value
+        }
+    }
+}
@@ -3,16 +3,16 @@
 //! Handles processing of inbound emails from webhook services like Mailgun and SendGrid.
 //! Extracts attachments and creates documents based on routing rules.
 
+use axum::body::Bytes;
 use axum::extract::Multipart;
-use chrono::Utc;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
 
 use crate::db::DbPool;
 use crate::error::{AppError, AppResult};
-use crate::models::{Document, EmailFilter, EmailRule};
-use crate::services::document_service::UPLOADS_DIR;
+use crate::models::{Document, DocumentSource, EmailFilter, EmailProvenance, EmailRule};
+use crate::services::content_service::ContentCheck;
+use crate::services::scan_service::{QuarantineOrigin, ScanOutcome};
+use crate::services::{ClientService, ContentService, FileTypeService, IntegrityService, PreviewService, ScanService, StorageService};
 
 /// Result of processing an inbound email
 #[allow(dead_code)]
@@ -21,6 +21,20 @@ pub struct EmailProcessingResult {
     pub subject: String,
     pub documents_created: usize,
     pub documents_filtered: usize,
+    /// Attachments refused by the upload content policy
+    pub documents_rejected: usize,
+    /// Attachments held back by the malware scanner
+    pub documents_quarantined: usize,
+    /// "filename: signature" (or scan error) for each quarantined attachment
+    pub threats: Vec<String>,
+}
+
+/// Where an email's attachments are filed
+struct Routing {
+    /// Target project, None for the Inbox
+    project_id: Option<String>,
+    /// Client the sender's address belongs to
+    client_id: Option<String>,
 }
 
 /// Service for handling email-related operations
@@ -158,8 +172,7 @@ impl EmailService {
             let pattern = rule.sender_pattern.to_lowercase();
             
             // Support wildcard patterns like "*@domain.com"
-            if pattern.starts_with('*') {
-                let suffix = &pattern[1..];
+            if let Some(suffix) = pattern.strip_prefix('*') {
                 if sender_lower.ends_with(suffix) {
                     return Ok(Some(rule));
                 }
@@ -187,12 +200,10 @@ impl EmailService {
         
         for filter in filters {
             match filter.filter_type.as_str() {
-                "filename" => {
-                    // Check if filename contains the pattern
-                    if filename_lower.contains(&filter.pattern.to_lowercase()) {
-                        tracing::debug!("Filtering '{}': matches filename pattern '{}'", filename, filter.pattern);
-                        return Ok(true);
-                    }
+                // Check if filename contains the pattern
+                "filename" if filename_lower.contains(&filter.pattern.to_lowercase()) => {
+                    tracing::debug!("Filtering '{}': matches filename pattern '{}'", filename, filter.pattern);
+                    return Ok(true);
                 }
                 "extension" => {
                     // Check file extension
@@ -233,8 +244,12 @@ impl EmailService {
         let mut sender = String::new();
         let mut subject = String::new();
         let mut body = String::new();
+        let mut message_id: Option<String> = None;
         let mut documents_created = 0;
         let mut documents_filtered = 0;
+        let mut documents_rejected = 0;
+        let mut documents_quarantined = 0;
+        let mut threats = Vec::new();
 
         // Collect attachments to process after we have metadata
         let mut attachments: Vec<(String, String, Vec<u8>)> = Vec::new();
@@ -255,6 +270,20 @@ impl EmailService {
                 "subject" => {
                     subject = field.text().await.unwrap_or_default();
                 }
+                // Mailgun sends the Message-Id as its own field
+                "Message-Id" | "message-id" => {
+                    let text = field.text().await.unwrap_or_default();
+                    if !text.trim().is_empty() {
+                        message_id = Some(text.trim().to_string());
+                    }
+                }
+                // SendGrid only sends the raw header block
+                "headers" => {
+                    let headers = field.text().await.unwrap_or_default();
+                    if message_id.is_none() {
+                        message_id = Self::extract_message_id(&headers);
+                    }
+                }
                 "body-plain" | "text" | "body-html" => {
                     if body.is_empty() {
                         body = field.text().await.unwrap_or_default();
@@ -320,7 +349,17 @@ impl EmailService {
             sender, subject
         );
 
-        // Find matching routing rule for this sender
+        let provenance = EmailProvenance {
+            sender: Some(sender.clone()),
+            subject: Some(subject.clone()),
+            message_id,
+        };
+
+        // Attachments remember the client whose address sent them
+        let client_id = ClientService::match_sender(pool, &sender).await?;
+
+        // Find matching routing rule for this sender; without one, a known
+        // client's mail goes to their project if they have a single active one
         let target_project_id = match Self::find_matching_rule(pool, &sender).await? {
             Some(rule) => {
                 tracing::info!(
@@ -332,10 +371,26 @@ impl EmailService {
                 rule.project_id
             }
             None => {
-                tracing::debug!("No routing rule matches sender '{}', going to Inbox", sender);
-                None
+                let project_id = match &client_id {
+                    Some(client_id) => ClientService::sole_active_project(pool, client_id).await?,
+                    None => None,
+                };
+                match &project_id {
+                    Some(project_id) => tracing::info!(
+                        "Email from '{}' matches client {:?} -> project {}",
+                        sender,
+                        client_id,
+                        project_id
+                    ),
+                    None => tracing::debug!("No routing rule matches sender '{}', going to Inbox", sender),
+                }
+                project_id
             }
         };
+        let routing = Routing {
+            project_id: target_project_id,
+            client_id,
+        };
 
         // Process each attachment
         for (filename, content_type, data) in attachments {
@@ -345,9 +400,45 @@ impl EmailService {
                 tracing::info!("Filtered out attachment: {} ({} bytes)", filename, data.len());
                 continue;
             }
+
+            // Verify the real content type and size against the upload policy
+            let check = match ContentService::check(&content_type, &data) {
+                Ok(check) => check,
+                Err(e) => {
+                    documents_rejected += 1;
+                    tracing::warn!("Rejected attachment '{}': {}", filename, e);
+                    continue;
+                }
+            };
+
+            // Scan for malware; infected or unscannable attachments go to quarantine
+            let scan = ScanService::scan_bytes(&data).await;
+            let held_back = match &scan {
+                Ok(ScanOutcome::Infected(signature)) => Some((Some(signature.as_str()), "Malware detected".to_string())),
+                Ok(_) => None,
+                Err(e) => Some((None, e.to_string())),
+            };
+            if let Some((signature, reason)) = held_back {
+                let origin = QuarantineOrigin {
+                    original_name: &filename,
+                    mime_type: Some(&check.claimed_mime),
+                    source: DocumentSource::Email,
+                    uploaded_by: Some(&sender),
+                    email: Some(&provenance),
+                };
+                if let Err(e) = ScanService::quarantine_bytes(pool, &data, &origin, signature, &reason).await {
+                    tracing::error!("Failed to quarantine attachment '{}': {}", filename, e);
+                }
+                documents_quarantined += 1;
+                threats.push(format!("{}: {}", filename, signature.unwrap_or(&reason)));
+                continue;
+            }
             
-            match Self::save_attachment(pool, &filename, &content_type, &data, &notes, target_project_id.as_deref()).await {
-                Ok(_) => {
+            match Self::save_attachment(pool, &filename, &check, &data, &notes, &provenance, &routing).await {
+                Ok(doc) => {
+                    if scan.is_ok_and(|outcome| outcome == ScanOutcome::Clean) {
+                        ScanService::mark_clean(pool, &doc.id).await?;
+                    }
                     documents_created += 1;
                     tracing::info!("Saved email attachment: {}", filename);
                 }
@@ -368,6 +459,9 @@ impl EmailService {
             subject,
             documents_created,
             documents_filtered,
+            documents_rejected,
+            documents_quarantined,
+            threats,
         })
     }
 
@@ -375,99 +469,86 @@ impl EmailService {
     async fn save_attachment(
         pool: &DbPool,
         original_name: &str,
-        content_type: &str,
+        check: &ContentCheck,
         data: &[u8],
         notes: &str,
-        project_id: Option<&str>,
+        provenance: &EmailProvenance,
+        routing: &Routing,
     ) -> AppResult<Document> {
-        // Generate unique filename with date prefix
-        let extension = Self::get_extension(original_name, content_type);
-        let date_prefix = Utc::now().format("%Y-%m-%d_%H-%M-%S");
-        let short_uuid = &Uuid::new_v4().to_string()[..4];
-        let safe_filename = format!("{}_{}.{}", date_prefix, short_uuid, extension);
-
-        // Write file to disk
-        let file_path = format!("{}/{}", UPLOADS_DIR, safe_filename);
-        let mut file = File::create(&file_path)
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to create file: {}", e)))?;
-
-        file.write_all(data)
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to write file: {}", e)))?;
-
-        file.flush().await?;
+        // Store under the content's checksum
+        let extension = Self::get_extension(original_name, &check.effective_mime);
+        let stored = StorageService::store_bytes(Bytes::copy_from_slice(data), &extension).await?;
 
         // Determine file type
-        let file_type = Self::categorize_content_type(content_type);
+        let file_type = FileTypeService::categorize(&check.effective_mime, original_name);
 
         // Create document record (with project_id if routing rule matched)
         let id = Uuid::new_v4().to_string();
         sqlx::query(
             r#"
-            INSERT INTO documents (id, project_id, file_path, file_type, original_name, notes)
-            VALUES (?, ?, ?, ?, ?, ?)
+            INSERT INTO documents (id, project_id, file_path, file_type, original_name, mime_type, detected_mime_type, checksum,
+                                   notes, uploaded_by, source, email_sender, email_subject, email_message_id, client_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&id)
-        .bind(project_id)
-        .bind(&safe_filename)
+        .bind(&routing.project_id)
+        .bind(stored.key())
         .bind(&file_type)
         .bind(original_name)
+        .bind(&check.claimed_mime)
+        .bind(&check.detected_mime)
+        .bind(IntegrityService::checksum(data))
         .bind(notes)
+        .bind(&provenance.sender)
+        .bind(DocumentSource::Email.as_str())
+        .bind(&provenance.sender)
+        .bind(&provenance.subject)
+        .bind(&provenance.message_id)
+        .bind(&routing.client_id)
         .execute(pool)
         .await?;
 
+        if file_type == "video" {
+            crate::services::VideoService::enqueue(pool, &id).await?;
+        }
+
         // Fetch and return the created document
         let doc = sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE id = ?")
             .bind(&id)
             .fetch_one(pool)
             .await?;
 
+        PreviewService::enqueue(pool, &doc);
+
         Ok(doc)
     }
 
+    /// Extract the Message-ID value from a raw email header block
+    fn extract_message_id(headers: &str) -> Option<String> {
+        headers.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            if name.trim().eq_ignore_ascii_case("message-id") {
+                let value = value.trim();
+                (!value.is_empty()).then(|| value.to_string())
+            } else {
+                None
+            }
+        })
+    }
+
     /// Get file extension from filename or content type
     fn get_extension(filename: &str, content_type: &str) -> String {
         // Try to get extension from filename first
-        if let Some(ext) = filename.rsplit('.').next() {
-            if !ext.contains('/') && ext.len() <= 10 {
+        if let Some((_, ext)) = filename.rsplit_once('.') {
+            if !ext.is_empty() && !ext.contains('/') && ext.len() <= 10 {
                 return ext.to_lowercase();
             }
         }
 
-        // Fall back to content type
-        match content_type {
-            "image/jpeg" => "jpg",
-            "image/png" => "png",
-            "image/gif" => "gif",
-            "image/webp" => "webp",
-            "application/pdf" => "pdf",
-            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => "xlsx",
-            "application/vnd.ms-excel" => "xls",
-            "text/csv" => "csv",
-            "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => "docx",
-            "application/msword" => "doc",
-            "text/plain" => "txt",
-            _ => "bin",
-        }
-        .to_string()
-    }
-
-    /// Categorize content type into simple file type
-    fn categorize_content_type(content_type: &str) -> String {
-        if content_type.starts_with("image/") {
-            "image".to_string()
-        } else if content_type == "application/pdf" {
-            "pdf".to_string()
-        } else if content_type.contains("spreadsheet") || content_type.contains("excel") || content_type == "text/csv" {
-            "excel".to_string()
-        } else if content_type.contains("word") || content_type.contains("document") {
-            "word".to_string()
-        } else if content_type.starts_with("video/") {
-            "video".to_string()
-        } else {
-            "other".to_string()
-        }
+        // Fall back to the file type registry
+        FileTypeService::extension_for(content_type)
+            .unwrap_or("bin")
+            .to_string()
     }
 }
@@ -0,0 +1,769 @@
+//! Encryption service module
+//!
+//! Optional envelope encryption of stored files. Every file gets its own
+//! random AES-256-GCM data key; the data key is wrapped (encrypted) with the
+//! master key from `ENCRYPTION_KEY` and stored next to the file under
+//! `keys/<key>`. Rotating the master key only re-wraps these small key
+//! files, the documents themselves are never re-encrypted.
+//!
+//! File content is encrypted in 64 KiB chunks (the STREAM construction), so
+//! downloads are decrypted while they stream and a byte range only decrypts
+//! the chunks it covers.
+//!
+//! # Architecture Decision
+//! Encryption wraps the configured backend, so every reader of stored files
+//! (`/files`, ZIP export, previews, the integrity scan) sees plain content
+//! without knowing about it. Files stored before encryption was enabled have
+//! no key file and are read as they are until `charta encryption
+//! encrypt-existing` converts them. With encryption on, files are never
+//! served straight from disk or through presigned URLs, which would hand
+//! out ciphertext.
+//!
+//! The key file and the content are two objects that can't be written
+//! together. Writes of the same file are serialized, and a file keeps its
+//! data key once it has one: rewriting it only changes the content, so
+//! whichever write lands last, or is interrupted, both objects still match.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{NewStream, StreamBE32, StreamPrimitive};
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{Mutex, MutexGuard};
+use tokio_util::io::StreamReader;
+
+use crate::error::{AppError, AppResult};
+use crate::models::EncryptionReport;
+use crate::services::storage_service::{ObjectInfo, ObjectReader, StorageBackend, StoredObject};
+use crate::services::StorageService;
+
+/// Prefix of the objects holding wrapped data keys
+const KEYS_PREFIX: &str = "keys/";
+
+/// Start of every encrypted file, followed by the STREAM nonce prefix
+const MAGIC: &[u8; 4] = b"CHE1";
+const NONCE_PREFIX_LEN: usize = 7;
+const HEADER_LEN: u64 = 11;
+
+/// Plaintext bytes per encrypted chunk
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Authentication tag appended to every chunk
+const TAG_LEN: u64 = 16;
+
+const ENCRYPTED_CHUNK_SIZE: u64 = CHUNK_SIZE + TAG_LEN;
+
+/// Number of locks writes are spread over by key
+const WRITE_LOCKS: usize = 64;
+
+/// A master key and its fingerprint
+struct MasterKey {
+    /// Start of the key's SHA-256, recorded with every data key it wraps
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl MasterKey {
+    fn parse(encoded: &str) -> AppResult<Self> {
+        let bytes = STANDARD.decode(encoded.trim()).map_err(|_| {
+            AppError::Internal("Encryption keys must be base64 encoded".to_string())
+        })?;
+        if bytes.len() != 32 {
+            return Err(AppError::Internal(
+                "Encryption keys must be 32 bytes; generate one with `openssl rand -base64 32`"
+                    .to_string(),
+            ));
+        }
+        Ok(MasterKey {
+            id: format!("{:x}", Sha256::digest(&bytes))[..16].to_string(),
+            cipher: cipher_from_slice(&bytes)?,
+        })
+    }
+}
+
+/// A data key wrapped with a master key, as stored under `keys/`
+#[derive(Serialize, Deserialize)]
+struct WrappedKey {
+    /// Id of the master key that wrapped it
+    master_key: String,
+    /// Base64 AES-GCM nonce
+    nonce: String,
+    /// Base64 wrapped data key
+    data_key: String,
+}
+
+/// The master keys configured in the environment
+///
+/// New data keys are wrapped with `ENCRYPTION_KEY`; the comma-separated
+/// `ENCRYPTION_PREVIOUS_KEYS` can still unwrap data keys until they're
+/// rotated.
+pub struct Keyring {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+impl Keyring {
+    /// The configured keys, or None when encryption is off
+    pub fn from_env() -> AppResult<Option<Self>> {
+        let current = match std::env::var("ENCRYPTION_KEY") {
+            Ok(key) if !key.trim().is_empty() => MasterKey::parse(&key)?,
+            _ => return Ok(None),
+        };
+        let previous = std::env::var("ENCRYPTION_PREVIOUS_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|key| !key.trim().is_empty())
+            .map(MasterKey::parse)
+            .collect::<AppResult<_>>()?;
+        Ok(Some(Keyring { current, previous }))
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> AppResult<WrappedKey> {
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let wrapped = self
+            .current
+            .cipher
+            .encrypt(&Nonce::from(nonce), data_key)
+            .map_err(|_| AppError::Internal("Failed to wrap data key".to_string()))?;
+        Ok(WrappedKey {
+            master_key: self.current.id.clone(),
+            nonce: STANDARD.encode(nonce),
+            data_key: STANDARD.encode(wrapped),
+        })
+    }
+
+    fn unwrap(&self, wrapped: &WrappedKey) -> AppResult<Vec<u8>> {
+        let master = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == wrapped.master_key)
+            .ok_or_else(|| {
+                AppError::Internal(format!(
+                    "Data key is wrapped with unknown master key {}; add it to ENCRYPTION_PREVIOUS_KEYS",
+                    wrapped.master_key
+                ))
+            })?;
+        let invalid = || AppError::Internal("Invalid wrapped data key".to_string());
+        let nonce: [u8; 12] = STANDARD
+            .decode(&wrapped.nonce)
+            .ok()
+            .and_then(|nonce| nonce.try_into().ok())
+            .ok_or_else(invalid)?;
+        let data_key = STANDARD.decode(&wrapped.data_key).map_err(|_| invalid())?;
+        let data_key = master
+            .cipher
+            .decrypt(&Nonce::from(nonce), data_key.as_slice())
+            .map_err(|_| invalid())?;
+        if data_key.len() != 32 {
+            return Err(invalid());
+        }
+        Ok(data_key)
+    }
+}
+
+fn cipher_from_slice(key: &[u8]) -> AppResult<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(key)
+        .map_err(|_| AppError::Internal("Encryption keys must be 32 bytes".to_string()))
+}
+
+/// Number of chunks a file of this size is encrypted in (empty files have one)
+fn chunk_count(plaintext_size: u64) -> u64 {
+    plaintext_size.div_ceil(CHUNK_SIZE).max(1)
+}
+
+/// Size of a file before encryption, from its encrypted size
+fn plaintext_size(key: &str, encrypted_size: u64) -> AppResult<u64> {
+    let truncated = || AppError::Internal(format!("Encrypted file '{}' is truncated", key));
+    let body = encrypted_size
+        .checked_sub(HEADER_LEN)
+        .ok_or_else(truncated)?;
+    let chunks = body.div_ceil(ENCRYPTED_CHUNK_SIZE).max(1);
+    let size = body.checked_sub(chunks * TAG_LEN).ok_or_else(truncated)?;
+    if chunk_count(size) != chunks {
+        return Err(truncated());
+    }
+    Ok(size)
+}
+
+/// Read up to `limit` bytes, fewer only at the end of the stream
+async fn read_full(reader: &mut (impl AsyncRead + Unpin), limit: u64) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(limit).read_to_end(&mut buf).await?;
+    Ok(buf)
+}
+
+/// Encrypt everything `reader` yields into `writer`, header first
+async fn encrypt_stream(
+    cipher: Aes256Gcm,
+    mut reader: impl AsyncRead + Unpin,
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> AppResult<()> {
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut nonce_prefix);
+    let stream = StreamBE32::from_aead(cipher, &nonce_prefix.into());
+    writer.write_all(MAGIC).await?;
+    writer.write_all(&nonce_prefix).await?;
+
+    let failed = || AppError::Internal("Failed to encrypt file".to_string());
+    let mut position = 0u32;
+    let mut chunk = read_full(&mut reader, CHUNK_SIZE).await?;
+    loop {
+        // A chunk is the last one when nothing follows it
+        let next = if chunk.len() as u64 == CHUNK_SIZE {
+            read_full(&mut reader, CHUNK_SIZE).await?
+        } else {
+            Vec::new()
+        };
+        let last = next.is_empty();
+        let encrypted = stream
+            .encrypt(position, last, chunk.as_slice())
+            .map_err(|_| failed())?;
+        writer.write_all(&encrypted).await?;
+        if last {
+            break;
+        }
+        chunk = next;
+        position = position.checked_add(1).ok_or_else(failed)?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Decrypt `length` bytes of chunks read from `reader`
+///
+/// `reader` starts at chunk `first_chunk`; the first `skip` decrypted bytes
+/// are dropped.
+fn decrypting_reader(
+    reader: ObjectReader,
+    stream: StreamBE32<Aes256Gcm>,
+    first_chunk: u64,
+    total_chunks: u64,
+    skip: u64,
+    length: u64,
+) -> ObjectReader {
+    let stream = Arc::new(stream);
+    let chunks = futures::stream::try_unfold(
+        (reader, first_chunk, skip, length),
+        move |(mut reader, position, skip, remaining)| {
+            let stream = stream.clone();
+            async move {
+                if remaining == 0 {
+                    return Ok(None);
+                }
+                let corrupted = || {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Encrypted file is corrupted or truncated",
+                    )
+                };
+                let encrypted = read_full(&mut reader, ENCRYPTED_CHUNK_SIZE).await?;
+                let counter = u32::try_from(position).map_err(|_| corrupted())?;
+                let mut chunk = stream
+                    .decrypt(counter, position + 1 == total_chunks, encrypted.as_slice())
+                    .map_err(|_| corrupted())?;
+                chunk.drain(..(skip as usize).min(chunk.len()));
+                chunk.truncate(remaining.min(chunk.len() as u64) as usize);
+                if chunk.is_empty() {
+                    return Err(corrupted());
+                }
+                let remaining = remaining - chunk.len() as u64;
+                Ok(Some((
+                    Bytes::from(chunk),
+                    (reader, position + 1, 0, remaining),
+                )))
+            }
+        },
+    );
+    Box::new(StreamReader::new(Box::pin(chunks)))
+}
+
+/// Storage backend encrypting everything stored in another backend
+pub struct EncryptedStorage {
+    inner: Box<dyn StorageBackend>,
+    keyring: Keyring,
+    /// Serialize writing a file's key file and content
+    writes: Vec<Mutex<()>>,
+}
+
+impl EncryptedStorage {
+    pub fn new(inner: Box<dyn StorageBackend>, keyring: Keyring) -> Self {
+        EncryptedStorage {
+            inner,
+            keyring,
+            writes: (0..WRITE_LOCKS).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    /// Lock out other writes, deletes and key rotations of a file
+    async fn lock(&self, key: &str) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.writes[hasher.finish() as usize % WRITE_LOCKS]
+            .lock()
+            .await
+    }
+
+    /// Object holding the wrapped data key of a file
+    fn key_file(key: &str) -> String {
+        format!("{}{}", KEYS_PREFIX, key)
+    }
+
+    fn not_found(key: &str) -> AppError {
+        AppError::NotFound(format!("File '{}' not found", key))
+    }
+
+    async fn wrapped_key(&self, key: &str) -> AppResult<Option<WrappedKey>> {
+        let mut object = match self.inner.get(&Self::key_file(key)).await {
+            Ok(object) => object,
+            Err(AppError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut json = Vec::new();
+        object.reader.read_to_end(&mut json).await?;
+        serde_json::from_slice(&json)
+            .map(Some)
+            .map_err(|e| AppError::Internal(format!("Invalid key file of '{}': {}", key, e)))
+    }
+
+    async fn store_wrapped_key(&self, key: &str, wrapped: &WrappedKey) -> AppResult<()> {
+        let json = serde_json::to_vec(wrapped)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize key file: {}", e)))?;
+        self.inner
+            .put_bytes(&Self::key_file(key), json.into())
+            .await
+    }
+
+    /// Cipher for a file's content, None for files stored in plain text
+    async fn data_cipher(&self, key: &str) -> AppResult<Option<Aes256Gcm>> {
+        match self.wrapped_key(key).await? {
+            Some(wrapped) => Ok(Some(cipher_from_slice(&self.keyring.unwrap(&wrapped)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Cipher to write a file's content with, held under the file's lock
+    ///
+    /// A file that already has a data key keeps it. Otherwise a new one is
+    /// generated and its key file written before the content, so a file with
+    /// a key file but no encryption header is one whose encryption was
+    /// interrupted.
+    async fn write_cipher(&self, key: &str) -> AppResult<Aes256Gcm> {
+        if let Some(cipher) = self.data_cipher(key).await? {
+            return Ok(cipher);
+        }
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let wrapped = self.keyring.wrap(&data_key)?;
+        self.store_wrapped_key(key, &wrapped).await?;
+        Ok(Aes256Gcm::new(&data_key))
+    }
+
+    /// STREAM decryptor for a file header, None when it isn't one
+    fn header_stream(cipher: Aes256Gcm, header: &[u8]) -> Option<StreamBE32<Aes256Gcm>> {
+        let nonce_prefix: [u8; NONCE_PREFIX_LEN] =
+            header.strip_prefix(MAGIC.as_slice())?.try_into().ok()?;
+        Some(StreamBE32::from_aead(cipher, &nonce_prefix.into()))
+    }
+
+    /// Whether a file's content is encrypted
+    async fn is_encrypted(&self, key: &str) -> AppResult<bool> {
+        if self.wrapped_key(key).await?.is_none() {
+            return Ok(false);
+        }
+        let size = match self.inner.head(key).await? {
+            Some(info) => info.size,
+            None => return Err(Self::not_found(key)),
+        };
+        self.has_header(key, size).await
+    }
+
+    /// Whether stored content of this size starts with the encryption header
+    async fn has_header(&self, key: &str, size: u64) -> AppResult<bool> {
+        if size < HEADER_LEN {
+            return Ok(false);
+        }
+        let mut object = self.inner.get_range(key, 0, MAGIC.len() as u64).await?;
+        Ok(read_full(&mut object.reader, MAGIC.len() as u64).await? == MAGIC)
+    }
+
+    /// Re-wrap every data key with the current master key
+    pub async fn rotate_keys(&self) -> AppResult<EncryptionReport> {
+        let mut report = EncryptionReport::default();
+        for object in self.list().await? {
+            report.files_scanned += 1;
+            let _lock = self.lock(&object.key).await;
+            let Some(wrapped) = self.wrapped_key(&object.key).await? else {
+                report.plaintext_files += 1;
+                continue;
+            };
+            if wrapped.master_key == self.keyring.current.id {
+                report.keys_current += 1;
+                continue;
+            }
+            let data_key = self.keyring.unwrap(&wrapped)?;
+            self.store_wrapped_key(&object.key, &self.keyring.wrap(&data_key)?)
+                .await?;
+            report.keys_rewrapped += 1;
+        }
+        tracing::info!(
+            "Re-wrapped {} data key(s), {} already current",
+            report.keys_rewrapped,
+            report.keys_current
+        );
+        Ok(report)
+    }
+
+    /// Encrypt files stored before encryption was enabled
+    pub async fn encrypt_existing(&self) -> AppResult<EncryptionReport> {
+        let mut report = EncryptionReport::default();
+        for object in self.list().await? {
+            report.files_scanned += 1;
+            if self.is_encrypted(&object.key).await? {
+                continue;
+            }
+            let copy = self.local_copy(&object.key).await?;
+            self.put_file(&object.key, copy.path()).await?;
+            report.files_encrypted += 1;
+        }
+        tracing::info!("Encrypted {} file(s)", report.files_encrypted);
+        Ok(report)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for EncryptedStorage {
+    fn describe(&self) -> String {
+        format!("{} (encrypted)", self.inner.describe())
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> AppResult<()> {
+        let _lock = self.lock(key).await;
+        let cipher = self.write_cipher(key).await?;
+        let encrypted = StorageService::staging_path("");
+        let result = async {
+            let source = tokio::fs::File::open(path).await?;
+            let mut target = tokio::fs::File::create(&encrypted).await?;
+            encrypt_stream(cipher, source, &mut target).await?;
+            self.inner.put_file(key, Path::new(&encrypted)).await
+        }
+        .await;
+        // Already gone when the inner backend moved it into place
+        let _ = tokio::fs::remove_file(&encrypted).await;
+        result?;
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, data: Bytes) -> AppResult<()> {
+        let _lock = self.lock(key).await;
+        let cipher = self.write_cipher(key).await?;
+        let mut encrypted = Vec::new();
+        encrypt_stream(cipher, data.as_ref(), &mut encrypted).await?;
+        self.inner.put_bytes(key, encrypted.into()).await
+    }
+
+    async fn get(&self, key: &str) -> AppResult<StoredObject> {
+        if key.starts_with(KEYS_PREFIX) {
+            return Err(Self::not_found(key));
+        }
+        let Some(cipher) = self.data_cipher(key).await? else {
+            return self.inner.get(key).await;
+        };
+
+        let mut object = self.inner.get(key).await?;
+        let header = read_full(&mut object.reader, HEADER_LEN).await?;
+        let Some(stream) = Self::header_stream(cipher, &header) else {
+            tracing::warn!("File '{}' has a data key but isn't encrypted", key);
+            let reader = std::io::Cursor::new(header).chain(object.reader);
+            return Ok(StoredObject {
+                size: object.size,
+                reader: Box::new(reader),
+            });
+        };
+        let size = plaintext_size(key, object.size)?;
+        Ok(StoredObject {
+            size,
+            reader: decrypting_reader(object.reader, stream, 0, chunk_count(size), 0, size),
+        })
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> AppResult<StoredObject> {
+        if key.starts_with(KEYS_PREFIX) {
+            return Err(Self::not_found(key));
+        }
+        let Some(cipher) = self.data_cipher(key).await? else {
+            return self.inner.get_range(key, offset, length).await;
+        };
+
+        let encrypted_size = match self.inner.head(key).await? {
+            Some(info) => info.size,
+            None => return Err(Self::not_found(key)),
+        };
+        let header = if encrypted_size >= HEADER_LEN {
+            let mut object = self.inner.get_range(key, 0, HEADER_LEN).await?;
+            read_full(&mut object.reader, HEADER_LEN).await?
+        } else {
+            Vec::new()
+        };
+        let Some(stream) = Self::header_stream(cipher, &header) else {
+            return self.inner.get_range(key, offset, length).await;
+        };
+
+        let size = plaintext_size(key, encrypted_size)?;
+        if offset.saturating_add(length) > size {
+            return Err(AppError::BadRequest(format!(
+                "Range outside of file '{}'",
+                key
+            )));
+        }
+        if length == 0 {
+            return Ok(StoredObject {
+                size: 0,
+                reader: Box::new(tokio::io::empty()),
+            });
+        }
+
+        // Only the chunks covering the range are fetched and decrypted
+        let first_chunk = offset / CHUNK_SIZE;
+        let last_chunk = (offset + length - 1) / CHUNK_SIZE;
+        let start = HEADER_LEN + first_chunk * ENCRYPTED_CHUNK_SIZE;
+        let end = (HEADER_LEN + (last_chunk + 1) * ENCRYPTED_CHUNK_SIZE).min(encrypted_size);
+        let object = self.inner.get_range(key, start, end - start).await?;
+        Ok(StoredObject {
+            size: length,
+            reader: decrypting_reader(
+                object.reader,
+                stream,
+                first_chunk,
+                chunk_count(size),
+                offset - first_chunk * CHUNK_SIZE,
+                length,
+            ),
+        })
+    }
+
+    async fn head(&self, key: &str) -> AppResult<Option<ObjectInfo>> {
+        if key.starts_with(KEYS_PREFIX) {
+            return Ok(None);
+        }
+        let Some(mut info) = self.inner.head(key).await? else {
+            return Ok(None);
+        };
+        // Like `get`, content whose encryption was interrupted is read as it is
+        if self.inner.head(&Self::key_file(key)).await?.is_some()
+            && self.has_header(key, info.size).await?
+        {
+            info.size = plaintext_size(key, info.size)?;
+        }
+        Ok(Some(info))
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        let _lock = self.lock(key).await;
+        self.inner.delete(key).await?;
+        self.inner.delete(&Self::key_file(key)).await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> AppResult<()> {
+        let _lock = self.lock(to).await;
+        if self.inner.head(&Self::key_file(from)).await?.is_some() {
+            self.inner
+                .copy(&Self::key_file(from), &Self::key_file(to))
+                .await?;
+        } else {
+            self.inner.delete(&Self::key_file(to)).await?;
+        }
+        self.inner.copy(from, to).await
+    }
+
+    async fn list(&self) -> AppResult<Vec<ObjectInfo>> {
+        let (key_files, mut objects): (Vec<ObjectInfo>, Vec<ObjectInfo>) = self
+            .inner
+            .list()
+            .await?
+            .into_iter()
+            .partition(|object| object.key.starts_with(KEYS_PREFIX));
+        let encrypted: HashSet<&str> = key_files
+            .iter()
+            .filter_map(|object| object.key.strip_prefix(KEYS_PREFIX))
+            .collect();
+        for object in &mut objects {
+            if encrypted.contains(object.key.as_str()) {
+                // A file whose encryption was interrupted keeps its size
+                object.size = plaintext_size(&object.key, object.size).unwrap_or(object.size);
+            }
+        }
+        Ok(objects)
+    }
+
+    async fn presigned_url(&self, _key: &str, _expires_in: Duration) -> AppResult<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Encryption service with static methods for the `charta encryption` commands
+pub struct EncryptionService;
+
+impl EncryptionService {
+    /// The configured backend with encryption, whether or not the server uses it
+    fn storage() -> AppResult<EncryptedStorage> {
+        let keyring = Keyring::from_env()?
+            .ok_or_else(|| AppError::Internal("ENCRYPTION_KEY is not set".to_string()))?;
+        Ok(EncryptedStorage::new(
+            StorageService::unencrypted_from_env()?,
+            keyring,
+        ))
+    }
+
+    /// Re-wrap all data keys with `ENCRYPTION_KEY`
+    ///
+    /// The old master key must be listed in `ENCRYPTION_PREVIOUS_KEYS`; it
+    /// can be dropped once this has run.
+    pub async fn rotate_keys() -> AppResult<EncryptionReport> {
+        Self::storage()?.rotate_keys().await
+    }
+
+    /// Encrypt files stored while encryption was off
+    pub async fn encrypt_existing() -> AppResult<EncryptionReport> {
+        Self::storage()?.encrypt_existing().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encrypted size of a file of `size` bytes
+    fn encrypted_size(size: u64) -> u64 {
+        HEADER_LEN + size + chunk_count(size) * TAG_LEN
+    }
+
+    fn cipher() -> Aes256Gcm {
+        Aes256Gcm::new(&Aes256Gcm::generate_key(OsRng))
+    }
+
+    fn content(size: u64) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    async fn encrypt(cipher: Aes256Gcm, plaintext: &[u8]) -> Vec<u8> {
+        let mut encrypted = Vec::new();
+        encrypt_stream(cipher, plaintext, &mut encrypted)
+            .await
+            .unwrap();
+        encrypted
+    }
+
+    /// Decrypt `length` bytes from `offset` the way `get_range` does
+    async fn decrypt(cipher: Aes256Gcm, encrypted: &[u8], offset: u64, length: u64) -> Vec<u8> {
+        let stream = EncryptedStorage::header_stream(cipher, &encrypted[..HEADER_LEN as usize])
+            .expect("encryption header");
+        let size = plaintext_size("test", encrypted.len() as u64).unwrap();
+        let first_chunk = offset / CHUNK_SIZE;
+        let start = (HEADER_LEN + first_chunk * ENCRYPTED_CHUNK_SIZE) as usize;
+        let reader: ObjectReader = Box::new(std::io::Cursor::new(encrypted[start..].to_vec()));
+        let mut reader = decrypting_reader(
+            reader,
+            stream,
+            first_chunk,
+            chunk_count(size),
+            offset - first_chunk * CHUNK_SIZE,
+            length,
+        );
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).await.unwrap();
+        plaintext
+    }
+
+    #[test]
+    fn empty_files_have_one_chunk() {
+        assert_eq!(chunk_count(0), 1);
+        assert_eq!(chunk_count(1), 1);
+        assert_eq!(chunk_count(CHUNK_SIZE), 1);
+        assert_eq!(chunk_count(CHUNK_SIZE + 1), 2);
+        assert_eq!(chunk_count(3 * CHUNK_SIZE), 3);
+    }
+
+    #[test]
+    fn plaintext_size_inverts_the_encrypted_size() {
+        for size in [
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            5 * CHUNK_SIZE - TAG_LEN,
+            5 * CHUNK_SIZE + 3,
+            1 << 40,
+        ] {
+            assert_eq!(plaintext_size("test", encrypted_size(size)).unwrap(), size);
+        }
+    }
+
+    #[test]
+    fn truncated_sizes_are_rejected() {
+        for encrypted in [0, HEADER_LEN - 1, HEADER_LEN, HEADER_LEN + TAG_LEN - 1] {
+            assert!(plaintext_size("test", encrypted).is_err(), "{}", encrypted);
+        }
+        // A final chunk holding nothing but part of its tag
+        let cut = encrypted_size(CHUNK_SIZE) + TAG_LEN - 1;
+        assert!(plaintext_size("test", cut).is_err());
+    }
+
+    #[tokio::test]
+    async fn files_round_trip_across_chunk_boundaries() {
+        for size in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE + 100] {
+            let cipher = cipher();
+            let plaintext = content(size);
+            let encrypted = encrypt(cipher.clone(), &plaintext).await;
+            assert_eq!(encrypted.len() as u64, encrypted_size(size));
+            assert_eq!(decrypt(cipher, &encrypted, 0, size).await, plaintext);
+        }
+    }
+
+    #[tokio::test]
+    async fn ranges_decrypt_only_their_bytes() {
+        let size = 2 * CHUNK_SIZE + 100;
+        let cipher = cipher();
+        let plaintext = content(size);
+        let encrypted = encrypt(cipher.clone(), &plaintext).await;
+        for (offset, length) in [
+            (0, 1),
+            (CHUNK_SIZE - 1, 2),
+            (CHUNK_SIZE, CHUNK_SIZE),
+            (2 * CHUNK_SIZE + 50, 50),
+            (size - 1, 1),
+        ] {
+            let range = decrypt(cipher.clone(), &encrypted, offset, length).await;
+            assert_eq!(
+                range,
+                plaintext[offset as usize..(offset + length) as usize]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn truncated_content_fails_to_decrypt() {
+        let cipher = cipher();
+        let encrypted = encrypt(cipher.clone(), &content(CHUNK_SIZE + 10)).await;
+        // Dropping the final chunk leaves a well-sized file whose last chunk isn't marked last
+        let cut = &encrypted[..encrypted_size(CHUNK_SIZE) as usize];
+        let stream = EncryptedStorage::header_stream(cipher, &cut[..HEADER_LEN as usize]).unwrap();
+        let reader: ObjectReader =
+            Box::new(std::io::Cursor::new(cut[HEADER_LEN as usize..].to_vec()));
+        let mut reader = decrypting_reader(reader, stream, 0, 1, 0, CHUNK_SIZE);
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+    }
+}
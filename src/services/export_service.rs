@@ -0,0 +1,684 @@
+//! Export service module
+//!
+//! Builds a self-contained archive of everything about a project, to hand
+//! over to the client or keep offline once an obra is finished: documents
+//! with their metadata, comments and annotations, voice memos, the forum
+//! transcript with replies and task lists with their completion history.
+//!
+//! # Bundle Layout
+//! - `index.html`: static page to browse the bundle without the server
+//! - `project.json`: all metadata ([`ProjectExport`]), read back by imports
+//! - `documents/<category>/<name>`: original files, voice memos next to them
+//! - `forum/`: voice messages of the forum
+//!
+//! # Architecture Decision
+//! The bundle is streamed like the plain ZIP downloads, so only the metadata
+//! is held in memory. Derived files (previews, video posters and transcodes)
+//! are left out; they can be regenerated from the originals.
+
+use std::collections::{HashMap, HashSet};
+
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::body::Body;
+use tokio::io::AsyncWrite;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{
+    AnnotationLayer, Document, DocumentComment, ExportedAnnotation, ExportedComment,
+    ExportedDocument, ExportedForumMessage, ExportedProject, ExportedTaskEvent, ExportedTaskItem,
+    ExportedVoiceMemo, ForumMessage, ProjectExport, TaskItem,
+};
+use crate::services::{
+    ColdStorageService, DocumentService, DownloadService, ProjectService, StorageService,
+};
+
+/// Value of `ProjectExport::format`
+pub const EXPORT_FORMAT: &str = "charta-project-export";
+
+/// Current version of the bundle layout
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Size of the in-memory pipe between the ZIP writer and the response body
+const PIPE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Folder of the bundle holding document files
+const DOCUMENTS_FOLDER: &str = "documents";
+
+/// Folder of the bundle holding forum voice messages
+const FORUM_FOLDER: &str = "forum";
+
+/// A stored file to be placed in the bundle
+struct BundleFile {
+    /// Path inside the ZIP
+    zip_path: String,
+    /// Storage key
+    key: String,
+}
+
+/// Export service building project bundles
+pub struct ExportService;
+
+impl ExportService {
+    /// Build the export bundle of a project
+    ///
+    /// Returns the suggested archive filename and a streaming body.
+    pub async fn project_bundle(pool: &DbPool, project_id: &str) -> AppResult<(String, Body)> {
+        let project = ProjectService::get_by_id(pool, project_id).await?;
+        let mut docs = DocumentService::list_by_project(pool, project_id).await?;
+        ColdStorageService::restore_documents(pool, &docs).await?;
+        // Oldest first reads naturally in the index
+        docs.reverse();
+
+        let mut used_paths = HashSet::new();
+        let mut files = Vec::new();
+        let documents = Self::export_documents(pool, docs, &mut used_paths, &mut files).await?;
+        let forum =
+            Self::export_forum(pool, project_id, &documents, &mut used_paths, &mut files).await?;
+
+        let export = ProjectExport {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            exported_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            project: ExportedProject {
+                id: project.id,
+                name: project.name,
+                status: project.status,
+                address: project.address,
+                client_phone: project.client_phone,
+                created_at: project.created_at,
+                archived_at: project.archived_at,
+            },
+            documents,
+            forum,
+        };
+
+        let index = Self::render_index(&export);
+        let metadata = serde_json::to_string_pretty(&export)
+            .map_err(|e| AppError::Internal(format!("Failed to encode export: {}", e)))?;
+        let filename = format!(
+            "{}_export_{}.zip",
+            DownloadService::sanitize(&export.project.name),
+            chrono::Local::now().format("%Y-%m-%d")
+        );
+
+        let (writer, reader) = tokio::io::duplex(PIPE_BUFFER_SIZE);
+        tokio::spawn(async move {
+            if let Err(e) = Self::write_bundle(writer, files, metadata, index).await {
+                // The client sees a truncated download; nothing else we can do mid-stream
+                tracing::error!("Failed to stream project export: {}", e);
+            }
+        });
+
+        Ok((filename, Body::from_stream(ReaderStream::new(reader))))
+    }
+
+    /// Document metadata, queueing their files and voice memos
+    async fn export_documents(
+        pool: &DbPool,
+        docs: Vec<Document>,
+        used_paths: &mut HashSet<String>,
+        files: &mut Vec<BundleFile>,
+    ) -> AppResult<Vec<ExportedDocument>> {
+        let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for (document_id, tag) in Self::fetch_for::<(String, String)>(
+            pool,
+            "SELECT document_id, tag FROM document_tags WHERE document_id IN ({}) ORDER BY tag",
+            &ids,
+        )
+        .await?
+        {
+            tags.entry(document_id).or_default().push(tag);
+        }
+        let mut comments: HashMap<String, Vec<DocumentComment>> = HashMap::new();
+        for comment in Self::fetch_for::<DocumentComment>(
+            pool,
+            "SELECT * FROM document_comments WHERE document_id IN ({}) ORDER BY created_at",
+            &ids,
+        )
+        .await?
+        {
+            comments
+                .entry(comment.document_id.clone())
+                .or_default()
+                .push(comment);
+        }
+        let mut annotations: HashMap<String, Vec<AnnotationLayer>> = HashMap::new();
+        for layer in Self::fetch_for::<AnnotationLayer>(
+            pool,
+            "SELECT * FROM document_annotations WHERE document_id IN ({}) ORDER BY created_at",
+            &ids,
+        )
+        .await?
+        {
+            annotations
+                .entry(layer.document_id.clone())
+                .or_default()
+                .push(layer);
+        }
+
+        let mut exported = Vec::with_capacity(docs.len());
+        for doc in docs {
+            let folder = format!(
+                "{}/{}",
+                DOCUMENTS_FOLDER,
+                DownloadService::category_folder(&doc)
+            );
+            let file_name = DownloadService::display_filename(&doc.original_name, &doc.file_path);
+
+            let file = if StorageService::exists(&doc.file_path).await? {
+                let zip_path = DownloadService::unique_path(used_paths, &folder, &file_name);
+                files.push(BundleFile {
+                    zip_path: zip_path.clone(),
+                    key: doc.file_path.clone(),
+                });
+                Some(zip_path)
+            } else {
+                tracing::warn!("Leaving missing file of document {} out of export", doc.id);
+                None
+            };
+
+            // Voice memo goes next to its document
+            let mut voice_memo = None;
+            if let Some(audio) = &doc.audio_path {
+                if StorageService::exists(audio).await? {
+                    let stem = file_name
+                        .rsplit_once('.')
+                        .map(|(stem, _)| stem)
+                        .unwrap_or(&file_name);
+                    let ext = audio.rsplit_once('.').map(|(_, e)| e).unwrap_or("webm");
+                    let memo_name = format!("{} - memo.{}", stem, ext);
+                    let zip_path = DownloadService::unique_path(used_paths, &folder, &memo_name);
+                    files.push(BundleFile {
+                        zip_path: zip_path.clone(),
+                        key: audio.clone(),
+                    });
+                    voice_memo = Some(ExportedVoiceMemo {
+                        file: zip_path,
+                        format: doc.audio_format.clone(),
+                        duration_seconds: doc.audio_duration_seconds,
+                    });
+                }
+            }
+
+            let doc_comments = comments
+                .remove(&doc.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|c| ExportedComment {
+                    id: c.id,
+                    parent_id: c.parent_id,
+                    author_name: c.author_name,
+                    content: c.content,
+                    x: c.x,
+                    y: c.y,
+                    page: c.page,
+                    resolved: c.resolved,
+                    resolved_by: c.resolved_by,
+                    resolved_at: c.resolved_at,
+                    created_at: c.created_at,
+                })
+                .collect();
+            let doc_annotations = annotations
+                .remove(&doc.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|layer| ExportedAnnotation {
+                    shapes: serde_json::from_str(&layer.shapes).unwrap_or_default(),
+                    id: layer.id,
+                    name: layer.name,
+                    author_name: layer.author_name,
+                    visible: layer.visible,
+                    created_at: layer.created_at,
+                    updated_at: layer.updated_at,
+                })
+                .collect();
+
+            exported.push(ExportedDocument {
+                tags: tags.remove(&doc.id).unwrap_or_default(),
+                comments: doc_comments,
+                annotations: doc_annotations,
+                voice_memo,
+                file,
+                id: doc.id,
+                checksum: doc.checksum,
+                original_name: doc.original_name,
+                file_type: doc.file_type,
+                mime_type: doc.mime_type,
+                detected_mime_type: doc.detected_mime_type,
+                uploaded_at: doc.uploaded_at,
+                captured_at: doc.captured_at,
+                uploaded_by: doc.uploaded_by,
+                source: doc.source,
+                status: doc.status,
+                category: doc.category,
+                notes: doc.notes,
+                email_sender: doc.email_sender,
+                email_subject: doc.email_subject,
+                email_message_id: doc.email_message_id,
+            });
+        }
+        Ok(exported)
+    }
+
+    /// Forum messages and replies with their task lists, queueing voice messages
+    async fn export_forum(
+        pool: &DbPool,
+        project_id: &str,
+        documents: &[ExportedDocument],
+        used_paths: &mut HashSet<String>,
+        files: &mut Vec<BundleFile>,
+    ) -> AppResult<Vec<ExportedForumMessage>> {
+        let messages = sqlx::query_as::<_, ForumMessage>(
+            "SELECT * FROM forum_messages WHERE project_id = ? ORDER BY created_at, rowid",
+        )
+        .bind(project_id)
+        .fetch_all(pool)
+        .await?;
+        let ids: Vec<&str> = messages.iter().map(|m| m.id.as_str()).collect();
+
+        let exported_docs: HashSet<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        let mut albums: HashMap<String, Vec<String>> = HashMap::new();
+        for (message_id, document_id) in Self::fetch_for::<(String, String)>(
+            pool,
+            "SELECT message_id, document_id FROM forum_message_documents WHERE message_id IN ({}) ORDER BY position",
+            &ids,
+        )
+        .await?
+        {
+            albums.entry(message_id).or_default().push(document_id);
+        }
+
+        let mut items: HashMap<String, Vec<TaskItem>> = HashMap::new();
+        for item in Self::fetch_for::<TaskItem>(
+            pool,
+            "SELECT * FROM task_items WHERE message_id IN ({}) ORDER BY created_at, rowid",
+            &ids,
+        )
+        .await?
+        {
+            items.entry(item.message_id.clone()).or_default().push(item);
+        }
+        let item_ids: Vec<&str> = items.values().flatten().map(|i| i.id.as_str()).collect();
+        let mut history: HashMap<String, Vec<ExportedTaskEvent>> = HashMap::new();
+        for (item_id, completed, actor, at) in Self::fetch_for::<(String, bool, Option<String>, String)>(
+            pool,
+            "SELECT item_id, completed, actor, created_at FROM task_item_events WHERE item_id IN ({}) ORDER BY created_at, rowid",
+            &item_ids,
+        )
+        .await?
+        {
+            history.entry(item_id).or_default().push(ExportedTaskEvent {
+                completed,
+                actor,
+                at,
+            });
+        }
+
+        let mut exported = Vec::with_capacity(messages.len());
+        for msg in messages {
+            let mut document_ids = albums
+                .remove(&msg.id)
+                .or_else(|| msg.document_id.clone().map(|id| vec![id]))
+                .unwrap_or_default();
+            document_ids.retain(|id| exported_docs.contains(id.as_str()));
+
+            let mut voice_memo = None;
+            if let Some(audio) = &msg.audio_path {
+                if StorageService::exists(audio).await? {
+                    let ext = audio.rsplit_once('.').map(|(_, e)| e).unwrap_or("webm");
+                    let name = format!(
+                        "{} {}.{}",
+                        msg.created_at.replace(':', "-"),
+                        DownloadService::sanitize(&msg.author_name),
+                        ext
+                    );
+                    let zip_path = DownloadService::unique_path(used_paths, FORUM_FOLDER, &name);
+                    files.push(BundleFile {
+                        zip_path: zip_path.clone(),
+                        key: audio.clone(),
+                    });
+                    voice_memo = Some(zip_path);
+                }
+            }
+
+            let msg_items = items
+                .remove(&msg.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|item| ExportedTaskItem {
+                    history: history.remove(&item.id).unwrap_or_default(),
+                    id: item.id,
+                    text: item.text,
+                    completed: item.completed,
+                    completed_by: item.completed_by,
+                    completed_at: item.completed_at,
+                    created_at: item.created_at,
+                })
+                .collect();
+
+            exported.push(ExportedForumMessage {
+                id: msg.id,
+                parent_id: msg.parent_id,
+                message_type: msg.message_type,
+                content: msg.content,
+                author_name: msg.author_name,
+                created_at: msg.created_at,
+                document_ids,
+                voice_memo,
+                items: msg_items,
+            });
+        }
+        Ok(exported)
+    }
+
+    /// Run a query with an `IN ({})` placeholder list bound to `ids`
+    async fn fetch_for<T>(pool: &DbPool, query: &str, ids: &[&str]) -> AppResult<Vec<T>>
+    where
+        T: for<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> + Send + Unpin,
+    {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query = query.replace("{}", &vec!["?"; ids.len()].join(", "));
+        let mut q = sqlx::query_as::<_, T>(&query);
+        for id in ids {
+            q = q.bind(*id);
+        }
+        Ok(q.fetch_all(pool).await?)
+    }
+
+    /// Write the files, metadata and index into a ZIP stream
+    async fn write_bundle<W: AsyncWrite + Unpin>(
+        writer: W,
+        files: Vec<BundleFile>,
+        metadata: String,
+        index: String,
+    ) -> Result<(), String> {
+        let mut zip = ZipFileWriter::with_tokio(writer);
+
+        for (name, content) in [("index.html", index), ("project.json", metadata)] {
+            let builder = ZipEntryBuilder::new(name.into(), Compression::Deflate);
+            zip.write_entry_whole(builder, content.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        // Photos and PDFs are already compressed, so files are stored as-is
+        for file in files {
+            let object = StorageService::backend()
+                .get(&file.key)
+                .await
+                .map_err(|e| format!("open {}: {}", file.key, e))?;
+
+            let builder = ZipEntryBuilder::new(file.zip_path.into(), Compression::Stored);
+            let mut entry_writer = zip
+                .write_entry_stream(builder)
+                .await
+                .map_err(|e| e.to_string())?;
+            futures::io::copy(object.reader.compat(), &mut entry_writer)
+                .await
+                .map_err(|e| format!("copy {}: {}", file.key, e))?;
+            entry_writer.close().await.map_err(|e| e.to_string())?;
+        }
+
+        zip.close().await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Static HTML page listing the documents and the forum transcript
+    fn render_index(export: &ProjectExport) -> String {
+        let project = &export.project;
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html lang=\"pt\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape(&project.name)));
+        html.push_str(INDEX_STYLE);
+        html.push_str("</head>\n<body>\n");
+
+        html.push_str(&format!("<h1>{}</h1>\n<dl>\n", escape(&project.name)));
+        let details = [
+            ("Estado", Some(project.status.as_str())),
+            ("Morada", project.address.as_deref()),
+            ("Telefone do cliente", project.client_phone.as_deref()),
+            ("Criada em", Some(project.created_at.as_str())),
+            ("Arquivada em", project.archived_at.as_deref()),
+            ("Exportada em", Some(export.exported_at.as_str())),
+        ];
+        for (label, value) in details {
+            if let Some(value) = value {
+                html.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", label, escape(value)));
+            }
+        }
+        html.push_str("</dl>\n<nav><a href=\"#documentos\">Documentos</a> · <a href=\"#forum\">Fórum</a></nav>\n");
+
+        html.push_str(&format!(
+            "<h2 id=\"documentos\">Documentos ({})</h2>\n",
+            export.documents.len()
+        ));
+        let mut categories: Vec<Option<&str>> = Vec::new();
+        for doc in &export.documents {
+            if !categories.contains(&doc.category.as_deref()) {
+                categories.push(doc.category.as_deref());
+            }
+        }
+        for category in categories {
+            html.push_str(&format!(
+                "<h3>{}</h3>\n",
+                escape(category.unwrap_or("Sem categoria"))
+            ));
+            for doc in export
+                .documents
+                .iter()
+                .filter(|d| d.category.as_deref() == category)
+            {
+                Self::render_document(&mut html, doc);
+            }
+        }
+
+        html.push_str("<h2 id=\"forum\">Fórum</h2>\n");
+        let documents: HashMap<&str, &ExportedDocument> = export
+            .documents
+            .iter()
+            .map(|d| (d.id.as_str(), d))
+            .collect();
+        let roots = export.forum.iter().filter(|m| m.parent_id.is_none());
+        for msg in roots {
+            html.push_str("<article class=\"message\">\n");
+            Self::render_message(&mut html, msg, &documents);
+            for reply in export
+                .forum
+                .iter()
+                .filter(|r| r.parent_id.as_deref() == Some(msg.id.as_str()))
+            {
+                html.push_str("<div class=\"reply\">\n");
+                Self::render_message(&mut html, reply, &documents);
+                html.push_str("</div>\n");
+            }
+            html.push_str("</article>\n");
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    fn render_document(html: &mut String, doc: &ExportedDocument) {
+        html.push_str(&format!(
+            "<article class=\"document\" id=\"doc-{}\">\n",
+            escape(&doc.id)
+        ));
+        match &doc.file {
+            Some(file) => {
+                let href = escape(&href(file));
+                match doc.file_type.as_str() {
+                    "image" => html.push_str(&format!(
+                        "<a href=\"{0}\"><img src=\"{0}\" alt=\"\" loading=\"lazy\"></a>\n",
+                        href
+                    )),
+                    "video" => html.push_str(&format!(
+                        "<video src=\"{}\" controls preload=\"none\"></video>\n",
+                        href
+                    )),
+                    _ => {}
+                }
+                html.push_str(&format!(
+                    "<h4><a href=\"{}\">{}</a></h4>\n",
+                    href,
+                    escape(&doc.original_name)
+                ));
+            }
+            None => html.push_str(&format!(
+                "<h4>{} <small>(ficheiro em falta)</small></h4>\n",
+                escape(&doc.original_name)
+            )),
+        }
+
+        let mut meta = vec![
+            doc.uploaded_at.clone(),
+            doc.uploaded_by.clone().unwrap_or_default(),
+            doc.status.clone(),
+        ];
+        if !doc.tags.is_empty() {
+            meta.push(doc.tags.join(", "));
+        }
+        meta.retain(|m| !m.is_empty());
+        html.push_str(&format!(
+            "<p class=\"meta\">{}</p>\n",
+            escape(&meta.join(" · "))
+        ));
+        if let Some(notes) = doc.notes.as_deref().filter(|n| !n.is_empty()) {
+            html.push_str(&format!("<p class=\"notes\">{}</p>\n", escape(notes)));
+        }
+        if let Some(memo) = &doc.voice_memo {
+            html.push_str(&format!(
+                "<audio src=\"{}\" controls preload=\"none\"></audio>\n",
+                escape(&href(&memo.file))
+            ));
+        }
+        if !doc.comments.is_empty() {
+            html.push_str("<ul class=\"comments\">\n");
+            for comment in &doc.comments {
+                let class = if comment.parent_id.is_some() {
+                    " class=\"reply\""
+                } else {
+                    ""
+                };
+                let resolved = if comment.resolved { " ✓" } else { "" };
+                html.push_str(&format!(
+                    "<li{}><b>{}</b> <small>{}{}</small><br>{}</li>\n",
+                    class,
+                    escape(&comment.author_name),
+                    escape(&comment.created_at),
+                    resolved,
+                    escape(&comment.content)
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</article>\n");
+    }
+
+    fn render_message(
+        html: &mut String,
+        msg: &ExportedForumMessage,
+        documents: &HashMap<&str, &ExportedDocument>,
+    ) {
+        html.push_str(&format!(
+            "<p class=\"meta\"><b>{}</b> · {}</p>\n",
+            escape(&msg.author_name),
+            escape(&msg.created_at)
+        ));
+        if let Some(content) = msg.content.as_deref().filter(|c| !c.is_empty()) {
+            html.push_str(&format!("<p>{}</p>\n", escape(content)));
+        }
+        if let Some(memo) = &msg.voice_memo {
+            html.push_str(&format!(
+                "<audio src=\"{}\" controls preload=\"none\"></audio>\n",
+                escape(&href(memo))
+            ));
+        }
+        for doc in msg
+            .document_ids
+            .iter()
+            .filter_map(|id| documents.get(id.as_str()))
+        {
+            let thumbnail = match (&doc.file, doc.file_type.as_str()) {
+                (Some(file), "image") => format!(
+                    "<img class=\"thumb\" src=\"{}\" alt=\"\" loading=\"lazy\">",
+                    escape(&href(file))
+                ),
+                _ => escape(&doc.original_name),
+            };
+            html.push_str(&format!(
+                "<a href=\"#doc-{}\">{}</a>\n",
+                escape(&doc.id),
+                thumbnail
+            ));
+        }
+        if !msg.items.is_empty() {
+            html.push_str("<ul class=\"tasks\">\n");
+            for item in &msg.items {
+                let mark = if item.completed { "☑" } else { "☐" };
+                html.push_str(&format!("<li>{} {}", mark, escape(&item.text)));
+                for event in &item.history {
+                    let action = if event.completed {
+                        "concluída"
+                    } else {
+                        "reaberta"
+                    };
+                    let actor = event
+                        .actor
+                        .as_deref()
+                        .map(|a| format!(" por {}", a))
+                        .unwrap_or_default();
+                    html.push_str(&format!(
+                        "<br><small>{}{} em {}</small>",
+                        action,
+                        escape(&actor),
+                        escape(&event.at)
+                    ));
+                }
+                html.push_str("</li>\n");
+            }
+            html.push_str("</ul>\n");
+        }
+    }
+}
+
+/// Inline stylesheet of the bundle index
+const INDEX_STYLE: &str = "<style>\n\
+body{font-family:system-ui,sans-serif;max-width:60rem;margin:2rem auto;padding:0 1rem;color:#222}\n\
+dt{font-weight:bold;float:left;clear:left;width:12rem}dd{margin-left:12rem}\n\
+article{border-top:1px solid #ddd;padding:.75rem 0}\n\
+.document img,.document video{max-width:16rem;max-height:12rem;float:right;margin-left:1rem}\n\
+.document::after{content:'';display:block;clear:both}\n\
+.thumb{max-width:8rem;max-height:6rem;margin:.25rem}\n\
+.meta{color:#666;font-size:.9em}.notes{white-space:pre-wrap}\n\
+.reply{margin-left:2rem;border-left:3px solid #ddd;padding-left:.75rem}\n\
+ul.comments,ul.tasks{list-style:none;padding-left:0}\n\
+</style>\n";
+
+/// Escape text for HTML content and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Relative URL of a bundle path, percent-encoding what browsers won't accept
+fn href(path: &str) -> String {
+    path.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-_.~/".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
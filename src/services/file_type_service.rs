@@ -0,0 +1,380 @@
+//! File type service module
+//!
+//! Single registry mapping MIME types and filename extensions to a document
+//! category, an icon hint and the kind of preview clients can show. Every
+//! ingestion path (app upload, email, import) categorizes through here, and
+//! the registry is served at `/file-types` so clients render the same icons.
+//!
+//! # Architecture Decision
+//! Classification tries an exact MIME match first, then a MIME family
+//! (`image/*`), then the extension. Generic types such as
+//! `application/octet-stream` or a bare ZIP container say nothing about the
+//! file, so they skip straight to the extension; that is how drawings, which
+//! mail clients rarely label properly, are recognised.
+
+use serde::Serialize;
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+
+/// What a client can show for a file without downloading a native app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewKind {
+    /// Displayable in an `<img>` (use `preview_url` when the document has one)
+    Image,
+    /// Displayable in a PDF viewer
+    Pdf,
+    /// Playable in a `<video>`
+    Video,
+    /// Playable in an `<audio>`
+    Audio,
+    /// No in-browser preview; offer a download
+    None,
+}
+
+/// One entry of the file type registry
+#[derive(Debug, Serialize)]
+pub struct FileTypeInfo {
+    /// Stable identifier (e.g. "dxf", "sketchup")
+    pub id: &'static str,
+    /// Human-readable name
+    pub label: &'static str,
+    /// Category stored in `documents.file_type`
+    pub category: &'static str,
+    /// Icon hint for clients
+    pub icon: &'static str,
+    pub preview: PreviewKind,
+    /// MIME types matched exactly
+    pub mime_types: &'static [&'static str],
+    /// MIME families matched by prefix (e.g. "image/")
+    pub mime_prefixes: &'static [&'static str],
+    /// Lowercase extensions without dot; the first one is the preferred extension
+    pub extensions: &'static [&'static str],
+}
+
+/// The registry; order matters only for MIME family matches
+static FILE_TYPES: &[FileTypeInfo] = &[
+    FileTypeInfo {
+        id: "dxf",
+        label: "Desenho DXF",
+        category: "cad",
+        icon: "cad-dxf",
+        preview: PreviewKind::Image,
+        mime_types: &[
+            "image/vnd.dxf",
+            "image/x-dxf",
+            "application/dxf",
+            "application/x-dxf",
+        ],
+        mime_prefixes: &[],
+        extensions: &["dxf"],
+    },
+    FileTypeInfo {
+        id: "dwg",
+        label: "Desenho AutoCAD",
+        category: "cad",
+        icon: "cad-dwg",
+        preview: PreviewKind::None,
+        mime_types: &[
+            "image/vnd.dwg",
+            "image/x-dwg",
+            "application/acad",
+            "application/x-acad",
+            "application/dwg",
+            "application/x-dwg",
+        ],
+        mime_prefixes: &[],
+        extensions: &["dwg"],
+    },
+    FileTypeInfo {
+        id: "sketchup",
+        label: "Modelo SketchUp",
+        category: "model",
+        icon: "sketchup",
+        preview: PreviewKind::None,
+        mime_types: &["application/vnd.sketchup.skp", "application/x-sketchup"],
+        mime_prefixes: &[],
+        extensions: &["skp"],
+    },
+    FileTypeInfo {
+        id: "rhino",
+        label: "Modelo Rhino",
+        category: "model",
+        icon: "rhino",
+        preview: PreviewKind::None,
+        mime_types: &["model/vnd.3dm", "application/x-3dm"],
+        mime_prefixes: &[],
+        extensions: &["3dm"],
+    },
+    FileTypeInfo {
+        id: "image",
+        label: "Imagem",
+        category: "image",
+        icon: "image",
+        preview: PreviewKind::Image,
+        mime_types: &[],
+        mime_prefixes: &["image/"],
+        extensions: &[
+            "jpg", "jpeg", "png", "gif", "webp", "heic", "heif", "bmp", "tif", "tiff", "svg",
+        ],
+    },
+    FileTypeInfo {
+        id: "pdf",
+        label: "PDF",
+        category: "pdf",
+        icon: "pdf",
+        preview: PreviewKind::Pdf,
+        mime_types: &["application/pdf"],
+        mime_prefixes: &[],
+        extensions: &["pdf"],
+    },
+    FileTypeInfo {
+        id: "video",
+        label: "Vídeo",
+        category: "video",
+        icon: "video",
+        preview: PreviewKind::Video,
+        mime_types: &[],
+        mime_prefixes: &["video/"],
+        extensions: &["mp4", "mov", "webm", "m4v", "avi", "mkv", "3gp"],
+    },
+    FileTypeInfo {
+        id: "audio",
+        label: "Áudio",
+        category: "audio",
+        icon: "audio",
+        preview: PreviewKind::Audio,
+        mime_types: &[],
+        mime_prefixes: &["audio/"],
+        extensions: &["mp3", "m4a", "ogg", "oga", "wav", "aac", "flac", "amr"],
+    },
+    FileTypeInfo {
+        id: "spreadsheet",
+        label: "Folha de cálculo",
+        category: "excel",
+        icon: "spreadsheet",
+        preview: PreviewKind::None,
+        mime_types: &[
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            "application/vnd.ms-excel",
+            "application/vnd.oasis.opendocument.spreadsheet",
+            "text/csv",
+        ],
+        mime_prefixes: &[],
+        extensions: &["xlsx", "xls", "ods", "csv"],
+    },
+    FileTypeInfo {
+        id: "text-document",
+        label: "Documento de texto",
+        category: "word",
+        icon: "document",
+        preview: PreviewKind::None,
+        mime_types: &[
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            "application/msword",
+            "application/vnd.oasis.opendocument.text",
+            "application/rtf",
+            "text/plain",
+        ],
+        mime_prefixes: &[],
+        extensions: &["docx", "doc", "odt", "rtf", "txt"],
+    },
+    FileTypeInfo {
+        id: "archive",
+        label: "Arquivo comprimido",
+        category: "archive",
+        icon: "archive",
+        preview: PreviewKind::None,
+        mime_types: &[
+            "application/zip",
+            "application/x-7z-compressed",
+            "application/vnd.rar",
+            "application/x-rar-compressed",
+        ],
+        mime_prefixes: &[],
+        extensions: &["zip", "7z", "rar"],
+    },
+];
+
+/// MIME types too generic to classify by; the extension decides instead
+const GENERIC_MIME_TYPES: [&str; 5] = [
+    "application/octet-stream",
+    "application/zip",
+    "application/x-ole-storage",
+    "text/plain",
+    "text/xml",
+];
+
+/// Fallback for anything the registry doesn't know
+static OTHER: FileTypeInfo = FileTypeInfo {
+    id: "other",
+    label: "Ficheiro",
+    category: "other",
+    icon: "file",
+    preview: PreviewKind::None,
+    mime_types: &[],
+    mime_prefixes: &[],
+    extensions: &[],
+};
+
+/// File type service with static methods over the registry
+pub struct FileTypeService;
+
+impl FileTypeService {
+    /// All registry entries, including the "other" fallback
+    pub fn all() -> Vec<&'static FileTypeInfo> {
+        FILE_TYPES.iter().chain(std::iter::once(&OTHER)).collect()
+    }
+
+    /// Classify a file by MIME type and filename
+    pub fn classify(mime: &str, filename: &str) -> &'static FileTypeInfo {
+        let mime = mime.split(';').next().unwrap_or("").trim().to_lowercase();
+        let extension = filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+
+        let by_mime = if GENERIC_MIME_TYPES.contains(&mime.as_str()) {
+            None
+        } else {
+            FILE_TYPES
+                .iter()
+                .find(|t| t.mime_types.contains(&mime.as_str()))
+                .or_else(|| {
+                    FILE_TYPES
+                        .iter()
+                        .find(|t| t.mime_prefixes.iter().any(|p| mime.starts_with(p)))
+                })
+        };
+
+        by_mime
+            .or_else(|| {
+                FILE_TYPES
+                    .iter()
+                    .find(|t| t.extensions.contains(&extension.as_str()))
+            })
+            .unwrap_or(&OTHER)
+    }
+
+    /// Category to store in `documents.file_type`
+    pub fn categorize(mime: &str, filename: &str) -> String {
+        Self::classify(mime, filename).category.to_string()
+    }
+
+    /// Preferred extension for a MIME type, for files arriving without one
+    ///
+    /// Family entries use the MIME subtype when it is a known extension
+    /// (`image/png` -> "png"), otherwise the entry's first extension.
+    pub fn extension_for(mime: &str) -> Option<&'static str> {
+        let mime = mime.split(';').next().unwrap_or("").trim().to_lowercase();
+        let subtype = mime
+            .rsplit('/')
+            .next()
+            .unwrap_or("")
+            .trim_start_matches("x-");
+
+        let info = FILE_TYPES
+            .iter()
+            .find(|t| t.mime_types.contains(&mime.as_str()))
+            .or_else(|| {
+                FILE_TYPES
+                    .iter()
+                    .find(|t| t.mime_prefixes.iter().any(|p| mime.starts_with(p)))
+            })?;
+        info.extensions
+            .iter()
+            .find(|ext| **ext == subtype)
+            .or_else(|| info.extensions.first())
+            .copied()
+    }
+
+    /// Re-categorize documents stored as "other" that the registry now knows
+    ///
+    /// Run at startup so drawings uploaded before the registry existed get
+    /// their proper category.
+    pub async fn reclassify_documents(pool: &DbPool) -> AppResult<()> {
+        let docs: Vec<(String, Option<String>, String, String)> = sqlx::query_as(
+            "SELECT id, mime_type, original_name, file_path FROM documents WHERE file_type = 'other'",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut updated = 0;
+        for (id, mime, original_name, file_path) in docs {
+            let mime = mime.unwrap_or_default();
+            let mut info = Self::classify(&mime, &original_name);
+            if info.id == OTHER.id {
+                info = Self::classify(&mime, &file_path);
+            }
+            if info.id != OTHER.id {
+                sqlx::query("UPDATE documents SET file_type = ? WHERE id = ?")
+                    .bind(info.category)
+                    .bind(&id)
+                    .execute(pool)
+                    .await?;
+                updated += 1;
+            }
+        }
+
+        if updated > 0 {
+            tracing::info!(
+                "Re-categorized {} document(s) using the file type registry",
+                updated
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(mime: &str, filename: &str) -> &'static str {
+        FileTypeService::classify(mime, filename).id
+    }
+
+    #[test]
+    fn exact_mime_type_wins_over_family_and_extension() {
+        assert_eq!(id("image/vnd.dxf", "plan.png"), "dxf");
+        assert_eq!(id("application/pdf", "scan.jpg"), "pdf");
+    }
+
+    #[test]
+    fn mime_family_matches_by_prefix() {
+        assert_eq!(id("image/heic", "IMG_0001"), "image");
+        assert_eq!(id("video/mp4", "clip.jpg"), "video");
+        assert_eq!(id("audio/x-wav", ""), "audio");
+    }
+
+    #[test]
+    fn mime_type_is_normalized() {
+        assert_eq!(id("Image/PNG; charset=binary", "photo"), "image");
+        assert_eq!(id(" application/PDF ", "doc"), "pdf");
+    }
+
+    #[test]
+    fn generic_mime_types_fall_back_to_the_extension() {
+        assert_eq!(id("application/octet-stream", "plan.DXF"), "dxf");
+        assert_eq!(id("application/zip", "house.skp"), "sketchup");
+        assert_eq!(id("application/x-ole-storage", "budget.xls"), "spreadsheet");
+        assert_eq!(id("text/plain", "notes.txt"), "text-document");
+        assert_eq!(id("text/xml", "drawing.svg"), "image");
+    }
+
+    #[test]
+    fn unknown_files_are_other() {
+        assert_eq!(id("application/x-unknown", "file.xyz"), "other");
+        assert_eq!(id("application/octet-stream", "README"), "other");
+        assert_eq!(id("", ""), "other");
+        assert_eq!(id("text/plain", "page.html"), "other");
+    }
+
+    #[test]
+    fn categorize_returns_the_stored_category() {
+        assert_eq!(FileTypeService::categorize("image/x-dwg", "a.dwg"), "cad");
+        assert_eq!(FileTypeService::categorize("text/csv", "a.csv"), "excel");
+        assert_eq!(FileTypeService::categorize("", "a.bin"), "other");
+    }
+}
@@ -5,9 +5,8 @@
 
 use crate::db::DbPool;
 use crate::error::{AppError, AppResult};
-use crate::models::{
-    Document, DocumentResponse, ForumMessage, ForumMessageResponse, TaskItem, TaskItemResponse,
-};
+use crate::models::{Document, ForumMessage, ForumMessageResponse, TaskItem, TaskItemResponse};
+use crate::services::DocumentService;
 
 /// Forum service with static methods for forum operations
 pub struct ForumService;
@@ -59,6 +58,38 @@ impl ForumService {
         Self::get_by_id(pool, &id).await
     }
 
+    /// Create one photo message for several documents uploaded together
+    ///
+    /// The first document is the message's `document_id`; with more than one
+    /// the full ordered list is stored as the album.
+    pub async fn create_album_message(
+        pool: &DbPool,
+        project_id: &str,
+        document_ids: &[String],
+        author_name: &str,
+    ) -> AppResult<ForumMessage> {
+        let first = document_ids
+            .first()
+            .ok_or_else(|| AppError::BadRequest("An album needs at least one document".into()))?;
+        let msg = Self::create_photo_message(pool, project_id, first, author_name).await?;
+
+        if document_ids.len() > 1 {
+            for (position, document_id) in document_ids.iter().enumerate() {
+                sqlx::query(
+                    "INSERT INTO forum_message_documents (message_id, document_id, position) VALUES (?, ?, ?)",
+                )
+                .bind(&msg.id)
+                .bind(document_id)
+                .bind(position as i64)
+                .execute(pool)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to create album: {}", e)))?;
+            }
+        }
+
+        Ok(msg)
+    }
+
     pub async fn create_voice_message(
         pool: &DbPool,
         project_id: &str,
@@ -229,6 +260,17 @@ impl ForumService {
         .await
         .map_err(|e| AppError::Internal(format!("Failed to toggle task item: {}", e)))?;
 
+        sqlx::query(
+            "INSERT INTO task_item_events (id, item_id, completed, actor) VALUES (?, ?, ?, ?)",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(item_id)
+        .bind(new_completed)
+        .bind(completed_by)
+        .execute(pool)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to record task item event: {}", e)))?;
+
         let updated: TaskItem = sqlx::query_as("SELECT * FROM task_items WHERE id = ?")
             .bind(item_id)
             .fetch_one(pool)
@@ -265,7 +307,10 @@ impl ForumService {
                     .fetch_optional(pool)
                     .await
                     .map_err(|e| AppError::Internal(format!("Failed to get document: {}", e)))?;
-                doc.map(DocumentResponse::from_document)
+                match doc {
+                    Some(doc) => Some(DocumentService::build_response(pool, doc).await?),
+                    None => None,
+                }
             } else {
                 None
             }
@@ -273,6 +318,29 @@ impl ForumService {
             None
         };
 
+        // Get all documents of album PHOTO messages
+        let documents = if msg.message_type == "PHOTO" {
+            let album: Vec<Document> = sqlx::query_as(
+                r#"
+                SELECT d.* FROM documents d
+                JOIN forum_message_documents fmd ON fmd.document_id = d.id
+                WHERE fmd.message_id = ?
+                ORDER BY fmd.position
+                "#,
+            )
+            .bind(&msg.id)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to get album: {}", e)))?;
+            if album.is_empty() {
+                None
+            } else {
+                Some(DocumentService::build_responses(pool, album).await?)
+            }
+        } else {
+            None
+        };
+
         // Get task items for TASK_LIST messages
         let items = if msg.message_type == "TASK_LIST" {
             let task_items = Self::get_task_items(pool, &msg.id).await?;
@@ -296,6 +364,7 @@ impl ForumService {
             created_at: msg.created_at,
             reply_count,
             document,
+            documents,
             items,
         })
     }
@@ -0,0 +1,1056 @@
+//! Import service module
+//!
+//! Recreates a project from an export bundle (see the export service): the
+//! project, its documents with tags, comments and annotations, voice memos,
+//! forum messages with replies and task lists with their history.
+//!
+//! # Architecture Decision
+//! An import is planned in full before anything is written, and a dry run
+//! returns that plan as the report. Files are verified against their
+//! checksums and go through the same content checks and scan as uploads,
+//! then stored under their content key, so content this instance already has
+//! isn't stored twice. A bundle is no more trusted than an upload: file
+//! types, MIME types and voice memo formats are detected again rather than
+//! taken from it, and each file is held to the upload size limits. Comments
+//! and annotation layers get the checks the API applies to them, and those
+//! that fail are reported instead of imported. All rows are inserted in one
+//! transaction: a failed import leaves no half project.
+//!
+//! IDs are either preserved, which makes re-importing the same bundle skip
+//! what is already there, or remapped to new ones. Documents whose content
+//! is already in the target project are not duplicated; forum messages
+//! pointing at them are linked to the existing document instead.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use async_zip::tokio::read::seek::ZipFileReader;
+use sha2::{Digest, Sha256};
+use sqlx::{Sqlite, Transaction};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{
+    DocumentSource, DocumentStatus, ExportedAnnotation, ExportedComment, ExportedDocument,
+    ExportedForumMessage, ImportOptions, ImportReport, ProjectExport,
+};
+use crate::services::annotation_service::LayerBudget;
+use crate::services::content_service::ContentCheck;
+use crate::services::document_service::VoiceMemo;
+use crate::services::export_service::{EXPORT_FORMAT, EXPORT_VERSION};
+use crate::services::lifecycle_service::{ACTIVE_ALIAS, ARCHIVED_STATUS, LEGACY_ACTIVE_STATE};
+use crate::services::scan_service::QuarantineOrigin;
+use crate::services::storage_service::{KeyPin, STAGING_DIR};
+use crate::services::{
+    AnnotationService, AudioService, CommentService, ContentService, DocumentService,
+    FileTypeService, LifecycleService, PreviewService, ProjectService, ScanService, StorageService,
+    VideoService,
+};
+
+/// An export bundle opened for reading
+struct Bundle {
+    zip: ZipFileReader<BufReader<File>>,
+    /// Entry index by path
+    entries: HashMap<String, usize>,
+    /// Bytes extracted so far
+    unpacked: u64,
+}
+
+impl Bundle {
+    async fn open(path: &str) -> AppResult<Self> {
+        let file = BufReader::new(File::open(path).await?);
+        let zip = ZipFileReader::with_tokio(file)
+            .await
+            .map_err(|e| AppError::BadRequest(format!("Not a ZIP archive: {}", e)))?;
+        let entries = zip
+            .file()
+            .entries()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((entry.filename().as_str().ok()?.to_string(), i)))
+            .collect();
+        Ok(Self {
+            zip,
+            entries,
+            unpacked: 0,
+        })
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Read a small entry such as `project.json` into memory
+    async fn read(&mut self, path: &str) -> AppResult<Vec<u8>> {
+        let limit = ContentService::max_upload_size();
+        let mut data = Vec::new();
+        self.reader(path)
+            .await?
+            .take(limit + 1)
+            .read_to_end(&mut data)
+            .await?;
+        if data.len() as u64 > limit {
+            return Err(AppError::PayloadTooLarge(format!(
+                "'{}' is larger than {} MB",
+                path,
+                limit / (1024 * 1024)
+            )));
+        }
+        Ok(data)
+    }
+
+    /// Whether the files extracted so far reached the import limit
+    fn exhausted(&self) -> bool {
+        self.unpacked >= ContentService::max_import_size()
+    }
+
+    /// Extract an entry into the staging directory, returning its path and SHA-256
+    ///
+    /// The entry's declared size isn't trusted: extraction stops as soon as
+    /// the entry is larger than any upload may be, or all entries together
+    /// unpack to more than the import limit.
+    async fn extract(&mut self, path: &str) -> AppResult<(String, String)> {
+        let max_entry = ContentService::max_upload_size();
+        let max_total = ContentService::max_import_size();
+        let unpacked = self.unpacked;
+        let staged = StorageService::staging_path("part");
+        let mut size = 0u64;
+        let written = async {
+            let mut reader = self.reader(path).await?;
+            let mut file = File::create(&staged).await?;
+            let mut hasher = Sha256::new();
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                let n = reader.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                size += n as u64;
+                if size > max_entry {
+                    return Err(AppError::PayloadTooLarge(format!(
+                        "file is larger than the {} MB upload limit",
+                        max_entry / (1024 * 1024)
+                    )));
+                }
+                if unpacked + size > max_total {
+                    return Err(AppError::PayloadTooLarge(format!(
+                        "bundle unpacks to more than {} MB",
+                        max_total / (1024 * 1024)
+                    )));
+                }
+                hasher.update(&buf[..n]);
+                file.write_all(&buf[..n]).await?;
+            }
+            file.flush().await?;
+            Ok::<_, AppError>(format!("{:x}", hasher.finalize()))
+        }
+        .await;
+        self.unpacked += size;
+        match written {
+            Ok(checksum) => Ok((staged, checksum)),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&staged).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn reader(&mut self, path: &str) -> AppResult<impl tokio::io::AsyncRead + Unpin + '_> {
+        let index = *self.entries.get(path).ok_or_else(|| {
+            AppError::BadRequest(format!("'{}' is missing from the bundle", path))
+        })?;
+        let reader = self
+            .zip
+            .reader_with_entry(index)
+            .await
+            .map_err(|e| AppError::BadRequest(format!("Failed to read '{}': {}", path, e)))?;
+        Ok(reader.compat())
+    }
+}
+
+/// A bundle file that passed the upload checks and was stored
+struct StoredFile {
+    /// Storage key, pinned until the rows are written
+    file: KeyPin,
+    check: ContentCheck,
+    scanned: bool,
+}
+
+/// A document to create
+struct NewDocument<'a> {
+    doc: &'a ExportedDocument,
+    id: String,
+    /// Whether the voice memo is in the bundle
+    with_memo: bool,
+    file: Option<StoredFile>,
+    memo: Option<VoiceMemo>,
+    /// Comments and annotation layers that passed their checks
+    comments: Vec<&'a ExportedComment>,
+    annotations: Vec<&'a ExportedAnnotation>,
+}
+
+/// A forum message to create
+struct NewMessage<'a> {
+    msg: &'a ExportedForumMessage,
+    id: String,
+    parent_id: Option<String>,
+    document_ids: Vec<String>,
+    /// Whether the voice message is in the bundle
+    with_memo: bool,
+    audio: Option<KeyPin>,
+}
+
+/// Everything an import will write
+struct Plan<'a> {
+    report: ImportReport,
+    preserve_ids: bool,
+    documents: Vec<NewDocument<'a>>,
+    messages: Vec<NewMessage<'a>>,
+}
+
+/// Import service with static methods for project bundles
+pub struct ImportService;
+
+impl ImportService {
+    /// Import a bundle sent as the `bundle` field of a multipart upload
+    pub async fn import_upload(
+        pool: &DbPool,
+        mut multipart: axum::extract::Multipart,
+        options: &ImportOptions,
+    ) -> AppResult<ImportReport> {
+        tokio::fs::create_dir_all(STAGING_DIR).await?;
+
+        while let Some(mut field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| AppError::BadRequest(e.to_string()))?
+        {
+            if field.name() != Some("bundle") {
+                continue;
+            }
+
+            let staged = StorageService::staging_path("zip");
+            let received = async {
+                let mut file = File::create(&staged).await?;
+                while let Some(chunk) = field.chunk().await.map_err(|e| {
+                    if e.status() == axum::http::StatusCode::PAYLOAD_TOO_LARGE {
+                        AppError::PayloadTooLarge(format!(
+                            "Import bundles are limited to {} MB",
+                            ContentService::max_import_size() / (1024 * 1024)
+                        ))
+                    } else {
+                        AppError::BadRequest(format!("Failed to read bundle: {}", e))
+                    }
+                })? {
+                    file.write_all(&chunk).await?;
+                }
+                file.flush().await?;
+                Ok::<_, AppError>(())
+            }
+            .await;
+            let result = match received {
+                Ok(()) => Self::import(pool, &staged, options).await,
+                Err(e) => Err(e),
+            };
+            let _ = tokio::fs::remove_file(&staged).await;
+            return result;
+        }
+
+        Err(AppError::BadRequest("No bundle file provided".into()))
+    }
+
+    /// Import the export bundle at `path`
+    pub async fn import(
+        pool: &DbPool,
+        path: &str,
+        options: &ImportOptions,
+    ) -> AppResult<ImportReport> {
+        let mut bundle = Bundle::open(path).await?;
+        let metadata = bundle.read("project.json").await?;
+        let export: ProjectExport = serde_json::from_slice(&metadata)
+            .map_err(|e| AppError::BadRequest(format!("Invalid project.json: {}", e)))?;
+        if export.format != EXPORT_FORMAT {
+            return Err(AppError::BadRequest("Not a project export bundle".into()));
+        }
+        if export.version > EXPORT_VERSION {
+            return Err(AppError::BadRequest(format!(
+                "Bundle version {} is newer than this server supports ({})",
+                export.version, EXPORT_VERSION
+            )));
+        }
+
+        let mut plan = Self::plan(pool, &export, &bundle, options).await?;
+        if options.dry_run {
+            return Ok(plan.report);
+        }
+
+        if let Err(e) = Self::store_files(pool, &mut bundle, &mut plan).await {
+            StorageService::remove_unreferenced(pool, &Self::release_files(plan)).await;
+            return Err(e);
+        }
+        let mut tx = pool.begin().await?;
+        let written = Self::write_rows(&mut tx, &export, &mut plan).await;
+        if let Err(e) = written {
+            drop(tx);
+            StorageService::remove_unreferenced(pool, &Self::release_files(plan)).await;
+            return Err(e);
+        }
+        tx.commit().await?;
+
+        for new in &plan.documents {
+            Self::process(pool, &new.id).await;
+        }
+        tracing::info!(
+            "Imported {} document(s) and {} forum message(s) into project {}",
+            plan.report.documents_created,
+            plan.report.forum_messages_created,
+            plan.report.project_id
+        );
+        Ok(plan.report)
+    }
+
+    /// Work out what to create, skip or link, without writing anything
+    async fn plan<'a>(
+        pool: &DbPool,
+        export: &'a ProjectExport,
+        bundle: &Bundle,
+        options: &ImportOptions,
+    ) -> AppResult<Plan<'a>> {
+        let mut report = ImportReport {
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
+
+        // Target project
+        let existing_project = match &options.project_id {
+            Some(id) => Some(ProjectService::get_by_id(pool, id).await?),
+            None if options.preserve_ids => {
+                match ProjectService::get_by_id(pool, &export.project.id).await {
+                    Ok(project) => Some(project),
+                    Err(AppError::NotFound(_)) => None,
+                    Err(e) => return Err(e),
+                }
+            }
+            None => None,
+        };
+        match existing_project {
+            Some(project) => {
+                report.project_id = project.id;
+                report.project_name = project.name;
+            }
+            None => {
+                report.project_id = Self::new_id(options, &export.project.id);
+                report.project_name = export.project.name.clone();
+                report.project_created = true;
+            }
+        }
+
+        // Content already in the target project
+        let existing_content: HashMap<String, String> = sqlx::query_as(
+            "SELECT checksum, id FROM documents WHERE project_id = ? AND checksum IS NOT NULL",
+        )
+        .bind(&report.project_id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        let mut document_ids: HashMap<&str, String> = HashMap::new();
+        let mut documents = Vec::new();
+        let mut new_content = HashSet::new();
+        for doc in &export.documents {
+            if options.preserve_ids && Self::exists(pool, "documents", &doc.id).await? {
+                report.documents_existing += 1;
+                document_ids.insert(&doc.id, doc.id.clone());
+                continue;
+            }
+            if let Some(existing) = doc
+                .checksum
+                .as_ref()
+                .and_then(|checksum| existing_content.get(checksum))
+            {
+                report.documents_duplicate += 1;
+                document_ids.insert(&doc.id, existing.clone());
+                continue;
+            }
+            let Some(file) = doc.file.as_deref().filter(|file| bundle.contains(file)) else {
+                report.problems.push(format!(
+                    "Document '{}': file is missing from the bundle",
+                    doc.original_name
+                ));
+                continue;
+            };
+
+            let with_memo = match &doc.voice_memo {
+                Some(memo) if bundle.contains(&memo.file) => true,
+                Some(_) => {
+                    report.problems.push(format!(
+                        "Document '{}': voice memo is missing from the bundle",
+                        doc.original_name
+                    ));
+                    false
+                }
+                None => false,
+            };
+
+            if let Some(checksum) = &doc.checksum {
+                let key = StorageService::content_key(checksum, Self::extension(file));
+                if new_content.insert(key.clone()) && !StorageService::exists(&key).await? {
+                    report.files_new += 1;
+                }
+            } else {
+                report.files_new += 1;
+            }
+
+            let (comments, annotations) = Self::vet_children(
+                doc,
+                &doc.file_type,
+                doc.comments.iter().collect(),
+                doc.annotations.iter().collect(),
+                &mut report.problems,
+            );
+
+            let id = Self::new_id(options, &doc.id);
+            document_ids.insert(&doc.id, id.clone());
+            report.documents_created += 1;
+            report.comments_created += comments.len();
+            report.annotations_created += annotations.len();
+            documents.push(NewDocument {
+                doc,
+                id,
+                with_memo,
+                file: None,
+                memo: None,
+                comments,
+                annotations,
+            });
+        }
+
+        // Parents come before their replies in the bundle
+        let mut message_ids: HashMap<&str, String> = HashMap::new();
+        let mut messages = Vec::new();
+        for msg in &export.forum {
+            if options.preserve_ids && Self::exists(pool, "forum_messages", &msg.id).await? {
+                report.forum_messages_existing += 1;
+                message_ids.insert(&msg.id, msg.id.clone());
+                continue;
+            }
+            let parent_id = match &msg.parent_id {
+                Some(parent) => match message_ids.get(parent.as_str()) {
+                    Some(id) => Some(id.clone()),
+                    None => {
+                        report.problems.push(format!(
+                            "Forum message {}: replies to a message not in the bundle",
+                            msg.id
+                        ));
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            let with_memo = match &msg.voice_memo {
+                Some(memo) if bundle.contains(memo) => true,
+                Some(_) => {
+                    report.problems.push(format!(
+                        "Forum message {}: voice message is missing from the bundle",
+                        msg.id
+                    ));
+                    false
+                }
+                None => false,
+            };
+
+            let id = Self::new_id(options, &msg.id);
+            message_ids.insert(&msg.id, id.clone());
+            report.forum_messages_created += 1;
+            report.task_items_created += msg.items.len();
+            messages.push(NewMessage {
+                msg,
+                id,
+                parent_id,
+                document_ids: msg
+                    .document_ids
+                    .iter()
+                    .filter_map(|id| document_ids.get(id.as_str()).cloned())
+                    .collect(),
+                with_memo,
+                audio: None,
+            });
+        }
+
+        Ok(Plan {
+            report,
+            preserve_ids: options.preserve_ids,
+            documents,
+            messages,
+        })
+    }
+
+    /// Extract, verify, scan and store the files of new documents and messages
+    ///
+    /// A document whose file fails is dropped from the plan; a failed voice
+    /// memo only leaves the memo out. Once the bundle has unpacked to the
+    /// import limit the whole import fails.
+    async fn store_files(pool: &DbPool, bundle: &mut Bundle, plan: &mut Plan<'_>) -> AppResult<()> {
+        tokio::fs::create_dir_all(STAGING_DIR).await?;
+
+        let mut failed = HashSet::new();
+        for new in plan.documents.iter_mut() {
+            let doc = new.doc;
+            let file = doc.file.as_deref().unwrap_or_default();
+            let origin = QuarantineOrigin {
+                original_name: &doc.original_name,
+                mime_type: doc.mime_type.as_deref(),
+                source: DocumentSource::Import,
+                uploaded_by: doc.uploaded_by.as_deref(),
+                email: None,
+            };
+            match Self::store_staged(pool, bundle, file, doc.checksum.as_deref(), &origin).await {
+                Ok(stored) => new.file = Some(stored),
+                Err(e) if bundle.exhausted() => return Err(e),
+                Err(e) => {
+                    plan.report
+                        .problems
+                        .push(format!("Document '{}': {}", doc.original_name, e));
+                    failed.insert(new.id.clone());
+                    continue;
+                }
+            }
+
+            if let (true, Some(memo)) = (new.with_memo, &doc.voice_memo) {
+                let origin = QuarantineOrigin {
+                    original_name: &memo.file,
+                    mime_type: None,
+                    ..origin
+                };
+                match Self::store_memo(pool, bundle, &memo.file, &origin).await {
+                    Ok(memo) => new.memo = Some(memo),
+                    Err(e) if bundle.exhausted() => return Err(e),
+                    Err(e) => plan.report.problems.push(format!(
+                        "Document '{}': voice memo not imported: {}",
+                        doc.original_name, e
+                    )),
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            for new in plan.documents.iter().filter(|d| failed.contains(&d.id)) {
+                plan.report.documents_created -= 1;
+                plan.report.comments_created -= new.comments.len();
+                plan.report.annotations_created -= new.annotations.len();
+            }
+            plan.documents.retain(|d| !failed.contains(&d.id));
+            for new in plan.messages.iter_mut() {
+                new.document_ids.retain(|id| !failed.contains(id));
+            }
+        }
+
+        for new in plan.messages.iter_mut().filter(|m| m.with_memo) {
+            let memo = new.msg.voice_memo.as_deref().unwrap_or_default();
+            let origin = QuarantineOrigin {
+                original_name: memo,
+                mime_type: None,
+                source: DocumentSource::Import,
+                uploaded_by: Some(&new.msg.author_name),
+                email: None,
+            };
+            match Self::store_memo(pool, bundle, memo, &origin).await {
+                Ok(memo) => new.audio = Some(memo.file),
+                Err(e) if bundle.exhausted() => return Err(e),
+                Err(e) => plan.report.problems.push(format!(
+                    "Forum message {}: voice message not imported: {}",
+                    new.msg.id, e
+                )),
+            }
+        }
+        Ok(())
+    }
+
+    /// Extract one bundle entry and store it like an upload
+    ///
+    /// The bundle's MIME type is only the claimed type: the content check
+    /// decides what the file is, and the storage key gets the extension of
+    /// that type rather than whatever the bundle named the entry.
+    async fn store_staged(
+        pool: &DbPool,
+        bundle: &mut Bundle,
+        path: &str,
+        expected: Option<&str>,
+        origin: &QuarantineOrigin<'_>,
+    ) -> AppResult<StoredFile> {
+        let claimed = origin.mime_type.unwrap_or("application/octet-stream");
+        let (staged, checksum, check) = Self::stage(bundle, path, expected, claimed).await?;
+        let scanned = ScanService::vet_file(pool, &staged, origin).await?;
+        let key = StorageService::content_key(
+            &checksum,
+            Self::stored_extension(&check.effective_mime, origin.original_name),
+        );
+        let file = StorageService::store_staged(&staged, &key).await?;
+        Ok(StoredFile {
+            file,
+            check,
+            scanned,
+        })
+    }
+
+    /// Extract one voice memo and store it like a recorded one, keyed with its detected format
+    async fn store_memo(
+        pool: &DbPool,
+        bundle: &mut Bundle,
+        path: &str,
+        origin: &QuarantineOrigin<'_>,
+    ) -> AppResult<VoiceMemo> {
+        let (staged, checksum, _) =
+            Self::stage(bundle, path, None, "application/octet-stream").await?;
+        let (format, duration_seconds) = match AudioService::inspect(&staged).await {
+            Ok(inspected) => inspected,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&staged).await;
+                return Err(e);
+            }
+        };
+        ScanService::vet_file(pool, &staged, origin).await?;
+        let file_path = StorageService::content_key(&checksum, format.as_str());
+        let file = StorageService::store_staged(&staged, &file_path).await?;
+        Ok(VoiceMemo {
+            file,
+            format,
+            duration_seconds,
+        })
+    }
+
+    /// Extract an entry to staging, verify its checksum and check it against the upload policy
+    ///
+    /// A rejected file is no longer in the staging directory when this fails.
+    async fn stage(
+        bundle: &mut Bundle,
+        path: &str,
+        expected: Option<&str>,
+        claimed_mime: &str,
+    ) -> AppResult<(String, String, ContentCheck)> {
+        let (staged, checksum) = bundle.extract(path).await?;
+        let checked = if expected.is_some_and(|expected| expected != checksum) {
+            Err(AppError::BadRequest(
+                "file doesn't match its checksum".into(),
+            ))
+        } else {
+            ContentService::check_file(&staged, claimed_mime).await
+        };
+        match checked {
+            Ok(check) => Ok((staged, checksum, check)),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&staged).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Comments and annotation layers of a document of `file_type` that pass
+    /// the checks the API applies to them; the others go to `problems`
+    ///
+    /// Replies are dropped with their thread.
+    fn vet_children<'a>(
+        doc: &ExportedDocument,
+        file_type: &str,
+        comments: Vec<&'a ExportedComment>,
+        annotations: Vec<&'a ExportedAnnotation>,
+        problems: &mut Vec<String>,
+    ) -> (Vec<&'a ExportedComment>, Vec<&'a ExportedAnnotation>) {
+        let mut threads = HashSet::new();
+        let mut kept_comments = Vec::with_capacity(comments.len());
+        for comment in comments {
+            let checked = match &comment.parent_id {
+                Some(parent) if !threads.contains(parent.as_str()) => {
+                    Err(AppError::BadRequest("Its thread is not imported".into()))
+                }
+                _ if comment.content.trim().is_empty() => {
+                    Err(AppError::BadRequest("Comment cannot be empty".into()))
+                }
+                Some(_) => Ok(()),
+                None => match (comment.x, comment.y) {
+                    (Some(x), Some(y)) => {
+                        CommentService::validate_anchor(file_type, Some((x, y)), comment.page)
+                    }
+                    (None, None) => CommentService::validate_anchor(file_type, None, comment.page),
+                    _ => Err(AppError::BadRequest(
+                        "Comment coordinates need both x and y".into(),
+                    )),
+                },
+            };
+            match checked {
+                Ok(()) => {
+                    if comment.parent_id.is_none() {
+                        threads.insert(comment.id.as_str());
+                    }
+                    kept_comments.push(comment);
+                }
+                Err(e) => problems.push(format!(
+                    "Document '{}': comment {} not imported: {}",
+                    doc.original_name, comment.id, e
+                )),
+            }
+        }
+
+        let mut budget = LayerBudget::default();
+        let mut kept_annotations = Vec::with_capacity(annotations.len());
+        for layer in annotations {
+            let checked = if file_type == "image" {
+                AnnotationService::validate_shapes(&layer.shapes)
+                    .and_then(|_| budget.add(&layer.shapes))
+            } else {
+                Err(AppError::BadRequest(
+                    "Annotations are only supported on image documents".into(),
+                ))
+            };
+            match checked {
+                Ok(()) => kept_annotations.push(layer),
+                Err(e) => problems.push(format!(
+                    "Document '{}': annotation layer {} not imported: {}",
+                    doc.original_name, layer.id, e
+                )),
+            }
+        }
+
+        (kept_comments, kept_annotations)
+    }
+
+    /// Insert all planned rows
+    async fn write_rows(
+        tx: &mut Transaction<'_, Sqlite>,
+        export: &ProjectExport,
+        plan: &mut Plan<'_>,
+    ) -> AppResult<()> {
+        let report = &mut plan.report;
+        let preserve_ids = plan.preserve_ids;
+        let project = &export.project;
+
+        if report.project_created {
+            // Bundles from before the lifecycle say ACTIVE, like migrated projects
+            let mut status = LifecycleService::normalize(&project.status);
+            if status == ACTIVE_ALIAS {
+                status = LEGACY_ACTIVE_STATE.to_string();
+            }
+            if !LifecycleService::exists(&mut **tx, &status).await? {
+                let initial = LifecycleService::initial_state(&mut **tx).await?;
+                report.problems.push(format!(
+                    "Project status '{}' is unknown here, imported as {}",
+                    project.status, initial
+                ));
+                status = initial;
+            }
+            sqlx::query(
+                r#"
+                INSERT INTO projects (id, name, status, address, client_phone, created_at, archived_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&report.project_id)
+            .bind(&project.name)
+            .bind(&status)
+            .bind(&project.address)
+            .bind(&project.client_phone)
+            .bind(&project.created_at)
+            .bind(project.archived_at.as_ref().filter(|_| status == ARCHIVED_STATUS))
+            .execute(&mut **tx)
+            .await?;
+            ProjectService::record_status(&mut **tx, &report.project_id, None, &status, None)
+                .await?;
+        }
+
+        for new in &plan.documents {
+            let doc = new.doc;
+            let file = new.file.as_ref();
+            let file_type = FileTypeService::categorize(
+                file.map_or("application/octet-stream", |f| &f.check.effective_mime),
+                &doc.original_name,
+            );
+            // The bundle's file type was only a claim; check again with the detected one
+            let (comments, annotations) = Self::vet_children(
+                doc,
+                &file_type,
+                new.comments.clone(),
+                new.annotations.clone(),
+                &mut report.problems,
+            );
+            report.comments_created -= new.comments.len() - comments.len();
+            report.annotations_created -= new.annotations.len() - annotations.len();
+            let status = DocumentStatus::parse(&doc.status).unwrap_or(DocumentStatus::Default);
+            let tags = DocumentService::normalize_tags(&doc.tags).unwrap_or_default();
+            sqlx::query(
+                r#"
+                INSERT INTO documents (id, project_id, file_path, file_type, original_name, uploaded_at, captured_at,
+                                       mime_type, detected_mime_type, checksum, notes, status, category,
+                                       audio_path, audio_format, audio_duration_seconds, uploaded_by, source,
+                                       email_sender, email_subject, email_message_id, scanned_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?,
+                        CASE WHEN ? THEN datetime('now') END)
+                "#,
+            )
+            .bind(&new.id)
+            .bind(&report.project_id)
+            .bind(file.map(|f| f.file.key()))
+            .bind(&file_type)
+            .bind(&doc.original_name)
+            .bind(&doc.uploaded_at)
+            .bind(&doc.captured_at)
+            .bind(file.map(|f| &f.check.claimed_mime))
+            .bind(file.and_then(|f| f.check.detected_mime.as_ref()))
+            .bind(file.and_then(|f| StorageService::checksum_of_key(f.file.key())))
+            .bind(&doc.notes)
+            .bind(status.as_str())
+            .bind(&doc.category)
+            .bind(new.memo.as_ref().map(|m| m.file.key()))
+            .bind(new.memo.as_ref().map(|m| m.format.as_str()))
+            .bind(new.memo.as_ref().and_then(|m| m.duration_seconds))
+            .bind(&doc.uploaded_by)
+            .bind(DocumentSource::Import.as_str())
+            .bind(&doc.email_sender)
+            .bind(&doc.email_subject)
+            .bind(&doc.email_message_id)
+            .bind(file.is_some_and(|f| f.scanned))
+            .execute(&mut **tx)
+            .await?;
+
+            for tag in tags {
+                sqlx::query("INSERT OR IGNORE INTO document_tags (document_id, tag) VALUES (?, ?)")
+                    .bind(&new.id)
+                    .bind(tag)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+
+            // Thread roots come before their replies in the bundle
+            let mut comment_ids: HashMap<&str, String> = HashMap::new();
+            for comment in comments {
+                let parent_id = match &comment.parent_id {
+                    Some(parent) => match comment_ids.get(parent.as_str()) {
+                        Some(id) => Some(id.clone()),
+                        None => {
+                            report.comments_created -= 1;
+                            continue;
+                        }
+                    },
+                    None => None,
+                };
+                let id = Self::child_id(tx, "document_comments", preserve_ids, &comment.id).await?;
+                comment_ids.insert(&comment.id, id.clone());
+                sqlx::query(
+                    r#"
+                    INSERT INTO document_comments (id, document_id, parent_id, author_name, content, x, y, page,
+                                                   resolved, resolved_by, resolved_at, created_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(&id)
+                .bind(&new.id)
+                .bind(&parent_id)
+                .bind(&comment.author_name)
+                .bind(&comment.content)
+                .bind(comment.x)
+                .bind(comment.y)
+                .bind(comment.page)
+                .bind(comment.resolved)
+                .bind(&comment.resolved_by)
+                .bind(&comment.resolved_at)
+                .bind(&comment.created_at)
+                .execute(&mut **tx)
+                .await?;
+            }
+
+            for layer in annotations {
+                let id =
+                    Self::child_id(tx, "document_annotations", preserve_ids, &layer.id).await?;
+                let shapes = serde_json::to_string(&layer.shapes)
+                    .map_err(|e| AppError::Internal(format!("Failed to encode shapes: {}", e)))?;
+                sqlx::query(
+                    r#"
+                    INSERT INTO document_annotations (id, document_id, name, author_name, shapes, visible, created_at, updated_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(&id)
+                .bind(&new.id)
+                .bind(&layer.name)
+                .bind(&layer.author_name)
+                .bind(&shapes)
+                .bind(layer.visible)
+                .bind(&layer.created_at)
+                .bind(&layer.updated_at)
+                .execute(&mut **tx)
+                .await?;
+            }
+        }
+
+        for new in &plan.messages {
+            let msg = new.msg;
+            sqlx::query(
+                r#"
+                INSERT INTO forum_messages (id, project_id, parent_id, message_type, content, document_id,
+                                            audio_path, author_name, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&new.id)
+            .bind(&report.project_id)
+            .bind(&new.parent_id)
+            .bind(&msg.message_type)
+            .bind(&msg.content)
+            .bind(new.document_ids.first())
+            .bind(new.audio.as_ref().map(KeyPin::key))
+            .bind(&msg.author_name)
+            .bind(&msg.created_at)
+            .execute(&mut **tx)
+            .await?;
+
+            if new.document_ids.len() > 1 {
+                for (position, document_id) in new.document_ids.iter().enumerate() {
+                    sqlx::query(
+                        "INSERT OR IGNORE INTO forum_message_documents (message_id, document_id, position) VALUES (?, ?, ?)",
+                    )
+                    .bind(&new.id)
+                    .bind(document_id)
+                    .bind(position as i64)
+                    .execute(&mut **tx)
+                    .await?;
+                }
+            }
+
+            for item in &msg.items {
+                let id = Self::child_id(tx, "task_items", preserve_ids, &item.id).await?;
+                sqlx::query(
+                    r#"
+                    INSERT INTO task_items (id, message_id, text, completed, completed_by, completed_at, created_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(&id)
+                .bind(&new.id)
+                .bind(&item.text)
+                .bind(item.completed)
+                .bind(&item.completed_by)
+                .bind(&item.completed_at)
+                .bind(&item.created_at)
+                .execute(&mut **tx)
+                .await?;
+
+                for event in &item.history {
+                    sqlx::query(
+                        "INSERT INTO task_item_events (id, item_id, completed, actor, created_at) VALUES (?, ?, ?, ?, ?)",
+                    )
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(&id)
+                    .bind(event.completed)
+                    .bind(&event.actor)
+                    .bind(&event.at)
+                    .execute(&mut **tx)
+                    .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Unpin the files stored for a plan, returning their keys
+    fn release_files(plan: Plan<'_>) -> Vec<String> {
+        let mut keys = Vec::new();
+        for new in plan.documents {
+            keys.extend(new.file.map(|f| f.file.release()));
+            keys.extend(new.memo.map(|m| m.file.release()));
+        }
+        keys.extend(
+            plan.messages
+                .into_iter()
+                .filter_map(|m| m.audio.map(KeyPin::release)),
+        );
+        keys
+    }
+
+    /// Start the processing an upload gets; failures only lose derived files
+    async fn process(pool: &DbPool, document_id: &str) {
+        let doc = match DocumentService::get_by_id(pool, document_id).await {
+            Ok(doc) => doc,
+            Err(e) => {
+                tracing::warn!("Imported document {} not found: {}", document_id, e);
+                return;
+            }
+        };
+        if doc.file_type == "video" {
+            if let Err(e) = VideoService::enqueue(pool, document_id).await {
+                tracing::warn!("Failed to queue video {}: {}", document_id, e);
+            }
+        }
+        PreviewService::enqueue(pool, &doc);
+    }
+
+    /// The bundle's ID when preserving IDs, a new one otherwise
+    fn new_id(options: &ImportOptions, id: &str) -> String {
+        if options.preserve_ids && !id.is_empty() {
+            id.to_string()
+        } else {
+            Uuid::new_v4().to_string()
+        }
+    }
+
+    /// ID of a row created under a new parent
+    ///
+    /// Keeps the bundle's ID when preserving IDs and it is still free.
+    async fn child_id(
+        tx: &mut Transaction<'_, Sqlite>,
+        table: &str,
+        preserve_ids: bool,
+        id: &str,
+    ) -> AppResult<String> {
+        if preserve_ids && !id.is_empty() {
+            let taken: Option<(i32,)> =
+                sqlx::query_as(&format!("SELECT 1 FROM {} WHERE id = ?", table))
+                    .bind(id)
+                    .fetch_optional(&mut **tx)
+                    .await?;
+            if taken.is_none() {
+                return Ok(id.to_string());
+            }
+        }
+        Ok(Uuid::new_v4().to_string())
+    }
+
+    async fn exists(pool: &DbPool, table: &str, id: &str) -> AppResult<bool> {
+        let found: Option<(i32,)> =
+            sqlx::query_as(&format!("SELECT 1 FROM {} WHERE id = ?", table))
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+        Ok(found.is_some())
+    }
+
+    fn extension(path: &str) -> &str {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("bin")
+    }
+
+    /// Extension to store a checked file under
+    ///
+    /// Files are served with the type their extension implies, so the name's
+    /// extension is only kept when it belongs to the detected type.
+    fn stored_extension<'a>(mime: &str, name: &'a str) -> &'a str {
+        let info = FileTypeService::classify(mime, name);
+        let extension = Self::extension(name);
+        if info
+            .extensions
+            .iter()
+            .any(|ext| ext.eq_ignore_ascii_case(extension))
+        {
+            return extension;
+        }
+        FileTypeService::extension_for(mime)
+            .filter(|ext| info.extensions.contains(ext))
+            .or_else(|| info.extensions.first().copied())
+            .unwrap_or("bin")
+    }
+}
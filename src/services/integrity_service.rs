@@ -0,0 +1,374 @@
+//! Integrity service module
+//!
+//! Cross-checks the storage backend against every database column that
+//! points into it. File deletes are best effort, voice messages and
+//! interrupted uploads can leave files behind, and disks fail, so the scan
+//! reports:
+//! - orphaned files no row references (including `.part` leftovers)
+//! - rows whose file is missing
+//! - zero-byte files
+//! - document files whose SHA-256 no longer matches the stored checksum
+//!
+//! Available as `GET/POST /admin/integrity` and as `charta integrity` on the
+//! command line.
+//!
+//! # Architecture Decision
+//! Nothing is deleted outright except empty files: orphans go to the
+//! malware quarantine directory, where they can be reviewed and purged with
+//! the quarantine endpoints. Repairs only touch derived files (previews,
+//! video posters and transcodes, voice memos); a document whose original is
+//! missing or corrupted is reported for a human to decide.
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::models::{
+    ChecksumMismatch, Document, DocumentSource, IntegrityOptions, IntegrityReport, MissingFile,
+    OrphanedFile,
+};
+use crate::services::scan_service::QuarantineOrigin;
+use crate::services::{DocumentService, PreviewService, ScanService, StorageService, VideoService};
+
+/// Unreferenced files younger than this may belong to an upload in progress
+const ORPHAN_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Suffixes of precompressed siblings served in place of the original
+const PRECOMPRESSED_SUFFIXES: [&str; 3] = [".gz", ".br", ".zz"];
+
+/// A database column pointing at a stored file
+struct FileReference {
+    table: &'static str,
+    column: &'static str,
+    row_id: String,
+    path: String,
+    /// Stored checksum, for document originals
+    checksum: Option<String>,
+}
+
+/// Integrity service with static methods for storage consistency checks
+pub struct IntegrityService;
+
+impl IntegrityService {
+    /// SHA-256 of in-memory content, hex encoded
+    pub fn checksum(data: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(data))
+    }
+
+    /// SHA-256 of a stored file, hex encoded
+    pub async fn checksum_stored(key: &str) -> AppResult<String> {
+        let object = StorageService::backend().get(key).await?;
+        Self::checksum_reader(object.reader).await
+    }
+
+    /// SHA-256 of a local file, hex encoded
+    pub async fn checksum_file(path: &str) -> AppResult<String> {
+        Self::checksum_reader(tokio::fs::File::open(path).await?).await
+    }
+
+    async fn checksum_reader(mut reader: impl AsyncRead + Unpin) -> AppResult<String> {
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Scan storage, applying the requested fixes
+    pub async fn check(pool: &DbPool, options: &IntegrityOptions) -> AppResult<IntegrityReport> {
+        let references = Self::references(pool).await?;
+        let files = StorageService::backend().list().await?;
+
+        let mut report = IntegrityReport {
+            files_scanned: files.len(),
+            references_checked: references.len(),
+            ..Default::default()
+        };
+
+        let referenced: HashSet<&str> = references.iter().map(|r| r.path.as_str()).collect();
+        let now = SystemTime::now();
+        for file in &files {
+            if file.size == 0 {
+                report.empty_files.push(file.key.clone());
+            }
+
+            let original = PRECOMPRESSED_SUFFIXES
+                .iter()
+                .find_map(|suffix| file.key.strip_suffix(suffix))
+                .unwrap_or(&file.key);
+            if referenced.contains(file.key.as_str()) || referenced.contains(original) {
+                continue;
+            }
+
+            let is_recent = file
+                .modified
+                .and_then(|m| now.duration_since(m).ok())
+                .is_some_and(|age| age < ORPHAN_GRACE_PERIOD);
+            if is_recent {
+                report.recent_files_skipped += 1;
+                continue;
+            }
+
+            report.orphaned_files.push(OrphanedFile {
+                path: file.key.clone(),
+                size: file.size,
+                modified_at: file.modified.map(|m| DateTime::<Utc>::from(m).to_rfc3339()),
+                partial: file.key.ends_with(".part"),
+            });
+        }
+
+        let existing: HashSet<&str> = files.iter().map(|f| f.key.as_str()).collect();
+        let mut missing = Vec::new();
+        for reference in &references {
+            if !existing.contains(reference.path.as_str()) {
+                missing.push(reference);
+                report.missing_files.push(MissingFile {
+                    table: reference.table,
+                    column: reference.column,
+                    row_id: reference.row_id.clone(),
+                    path: reference.path.clone(),
+                });
+            }
+        }
+
+        if options.verify_checksums {
+            Self::verify_checksums(pool, &references, &existing, options.repair, &mut report)
+                .await?;
+        }
+
+        if options.quarantine_orphans {
+            for orphan in &report.orphaned_files {
+                let action = Self::quarantine_orphan(pool, orphan).await?;
+                report.actions.push(action);
+            }
+        }
+
+        if options.repair {
+            let mut queued_videos = HashSet::new();
+            for reference in missing {
+                // A video missing both poster and transcode is reprocessed once
+                if reference.table == "video_metadata" && !queued_videos.insert(&reference.row_id) {
+                    continue;
+                }
+                if let Some(action) = Self::repair_missing(pool, reference).await? {
+                    report.actions.push(action);
+                }
+            }
+        }
+
+        if !report.is_clean() {
+            tracing::warn!(
+                "Integrity scan: {} orphaned, {} missing, {} empty, {} checksum mismatch(es)",
+                report.orphaned_files.len(),
+                report.missing_files.len(),
+                report.empty_files.len(),
+                report.checksum_mismatches.len()
+            );
+        }
+        Ok(report)
+    }
+
+    /// Every database column that points at a stored file
+    async fn references(pool: &DbPool) -> AppResult<Vec<FileReference>> {
+        let mut references = Vec::new();
+        let mut push = |table, column, row_id: &str, path: Option<String>, checksum| {
+            if let Some(path) = path.filter(|p| !p.is_empty()) {
+                references.push(FileReference {
+                    table,
+                    column,
+                    row_id: row_id.to_string(),
+                    path,
+                    checksum,
+                });
+            }
+        };
+
+        let documents = sqlx::query_as::<_, Document>("SELECT * FROM documents")
+            .fetch_all(pool)
+            .await?;
+        for doc in documents {
+            // Files moved to cold storage are checked when the archive is written
+            if doc.archive_id.is_none() {
+                push(
+                    "documents",
+                    "file_path",
+                    &doc.id,
+                    Some(doc.file_path),
+                    doc.checksum,
+                );
+                push("documents", "audio_path", &doc.id, doc.audio_path, None);
+            }
+            push("documents", "preview_path", &doc.id, doc.preview_path, None);
+        }
+
+        // Transcodes of documents in cold storage live in their archive
+        let videos: Vec<(String, Option<String>, Option<String>)> = sqlx::query_as(
+            r#"
+            SELECT v.document_id, v.poster_path,
+                   CASE WHEN d.archive_id IS NULL THEN v.transcoded_path END
+            FROM video_metadata v
+            LEFT JOIN documents d ON d.id = v.document_id
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+        for (id, poster_path, transcoded_path) in videos {
+            push("video_metadata", "poster_path", &id, poster_path, None);
+            push(
+                "video_metadata",
+                "transcoded_path",
+                &id,
+                transcoded_path,
+                None,
+            );
+        }
+
+        let voice_messages: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT id, audio_path FROM forum_messages WHERE audio_path IS NOT NULL",
+        )
+        .fetch_all(pool)
+        .await?;
+        for (id, audio_path) in voice_messages {
+            push("forum_messages", "audio_path", &id, audio_path, None);
+        }
+
+        Ok(references)
+    }
+
+    /// Re-hash document originals; with `repair`, record missing checksums
+    async fn verify_checksums(
+        pool: &DbPool,
+        references: &[FileReference],
+        existing: &HashSet<&str>,
+        repair: bool,
+        report: &mut IntegrityReport,
+    ) -> AppResult<()> {
+        let originals = references.iter().filter(|r| {
+            r.table == "documents" && r.column == "file_path" && existing.contains(r.path.as_str())
+        });
+
+        for reference in originals {
+            if reference.checksum.is_none() && !repair {
+                continue;
+            }
+            let actual = Self::checksum_stored(&reference.path).await?;
+
+            match &reference.checksum {
+                Some(expected) => {
+                    report.checksums_verified += 1;
+                    if *expected != actual {
+                        report.checksum_mismatches.push(ChecksumMismatch {
+                            document_id: reference.row_id.clone(),
+                            path: reference.path.clone(),
+                            expected: expected.clone(),
+                            actual,
+                        });
+                    }
+                }
+                None => {
+                    sqlx::query("UPDATE documents SET checksum = ? WHERE id = ?")
+                        .bind(&actual)
+                        .bind(&reference.row_id)
+                        .execute(pool)
+                        .await?;
+                    report.actions.push(format!(
+                        "Recorded checksum of document {}",
+                        reference.row_id
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Move an orphaned file into quarantine, or delete it when empty
+    async fn quarantine_orphan(pool: &DbPool, orphan: &OrphanedFile) -> AppResult<String> {
+        let backend = StorageService::backend();
+        // An upload may have found the file stored since the scan
+        let in_use = StorageService::is_referenced(pool, &orphan.path);
+        let Some(_lock) = StorageService::lock_unused(&orphan.path, in_use).await? else {
+            return Ok(format!("Kept {}, it is in use again", orphan.path));
+        };
+        if orphan.size == 0 {
+            backend.delete(&orphan.path).await?;
+            return Ok(format!("Deleted empty orphaned file {}", orphan.path));
+        }
+
+        // Orphans are almost always leftovers of app uploads or voice messages
+        let origin = QuarantineOrigin {
+            original_name: &orphan.path,
+            mime_type: None,
+            source: DocumentSource::App,
+            uploaded_by: None,
+            email: None,
+        };
+        let reason = if orphan.partial {
+            "Interrupted upload found by integrity scan"
+        } else {
+            "Orphaned file found by integrity scan"
+        };
+        // Quarantine lives on local disk; on object storage this moves a downloaded copy
+        let copy = backend.local_copy(&orphan.path).await?;
+        let quarantined =
+            ScanService::quarantine_file(pool, &copy.path_str(), &origin, None, reason).await?;
+        backend.delete(&orphan.path).await?;
+        Ok(format!(
+            "Quarantined orphaned file {} as {}",
+            orphan.path, quarantined.id
+        ))
+    }
+
+    /// Fix a reference to a missing derived file
+    ///
+    /// Returns None for document originals, which can't be repaired.
+    async fn repair_missing(pool: &DbPool, reference: &FileReference) -> AppResult<Option<String>> {
+        let id = reference.row_id.as_str();
+        let action = match (reference.table, reference.column) {
+            ("documents", "audio_path") => {
+                sqlx::query(
+                    "UPDATE documents SET audio_path = NULL, audio_format = NULL, audio_duration_seconds = NULL WHERE id = ?",
+                )
+                .bind(id)
+                .execute(pool)
+                .await?;
+                format!("Removed missing voice memo from document {}", id)
+            }
+            ("documents", "preview_path") => {
+                sqlx::query("UPDATE documents SET preview_path = NULL WHERE id = ?")
+                    .bind(id)
+                    .execute(pool)
+                    .await?;
+                let doc = DocumentService::get_by_id(pool, id).await?;
+                if PreviewService::supports(&doc) {
+                    PreviewService::enqueue(pool, &doc);
+                    format!("Queued a new preview of document {}", id)
+                } else {
+                    format!("Cleared missing preview of document {}", id)
+                }
+            }
+            ("video_metadata", _) => {
+                VideoService::enqueue(pool, id).await?;
+                format!("Queued video processing for document {}", id)
+            }
+            ("forum_messages", "audio_path") => {
+                sqlx::query("UPDATE forum_messages SET audio_path = NULL WHERE id = ?")
+                    .bind(id)
+                    .execute(pool)
+                    .await?;
+                format!("Removed missing audio from forum message {}", id)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(action))
+    }
+}
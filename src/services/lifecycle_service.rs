@@ -0,0 +1,450 @@
+//! Project lifecycle service
+//!
+//! The lifecycle is the ordered list of states a project goes through
+//! (quote, approved, in production, ... archived) and the transitions allowed
+//! between them. It lives in the `project_states` and
+//! `project_state_transitions` tables so each workshop can adapt it.
+//!
+//! `ARCHIVED` is built in: archiving stamps `archived_at` and eventually moves
+//! files to cold storage. `ACTIVE`, the only other state before the lifecycle
+//! existed, is kept as an alias meaning "any state but archived".
+
+use std::collections::HashSet;
+
+use sqlx::SqliteConnection;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::LifecycleState;
+
+/// State of archived projects, present in every lifecycle
+pub const ARCHIVED_STATUS: &str = "ARCHIVED";
+
+/// Pre-lifecycle status name: filters for open projects and reopens archived ones
+pub const ACTIVE_ALIAS: &str = "ACTIVE";
+
+/// State projects that were ACTIVE before the lifecycle were moved to
+pub const LEGACY_ACTIVE_STATE: &str = "IN_PRODUCTION";
+
+/// Project lifecycle service
+pub struct LifecycleService;
+
+impl LifecycleService {
+    /// Normalize a state code: trimmed, uppercase
+    pub fn normalize(code: &str) -> String {
+        code.trim().to_uppercase()
+    }
+
+    /// The lifecycle in order, each state with the states it can move to
+    pub async fn get(pool: &DbPool) -> AppResult<Vec<LifecycleState>> {
+        let states: Vec<(String, String)> =
+            sqlx::query_as("SELECT code, label FROM project_states ORDER BY position")
+                .fetch_all(pool)
+                .await?;
+        let transitions: Vec<(String, String)> = sqlx::query_as(
+            r#"
+            SELECT t.from_state, t.to_state FROM project_state_transitions t
+            JOIN project_states s ON s.code = t.to_state
+            ORDER BY s.position
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(states
+            .into_iter()
+            .map(|(code, label)| LifecycleState {
+                next: transitions
+                    .iter()
+                    .filter(|(from, _)| *from == code)
+                    .map(|(_, to)| to.clone())
+                    .collect(),
+                code,
+                label,
+            })
+            .collect())
+    }
+
+    /// Replace the whole lifecycle
+    ///
+    /// The list order is the lifecycle order; new projects start in the first
+    /// state. ARCHIVED must be kept, and states still used by a project can't
+    /// be removed.
+    pub async fn replace(
+        pool: &DbPool,
+        states: Vec<LifecycleState>,
+    ) -> AppResult<Vec<LifecycleState>> {
+        let states = Self::validate(states)?;
+
+        let in_use: Vec<(String,)> = sqlx::query_as("SELECT DISTINCT status FROM projects")
+            .fetch_all(pool)
+            .await?;
+        let removed: Vec<String> = in_use
+            .into_iter()
+            .map(|(status,)| status)
+            .filter(|status| !states.iter().any(|s| s.code == *status))
+            .collect();
+        if !removed.is_empty() {
+            return Err(AppError::BadRequest(format!(
+                "States still used by projects can't be removed: {}",
+                removed.join(", ")
+            )));
+        }
+
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM project_state_transitions")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM project_states")
+            .execute(&mut *tx)
+            .await?;
+        for (position, state) in states.iter().enumerate() {
+            sqlx::query("INSERT INTO project_states (code, label, position) VALUES (?, ?, ?)")
+                .bind(&state.code)
+                .bind(&state.label)
+                .bind(position as i64 + 1)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for state in &states {
+            for next in &state.next {
+                sqlx::query(
+                    "INSERT INTO project_state_transitions (from_state, to_state) VALUES (?, ?)",
+                )
+                .bind(&state.code)
+                .bind(next)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+
+        tracing::info!("Project lifecycle updated ({} states)", states.len());
+
+        Self::get(pool).await
+    }
+
+    /// The state new projects start in
+    pub async fn initial_state<'e, E>(executor: E) -> AppResult<String>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        let (code,): (String,) = sqlx::query_as(
+            "SELECT code FROM project_states WHERE code != ? ORDER BY position LIMIT 1",
+        )
+        .bind(ARCHIVED_STATUS)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| AppError::Internal("The project lifecycle has no states".into()))?;
+        Ok(code)
+    }
+
+    /// Check whether `code` is a state of the lifecycle
+    pub async fn exists<'e, E>(executor: E, code: &str) -> AppResult<bool>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        let (count,): (i32,) = sqlx::query_as("SELECT COUNT(*) FROM project_states WHERE code = ?")
+            .bind(code)
+            .fetch_one(executor)
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// Fail unless a project may move from `from` to `to`
+    pub async fn check_transition(
+        conn: &mut SqliteConnection,
+        from: &str,
+        to: &str,
+    ) -> AppResult<()> {
+        if !Self::exists(&mut *conn, to).await? {
+            return Err(AppError::BadRequest(format!(
+                "Unknown project status '{}'. Use one of: {}",
+                to,
+                Self::codes(&mut *conn).await?.join(", ")
+            )));
+        }
+
+        let allowed: Vec<(String,)> = sqlx::query_as(
+            r#"
+            SELECT t.to_state FROM project_state_transitions t
+            JOIN project_states s ON s.code = t.to_state
+            WHERE t.from_state = ?
+            ORDER BY s.position
+            "#,
+        )
+        .bind(from)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        if allowed.iter().any(|(code,)| code == to) {
+            return Ok(());
+        }
+
+        let allowed: Vec<String> = allowed.into_iter().map(|(code,)| code).collect();
+        Err(AppError::BadRequest(format!(
+            "A project can't move from {} to {}. Allowed: {}",
+            from,
+            to,
+            if allowed.is_empty() {
+                "none".to_string()
+            } else {
+                allowed.join(", ")
+            }
+        )))
+    }
+
+    /// All state codes, in lifecycle order
+    pub async fn codes<'e, E>(executor: E) -> AppResult<Vec<String>>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        let codes: Vec<(String,)> =
+            sqlx::query_as("SELECT code FROM project_states ORDER BY position")
+                .fetch_all(executor)
+                .await?;
+        Ok(codes.into_iter().map(|(code,)| code).collect())
+    }
+
+    /// Normalize a lifecycle and check it is consistent
+    fn validate(states: Vec<LifecycleState>) -> AppResult<Vec<LifecycleState>> {
+        let mut seen = HashSet::new();
+        let mut normalized = Vec::with_capacity(states.len());
+
+        for state in states {
+            let code = Self::normalize(&state.code);
+            if code.is_empty()
+                || !code
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            {
+                return Err(AppError::BadRequest(format!(
+                    "Invalid state code '{}': use letters, digits and underscores",
+                    state.code
+                )));
+            }
+            if code == ACTIVE_ALIAS {
+                return Err(AppError::BadRequest(format!(
+                    "'{}' is reserved for reopening archived projects",
+                    ACTIVE_ALIAS
+                )));
+            }
+            if !seen.insert(code.clone()) {
+                return Err(AppError::BadRequest(format!(
+                    "State '{}' is listed twice",
+                    code
+                )));
+            }
+
+            let label = state.label.trim().to_string();
+            if label.is_empty() {
+                return Err(AppError::BadRequest(format!(
+                    "State '{}' needs a label",
+                    code
+                )));
+            }
+
+            let mut next: Vec<String> = Vec::new();
+            for to in &state.next {
+                let to = Self::normalize(to);
+                if to != code && !next.contains(&to) {
+                    next.push(to);
+                }
+            }
+
+            normalized.push(LifecycleState { code, label, next });
+        }
+
+        if !seen.contains(ARCHIVED_STATUS) {
+            return Err(AppError::BadRequest(format!(
+                "The lifecycle must include {}",
+                ARCHIVED_STATUS
+            )));
+        }
+        if normalized.iter().all(|s| s.code == ARCHIVED_STATUS) {
+            return Err(AppError::BadRequest(format!(
+                "The lifecycle needs a state besides {}",
+                ARCHIVED_STATUS
+            )));
+        }
+        for state in &normalized {
+            if let Some(unknown) = state.next.iter().find(|to| !seen.contains(*to)) {
+                return Err(AppError::BadRequest(format!(
+                    "State '{}' moves to unknown state '{}'",
+                    state.code, unknown
+                )));
+            }
+        }
+
+        Ok(normalized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(code: &str, label: &str, next: &[&str]) -> LifecycleState {
+        LifecycleState {
+            code: code.to_string(),
+            label: label.to_string(),
+            next: next.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn rejection(states: Vec<LifecycleState>) -> String {
+        match LifecycleService::validate(states) {
+            Err(AppError::BadRequest(message)) => message,
+            other => panic!("expected a bad request, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    /// A fresh database with the default lifecycle
+    async fn pool() -> DbPool {
+        crate::db::init_db(&format!(
+            "sqlite:file:lifecycle-{}?mode=memory&cache=shared",
+            uuid::Uuid::new_v4()
+        ))
+        .await
+    }
+
+    #[test]
+    fn validate_normalizes_codes_and_transitions() {
+        let states = LifecycleService::validate(vec![
+            state(" quote ", " Quote ", &["done", "QUOTE", "Done", "archived"]),
+            state("done", "Done", &[]),
+            state("Archived", "Archived", &["quote"]),
+        ])
+        .unwrap();
+        assert_eq!(states[0].code, "QUOTE");
+        assert_eq!(states[0].label, "Quote");
+        assert_eq!(states[0].next, ["DONE", "ARCHIVED"]);
+        assert_eq!(states[2].code, ARCHIVED_STATUS);
+        assert_eq!(states[2].next, ["QUOTE"]);
+    }
+
+    #[test]
+    fn validate_rejects_bad_codes() {
+        let archived = || state("ARCHIVED", "Archived", &[]);
+        for code in ["", "  ", "IN PRODUCTION", "IN-PRODUCTION", "ÇA"] {
+            let message = rejection(vec![state(code, "Label", &[]), archived()]);
+            assert!(message.starts_with("Invalid state code"), "{}", message);
+        }
+        let message = rejection(vec![state("active", "Active", &[]), archived()]);
+        assert!(message.contains("reserved"), "{}", message);
+        let message = rejection(vec![
+            state("QUOTE", "Quote", &[]),
+            state("quote", "Quote again", &[]),
+            archived(),
+        ]);
+        assert!(message.contains("listed twice"), "{}", message);
+    }
+
+    #[test]
+    fn validate_requires_labels() {
+        let message = rejection(vec![
+            state("QUOTE", "  ", &[]),
+            state("ARCHIVED", "Archived", &[]),
+        ]);
+        assert!(message.contains("needs a label"), "{}", message);
+    }
+
+    #[test]
+    fn validate_requires_archived_and_another_state() {
+        let message = rejection(vec![state("QUOTE", "Quote", &[])]);
+        assert!(message.contains("must include ARCHIVED"), "{}", message);
+        let message = rejection(vec![state("ARCHIVED", "Archived", &[])]);
+        assert!(message.contains("besides ARCHIVED"), "{}", message);
+        assert!(rejection(Vec::new()).contains("must include ARCHIVED"));
+    }
+
+    #[test]
+    fn validate_rejects_transitions_to_unknown_states() {
+        let message = rejection(vec![
+            state("QUOTE", "Quote", &["APPROVED"]),
+            state("ARCHIVED", "Archived", &[]),
+        ]);
+        assert_eq!(message, "State 'QUOTE' moves to unknown state 'APPROVED'");
+    }
+
+    #[tokio::test]
+    async fn allowed_transitions_pass() {
+        let pool = pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        for (from, to) in [
+            ("QUOTE", "APPROVED"),
+            ("APPROVED", "QUOTE"),
+            ("DONE", ARCHIVED_STATUS),
+            (ARCHIVED_STATUS, "QUOTE"),
+        ] {
+            LifecycleService::check_transition(&mut conn, from, to)
+                .await
+                .unwrap_or_else(|e| panic!("{} -> {}: {}", from, to, e));
+        }
+    }
+
+    #[tokio::test]
+    async fn disallowed_transitions_list_the_allowed_ones() {
+        let pool = pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        match LifecycleService::check_transition(&mut conn, "QUOTE", "DONE").await {
+            Err(AppError::BadRequest(message)) => assert_eq!(
+                message,
+                "A project can't move from QUOTE to DONE. Allowed: APPROVED, ARCHIVED"
+            ),
+            other => panic!("expected a bad request, got {:?}", other),
+        }
+        // Staying put is not a transition
+        assert!(
+            LifecycleService::check_transition(&mut conn, "QUOTE", "QUOTE")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_states_are_rejected() {
+        let pool = pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        match LifecycleService::check_transition(&mut conn, "QUOTE", "SHIPPED").await {
+            Err(AppError::BadRequest(message)) => {
+                assert!(
+                    message.starts_with("Unknown project status 'SHIPPED'"),
+                    "{}",
+                    message
+                )
+            }
+            other => panic!("expected a bad request, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn replaced_lifecycle_drives_transitions() {
+        let pool = pool().await;
+        LifecycleService::replace(
+            &pool,
+            vec![
+                state("QUOTE", "Quote", &["IN_PRODUCTION"]),
+                state("IN_PRODUCTION", "In production", &["ARCHIVED"]),
+                state("ARCHIVED", "Archived", &[]),
+            ],
+        )
+        .await
+        .unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        LifecycleService::check_transition(&mut conn, "QUOTE", "IN_PRODUCTION")
+            .await
+            .unwrap();
+        match LifecycleService::check_transition(&mut conn, "ARCHIVED", "QUOTE").await {
+            Err(AppError::BadRequest(message)) => {
+                assert!(message.ends_with("Allowed: none"), "{}", message)
+            }
+            other => panic!("expected a bad request, got {:?}", other),
+        }
+        assert!(
+            LifecycleService::check_transition(&mut conn, "QUOTE", "APPROVED")
+                .await
+                .is_err()
+        );
+    }
+}
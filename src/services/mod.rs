@@ -4,16 +4,52 @@
 //! Services abstract away database operations and provide a clean API
 //! for the HTTP handlers to use.
 
+pub mod activity_service;
+pub mod annotation_service;
+pub mod audio_service;
+pub mod client_service;
+pub mod cold_storage_service;
+pub mod comment_service;
+pub mod content_service;
 pub mod document_service;
+pub mod download_service;
 pub mod email_service;
+pub mod encryption_service;
+pub mod export_service;
+pub mod file_type_service;
 pub mod forum_service;
+pub mod import_service;
+pub mod integrity_service;
+pub mod lifecycle_service;
+pub mod preview_service;
 pub mod project_service;
 pub mod push_service;
+pub mod scan_service;
+pub mod storage_service;
 pub mod user_service;
+pub mod video_service;
 
+pub use activity_service::ActivityService;
+pub use annotation_service::AnnotationService;
+pub use audio_service::AudioService;
+pub use client_service::ClientService;
+pub use cold_storage_service::ColdStorageService;
+pub use comment_service::CommentService;
+pub use content_service::ContentService;
 pub use document_service::DocumentService;
+pub use download_service::DownloadService;
 pub use email_service::EmailService;
+pub use encryption_service::EncryptionService;
+pub use export_service::ExportService;
+pub use file_type_service::FileTypeService;
 pub use forum_service::ForumService;
+pub use import_service::ImportService;
+pub use integrity_service::IntegrityService;
+pub use lifecycle_service::LifecycleService;
+pub use preview_service::PreviewService;
 pub use project_service::ProjectService;
 pub use push_service::PushService;
+pub use scan_service::ScanService;
+pub use storage_service::StorageService;
 pub use user_service::UserService;
+pub use video_service::VideoService;
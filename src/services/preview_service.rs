@@ -0,0 +1,959 @@
+//! Preview service module
+//!
+//! Renders SVG previews of DXF drawings so clients can show a drawing in an
+//! `<img>` instead of asking the user to open a CAD program. The preview is
+//! stored like any other file and exposed as the document's `preview_url`.
+//!
+//! Supported entities: lines, arcs, circles, ellipses, (lightweight)
+//! polylines with bulges, splines, solids, text, multiline text, dimensions
+//! and block references (including nested blocks and attributes). Hatches,
+//! images and 3D solids are skipped.
+//!
+//! # Architecture Decision
+//! Everything is flattened to polylines in drawing coordinates (arcs and
+//! curves are sampled) and the Y axis is flipped once when writing the SVG.
+//! That keeps block transforms trivial, including mirrored and non-uniformly
+//! scaled inserts. Parsing and rendering are CPU-bound: they run as
+//! background jobs, one at a time, on the blocking thread pool and give up
+//! after `PREVIEW_TIMEOUT`. A preview that fails never fails the upload, and
+//! a drawing only gets its `preview_url` once its job has finished.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Semaphore;
+
+use dxf::entities::{DimensionBase, Entity, EntityType};
+use dxf::{Drawing, Point};
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::Document;
+use crate::services::{ColdStorageService, FileTypeService, StorageService};
+
+/// Maximum nesting of block references
+const MAX_BLOCK_DEPTH: usize = 16;
+
+/// Rendering stops adding geometry past this many points
+const MAX_POINTS: usize = 500_000;
+
+/// Arcs and curves are sampled every this many degrees
+const ARC_STEP_DEGREES: f64 = 5.0;
+
+/// Most segments a single curve is sampled into
+const MAX_CURVE_SEGMENTS: usize = 4_096;
+
+/// Highest spline degree rendered; real drawings stay far below
+const MAX_SPLINE_DEGREE: usize = 25;
+
+/// Most placements of one block reference array (rows × columns)
+const MAX_INSERT_PLACEMENTS: usize = 10_000;
+
+/// A preview job is abandoned after this long
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Longest side of the SVG's intrinsic size, in pixels
+const PREVIEW_SIZE_PX: f64 = 1600.0;
+
+/// Stroke and text colour
+const INK: &str = "#1f2937";
+
+/// 2D affine transform: x' = a*x + c*y + e, y' = b*x + d*y + f
+#[derive(Debug, Clone, Copy)]
+struct Transform {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+    f: f64,
+}
+
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        a: 1.0,
+        b: 0.0,
+        c: 0.0,
+        d: 1.0,
+        e: 0.0,
+        f: 0.0,
+    };
+
+    /// Transform placing a block's contents at an insert
+    fn insert(base: &Point, location: (f64, f64), scale: (f64, f64), rotation_deg: f64) -> Self {
+        let (sin, cos) = rotation_deg.to_radians().sin_cos();
+        let a = scale.0 * cos;
+        let b = scale.0 * sin;
+        let c = -scale.1 * sin;
+        let d = scale.1 * cos;
+        Transform {
+            a,
+            b,
+            c,
+            d,
+            e: location.0 - (a * base.x + c * base.y),
+            f: location.1 - (b * base.x + d * base.y),
+        }
+    }
+
+    fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    /// `self` applied after `inner`
+    fn compose(&self, inner: &Transform) -> Transform {
+        Transform {
+            a: self.a * inner.a + self.c * inner.b,
+            b: self.b * inner.a + self.d * inner.b,
+            c: self.a * inner.c + self.c * inner.d,
+            d: self.b * inner.c + self.d * inner.d,
+            e: self.a * inner.e + self.c * inner.f + self.e,
+            f: self.b * inner.e + self.d * inner.f + self.f,
+        }
+    }
+
+    /// Average scale, used for text heights
+    fn scale(&self) -> f64 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+
+    /// Rotation of the X axis in degrees
+    fn rotation(&self) -> f64 {
+        self.b.atan2(self.a).to_degrees()
+    }
+}
+
+/// A piece of text placed in drawing coordinates
+struct Label {
+    x: f64,
+    y: f64,
+    height: f64,
+    rotation: f64,
+    lines: Vec<String>,
+}
+
+/// Flattened drawing: polylines and labels in drawing coordinates
+#[derive(Default)]
+struct Scene {
+    paths: Vec<(Vec<(f64, f64)>, bool)>,
+    labels: Vec<Label>,
+    points: usize,
+}
+
+/// Walks the drawing's entities and flattens them into a `Scene`
+struct Renderer<'a> {
+    blocks: HashMap<&'a str, &'a dxf::Block>,
+    hidden_layers: HashSet<&'a str>,
+    scene: Scene,
+    /// Rendering stops when this passes
+    deadline: Instant,
+    timed_out: bool,
+}
+
+/// Preview service with static methods for drawing previews
+pub struct PreviewService;
+
+impl PreviewService {
+    /// Whether a preview can be rendered for this document
+    pub fn supports(doc: &Document) -> bool {
+        let mime = doc
+            .detected_mime_type
+            .as_deref()
+            .or(doc.mime_type.as_deref())
+            .unwrap_or("");
+        FileTypeService::classify(mime, &doc.original_name).id == "dxf"
+            || FileTypeService::classify("", &doc.file_path).id == "dxf"
+    }
+
+    /// Queue a background job rendering the preview of a document
+    ///
+    /// Does nothing for documents that have no preview renderer. Jobs run one
+    /// at a time and failures are only logged.
+    pub fn enqueue(pool: &DbPool, doc: &Document) {
+        static JOBS: OnceLock<Semaphore> = OnceLock::new();
+
+        if !Self::supports(doc) {
+            return;
+        }
+        let pool = pool.clone();
+        let doc = doc.clone();
+        tokio::spawn(async move {
+            let semaphore = JOBS.get_or_init(|| Semaphore::new(1));
+            let Ok(_permit) = semaphore.acquire().await else {
+                return;
+            };
+
+            match tokio::time::timeout(PREVIEW_TIMEOUT, Self::generate(&pool, &doc)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("No preview for document {}: {}", doc.id, e),
+                Err(_) => tracing::warn!("Preview of document {} timed out", doc.id),
+            }
+        });
+    }
+
+    /// Render the preview of a document and store its path
+    ///
+    /// Does nothing for documents that have no preview renderer.
+    pub async fn generate(pool: &DbPool, doc: &Document) -> AppResult<()> {
+        if !Self::supports(doc) {
+            return Ok(());
+        }
+
+        ColdStorageService::ensure_restored(pool, doc).await?;
+        let source = StorageService::backend().local_copy(&doc.file_path).await?;
+        let deadline = Instant::now() + PREVIEW_TIMEOUT;
+        let svg = tokio::task::spawn_blocking(move || Self::render_dxf(source.path(), deadline))
+            .await
+            .map_err(|e| AppError::Internal(format!("Preview task failed: {}", e)))?
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to render '{}': {}", doc.original_name, e))
+            })?;
+
+        let preview = StorageService::store_bytes(svg.into(), "svg").await?;
+
+        sqlx::query("UPDATE documents SET preview_path = ? WHERE id = ?")
+            .bind(preview.key())
+            .bind(&doc.id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Queue previews for drawings uploaded before previews existed
+    pub async fn generate_missing(pool: &DbPool) -> AppResult<()> {
+        let docs = sqlx::query_as::<_, Document>(
+            "SELECT * FROM documents WHERE file_type = 'cad' AND preview_path IS NULL",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let docs: Vec<&Document> = docs.iter().filter(|doc| Self::supports(doc)).collect();
+        if !docs.is_empty() {
+            tracing::info!("Queued {} drawing preview(s)", docs.len());
+        }
+        for doc in docs {
+            Self::enqueue(pool, doc);
+        }
+        Ok(())
+    }
+
+    /// Parse a DXF file and render it as an SVG document
+    fn render_dxf(path: &Path, deadline: Instant) -> Result<String, String> {
+        let drawing = Drawing::load_file(path).map_err(|e| e.to_string())?;
+
+        let mut renderer = Renderer {
+            blocks: drawing.blocks().map(|b| (b.name.as_str(), b)).collect(),
+            hidden_layers: drawing
+                .layers()
+                .filter(|l| !l.is_layer_on)
+                .map(|l| l.name.as_str())
+                .collect(),
+            scene: Scene::default(),
+            deadline,
+            timed_out: false,
+        };
+        let model_space = drawing.entities().filter(|e| !e.common.is_in_paper_space);
+        renderer.add_entities(model_space, &Transform::IDENTITY, 0);
+
+        if renderer.timed_out {
+            return Err("rendering took too long".to_string());
+        }
+        if renderer.scene.points >= MAX_POINTS {
+            tracing::warn!(
+                "Drawing {} is too large, preview is incomplete",
+                path.display()
+            );
+        }
+        Self::to_svg(&renderer.scene).ok_or_else(|| "drawing has no visible entities".to_string())
+    }
+
+    /// Serialize a scene, flipping the Y axis (DXF points up, SVG down)
+    fn to_svg(scene: &Scene) -> Option<String> {
+        let (mut min_x, mut min_y) = (f64::INFINITY, f64::INFINITY);
+        let (mut max_x, mut max_y) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        let mut extend = |x: f64, y: f64| {
+            if x.is_finite() && y.is_finite() {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        };
+        for (points, _) in &scene.paths {
+            for &(x, y) in points {
+                extend(x, y);
+            }
+        }
+        for label in &scene.labels {
+            extend(label.x, label.y);
+            extend(label.x, label.y + label.height);
+        }
+        if min_x > max_x {
+            return None;
+        }
+
+        let extent = (max_x - min_x).max(max_y - min_y).max(1e-6);
+        let pad = extent * 0.02;
+        let (x, y) = (min_x - pad, -(max_y + pad));
+        let (width, height) = (max_x - min_x + 2.0 * pad, max_y - min_y + 2.0 * pad);
+        let px = PREVIEW_SIZE_PX / width.max(height);
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+            num(x),
+            num(y),
+            num(width),
+            num(height),
+            (width * px).round().max(1.0),
+            (height * px).round().max(1.0)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white"/>"#,
+            num(x),
+            num(y),
+            num(width),
+            num(height)
+        );
+
+        let _ = write!(
+            svg,
+            r#"<path fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round" d=""#,
+            INK,
+            num(extent / 1000.0)
+        );
+        for (points, closed) in &scene.paths {
+            for (i, &(px, py)) in points.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                let _ = write!(svg, "{}{} {}", command, num(px), num(-py));
+            }
+            if *closed {
+                svg.push('Z');
+            }
+        }
+        svg.push_str("\"/>\n");
+
+        if !scene.labels.is_empty() {
+            let _ = writeln!(svg, r#"<g fill="{}" font-family="sans-serif">"#, INK);
+            for label in &scene.labels {
+                let (lx, ly) = (num(label.x), num(-label.y));
+                let _ = write!(
+                    svg,
+                    r#"<text x="{}" y="{}" font-size="{}""#,
+                    lx,
+                    ly,
+                    num(label.height)
+                );
+                if label.rotation.abs() > 1e-6 {
+                    let _ = write!(
+                        svg,
+                        r#" transform="rotate({} {} {})""#,
+                        num(-label.rotation),
+                        lx,
+                        ly
+                    );
+                }
+                svg.push('>');
+                for (i, line) in label.lines.iter().enumerate() {
+                    if i == 0 {
+                        svg.push_str(&escape_xml(line));
+                    } else {
+                        let _ = write!(
+                            svg,
+                            r#"<tspan x="{}" dy="1.25em">{}</tspan>"#,
+                            lx,
+                            escape_xml(line)
+                        );
+                    }
+                }
+                svg.push_str("</text>\n");
+            }
+            svg.push_str("</g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        Some(svg)
+    }
+}
+
+impl<'a> Renderer<'a> {
+    fn add_entities<I>(&mut self, entities: I, t: &Transform, depth: usize)
+    where
+        I: Iterator<Item = &'a Entity>,
+    {
+        for entity in entities {
+            if self.scene.points >= MAX_POINTS || self.timed_out {
+                return;
+            }
+            if Instant::now() >= self.deadline {
+                self.timed_out = true;
+                return;
+            }
+            if !entity.common.is_visible
+                || self.hidden_layers.contains(entity.common.layer.as_str())
+            {
+                continue;
+            }
+            self.add_entity(entity, t, depth);
+        }
+    }
+
+    fn add_entity(&mut self, entity: &'a Entity, t: &Transform, depth: usize) {
+        match &entity.specific {
+            EntityType::Line(line) => self.add_path(
+                t,
+                vec![(line.p1.x, line.p1.y), (line.p2.x, line.p2.y)],
+                false,
+            ),
+            EntityType::Circle(circle) => {
+                let points = arc_points(&circle.center, circle.radius, 0.0, 360.0);
+                self.add_path(t, points, true);
+            }
+            EntityType::Arc(arc) => {
+                let Some(sweep) = sweep(arc.start_angle, arc.end_angle, 360.0) else {
+                    return;
+                };
+                let points = arc_points(&arc.center, arc.radius, arc.start_angle, sweep);
+                self.add_path(t, points, false);
+            }
+            EntityType::Ellipse(ellipse) => {
+                let (mx, my) = (ellipse.major_axis.x, ellipse.major_axis.y);
+                let ratio = ellipse.minor_axis_ratio;
+                let start = ellipse.start_parameter;
+                let Some(sweep) = sweep(start, ellipse.end_parameter, std::f64::consts::TAU) else {
+                    return;
+                };
+                let steps = segments(sweep.to_degrees());
+                let points = (0..=steps)
+                    .map(|i| {
+                        let (sin, cos) = (start + sweep * i as f64 / steps as f64).sin_cos();
+                        (
+                            ellipse.center.x + mx * cos - my * ratio * sin,
+                            ellipse.center.y + my * cos + mx * ratio * sin,
+                        )
+                    })
+                    .collect();
+                self.add_path(t, points, false);
+            }
+            EntityType::LwPolyline(poly) => {
+                let vertices: Vec<_> = poly.vertices.iter().map(|v| (v.x, v.y, v.bulge)).collect();
+                let points = bulge_points(&vertices, poly.is_closed());
+                self.add_path(t, points, poly.is_closed());
+            }
+            EntityType::Polyline(poly) => {
+                if poly.is_polyface_mesh() || poly.is_3d_polygon_mesh() {
+                    return;
+                }
+                let vertices: Vec<_> = poly
+                    .vertices()
+                    .map(|v| (v.location.x, v.location.y, v.bulge))
+                    .collect();
+                let points = bulge_points(&vertices, poly.is_closed());
+                self.add_path(t, points, poly.is_closed());
+            }
+            EntityType::Spline(spline) => {
+                let points = spline_points(
+                    spline.degree_of_curve,
+                    &spline.knot_values,
+                    &spline.control_points,
+                )
+                .unwrap_or_else(|| {
+                    let source = if spline.fit_points.is_empty() {
+                        &spline.control_points
+                    } else {
+                        &spline.fit_points
+                    };
+                    source.iter().map(|p| (p.x, p.y)).collect()
+                });
+                self.add_path(t, points, spline.is_closed());
+            }
+            EntityType::Solid(solid) => {
+                // Corners are stored in "Z" order
+                let points = [
+                    &solid.first_corner,
+                    &solid.second_corner,
+                    &solid.fourth_corner,
+                    &solid.third_corner,
+                ]
+                .iter()
+                .map(|p| (p.x, p.y))
+                .collect();
+                self.add_path(t, points, true);
+            }
+            EntityType::Text(text) => self.add_label(
+                t,
+                &text.location,
+                text.text_height,
+                text.rotation,
+                vec![decode_specials(&text.value)],
+            ),
+            EntityType::MText(mtext) => {
+                let raw = mtext.extended_text.concat() + &mtext.text;
+                let direction = &mtext.x_axis_direction;
+                let rotation = if direction.x != 0.0 || direction.y != 0.0 {
+                    direction.y.atan2(direction.x).to_degrees()
+                } else {
+                    mtext.rotation_angle.to_degrees()
+                };
+                // The insertion point is the top of the first line
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                let height = mtext.initial_text_height;
+                let baseline = Point::new(
+                    mtext.insertion_point.x + height * sin,
+                    mtext.insertion_point.y - height * cos,
+                    0.0,
+                );
+                self.add_label(t, &baseline, height, rotation, clean_mtext(&raw));
+            }
+            EntityType::RotatedDimension(dim) => self.add_dimension(&dim.dimension_base, t, depth),
+            EntityType::RadialDimension(dim) => self.add_dimension(&dim.dimension_base, t, depth),
+            EntityType::DiameterDimension(dim) => self.add_dimension(&dim.dimension_base, t, depth),
+            EntityType::AngularThreePointDimension(dim) => {
+                self.add_dimension(&dim.dimension_base, t, depth)
+            }
+            EntityType::OrdinateDimension(dim) => self.add_dimension(&dim.dimension_base, t, depth),
+            EntityType::Insert(insert) => {
+                for attribute in insert.attributes() {
+                    self.add_label(
+                        t,
+                        &attribute.location,
+                        attribute.text_height,
+                        attribute.rotation,
+                        vec![decode_specials(&attribute.value)],
+                    );
+                }
+
+                let Some(block) = self.blocks.get(insert.name.as_str()).copied() else {
+                    return;
+                };
+                if depth >= MAX_BLOCK_DEPTH {
+                    return;
+                }
+                let (sin, cos) = insert.rotation.to_radians().sin_cos();
+                let rows = insert.row_count.max(1) as usize;
+                let columns = insert.column_count.max(1) as usize;
+                let rows = rows.min(MAX_INSERT_PLACEMENTS / columns).max(1);
+                let columns = columns.min(MAX_INSERT_PLACEMENTS);
+                for row in 0..rows {
+                    for column in 0..columns {
+                        // Each placement counts, so arrays of empty blocks end too
+                        self.scene.points += 1;
+                        if self.scene.points >= MAX_POINTS || self.timed_out {
+                            return;
+                        }
+                        let dx = column as f64 * insert.column_spacing;
+                        let dy = row as f64 * insert.row_spacing;
+                        let location = (
+                            insert.location.x + dx * cos - dy * sin,
+                            insert.location.y + dx * sin + dy * cos,
+                        );
+                        let placement = Transform::insert(
+                            &block.base_point,
+                            location,
+                            (insert.x_scale_factor, insert.y_scale_factor),
+                            insert.rotation,
+                        );
+                        self.add_entities(block.entities.iter(), &t.compose(&placement), depth + 1);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Dimensions reference an anonymous block holding their lines, arrows
+    /// and text; without one, at least show the measurement
+    fn add_dimension(&mut self, dim: &DimensionBase, t: &Transform, depth: usize) {
+        if let Some(block) = self.blocks.get(dim.block_name.as_str()).copied() {
+            if depth < MAX_BLOCK_DEPTH && !block.entities.is_empty() {
+                self.add_entities(block.entities.iter(), t, depth + 1);
+                return;
+            }
+        }
+
+        let measurement = format!("{:.2}", dim.actual_measurement);
+        let measurement = measurement.trim_end_matches('0').trim_end_matches('.');
+        let text = if dim.text.is_empty() {
+            measurement.to_string()
+        } else {
+            clean_mtext(&dim.text.replace("<>", measurement)).join(" ")
+        };
+        let height = (dim.actual_measurement.abs() / 20.0).max(1e-3);
+        self.add_label(
+            t,
+            &dim.text_mid_point,
+            height,
+            dim.text_rotation_angle,
+            vec![text],
+        );
+    }
+
+    fn add_path(&mut self, t: &Transform, points: Vec<(f64, f64)>, closed: bool) {
+        if points.len() < 2 {
+            return;
+        }
+        self.scene.points += points.len();
+        let points = points.into_iter().map(|(x, y)| t.apply(x, y)).collect();
+        self.scene.paths.push((points, closed));
+    }
+
+    fn add_label(
+        &mut self,
+        t: &Transform,
+        location: &Point,
+        height: f64,
+        rotation: f64,
+        lines: Vec<String>,
+    ) {
+        if lines.iter().all(|line| line.trim().is_empty()) || height <= 0.0 {
+            return;
+        }
+        let (x, y) = t.apply(location.x, location.y);
+        self.scene.points += 1;
+        self.scene.labels.push(Label {
+            x,
+            y,
+            height: height * t.scale(),
+            rotation: rotation + t.rotation(),
+            lines,
+        });
+    }
+}
+
+/// Counter-clockwise sweep from `start` to `end`, both in units where a
+/// full turn is `full_turn`
+///
+/// Sweeps are reduced to one turn and an empty sweep means a full one, like
+/// CAD programs read them. None when either angle isn't a finite number.
+fn sweep(start: f64, end: f64, full_turn: f64) -> Option<f64> {
+    if !start.is_finite() || !end.is_finite() {
+        return None;
+    }
+    let sweep = (end - start).rem_euclid(full_turn);
+    // Very large angles can round to exactly one turn
+    Some(if sweep == 0.0 || sweep >= full_turn {
+        full_turn
+    } else {
+        sweep
+    })
+}
+
+/// Number of segments used to sample a sweep of the given size
+fn segments(sweep_degrees: f64) -> usize {
+    let steps = (sweep_degrees.abs() / ARC_STEP_DEGREES).ceil();
+    if steps.is_finite() {
+        (steps as usize).clamp(2, MAX_CURVE_SEGMENTS)
+    } else {
+        2
+    }
+}
+
+/// Sample a circular arc, counter-clockwise from `start` (degrees)
+fn arc_points(center: &Point, radius: f64, start: f64, sweep: f64) -> Vec<(f64, f64)> {
+    let steps = segments(sweep);
+    (0..=steps)
+        .map(|i| {
+            let angle = (start + sweep * i as f64 / steps as f64).to_radians();
+            (
+                center.x + radius * angle.cos(),
+                center.y + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+/// Expand polyline vertices `(x, y, bulge)` into points
+///
+/// A bulge is the tangent of a quarter of the included angle of the arc to
+/// the next vertex; positive bulges turn counter-clockwise.
+fn bulge_points(vertices: &[(f64, f64, f64)], closed: bool) -> Vec<(f64, f64)> {
+    let mut points = Vec::with_capacity(vertices.len());
+    for (i, &(x0, y0, bulge)) in vertices.iter().enumerate() {
+        points.push((x0, y0));
+
+        let next = if i + 1 < vertices.len() {
+            vertices[i + 1]
+        } else if closed {
+            vertices[0]
+        } else {
+            break;
+        };
+        let (x1, y1) = (next.0, next.1);
+        let chord = (x1 - x0).hypot(y1 - y0);
+        if bulge.abs() < 1e-9 || chord < 1e-12 {
+            continue;
+        }
+
+        // Centre lies on the chord's perpendicular bisector
+        let offset = chord * (1.0 - bulge * bulge) / (4.0 * bulge);
+        let (nx, ny) = (-(y1 - y0) / chord, (x1 - x0) / chord);
+        let cx = (x0 + x1) / 2.0 + offset * nx;
+        let cy = (y0 + y1) / 2.0 + offset * ny;
+        let radius = (x0 - cx).hypot(y0 - cy);
+        let start = (y0 - cy).atan2(x0 - cx).to_degrees();
+        let sweep = (4.0 * bulge.atan()).to_degrees();
+
+        let center = Point::new(cx, cy, 0.0);
+        let arc = arc_points(&center, radius, start, sweep);
+        // Skip the endpoints, which are the vertices themselves
+        points.extend_from_slice(&arc[1..arc.len() - 1]);
+    }
+    points
+}
+
+/// Evaluate a non-rational B-spline with de Boor's algorithm
+///
+/// Returns None when the knot vector doesn't match the control points.
+fn spline_points(degree: i32, knots: &[f64], control: &[Point]) -> Option<Vec<(f64, f64)>> {
+    let p = usize::try_from(degree)
+        .ok()
+        .filter(|p| (1..=MAX_SPLINE_DEGREE).contains(p))?;
+    let n = control.len();
+    if n <= p || knots.len() != n + p + 1 {
+        return None;
+    }
+
+    let (start, end) = (knots[p], knots[n]);
+    if end <= start {
+        return None;
+    }
+    let steps = (n * 8).min(MAX_CURVE_SEGMENTS);
+    let points = (0..=steps)
+        .map(|i| {
+            let u = start + (end - start) * i as f64 / steps as f64;
+            // Knot span containing u, clamped so the last point is included
+            let k = (p..n)
+                .rev()
+                .find(|&k| knots[k] <= u && knots[k] < knots[k + 1])
+                .unwrap_or(p);
+
+            let mut d: Vec<(f64, f64)> = (0..=p)
+                .map(|j| (control[j + k - p].x, control[j + k - p].y))
+                .collect();
+            for r in 1..=p {
+                for j in (r..=p).rev() {
+                    let left = knots[j + k - p];
+                    let denominator = knots[j + 1 + k - r] - left;
+                    let alpha = if denominator.abs() < 1e-12 {
+                        0.0
+                    } else {
+                        (u - left) / denominator
+                    };
+                    d[j] = (
+                        (1.0 - alpha) * d[j - 1].0 + alpha * d[j].0,
+                        (1.0 - alpha) * d[j - 1].1 + alpha * d[j].1,
+                    );
+                }
+            }
+            d[p]
+        })
+        .collect();
+    Some(points)
+}
+
+/// Replace AutoCAD control codes (`%%c`, `%%d`, `%%p`) with their symbols
+fn decode_specials(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find("%%") {
+        out.push_str(&rest[..pos]);
+        let code = rest[pos + 2..].chars().next();
+        match code.map(|c| c.to_ascii_lowercase()) {
+            Some('c') => out.push('\u{2300}'),
+            Some('d') => out.push('\u{00B0}'),
+            Some('p') => out.push('\u{00B1}'),
+            Some('%') => out.push('%'),
+            // Underline/overline toggles have no visible character
+            Some('u') | Some('o') => {}
+            Some(c) => {
+                out.push_str("%%");
+                out.push(c);
+            }
+            None => out.push_str("%%"),
+        }
+        rest = &rest[pos + 2 + code.map_or(0, char::len_utf8)..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Strip MTEXT formatting codes, splitting paragraphs into lines
+fn clean_mtext(raw: &str) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        let line = lines.last_mut().expect("at least one line");
+        match c {
+            '\\' => match chars.next() {
+                Some('P') => lines.push(String::new()),
+                Some('~') => line.push(' '),
+                Some(c @ ('\\' | '{' | '}')) => line.push(c),
+                // Toggles without arguments (underline, overline, strike-through)
+                Some('L' | 'l' | 'O' | 'o' | 'K' | 'k') => {}
+                Some('U') if chars.peek() == Some(&'+') => {
+                    chars.next();
+                    let hex: String = chars.by_ref().take(4).collect();
+                    if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                        line.push(c);
+                    }
+                }
+                // Stacked fractions: \S1/2; or \S1^2;
+                Some('S') => {
+                    for c in chars.by_ref().take_while(|c| *c != ';') {
+                        line.push(if c == '^' || c == '#' { '/' } else { c });
+                    }
+                }
+                // Codes with an argument up to ';' (font, height, colour, ...)
+                Some(_) => for _ in chars.by_ref().take_while(|c| *c != ';') {},
+                None => {}
+            },
+            '{' | '}' => {}
+            _ => line.push(c),
+        }
+    }
+    lines.iter().map(|line| decode_specials(line)).collect()
+}
+
+/// Format a coordinate compactly (at most 3 decimals, no trailing zeros)
+fn num(value: f64) -> String {
+    let formatted = format!("{:.3}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "" | "-" | "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn sweep_is_counter_clockwise_within_one_turn() {
+        assert_eq!(sweep(0.0, 90.0, 360.0), Some(90.0));
+        assert_eq!(sweep(350.0, 10.0, 360.0), Some(20.0));
+        assert_eq!(sweep(10.0, 370.0 + 45.0, 360.0), Some(45.0));
+        assert_eq!(sweep(-90.0, 0.0, 360.0), Some(90.0));
+        assert_eq!(sweep(10.0, -10.0, 360.0), Some(340.0));
+    }
+
+    #[test]
+    fn empty_sweep_is_a_full_turn() {
+        assert_eq!(sweep(90.0, 90.0, 360.0), Some(360.0));
+        assert_eq!(sweep(0.0, 360.0, 360.0), Some(360.0));
+        assert_eq!(sweep(0.0, -720.0, 360.0), Some(360.0));
+        assert_eq!(
+            sweep(0.0, std::f64::consts::TAU, std::f64::consts::TAU),
+            Some(std::f64::consts::TAU)
+        );
+    }
+
+    #[test]
+    fn huge_sweeps_stay_within_one_turn() {
+        for end in [1e15, -1e15, 1e300, f64::MAX, f64::MIN] {
+            let sweep = sweep(0.0, end, 360.0).unwrap();
+            assert!(sweep > 0.0 && sweep <= 360.0, "{} gave {}", end, sweep);
+        }
+    }
+
+    #[test]
+    fn non_finite_angles_have_no_sweep() {
+        assert_eq!(sweep(f64::NAN, 90.0, 360.0), None);
+        assert_eq!(sweep(0.0, f64::INFINITY, 360.0), None);
+        assert_eq!(sweep(f64::NEG_INFINITY, 0.0, std::f64::consts::TAU), None);
+    }
+
+    #[test]
+    fn segments_are_bounded() {
+        assert_eq!(segments(0.0), 2);
+        assert_eq!(segments(1.0), 2);
+        assert_eq!(segments(90.0), 18);
+        assert_eq!(segments(-90.0), 18);
+        assert_eq!(segments(360.0), 72);
+        assert_eq!(segments(1e12), MAX_CURVE_SEGMENTS);
+        assert_eq!(segments(f64::INFINITY), 2);
+        assert_eq!(segments(f64::NAN), 2);
+    }
+
+    #[test]
+    fn arc_points_run_from_start_to_end() {
+        let center = Point::new(1.0, 2.0, 0.0);
+        let points = arc_points(&center, 2.0, 0.0, 90.0);
+        assert_eq!(points.len(), segments(90.0) + 1);
+        let (first, last) = (points[0], points[points.len() - 1]);
+        assert!(close(first.0, 3.0) && close(first.1, 2.0));
+        assert!(close(last.0, 1.0) && close(last.1, 4.0));
+        assert!(points
+            .iter()
+            .all(|&(x, y)| close((x - 1.0).hypot(y - 2.0), 2.0)));
+    }
+
+    #[test]
+    fn full_circle_closes() {
+        let points = arc_points(&Point::origin(), 1.0, 45.0, 360.0);
+        let (first, last) = (points[0], points[points.len() - 1]);
+        assert!(close(first.0, last.0) && close(first.1, last.1));
+    }
+
+    #[test]
+    fn semicircle_bulge_passes_through_the_top() {
+        let points = bulge_points(&[(-1.0, 0.0, -1.0), (1.0, 0.0, 0.0)], false);
+        assert_eq!(points[0], (-1.0, 0.0));
+        assert_eq!(points[points.len() - 1], (1.0, 0.0));
+        assert!(points.iter().any(|&(x, y)| close(x, 0.0) && close(y, 1.0)));
+    }
+
+    #[test]
+    fn spline_degree_and_knots_are_checked() {
+        let control: Vec<Point> = (0..4).map(|i| Point::new(i as f64, 0.0, 0.0)).collect();
+        let knots = [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0];
+        assert!(spline_points(3, &knots, &control).is_some());
+        assert!(spline_points(0, &knots, &control).is_none());
+        assert!(spline_points(-1, &knots, &control).is_none());
+        assert!(spline_points(MAX_SPLINE_DEGREE as i32 + 1, &knots, &control).is_none());
+        assert!(spline_points(3, &knots[1..], &control).is_none());
+    }
+
+    #[test]
+    fn spline_is_clamped_to_its_end_points() {
+        let control = [
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 2.0, 0.0),
+            Point::new(3.0, 2.0, 0.0),
+            Point::new(4.0, 0.0, 0.0),
+        ];
+        let knots = [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0];
+        let points = spline_points(3, &knots, &control).unwrap();
+        assert_eq!(points.len(), control.len() * 8 + 1);
+        assert_eq!(points[0], (0.0, 0.0));
+        assert_eq!(points[points.len() - 1], (4.0, 0.0));
+    }
+
+    #[test]
+    fn spline_samples_are_capped() {
+        let n = MAX_CURVE_SEGMENTS;
+        let control: Vec<Point> = (0..n).map(|i| Point::new(i as f64, 0.0, 0.0)).collect();
+        let knots: Vec<f64> = (0..n + 2).map(|i| i as f64).collect();
+        let points = spline_points(1, &knots, &control).unwrap();
+        assert_eq!(points.len(), MAX_CURVE_SEGMENTS + 1);
+    }
+}
@@ -9,39 +9,60 @@
 
 use crate::db::DbPool;
 use crate::error::{AppError, AppResult};
-use crate::models::{Project, ProjectStatus};
+use crate::models::{
+    DeletedProjectDocuments, Document, Project, ProjectDeleteReport, ProjectMergeReport,
+    ProjectResponse, ProjectStatusChange,
+};
+use crate::services::lifecycle_service::{ACTIVE_ALIAS, ARCHIVED_STATUS, LEGACY_ACTIVE_STATE};
+use crate::services::{
+    ClientService, ColdStorageService, DocumentService, LifecycleService, StorageService,
+};
+use sqlx::{Sqlite, Transaction};
 use uuid::Uuid;
 
+/// Name of the special project holding the global forum
+const GERAL_PROJECT: &str = "Geral";
+
 /// Project service handling all project-related business logic
 pub struct ProjectService;
 
 impl ProjectService {
-    /// Create a new project
+    /// Create a new project in the first state of the lifecycle
     pub async fn create(
         pool: &DbPool,
         name: String,
         address: Option<String>,
         client_phone: Option<String>,
+        client_id: Option<String>,
     ) -> AppResult<Project> {
         let name = name.trim().to_string();
         if name.is_empty() {
             return Err(AppError::BadRequest("Project name cannot be empty".into()));
         }
+        if let Some(client_id) = &client_id {
+            ClientService::get_by_id(pool, client_id).await?;
+        }
 
         let id = Uuid::new_v4().to_string();
+        let status = LifecycleService::initial_state(pool).await?;
 
+        let mut tx = pool.begin().await?;
         sqlx::query(
             r#"
-            INSERT INTO projects (id, name, status, address, client_phone, created_at)
-            VALUES (?, ?, 'ACTIVE', ?, ?, datetime('now'))
+            INSERT INTO projects (id, name, status, address, client_phone, client_id, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, datetime('now'))
             "#,
         )
         .bind(&id)
         .bind(&name)
+        .bind(&status)
         .bind(&address)
         .bind(&client_phone)
-        .execute(pool)
+        .bind(&client_id)
+        .execute(&mut *tx)
         .await?;
+        Self::record_status(&mut *tx, &id, None, &status, None).await?;
+        tx.commit().await?;
 
         Self::get_by_id(pool, &id).await
     }
@@ -56,14 +77,25 @@ impl ProjectService {
     }
 
     /// List all projects, optionally filtered by status
+    ///
+    /// The filter is a lifecycle state, or "active" for every state but archived.
     pub async fn list(pool: &DbPool, status_filter: Option<&str>) -> AppResult<Vec<Project>> {
         let projects = match status_filter {
+            Some(status) if LifecycleService::normalize(status) == ACTIVE_ALIAS => {
+                sqlx::query_as::<_, Project>(
+                    "SELECT * FROM projects WHERE status != ? ORDER BY created_at DESC",
+                )
+                .bind(ARCHIVED_STATUS)
+                .fetch_all(pool)
+                .await?
+            }
             Some(status) => {
-                let status = status.to_uppercase();
-                if status != "ACTIVE" && status != "ARCHIVED" {
+                let status = LifecycleService::normalize(status);
+                if !LifecycleService::exists(pool, &status).await? {
                     return Err(AppError::BadRequest(format!(
-                        "Invalid status filter '{}'. Use 'active' or 'archived'",
-                        status
+                        "Invalid status filter '{}'. Use 'active' or one of: {}",
+                        status,
+                        LifecycleService::codes(pool).await?.join(", ")
                     )));
                 }
                 sqlx::query_as::<_, Project>(
@@ -82,28 +114,152 @@ impl ProjectService {
         Ok(projects)
     }
 
-    /// Update project status
+    /// Move a project to another lifecycle state
+    ///
+    /// Only transitions allowed by the lifecycle are accepted, and each one
+    /// is recorded in the status history. "ACTIVE" reopens an archived
+    /// project in the state it was archived from.
     pub async fn update_status(
         pool: &DbPool,
         id: &str,
-        status: ProjectStatus,
+        status: &str,
+        actor: Option<&str>,
     ) -> AppResult<Project> {
-        let result = sqlx::query("UPDATE projects SET status = ? WHERE id = ?")
-            .bind(status.as_str())
+        // Take the write lock up front so concurrent changes queue behind
+        // this one instead of failing when the read turns into a write
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        let project = sqlx::query_as::<_, Project>("SELECT * FROM projects WHERE id = ?")
             .bind(id)
-            .execute(pool)
-            .await?;
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Project with id '{}' not found", id)))?;
 
-        if result.rows_affected() == 0 {
-            return Err(AppError::NotFound(format!(
-                "Project with id '{}' not found",
+        let requested = LifecycleService::normalize(status);
+        let target = if requested == ACTIVE_ALIAS {
+            if project.status != ARCHIVED_STATUS {
+                return Ok(project);
+            }
+            Self::status_before_archiving(&mut tx, id).await?
+        } else {
+            requested
+        };
+        if target == project.status {
+            return Ok(project);
+        }
+
+        LifecycleService::check_transition(&mut tx, &project.status, &target).await?;
+
+        // Only move from the status the transition was checked against
+        let updated = sqlx::query(
+            r#"
+            UPDATE projects
+            SET status = ?1,
+                archived_at = CASE WHEN ?1 = ?2 THEN COALESCE(archived_at, datetime('now')) ELSE NULL END
+            WHERE id = ?3 AND status = ?4
+            "#,
+        )
+        .bind(&target)
+        .bind(ARCHIVED_STATUS)
+        .bind(id)
+        .bind(&project.status)
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(AppError::Conflict(format!(
+                "Project '{}' changed status while updating it, try again",
                 id
             )));
         }
+        Self::record_status(&mut *tx, id, Some(&project.status), &target, actor).await?;
+        tx.commit().await?;
+
+        tracing::info!(
+            "Project {} moved from {} to {} by {}",
+            id,
+            project.status,
+            target,
+            actor.unwrap_or("unknown")
+        );
+
+        // A reopened project needs its files back
+        if project.status == ARCHIVED_STATUS && ColdStorageService::is_enabled() {
+            ColdStorageService::spawn_restore_project(pool, id);
+        }
 
         Self::get_by_id(pool, id).await
     }
 
+    /// A project's lifecycle transitions, oldest first
+    pub async fn status_history(pool: &DbPool, id: &str) -> AppResult<Vec<ProjectStatusChange>> {
+        Self::get_by_id(pool, id).await?;
+
+        let history = sqlx::query_as::<_, ProjectStatusChange>(
+            "SELECT * FROM project_status_history WHERE project_id = ? ORDER BY created_at, rowid",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(history)
+    }
+
+    /// Record a lifecycle transition in the status history
+    pub async fn record_status<'e, E>(
+        executor: E,
+        project_id: &str,
+        from: Option<&str>,
+        to: &str,
+        actor: Option<&str>,
+    ) -> AppResult<()>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        sqlx::query(
+            "INSERT INTO project_status_history (id, project_id, from_status, to_status, actor) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(project_id)
+        .bind(from)
+        .bind(to)
+        .bind(actor)
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// The state an archived project was archived from, if it still exists
+    ///
+    /// Projects archived before the history existed were ACTIVE before, so
+    /// they go back to the state ACTIVE projects were migrated to, or to the
+    /// first state if the lifecycle no longer has it.
+    async fn status_before_archiving(
+        tx: &mut Transaction<'_, Sqlite>,
+        id: &str,
+    ) -> AppResult<String> {
+        let previous: Option<(String,)> = sqlx::query_as(
+            r#"
+            SELECT h.from_status FROM project_status_history h
+            JOIN project_states s ON s.code = h.from_status
+            WHERE h.project_id = ? AND h.to_status = ? AND h.from_status != ?
+            ORDER BY h.created_at DESC, h.rowid DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(id)
+        .bind(ARCHIVED_STATUS)
+        .bind(ARCHIVED_STATUS)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        match previous {
+            Some((status,)) => Ok(status),
+            None if LifecycleService::exists(&mut **tx, LEGACY_ACTIVE_STATE).await? => {
+                Ok(LEGACY_ACTIVE_STATE.to_string())
+            }
+            None => LifecycleService::initial_state(&mut **tx).await,
+        }
+    }
+
     /// Update project address and client phone
     pub async fn update_details(
         pool: &DbPool,
@@ -128,6 +284,252 @@ impl ProjectService {
         Self::get_by_id(pool, id).await
     }
 
+    /// Link a project to a client, or unlink it with None
+    ///
+    /// The project's own client phone, if any, is added to the client's phones.
+    pub async fn set_client(
+        pool: &DbPool,
+        id: &str,
+        client_id: Option<&str>,
+    ) -> AppResult<Project> {
+        let project = Self::get_by_id(pool, id).await?;
+        if let Some(client_id) = client_id {
+            ClientService::get_by_id(pool, client_id).await?;
+            if let Some(phone) = &project.client_phone {
+                ClientService::add_phone(pool, client_id, phone).await?;
+            }
+        }
+
+        sqlx::query("UPDATE projects SET client_id = ? WHERE id = ?")
+            .bind(client_id)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Self::get_by_id(pool, id).await
+    }
+
+    /// Rename a project
+    pub async fn rename(pool: &DbPool, id: &str, name: String) -> AppResult<Project> {
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return Err(AppError::BadRequest("Project name cannot be empty".into()));
+        }
+
+        let project = Self::get_by_id(pool, id).await?;
+        if project.name == GERAL_PROJECT || name == GERAL_PROJECT {
+            return Err(AppError::BadRequest(format!(
+                "The '{}' project name is reserved for the global forum",
+                GERAL_PROJECT
+            )));
+        }
+
+        sqlx::query("UPDATE projects SET name = ? WHERE id = ?")
+            .bind(&name)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Self::get_by_id(pool, id).await
+    }
+
+    /// Merge project `id` into project `into`
+    ///
+    /// Documents, forum messages (with their replies and task lists), email
+    /// rules and cold storage archives move to `into`, then `id` is removed.
+    /// The target keeps its own name, status and details.
+    pub async fn merge(pool: &DbPool, id: &str, into: &str) -> AppResult<ProjectMergeReport> {
+        if id == into {
+            return Err(AppError::BadRequest(
+                "A project cannot be merged into itself".into(),
+            ));
+        }
+        let source = Self::get_by_id(pool, id).await?;
+        Self::get_by_id(pool, into).await?;
+        Self::ensure_not_geral(&source)?;
+
+        let mut tx = pool.begin().await?;
+
+        let task_items_moved = Self::count_task_items(&mut tx, id).await?;
+        let documents_moved = Self::reassign(&mut tx, "documents", id, into).await?;
+        let forum_messages_moved = Self::reassign(&mut tx, "forum_messages", id, into).await?;
+        let email_rules_moved = Self::reassign(&mut tx, "email_rules", id, into).await?;
+        Self::reassign(&mut tx, "cold_archives", id, into).await?;
+        Self::reassign(&mut tx, "document_events", id, into).await?;
+
+        sqlx::query("DELETE FROM projects WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        tracing::info!(
+            "Merged project {} into {}: {} documents, {} forum messages, {} email rules",
+            id,
+            into,
+            documents_moved,
+            forum_messages_moved,
+            email_rules_moved
+        );
+
+        let project = Self::get_by_id(pool, into).await?;
+        let count = Self::get_document_count(pool, into).await?;
+
+        Ok(ProjectMergeReport {
+            project: ProjectResponse::from_project(project, count),
+            merged_project_id: id.to_string(),
+            documents_moved,
+            forum_messages_moved,
+            task_items_moved,
+            email_rules_moved,
+        })
+    }
+
+    /// Delete a project
+    ///
+    /// Forum messages (with replies, task lists and voice messages) and email
+    /// rules are deleted with the project. Documents either go back to the
+    /// inbox or are deleted permanently with their files, as requested.
+    ///
+    /// Every step is explicit rather than left to the foreign keys, which
+    /// disagree: documents are set to NULL while forum messages cascade.
+    pub async fn delete(
+        pool: &DbPool,
+        id: &str,
+        documents: DeletedProjectDocuments,
+    ) -> AppResult<ProjectDeleteReport> {
+        let project = Self::get_by_id(pool, id).await?;
+        Self::ensure_not_geral(&project)?;
+
+        let mut report = ProjectDeleteReport {
+            project_id: id.to_string(),
+            ..Default::default()
+        };
+        let mut files: Vec<String> = Vec::new();
+
+        let mut tx = pool.begin().await?;
+
+        report.task_items_deleted = Self::count_task_items(&mut tx, id).await?;
+        let voice_files: Vec<(String,)> = sqlx::query_as(
+            "SELECT audio_path FROM forum_messages WHERE project_id = ? AND audio_path IS NOT NULL",
+        )
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+        files.extend(voice_files.into_iter().map(|(path,)| path));
+
+        // Counted up front: rows removed by cascade don't show in rows_affected
+        let (messages,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM forum_messages WHERE project_id = ?")
+                .bind(id)
+                .fetch_one(&mut *tx)
+                .await?;
+        report.forum_messages_deleted = messages as u64;
+
+        // Replies, task items and album links cascade from their messages
+        sqlx::query("DELETE FROM forum_messages WHERE project_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        match documents {
+            DeletedProjectDocuments::Inbox => {
+                report.documents_to_inbox =
+                    sqlx::query("UPDATE documents SET project_id = NULL WHERE project_id = ?")
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?
+                        .rows_affected();
+            }
+            DeletedProjectDocuments::Delete => {
+                let docs =
+                    sqlx::query_as::<_, Document>("SELECT * FROM documents WHERE project_id = ?")
+                        .bind(id)
+                        .fetch_all(&mut *tx)
+                        .await?;
+                for doc in &docs {
+                    files.extend(DocumentService::stored_files(&mut *tx, doc).await?);
+                }
+                report.documents_deleted =
+                    sqlx::query("DELETE FROM documents WHERE project_id = ?")
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?
+                        .rows_affected();
+            }
+        }
+
+        report.email_rules_deleted = sqlx::query("DELETE FROM email_rules WHERE project_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        // Archives stay until their documents are gone; garbage collection removes them
+        sqlx::query("UPDATE cold_archives SET project_id = NULL WHERE project_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM projects WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        StorageService::remove_unreferenced(pool, &files).await;
+
+        tracing::info!("Deleted project {}: {:?}", id, report);
+
+        Ok(report)
+    }
+
+    /// The global forum project can't be merged away or deleted
+    fn ensure_not_geral(project: &Project) -> AppResult<()> {
+        if project.name == GERAL_PROJECT {
+            return Err(AppError::BadRequest(format!(
+                "The '{}' project holds the global forum and cannot be removed",
+                GERAL_PROJECT
+            )));
+        }
+        Ok(())
+    }
+
+    /// Move every row of `table` from one project to another
+    async fn reassign(
+        tx: &mut Transaction<'_, Sqlite>,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> AppResult<u64> {
+        let result = sqlx::query(&format!(
+            "UPDATE {} SET project_id = ? WHERE project_id = ?",
+            table
+        ))
+        .bind(to)
+        .bind(from)
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Count the task list items in a project's forum
+    async fn count_task_items(tx: &mut Transaction<'_, Sqlite>, id: &str) -> AppResult<u64> {
+        let (count,): (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM task_items t
+            JOIN forum_messages m ON m.id = t.message_id
+            WHERE m.project_id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(count as u64)
+    }
+
     /// Get the number of documents for a project
     pub async fn get_document_count(pool: &DbPool, project_id: &str) -> AppResult<i32> {
         let count: (i32,) =
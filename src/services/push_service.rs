@@ -166,19 +166,6 @@ impl PushService {
         author_name: &str,
         content: &str,
     ) {
-        // Get VAPID private PEM
-        let private_pem: Option<(String,)> =
-            sqlx::query_as("SELECT value FROM app_settings WHERE key = 'vapid_private_pem'")
-                .fetch_optional(pool)
-                .await
-                .ok()
-                .flatten();
-
-        let Some((ref pem_str,)) = private_pem else {
-            tracing::debug!("No VAPID keys, skipping push");
-            return;
-        };
-
         // Get all subscriptions except sender's
         let subs: Vec<PushSubscription> = sqlx::query_as(
             "SELECT * FROM push_subscriptions WHERE author_name IS NULL OR author_name != ?",
@@ -188,14 +175,77 @@ impl PushService {
         .await
         .unwrap_or_default();
 
+        Self::send_to_subscriptions(pool, subs, project_name, author_name, content).await;
+    }
+
+    /// Send notification only to the given participants (by author name),
+    /// skipping the sender
+    pub async fn notify_participants(
+        pool: &DbPool,
+        participants: &[String],
+        topic: &str,
+        author_name: &str,
+        content: &str,
+    ) {
+        let mut recipients: Vec<&String> = participants
+            .iter()
+            .filter(|name| name.as_str() != author_name)
+            .collect();
+        recipients.sort();
+        recipients.dedup();
+
+        if recipients.is_empty() {
+            return;
+        }
+
+        let placeholders = vec!["?"; recipients.len()].join(", ");
+        let query = format!(
+            "SELECT * FROM push_subscriptions WHERE author_name IN ({})",
+            placeholders
+        );
+        let mut q = sqlx::query_as::<_, PushSubscription>(&query);
+        for name in recipients {
+            q = q.bind(name);
+        }
+        let subs = q.fetch_all(pool).await.unwrap_or_default();
+
+        Self::send_to_subscriptions(pool, subs, topic, author_name, content).await;
+    }
+
+    /// Deliver a notification to a set of subscriptions, pruning expired ones
+    async fn send_to_subscriptions(
+        pool: &DbPool,
+        subs: Vec<PushSubscription>,
+        topic: &str,
+        author_name: &str,
+        content: &str,
+    ) {
         if subs.is_empty() {
             return;
         }
 
+        // Get VAPID private PEM
+        let private_pem: Option<(String,)> =
+            sqlx::query_as("SELECT value FROM app_settings WHERE key = 'vapid_private_pem'")
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten();
+
+        let Some((ref pem_str,)) = private_pem else {
+            tracing::debug!("No VAPID keys, skipping push");
+            return;
+        };
+
+        let body = if content.chars().count() > 100 {
+            format!("{}...", content.chars().take(97).collect::<String>())
+        } else {
+            content.to_string()
+        };
         let payload = serde_json::json!({
-            "title": format!("{} - {}", project_name, author_name),
-            "body": if content.len() > 100 { format!("{}...", &content[..97]) } else { content.to_string() },
-            "tag": project_name,
+            "title": format!("{} - {}", topic, author_name),
+            "body": body,
+            "tag": topic,
         });
         let payload_str = payload.to_string();
 
@@ -219,7 +269,7 @@ impl PushService {
             };
 
             let mut builder = WebPushMessageBuilder::new(&sub_info);
-            let _ = builder.set_payload(ContentEncoding::Aes128Gcm, payload_str.as_bytes());
+            builder.set_payload(ContentEncoding::Aes128Gcm, payload_str.as_bytes());
             builder.set_vapid_signature(signature);
 
             let message = match builder.build() {
@@ -0,0 +1,344 @@
+//! Scan service module
+//!
+//! Optional malware scanning of every new file through a ClamAV `clamd`
+//! daemon (or anything speaking its INSTREAM protocol). Infected files never
+//! become documents: they are moved to a quarantine directory outside the
+//! served uploads folder and recorded in `quarantined_files`.
+//!
+//! # Architecture Decision
+//! Scanning is opt-in because most installs won't run clamd. When it is
+//! configured but unreachable, uploads fail closed: app uploads get an error
+//! the user can retry, and email attachments are quarantined with the scan
+//! error as reason so nothing is lost. Set `CLAMD_FAIL_OPEN=true` to accept
+//! files unscanned instead.
+//!
+//! # Configuration
+//! - `CLAMD_ADDRESS`: `host:port` for TCP or a socket path such as
+//!   `/var/run/clamav/clamd.ctl` (unset: scanning disabled)
+//! - `CLAMD_TIMEOUT_SECS`: per-file timeout (default: 60)
+//! - `CLAMD_FAIL_OPEN`: accept files when clamd is unavailable (default: false)
+
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::models::{DocumentSource, EmailProvenance, QuarantinedFile};
+
+/// Quarantine directory path, deliberately outside the served uploads directory
+pub const QUARANTINE_DIR: &str = "./quarantine";
+
+/// Size of each INSTREAM chunk sent to clamd
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Default per-file scan timeout
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Result of scanning one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanOutcome {
+    /// No threat found
+    Clean,
+    /// Threat found, with the signature name reported by clamd
+    Infected(String),
+    /// Scanning is disabled, or clamd was unavailable and `CLAMD_FAIL_OPEN` is set
+    Skipped,
+}
+
+/// Where a quarantined file came from
+pub struct QuarantineOrigin<'a> {
+    pub original_name: &'a str,
+    pub mime_type: Option<&'a str>,
+    pub source: DocumentSource,
+    pub uploaded_by: Option<&'a str>,
+    pub email: Option<&'a EmailProvenance>,
+}
+
+/// Scan service with static methods for clamd scanning and quarantine
+pub struct ScanService;
+
+impl ScanService {
+    /// Whether a clamd address is configured
+    pub fn enabled() -> bool {
+        Self::address().is_some()
+    }
+
+    /// Scan a file on disk
+    pub async fn scan_file(path: &str) -> AppResult<ScanOutcome> {
+        if !Self::enabled() {
+            return Ok(ScanOutcome::Skipped);
+        }
+        let file = tokio::fs::File::open(path).await?;
+        Self::scan(file).await
+    }
+
+    /// Scan in-memory content, such as an email attachment
+    pub async fn scan_bytes(data: &[u8]) -> AppResult<ScanOutcome> {
+        if !Self::enabled() {
+            return Ok(ScanOutcome::Skipped);
+        }
+        Self::scan(data).await
+    }
+
+    /// Scan with the configured timeout, applying the fail-open setting
+    async fn scan<R: AsyncRead + Unpin>(reader: R) -> AppResult<ScanOutcome> {
+        let address = Self::address().unwrap_or_default();
+        let timeout = std::env::var("CLAMD_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_TIMEOUT_SECS);
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(timeout),
+            Self::instream(&address, reader),
+        )
+        .await
+        .unwrap_or_else(|_| Err(format!("no answer within {}s", timeout)));
+
+        match result {
+            Ok(outcome) => Ok(outcome),
+            Err(e) if Self::fail_open() => {
+                tracing::warn!("clamd unavailable, accepting file unscanned: {}", e);
+                Ok(ScanOutcome::Skipped)
+            }
+            Err(e) => Err(AppError::Internal(format!("Malware scan failed: {}", e))),
+        }
+    }
+
+    /// Connect to clamd and run an INSTREAM scan
+    async fn instream<R: AsyncRead + Unpin>(
+        address: &str,
+        reader: R,
+    ) -> Result<ScanOutcome, String> {
+        #[cfg(unix)]
+        if address.starts_with('/') {
+            let stream = tokio::net::UnixStream::connect(address)
+                .await
+                .map_err(|e| format!("connect {}: {}", address, e))?;
+            return Self::instream_on(stream, reader).await;
+        }
+
+        let stream = tokio::net::TcpStream::connect(address)
+            .await
+            .map_err(|e| format!("connect {}: {}", address, e))?;
+        Self::instream_on(stream, reader).await
+    }
+
+    /// Speak the INSTREAM protocol: length-prefixed chunks, then a zero-length chunk
+    async fn instream_on<S, R>(mut stream: S, mut reader: R) -> Result<ScanOutcome, String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        R: AsyncRead + Unpin,
+    {
+        let io = |e: std::io::Error| e.to_string();
+
+        stream.write_all(b"zINSTREAM\0").await.map_err(io)?;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = reader.read(&mut buf).await.map_err(io)?;
+            if n == 0 {
+                break;
+            }
+            stream
+                .write_all(&(n as u32).to_be_bytes())
+                .await
+                .map_err(io)?;
+            stream.write_all(&buf[..n]).await.map_err(io)?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await.map_err(io)?;
+        stream.flush().await.map_err(io)?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.map_err(io)?;
+        let reply = String::from_utf8_lossy(&reply);
+        let reply = reply.trim_end_matches(['\0', '\n']).trim();
+
+        // Replies look like "stream: OK" or "stream: Eicar-Signature FOUND"
+        let verdict = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+        if verdict == "OK" {
+            Ok(ScanOutcome::Clean)
+        } else if let Some(signature) = verdict.strip_suffix("FOUND") {
+            Ok(ScanOutcome::Infected(signature.trim().to_string()))
+        } else {
+            Err(format!("unexpected clamd reply '{}'", reply))
+        }
+    }
+
+    /// Scan a newly written upload, quarantining it when infected
+    ///
+    /// Returns whether the file was actually scanned (false when skipped).
+    /// On error the file is no longer in the uploads directory.
+    pub async fn vet_file(
+        pool: &DbPool,
+        path: &str,
+        origin: &QuarantineOrigin<'_>,
+    ) -> AppResult<bool> {
+        match Self::scan_file(path).await {
+            Ok(ScanOutcome::Clean) => Ok(true),
+            Ok(ScanOutcome::Skipped) => Ok(false),
+            Ok(ScanOutcome::Infected(signature)) => {
+                Self::quarantine_file(pool, path, origin, Some(&signature), "Malware detected")
+                    .await?;
+                Err(AppError::MalwareDetected(format!(
+                    "'{}' contains {}",
+                    origin.original_name, signature
+                )))
+            }
+            Err(e) => {
+                let _ = tokio::fs::remove_file(path).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Record that a document's file passed the malware scan
+    pub async fn mark_clean<'e, E>(executor: E, document_id: &str) -> AppResult<()>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        sqlx::query("UPDATE documents SET scanned_at = datetime('now') WHERE id = ?")
+            .bind(document_id)
+            .execute(executor)
+            .await?;
+        Ok(())
+    }
+
+    /// Move a file that was already written to disk into quarantine
+    pub async fn quarantine_file(
+        pool: &DbPool,
+        path: &str,
+        origin: &QuarantineOrigin<'_>,
+        signature: Option<&str>,
+        reason: &str,
+    ) -> AppResult<QuarantinedFile> {
+        tokio::fs::create_dir_all(QUARANTINE_DIR).await?;
+        let size = tokio::fs::metadata(path).await?.len() as i64;
+
+        let id = Uuid::new_v4().to_string();
+        let file_name = format!("{}.quarantined", id);
+        let target = format!("{}/{}", QUARANTINE_DIR, file_name);
+        if tokio::fs::rename(path, &target).await.is_err() {
+            // Different filesystem: copy then remove
+            tokio::fs::copy(path, &target).await?;
+            tokio::fs::remove_file(path).await?;
+        }
+
+        Self::record(pool, &id, &file_name, size, origin, signature, reason).await
+    }
+
+    /// Write in-memory content straight into quarantine
+    pub async fn quarantine_bytes(
+        pool: &DbPool,
+        data: &[u8],
+        origin: &QuarantineOrigin<'_>,
+        signature: Option<&str>,
+        reason: &str,
+    ) -> AppResult<QuarantinedFile> {
+        tokio::fs::create_dir_all(QUARANTINE_DIR).await?;
+
+        let id = Uuid::new_v4().to_string();
+        let file_name = format!("{}.quarantined", id);
+        tokio::fs::write(format!("{}/{}", QUARANTINE_DIR, file_name), data).await?;
+
+        Self::record(
+            pool,
+            &id,
+            &file_name,
+            data.len() as i64,
+            origin,
+            signature,
+            reason,
+        )
+        .await
+    }
+
+    async fn record(
+        pool: &DbPool,
+        id: &str,
+        file_name: &str,
+        size: i64,
+        origin: &QuarantineOrigin<'_>,
+        signature: Option<&str>,
+        reason: &str,
+    ) -> AppResult<QuarantinedFile> {
+        let email = origin.email.cloned().unwrap_or_default();
+        sqlx::query(
+            r#"
+            INSERT INTO quarantined_files (id, original_name, file_path, mime_type, size, signature, reason,
+                                           source, uploaded_by, email_sender, email_subject, email_message_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(id)
+        .bind(origin.original_name)
+        .bind(file_name)
+        .bind(origin.mime_type)
+        .bind(size)
+        .bind(signature)
+        .bind(reason)
+        .bind(origin.source.as_str())
+        .bind(origin.uploaded_by)
+        .bind(&email.sender)
+        .bind(&email.subject)
+        .bind(&email.message_id)
+        .execute(pool)
+        .await?;
+
+        tracing::warn!(
+            "Quarantined '{}' ({}): {}",
+            origin.original_name,
+            signature.unwrap_or("no signature"),
+            reason
+        );
+
+        Self::get_quarantined(pool, id).await
+    }
+
+    /// List quarantined files, newest first
+    pub async fn list_quarantined(pool: &DbPool) -> AppResult<Vec<QuarantinedFile>> {
+        let files = sqlx::query_as::<_, QuarantinedFile>(
+            "SELECT * FROM quarantined_files ORDER BY created_at DESC",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(files)
+    }
+
+    /// Get a quarantined file by ID
+    pub async fn get_quarantined(pool: &DbPool, id: &str) -> AppResult<QuarantinedFile> {
+        sqlx::query_as::<_, QuarantinedFile>("SELECT * FROM quarantined_files WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Quarantined file '{}' not found", id)))
+    }
+
+    /// Permanently delete a quarantined file and its record
+    pub async fn delete_quarantined(pool: &DbPool, id: &str) -> AppResult<()> {
+        let file = Self::get_quarantined(pool, id).await?;
+
+        sqlx::query("DELETE FROM quarantined_files WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        let path = format!("{}/{}", QUARANTINE_DIR, file.file_path);
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            tracing::warn!("Failed to delete quarantined file {}: {}", path, e);
+        }
+        Ok(())
+    }
+
+    fn address() -> Option<String> {
+        std::env::var("CLAMD_ADDRESS")
+            .ok()
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+    }
+
+    fn fail_open() -> bool {
+        std::env::var("CLAMD_FAIL_OPEN").is_ok_and(|v| v == "true" || v == "1")
+    }
+}
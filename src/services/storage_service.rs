@@ -0,0 +1,1099 @@
+//! Storage service module
+//!
+//! Where document files live. Everything that stores, reads or deletes a
+//! file (uploads, email attachments, voice messages, previews, video
+//! derivatives, ZIP downloads, `/files`) goes through a [`StorageBackend`]
+//! addressed by key: the path relative to the storage root that the database
+//! columns (`file_path`, `audio_path`, ...) hold.
+//!
+//! Files are content-addressed: the key is the SHA-256 of the content,
+//! sharded on its first two bytes (`ab/cd/abcd…ef.pdf`), so names never
+//! collide, no directory grows past a few hundred entries and identical
+//! content is stored once. The original filename lives only in the database.
+//!
+//! Two backends ship:
+//! - `local`: the `./uploads` directory, served by the web server itself
+//! - `s3`: any S3-compatible object storage (AWS, MinIO, ...); `/files`
+//!   redirects to short-lived presigned URLs
+//!
+//! Either can be wrapped in an [`EncryptedStorage`] when `ENCRYPTION_KEY`
+//! is set (see the encryption service).
+//!
+//! # Architecture Decision
+//! New files are first written to a local staging directory, where the
+//! content sniffing, malware scan and checksum need a real file, and are
+//! handed to the backend only once accepted. Tools that need a path of their
+//! own (ffmpeg, the DXF parser) get a [`LocalCopy`], which is the file itself
+//! on local storage and a temporary download on object storage.
+//!
+//! Because several rows may share a file, files are only deleted once no
+//! column in [`KEY_COLUMNS`] refers to them. The extension stays in the key
+//! so files keep being served with the right content type. A writer that
+//! finds its content already stored holds a [`KeyPin`] until its own row
+//! refers to the key, and deletes check pins and references under the same
+//! per-key lock as writes, so the shared file can't go in between.
+//!
+//! # Configuration
+//! - `STORAGE_BACKEND`: `local` (default) or `s3`
+//! - `S3_BUCKET`: bucket name (required for `s3`)
+//! - `S3_REGION`: region (default: `us-east-1`)
+//! - `S3_ENDPOINT`: endpoint of a non-AWS service, e.g. `http://minio:9000`
+//! - `S3_ACCESS_KEY_ID` / `S3_SECRET_ACCESS_KEY`: credentials (default: the
+//!   usual `AWS_*` variables and instance credentials)
+//! - `S3_PREFIX`: key prefix inside the bucket (default: none)
+//! - `S3_PRESIGNED_DOWNLOADS`: redirect downloads to the bucket instead of
+//!   proxying them through the server (default: true)
+//! - `S3_PRESIGN_EXPIRY_SECS`: lifetime of presigned URLs (default: 3600)
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex as StdMutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::http::Method;
+use futures::TryStreamExt;
+use object_store::aws::{AmazonS3, AmazonS3Builder};
+use object_store::path::Path as ObjectPath;
+use object_store::signer::Signer;
+use object_store::{
+    Attribute, Attributes, GetOptions, GetRange, ObjectStore, PutMultipartOptions, PutOptions,
+    WriteMultipart,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio::sync::{Mutex, MutexGuard};
+use tokio_util::io::StreamReader;
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::services::encryption_service::{EncryptedStorage, Keyring};
+use crate::services::IntegrityService;
+
+/// Root directory of the local backend
+pub const UPLOADS_DIR: &str = "./uploads";
+
+/// Local working directory for files not yet handed to the backend
+///
+/// Kept outside the uploads directory so unvetted files are never served.
+pub const STAGING_DIR: &str = "./staging";
+
+/// Every database column holding a storage key, as (table, column)
+pub const KEY_COLUMNS: [(&str, &str); 6] = [
+    ("documents", "file_path"),
+    ("documents", "audio_path"),
+    ("documents", "preview_path"),
+    ("video_metadata", "poster_path"),
+    ("video_metadata", "transcoded_path"),
+    ("forum_messages", "audio_path"),
+];
+
+/// Default lifetime of presigned download URLs
+const DEFAULT_PRESIGN_EXPIRY_SECS: u64 = 3600;
+
+/// Files up to this size are sent to object storage in a single request
+const SINGLE_PUT_LIMIT: u64 = 8 * 1024 * 1024;
+
+/// Size of each part of a multipart upload to object storage
+const MULTIPART_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Number of locks storing and deleting are spread over by key
+const KEY_LOCK_COUNT: usize = 64;
+
+/// Serialize storing a key with deleting it
+static KEY_LOCKS: [Mutex<()>; KEY_LOCK_COUNT] = [const { Mutex::const_new(()) }; KEY_LOCK_COUNT];
+
+/// Pinned keys with their number of pins
+static PINNED: StdMutex<BTreeMap<String, usize>> = StdMutex::new(BTreeMap::new());
+
+/// Streaming reader over a stored object
+pub type ObjectReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// A stored object opened for reading
+pub struct StoredObject {
+    pub size: u64,
+    pub reader: ObjectReader,
+}
+
+/// Metadata of a stored object
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    /// Key relative to the storage root
+    pub key: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// A local file holding a stored object's content
+///
+/// Temporary copies are deleted when dropped.
+pub struct LocalCopy {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl LocalCopy {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path as a string, for APIs and tools that take one
+    pub fn path_str(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for LocalCopy {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// A place to keep document files
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Human-readable location for logs
+    fn describe(&self) -> String;
+
+    /// Directory holding the files when the server can serve them directly
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Store a local file under `key`, consuming it
+    async fn put_file(&self, key: &str, path: &Path) -> AppResult<()>;
+
+    /// Store in-memory content under `key`
+    async fn put_bytes(&self, key: &str, data: Bytes) -> AppResult<()>;
+
+    /// Open an object for streaming; NotFound when it doesn't exist
+    async fn get(&self, key: &str) -> AppResult<StoredObject>;
+
+    /// Open `length` bytes of an object starting at `offset`
+    ///
+    /// The range must lie within the object; `size` is the length of the range.
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> AppResult<StoredObject>;
+
+    /// Metadata of an object, or None when it doesn't exist
+    async fn head(&self, key: &str) -> AppResult<Option<ObjectInfo>>;
+
+    /// Delete an object; deleting a missing object is not an error
+    async fn delete(&self, key: &str) -> AppResult<()>;
+
+    /// Copy an object to another key, replacing whatever is there
+    async fn copy(&self, from: &str, to: &str) -> AppResult<()>;
+
+    /// Every stored object, sorted by key
+    async fn list(&self) -> AppResult<Vec<ObjectInfo>>;
+
+    /// Time-limited URL clients can download an object from directly
+    ///
+    /// None when downloads go through the server.
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> AppResult<Option<String>>;
+
+    /// A local file with the object's content
+    async fn local_copy(&self, key: &str) -> AppResult<LocalCopy> {
+        let mut object = self.get(key).await?;
+        let path = staging_path(extension_of(key));
+        let mut file = tokio::fs::File::create(&path).await?;
+        // Created before copying so a failed download is cleaned up too
+        let copy = LocalCopy {
+            path,
+            temporary: true,
+        };
+        tokio::io::copy(&mut object.reader, &mut file).await?;
+        Ok(copy)
+    }
+}
+
+/// Reject keys that could escape the storage root
+fn validate_key(key: &str) -> AppResult<()> {
+    let valid = !key.is_empty()
+        && !key.starts_with('/')
+        && !key.contains('\\')
+        && key
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!(
+            "Invalid storage key '{}'",
+            key
+        )))
+    }
+}
+
+/// Extension of a key, empty when it has none
+fn extension_of(key: &str) -> &str {
+    Path::new(key)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+}
+
+/// Unique path in the staging directory with the given extension
+fn staging_path(extension: &str) -> PathBuf {
+    let name = Uuid::new_v4().simple().to_string();
+    if extension.is_empty() {
+        Path::new(STAGING_DIR).join(name)
+    } else {
+        Path::new(STAGING_DIR).join(format!("{}.{}", name, extension))
+    }
+}
+
+/// The lock a key is stored and deleted under
+fn key_lock(key: &str) -> &'static Mutex<()> {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    &KEY_LOCKS[hasher.finish() as usize % KEY_LOCK_COUNT]
+}
+
+/// A stored key that isn't deleted as unreferenced while this is held
+///
+/// Returned by the `store_*` functions; keep it until the row referring to
+/// the key is written.
+#[must_use = "the key may be deleted as unreferenced once the pin is dropped"]
+pub struct KeyPin {
+    key: String,
+}
+
+impl KeyPin {
+    fn new(key: &str) -> Self {
+        *PINNED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key.to_string())
+            .or_default() += 1;
+        KeyPin {
+            key: key.to_string(),
+        }
+    }
+
+    fn is_pinned(key: &str) -> bool {
+        PINNED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(key)
+    }
+
+    /// The pinned storage key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Drop the pin, returning the key
+    pub fn release(self) -> String {
+        self.key.clone()
+    }
+}
+
+impl Drop for KeyPin {
+    fn drop(&mut self) {
+        let mut pinned = PINNED.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = pinned.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                pinned.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Files in the uploads directory
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> AppResult<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+
+    async fn open(&self, key: &str) -> AppResult<tokio::fs::File> {
+        match tokio::fs::File::open(self.path(key)?).await {
+            Ok(file) => Ok(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(AppError::NotFound(format!("File '{}' not found", key)))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn create_parent(path: &Path) -> AppResult<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Ok(())
+    }
+
+    /// Hidden file next to `target` that is renamed over it once complete
+    ///
+    /// Readers see either the old or the new object, never a partial one.
+    fn temp_path(target: &Path) -> PathBuf {
+        let name = target
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        target.with_file_name(format!(".{}.{}.tmp", name, Uuid::new_v4().simple()))
+    }
+
+    /// Move a written temporary file into place, removing it on failure
+    async fn commit(temp: &Path, target: &Path) -> AppResult<()> {
+        if let Err(e) = tokio::fs::rename(temp, target).await {
+            let _ = tokio::fs::remove_file(temp).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    fn describe(&self) -> String {
+        format!("local directory {}", self.root.display())
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> AppResult<()> {
+        let target = self.path(key)?;
+        Self::create_parent(&target).await?;
+        if tokio::fs::rename(path, &target).await.is_err() {
+            // Different filesystem: copy next to the target, then move it in
+            let temp = Self::temp_path(&target);
+            if let Err(e) = tokio::fs::copy(path, &temp).await {
+                let _ = tokio::fs::remove_file(&temp).await;
+                return Err(e.into());
+            }
+            Self::commit(&temp, &target).await?;
+            tokio::fs::remove_file(path).await?;
+        }
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, data: Bytes) -> AppResult<()> {
+        let target = self.path(key)?;
+        Self::create_parent(&target).await?;
+        let temp = Self::temp_path(&target);
+        if let Err(e) = tokio::fs::write(&temp, &data).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e.into());
+        }
+        Self::commit(&temp, &target).await
+    }
+
+    async fn get(&self, key: &str) -> AppResult<StoredObject> {
+        let file = self.open(key).await?;
+        let size = file.metadata().await?.len();
+        Ok(StoredObject {
+            size,
+            reader: Box::new(file),
+        })
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> AppResult<StoredObject> {
+        let mut file = self.open(key).await?;
+        if offset.saturating_add(length) > file.metadata().await?.len() {
+            return Err(AppError::BadRequest(format!(
+                "Range outside of file '{}'",
+                key
+            )));
+        }
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        Ok(StoredObject {
+            size: length,
+            reader: Box::new(file.take(length)),
+        })
+    }
+
+    async fn head(&self, key: &str) -> AppResult<Option<ObjectInfo>> {
+        let path = self.path(key)?;
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => Ok(Some(ObjectInfo {
+                key: key.to_string(),
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            })),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Hard-linked when possible, which is instant and takes no space
+    async fn copy(&self, from: &str, to: &str) -> AppResult<()> {
+        let source = self.path(from)?;
+        let target = self.path(to)?;
+        Self::create_parent(&target).await?;
+        let _ = tokio::fs::remove_file(&target).await;
+        if tokio::fs::hard_link(&source, &target).await.is_err() {
+            tokio::fs::copy(&source, &target).await?;
+        }
+        Ok(())
+    }
+
+    /// Hidden files (such as `.gitkeep`) are ignored
+    async fn list(&self) -> AppResult<Vec<ObjectInfo>> {
+        let mut objects = Vec::new();
+        let mut pending = vec![String::new()];
+        while let Some(relative_dir) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(self.root.join(&relative_dir)).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') {
+                    continue;
+                }
+                let key = if relative_dir.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", relative_dir, name)
+                };
+
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    pending.push(key);
+                } else if metadata.is_file() {
+                    objects.push(ObjectInfo {
+                        key,
+                        size: metadata.len(),
+                        modified: metadata.modified().ok(),
+                    });
+                }
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn presigned_url(&self, _key: &str, _expires_in: Duration) -> AppResult<Option<String>> {
+        Ok(None)
+    }
+
+    async fn local_copy(&self, key: &str) -> AppResult<LocalCopy> {
+        let path = self.path(key)?;
+        if tokio::fs::metadata(&path).await.is_err() {
+            return Err(AppError::NotFound(format!("File '{}' not found", key)));
+        }
+        Ok(LocalCopy {
+            path,
+            temporary: false,
+        })
+    }
+}
+
+/// Objects in an S3-compatible bucket
+pub struct S3Storage {
+    store: AmazonS3,
+    bucket: String,
+    /// Key prefix inside the bucket, empty or ending with '/'
+    prefix: String,
+    presigned_downloads: bool,
+}
+
+impl S3Storage {
+    /// Configure the bucket from the `S3_*` environment variables
+    pub fn from_env() -> AppResult<Self> {
+        let bucket = std::env::var("S3_BUCKET")
+            .map_err(|_| AppError::Internal("S3_BUCKET is required for S3 storage".into()))?;
+
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(&bucket)
+            .with_region(std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".into()));
+        if let Ok(endpoint) = std::env::var("S3_ENDPOINT") {
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint);
+        }
+        if let Ok(key_id) = std::env::var("S3_ACCESS_KEY_ID") {
+            builder = builder.with_access_key_id(key_id);
+        }
+        if let Ok(secret) = std::env::var("S3_SECRET_ACCESS_KEY") {
+            builder = builder.with_secret_access_key(secret);
+        }
+        let store = builder
+            .build()
+            .map_err(|e| AppError::Internal(format!("Invalid S3 configuration: {}", e)))?;
+
+        let prefix = std::env::var("S3_PREFIX")
+            .map(|p| p.trim_matches('/').to_string())
+            .unwrap_or_default();
+        let prefix = if prefix.is_empty() {
+            prefix
+        } else {
+            format!("{}/", prefix)
+        };
+
+        Ok(S3Storage {
+            store,
+            bucket,
+            prefix,
+            presigned_downloads: std::env::var("S3_PRESIGNED_DOWNLOADS")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+        })
+    }
+
+    fn location(&self, key: &str) -> AppResult<ObjectPath> {
+        validate_key(key)?;
+        ObjectPath::parse(format!("{}{}", self.prefix, key))
+            .map_err(|e| AppError::BadRequest(format!("Invalid storage key '{}': {}", key, e)))
+    }
+
+    /// Content type stored with the object, so presigned downloads display inline
+    fn attributes(key: &str) -> Attributes {
+        let mime = mime_guess::from_path(key).first_or_octet_stream();
+        Attributes::from_iter([(Attribute::ContentType, mime.to_string())])
+    }
+
+    fn info(&self, meta: object_store::ObjectMeta) -> ObjectInfo {
+        let location = meta.location.to_string();
+        ObjectInfo {
+            key: location
+                .strip_prefix(&self.prefix)
+                .unwrap_or(&location)
+                .to_string(),
+            size: meta.size,
+            modified: Some(meta.last_modified.into()),
+        }
+    }
+
+    fn error(key: &str, e: object_store::Error) -> AppError {
+        match e {
+            object_store::Error::NotFound { .. } => {
+                AppError::NotFound(format!("File '{}' not found", key))
+            }
+            e => AppError::Internal(format!("Object storage error for '{}': {}", key, e)),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    fn describe(&self) -> String {
+        format!("S3 bucket {}/{}", self.bucket, self.prefix)
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> AppResult<()> {
+        let location = self.location(key)?;
+        let size = tokio::fs::metadata(path).await?.len();
+
+        if size <= SINGLE_PUT_LIMIT {
+            let data = tokio::fs::read(path).await?;
+            let options = PutOptions {
+                attributes: Self::attributes(key),
+                ..Default::default()
+            };
+            self.store
+                .put_opts(&location, data.into(), options)
+                .await
+                .map_err(|e| Self::error(key, e))?;
+        } else {
+            let options = PutMultipartOptions {
+                attributes: Self::attributes(key),
+                ..Default::default()
+            };
+            let upload = self
+                .store
+                .put_multipart_opts(&location, options)
+                .await
+                .map_err(|e| Self::error(key, e))?;
+            let mut writer = WriteMultipart::new_with_chunk_size(upload, MULTIPART_CHUNK_SIZE);
+
+            let mut file = tokio::fs::File::open(path).await?;
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                // Keep at most a couple of parts in flight
+                if let Err(e) = writer.wait_for_capacity(2).await {
+                    let _ = writer.abort().await;
+                    return Err(Self::error(key, e));
+                }
+                let n = match file.read(&mut buf).await {
+                    Ok(n) => n,
+                    Err(e) => {
+                        let _ = writer.abort().await;
+                        return Err(e.into());
+                    }
+                };
+                if n == 0 {
+                    break;
+                }
+                writer.write(&buf[..n]);
+            }
+            writer.finish().await.map_err(|e| Self::error(key, e))?;
+        }
+
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    async fn put_bytes(&self, key: &str, data: Bytes) -> AppResult<()> {
+        let options = PutOptions {
+            attributes: Self::attributes(key),
+            ..Default::default()
+        };
+        self.store
+            .put_opts(&self.location(key)?, data.into(), options)
+            .await
+            .map_err(|e| Self::error(key, e))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> AppResult<StoredObject> {
+        let result = self
+            .store
+            .get(&self.location(key)?)
+            .await
+            .map_err(|e| Self::error(key, e))?;
+        let size = result.meta.size;
+        let stream = result.into_stream().map_err(std::io::Error::other);
+        Ok(StoredObject {
+            size,
+            reader: Box::new(StreamReader::new(stream)),
+        })
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> AppResult<StoredObject> {
+        let options = GetOptions {
+            range: Some(GetRange::Bounded(offset..offset.saturating_add(length))),
+            ..Default::default()
+        };
+        let result = self
+            .store
+            .get_opts(&self.location(key)?, options)
+            .await
+            .map_err(|e| Self::error(key, e))?;
+        let size = result.range.end - result.range.start;
+        let stream = result.into_stream().map_err(std::io::Error::other);
+        Ok(StoredObject {
+            size,
+            reader: Box::new(StreamReader::new(stream)),
+        })
+    }
+
+    async fn head(&self, key: &str) -> AppResult<Option<ObjectInfo>> {
+        match self.store.head(&self.location(key)?).await {
+            Ok(meta) => Ok(Some(self.info(meta))),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(Self::error(key, e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        match self.store.delete(&self.location(key)?).await {
+            Err(object_store::Error::NotFound { .. }) | Ok(()) => Ok(()),
+            Err(e) => Err(Self::error(key, e)),
+        }
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> AppResult<()> {
+        self.store
+            .copy(&self.location(from)?, &self.location(to)?)
+            .await
+            .map_err(|e| Self::error(from, e))
+    }
+
+    async fn list(&self) -> AppResult<Vec<ObjectInfo>> {
+        let prefix = ObjectPath::from(self.prefix.trim_end_matches('/'));
+        let prefix = (!self.prefix.is_empty()).then_some(&prefix);
+        let metas: Vec<object_store::ObjectMeta> = self
+            .store
+            .list(prefix)
+            .try_collect()
+            .await
+            .map_err(|e| Self::error(&self.prefix, e))?;
+
+        let mut objects: Vec<ObjectInfo> = metas.into_iter().map(|m| self.info(m)).collect();
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> AppResult<Option<String>> {
+        if !self.presigned_downloads {
+            return Ok(None);
+        }
+        let url = self
+            .store
+            .signed_url(Method::GET, &self.location(key)?, expires_in)
+            .await
+            .map_err(|e| Self::error(key, e))?;
+        Ok(Some(url.to_string()))
+    }
+}
+
+/// The configured storage backend
+static BACKEND: OnceLock<Box<dyn StorageBackend>> = OnceLock::new();
+
+/// Storage service with static methods over the configured backend
+pub struct StorageService;
+
+impl StorageService {
+    /// The configured backend, set up from the environment on first use
+    ///
+    /// Panics on an invalid configuration; `main` touches it at startup so
+    /// that happens before the server accepts requests.
+    pub fn backend() -> &'static dyn StorageBackend {
+        BACKEND
+            .get_or_init(|| {
+                Self::from_env().unwrap_or_else(|e| panic!("Invalid storage configuration: {}", e))
+            })
+            .as_ref()
+    }
+
+    fn from_env() -> AppResult<Box<dyn StorageBackend>> {
+        let backend = Self::unencrypted_from_env()?;
+        match Keyring::from_env()? {
+            Some(keyring) => Ok(Box::new(EncryptedStorage::new(backend, keyring))),
+            None => Ok(backend),
+        }
+    }
+
+    /// The backend selected by `STORAGE_BACKEND`, without encryption
+    pub fn unencrypted_from_env() -> AppResult<Box<dyn StorageBackend>> {
+        // Staging is needed whichever backend is used
+        std::fs::create_dir_all(STAGING_DIR)?;
+
+        match std::env::var("STORAGE_BACKEND").as_deref() {
+            Ok("local") | Err(_) => {
+                std::fs::create_dir_all(UPLOADS_DIR)?;
+                Ok(Box::new(LocalStorage::new(UPLOADS_DIR)))
+            }
+            Ok("s3") => Ok(Box::new(S3Storage::from_env()?)),
+            Ok(other) => Err(AppError::Internal(format!(
+                "Unknown STORAGE_BACKEND '{}'. Use 'local' or 's3'",
+                other
+            ))),
+        }
+    }
+
+    /// A fresh path in the staging directory for a file with this extension
+    pub fn staging_path(extension: &str) -> String {
+        staging_path(extension).to_string_lossy().to_string()
+    }
+
+    /// Content-addressed key for a SHA-256 checksum (hex) and file extension
+    ///
+    /// Extensions come from client filenames, so only short alphanumeric ones are kept.
+    pub fn content_key(checksum: &str, extension: &str) -> String {
+        let extension: String = extension
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .take(10)
+            .collect::<String>()
+            .to_lowercase();
+        let shards = format!("{}/{}/{}", &checksum[0..2], &checksum[2..4], checksum);
+        if extension.is_empty() {
+            shards
+        } else {
+            format!("{}.{}", shards, extension)
+        }
+    }
+
+    /// The checksum a content-addressed key was derived from
+    ///
+    /// None for keys outside the layout, such as files of older versions
+    /// that couldn't be migrated.
+    pub fn checksum_of_key(key: &str) -> Option<&str> {
+        let (shards, name) = key.rsplit_once('/')?;
+        let checksum = name.split('.').next()?;
+        let is_checksum = checksum.len() == 64
+            && checksum
+                .bytes()
+                .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        (is_checksum && shards == format!("{}/{}", &checksum[0..2], &checksum[2..4]))
+            .then_some(checksum)
+    }
+
+    /// Hand a vetted file from the staging directory to the backend
+    ///
+    /// When the content is already stored the staged copy is just dropped.
+    /// The staged file is gone afterwards, whether this succeeds or not.
+    pub async fn store_staged(staged: &str, key: &str) -> AppResult<KeyPin> {
+        let backend = Self::backend();
+        let _lock = key_lock(key).lock().await;
+        let pin = KeyPin::new(key);
+        let stored = match backend.head(key).await {
+            Ok(Some(_)) => tokio::fs::remove_file(staged).await.map_err(AppError::from),
+            Ok(None) => backend.put_file(key, Path::new(staged)).await,
+            Err(e) => Err(e),
+        };
+        if stored.is_err() {
+            let _ = tokio::fs::remove_file(staged).await;
+        }
+        stored.map(|()| pin)
+    }
+
+    /// Store a staged file under its content key
+    pub async fn store_staged_content(staged: &str) -> AppResult<KeyPin> {
+        let checksum = match IntegrityService::checksum_file(staged).await {
+            Ok(checksum) => checksum,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(staged).await;
+                return Err(e);
+            }
+        };
+        let key = Self::content_key(&checksum, extension_of(staged));
+        Self::store_staged(staged, &key).await
+    }
+
+    /// Store in-memory content under its content key
+    pub async fn store_bytes(data: Bytes, extension: &str) -> AppResult<KeyPin> {
+        let key = Self::content_key(&IntegrityService::checksum(&data), extension);
+        let backend = Self::backend();
+        let _lock = key_lock(&key).lock().await;
+        let pin = KeyPin::new(&key);
+        if backend.head(&key).await?.is_none() {
+            backend.put_bytes(&key, data).await?;
+        }
+        Ok(pin)
+    }
+
+    /// Read a whole object into memory
+    pub async fn read(key: &str) -> AppResult<Vec<u8>> {
+        let mut object = Self::backend().get(key).await?;
+        let mut data = Vec::with_capacity(object.size as usize);
+        object.reader.read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    /// Whether an object exists
+    pub async fn exists(key: &str) -> AppResult<bool> {
+        Ok(Self::backend().head(key).await?.is_some())
+    }
+
+    /// Whether any database row still refers to a key
+    pub async fn is_referenced(pool: &DbPool, key: &str) -> AppResult<bool> {
+        let query = KEY_COLUMNS
+            .iter()
+            .map(|(table, column)| format!("SELECT 1 FROM {} WHERE {} = ?1", table, column))
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+        let found: Option<(i32,)> = sqlx::query_as(&format!("{} LIMIT 1", query))
+            .bind(key)
+            .fetch_optional(pool)
+            .await?;
+        Ok(found.is_some())
+    }
+
+    /// Delete objects no database row refers to any more
+    ///
+    /// Identical content is shared, so a file goes only with its last row.
+    /// Best effort - a failure is logged but doesn't fail the caller.
+    pub async fn remove_unreferenced(pool: &DbPool, keys: &[String]) {
+        for key in keys {
+            match Self::delete_unused(key, Self::is_referenced(pool, key)).await {
+                Ok(true) => tracing::debug!("Deleted file: {}", key),
+                Ok(false) => tracing::debug!("Keeping shared file: {}", key),
+                Err(e) => tracing::warn!("Failed to delete file {}: {}", key, e),
+            }
+        }
+    }
+
+    /// Delete an object unless it is pinned or `in_use` says it's still needed
+    ///
+    /// Returns whether it was deleted.
+    pub async fn delete_unused(
+        key: &str,
+        in_use: impl Future<Output = AppResult<bool>>,
+    ) -> AppResult<bool> {
+        let Some(_lock) = Self::lock_unused(key, in_use).await? else {
+            return Ok(false);
+        };
+        Self::backend().delete(key).await?;
+        Ok(true)
+    }
+
+    /// Lock a key to remove its object, None when it is pinned or `in_use`
+    /// says it's still needed
+    ///
+    /// Both are checked under the lock writers take, so none can find the
+    /// object stored while the guard is held.
+    pub async fn lock_unused(
+        key: &str,
+        in_use: impl Future<Output = AppResult<bool>>,
+    ) -> AppResult<Option<MutexGuard<'static, ()>>> {
+        let lock = key_lock(key).lock().await;
+        if KeyPin::is_pinned(key) || in_use.await? {
+            return Ok(None);
+        }
+        Ok(Some(lock))
+    }
+
+    /// Move files stored under the flat layout of older versions to content keys
+    ///
+    /// Each file is copied to its new key first, then every column pointing
+    /// at it is rewritten in one transaction, and only then is the old file
+    /// deleted. An interrupted run leaves at worst an orphaned copy and picks
+    /// up where it stopped on the next start. Missing files are left for the
+    /// integrity scan to report.
+    pub async fn migrate_layout(pool: &DbPool) -> AppResult<()> {
+        let mut old_keys = std::collections::BTreeSet::new();
+        for (table, column) in KEY_COLUMNS {
+            let keys: Vec<(String,)> = sqlx::query_as(&format!(
+                "SELECT DISTINCT {column} FROM {table} WHERE {column} IS NOT NULL AND {column} != '' AND instr({column}, '/') = 0"
+            ))
+            .fetch_all(pool)
+            .await?;
+            old_keys.extend(keys.into_iter().map(|(key,)| key));
+        }
+        if old_keys.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "Moving {} file(s) to the content-addressed layout",
+            old_keys.len()
+        );
+        let backend = Self::backend();
+        let mut moved = 0;
+        for old_key in &old_keys {
+            if backend.head(old_key).await?.is_none() {
+                tracing::warn!("Not moving missing file {}", old_key);
+                continue;
+            }
+
+            let checksum = IntegrityService::checksum_stored(old_key).await?;
+            let new_key = Self::content_key(&checksum, extension_of(old_key));
+            if backend.head(&new_key).await?.is_none() {
+                backend.copy(old_key, &new_key).await?;
+            }
+
+            let mut tx = pool.begin().await?;
+            for (table, column) in KEY_COLUMNS {
+                sqlx::query(&format!(
+                    "UPDATE {table} SET {column} = ? WHERE {column} = ?"
+                ))
+                .bind(&new_key)
+                .bind(old_key)
+                .execute(&mut *tx)
+                .await?;
+            }
+            sqlx::query(
+                "UPDATE documents SET checksum = ? WHERE file_path = ? AND checksum IS NULL",
+            )
+            .bind(&checksum)
+            .bind(&new_key)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            backend.delete(old_key).await?;
+            moved += 1;
+        }
+
+        tracing::info!("Moved {} file(s) to the content-addressed layout", moved);
+        Ok(())
+    }
+
+    /// Presigned download URL for an object, when the backend hands them out
+    pub async fn download_url(key: &str) -> AppResult<Option<String>> {
+        let expires_in = std::env::var("S3_PRESIGN_EXPIRY_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_PRESIGN_EXPIRY_SECS);
+        Self::backend()
+            .presigned_url(key, Duration::from_secs(expires_in))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUM: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn content_key_is_sharded_by_checksum() {
+        assert_eq!(
+            StorageService::content_key(CHECKSUM, "pdf"),
+            format!("9f/86/{}.pdf", CHECKSUM)
+        );
+    }
+
+    #[test]
+    fn content_key_cleans_the_extension() {
+        let key = StorageService::content_key(CHECKSUM, "../P.D-F");
+        assert_eq!(key, format!("9f/86/{}.pdf", CHECKSUM));
+        let key = StorageService::content_key(CHECKSUM, "abcdefghijklmnop");
+        assert!(key.ends_with(".abcdefghij"));
+        assert_eq!(
+            StorageService::content_key(CHECKSUM, "./"),
+            format!("9f/86/{}", CHECKSUM)
+        );
+    }
+
+    #[test]
+    fn checksum_round_trips_through_the_key() {
+        for extension in ["pdf", "", "tar.gz"] {
+            let key = StorageService::content_key(CHECKSUM, extension);
+            assert_eq!(StorageService::checksum_of_key(&key), Some(CHECKSUM));
+        }
+    }
+
+    #[test]
+    fn keys_outside_the_layout_have_no_checksum() {
+        let upper = CHECKSUM.to_uppercase();
+        for key in [
+            "report.pdf".to_string(),
+            CHECKSUM.to_string(),
+            format!("00/00/{}.pdf", CHECKSUM),
+            format!("9f/{}.pdf", CHECKSUM),
+            format!("9f/86/{}.pdf", &CHECKSUM[..63]),
+            format!("{}/{}/{}", &upper[0..2], &upper[2..4], upper),
+            "9f/86/not-a-checksum.pdf".to_string(),
+        ] {
+            assert_eq!(StorageService::checksum_of_key(&key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn pins_are_counted() {
+        let key = "test/pins_are_counted";
+        let first = KeyPin::new(key);
+        let second = KeyPin::new(key);
+        drop(first);
+        assert!(KeyPin::is_pinned(key));
+        assert_eq!(second.release(), key);
+        assert!(!KeyPin::is_pinned(key));
+    }
+
+    #[tokio::test]
+    async fn pinned_or_used_keys_are_not_locked_for_removal() {
+        let key = "test/pinned_or_used_keys";
+        let pin = KeyPin::new(key);
+        let guard = StorageService::lock_unused(key, async { Ok(false) }).await;
+        assert!(guard.unwrap().is_none());
+        drop(pin);
+        let guard = StorageService::lock_unused(key, async { Ok(true) }).await;
+        assert!(guard.unwrap().is_none());
+        let guard = StorageService::lock_unused(key, async { Ok(false) }).await;
+        assert!(guard.unwrap().is_some());
+    }
+}
@@ -0,0 +1,347 @@
+//! Video processing service
+//!
+//! Background processing of video uploads using a local ffmpeg install:
+//! - Extracts duration, resolution and codec with `ffprobe`
+//! - Grabs a poster frame for thumbnails
+//! - Optionally transcodes to H.264 MP4 so browsers can play phone videos (often HEVC)
+//!
+//! # Architecture Decision
+//! Processing runs in spawned tasks limited by a global semaphore, because
+//! ffmpeg is CPU-heavy and the server usually runs on a small office machine.
+//! Jobs are tracked in `video_metadata`, so anything interrupted by a restart
+//! is picked up again by `resume_pending` at startup. ffprobe and ffmpeg are
+//! killed when they run past their time limit, so a malformed upload that
+//! hangs them fails its own job instead of blocking every later one.
+//!
+//! # Configuration
+//! - `FFMPEG_PATH` / `FFPROBE_PATH`: binaries to use (default: from PATH)
+//! - `VIDEO_TRANSCODE`: "auto" (default, only non-H.264 or non-MP4 sources),
+//!   "always" or "never"
+//! - `FFMPEG_TIMEOUT_SECS`: time limit of each ffmpeg run (default: 3600)
+
+use std::process::Output;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult};
+use crate::services::storage_service::KeyPin;
+use crate::services::{ColdStorageService, DocumentService, StorageService};
+
+/// Maximum number of videos processed at the same time
+const MAX_CONCURRENT_JOBS: usize = 1;
+
+/// Poster frames are scaled down to at most this width
+const POSTER_MAX_WIDTH: u32 = 1280;
+
+/// ffprobe only reads headers, so it gets a short fixed limit
+const PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default time limit of each ffmpeg run, long enough to transcode long clips
+const DEFAULT_FFMPEG_TIMEOUT_SECS: u64 = 3600;
+
+/// Result of probing a video file
+struct ProbeResult {
+    duration_seconds: Option<f64>,
+    width: Option<i32>,
+    height: Option<i32>,
+    codec: Option<String>,
+}
+
+/// Video service handling background ffmpeg jobs
+pub struct VideoService;
+
+impl VideoService {
+    /// Queue a video document for background processing
+    pub async fn enqueue(pool: &DbPool, document_id: &str) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO video_metadata (document_id, status) VALUES (?, 'PENDING')
+            ON CONFLICT(document_id) DO UPDATE SET status = 'PENDING', error = NULL, updated_at = datetime('now')
+            "#,
+        )
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+
+        Self::spawn(pool, document_id);
+        Ok(())
+    }
+
+    /// Re-queue jobs that were pending or interrupted by a restart
+    pub async fn resume_pending(pool: &DbPool) -> AppResult<()> {
+        let pending: Vec<(String,)> = sqlx::query_as(
+            "SELECT document_id FROM video_metadata WHERE status IN ('PENDING', 'PROCESSING')",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        if !pending.is_empty() {
+            tracing::info!("Resuming {} pending video job(s)", pending.len());
+        }
+        for (document_id,) in pending {
+            Self::spawn(pool, &document_id);
+        }
+        Ok(())
+    }
+
+    /// Spawn a processing task, serialized by the job semaphore
+    fn spawn(pool: &DbPool, document_id: &str) {
+        static JOBS: OnceLock<Semaphore> = OnceLock::new();
+
+        let pool = pool.clone();
+        let document_id = document_id.to_string();
+        tokio::spawn(async move {
+            let semaphore = JOBS.get_or_init(|| Semaphore::new(MAX_CONCURRENT_JOBS));
+            let Ok(_permit) = semaphore.acquire().await else {
+                return;
+            };
+
+            if let Err(e) = Self::process(&pool, &document_id).await {
+                tracing::error!("Video processing failed for {}: {}", document_id, e);
+                let _ = sqlx::query(
+                    "UPDATE video_metadata SET status = 'FAILED', error = ?, updated_at = datetime('now') WHERE document_id = ?",
+                )
+                .bind(e.to_string())
+                .bind(&document_id)
+                .execute(&pool)
+                .await;
+            }
+        });
+    }
+
+    /// Probe, extract a poster frame and optionally transcode one video
+    async fn process(pool: &DbPool, document_id: &str) -> AppResult<()> {
+        let doc = match DocumentService::get_by_id(pool, document_id).await {
+            Ok(doc) => doc,
+            // Deleted while queued; the metadata row is gone with it
+            Err(AppError::NotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        sqlx::query(
+            "UPDATE video_metadata SET status = 'PROCESSING', updated_at = datetime('now') WHERE document_id = ?",
+        )
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+
+        // Outputs of an earlier run, removed once they are replaced
+        let previous: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT poster_path, transcoded_path FROM video_metadata WHERE document_id = ?",
+        )
+        .bind(document_id)
+        .fetch_optional(pool)
+        .await?;
+        let (old_poster, old_transcoded) = previous.unwrap_or_default();
+
+        ColdStorageService::ensure_restored(pool, &doc).await?;
+
+        // ffmpeg needs a file of its own when the original lives in object storage
+        let source = StorageService::backend().local_copy(&doc.file_path).await?;
+        let input = source.path_str();
+
+        let probe = match Self::probe(&input).await {
+            Ok(probe) => probe,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                tracing::warn!("ffprobe not available, skipping video processing");
+                sqlx::query(
+                    "UPDATE video_metadata SET status = 'SKIPPED', error = 'ffmpeg not available', updated_at = datetime('now') WHERE document_id = ?",
+                )
+                .bind(document_id)
+                .execute(pool)
+                .await?;
+                return Ok(());
+            }
+            Err(e) => return Err(AppError::Internal(format!("ffprobe failed: {}", e))),
+        };
+
+        sqlx::query(
+            "UPDATE video_metadata SET duration_seconds = ?, width = ?, height = ?, codec = ?, updated_at = datetime('now') WHERE document_id = ?",
+        )
+        .bind(probe.duration_seconds)
+        .bind(probe.width)
+        .bind(probe.height)
+        .bind(&probe.codec)
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+
+        // Poster frame: one second in, or halfway through very short clips
+        let seek = probe
+            .duration_seconds
+            .map(|d| (d / 2.0).min(1.0))
+            .unwrap_or(0.0);
+        let scale = format!("scale='min({},iw)':-2", POSTER_MAX_WIDTH);
+        let poster = Self::run_ffmpeg_into(
+            &[
+                "-y",
+                "-ss",
+                &format!("{:.2}", seek),
+                "-i",
+                &input,
+                "-frames:v",
+                "1",
+                "-vf",
+                &scale,
+            ],
+            "jpg",
+        )
+        .await?;
+
+        sqlx::query(
+            "UPDATE video_metadata SET poster_path = ?, updated_at = datetime('now') WHERE document_id = ?",
+        )
+        .bind(poster.key())
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+        drop(poster);
+        StorageService::remove_unreferenced(pool, old_poster.as_slice()).await;
+
+        let transcoded = if Self::needs_transcode(&doc.file_path, probe.codec.as_deref()) {
+            let path = Self::run_ffmpeg_into(
+                &[
+                    "-y",
+                    "-i",
+                    &input,
+                    "-c:v",
+                    "libx264",
+                    "-preset",
+                    "veryfast",
+                    "-crf",
+                    "23",
+                    "-pix_fmt",
+                    "yuv420p",
+                    "-c:a",
+                    "aac",
+                    "-movflags",
+                    "+faststart",
+                ],
+                "mp4",
+            )
+            .await?;
+            Some(path)
+        } else {
+            None
+        };
+
+        sqlx::query(
+            "UPDATE video_metadata SET status = 'DONE', transcoded_path = ?, error = NULL, updated_at = datetime('now') WHERE document_id = ?",
+        )
+        .bind(transcoded.as_ref().map(KeyPin::key))
+        .bind(document_id)
+        .execute(pool)
+        .await?;
+        drop(transcoded);
+        StorageService::remove_unreferenced(pool, old_transcoded.as_slice()).await;
+
+        tracing::info!("Processed video document {}", document_id);
+        Ok(())
+    }
+
+    /// Decide whether a video should get a web-friendly copy
+    fn needs_transcode(file_path: &str, codec: Option<&str>) -> bool {
+        match std::env::var("VIDEO_TRANSCODE").as_deref() {
+            Ok("always") => true,
+            Ok("never") => false,
+            _ => {
+                let is_mp4 = file_path.to_lowercase().ends_with(".mp4");
+                !(is_mp4 && codec == Some("h264"))
+            }
+        }
+    }
+
+    /// Run a command to completion, killing it once `limit` has passed
+    async fn output_within(command: &mut Command, limit: Duration) -> std::io::Result<Output> {
+        // Dropping the timed out future drops the child, which kills it
+        command.kill_on_drop(true);
+        tokio::time::timeout(limit, command.output())
+            .await
+            .unwrap_or_else(|_| {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("killed after {}s", limit.as_secs()),
+                ))
+            })
+    }
+
+    /// Run ffprobe and extract duration, resolution and codec
+    async fn probe(input: &str) -> std::io::Result<ProbeResult> {
+        let ffprobe = std::env::var("FFPROBE_PATH").unwrap_or_else(|_| "ffprobe".to_string());
+        let mut command = Command::new(ffprobe);
+        command.args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=codec_name,width,height:format=duration",
+            "-of",
+            "json",
+            input,
+        ]);
+        let output = Self::output_within(&mut command, PROBE_TIMEOUT).await?;
+
+        if !output.status.success() {
+            return Err(std::io::Error::other(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.stdout).map_err(std::io::Error::other)?;
+        let stream = &json["streams"][0];
+
+        Ok(ProbeResult {
+            duration_seconds: json["format"]["duration"]
+                .as_str()
+                .and_then(|d| d.parse().ok()),
+            width: stream["width"].as_i64().map(|w| w as i32),
+            height: stream["height"].as_i64().map(|h| h as i32),
+            codec: stream["codec_name"].as_str().map(|c| c.to_string()),
+        })
+    }
+
+    /// Run ffmpeg with its output file in staging, then store the output
+    ///
+    /// `extension` picks the output format. Returns the pinned storage key.
+    async fn run_ffmpeg_into(args: &[&str], extension: &str) -> AppResult<KeyPin> {
+        let staged = StorageService::staging_path(extension);
+        let mut args = args.to_vec();
+        args.push(&staged);
+
+        if let Err(e) = Self::run_ffmpeg(&args).await {
+            let _ = tokio::fs::remove_file(&staged).await;
+            return Err(e);
+        }
+        StorageService::store_staged_content(&staged).await
+    }
+
+    /// Run ffmpeg with the given arguments, failing on a non-zero exit
+    async fn run_ffmpeg(args: &[&str]) -> AppResult<()> {
+        let ffmpeg = std::env::var("FFMPEG_PATH").unwrap_or_else(|_| "ffmpeg".to_string());
+        let timeout = std::env::var("FFMPEG_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_FFMPEG_TIMEOUT_SECS);
+        let mut command = Command::new(ffmpeg);
+        command
+            .args(["-hide_banner", "-loglevel", "error"])
+            .args(args);
+        let output = Self::output_within(&mut command, Duration::from_secs(timeout))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to run ffmpeg: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(AppError::Internal(format!(
+                "ffmpeg failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
@@ -741,7 +741,7 @@ function App() {
   }
 
   const toggleProjectStatus = async (project) => {
-    const newStatus = project.status === 'ACTIVE' ? 'ARCHIVED' : 'ACTIVE'
+    const newStatus = project.status !== 'ARCHIVED' ? 'ARCHIVED' : 'ACTIVE'
     try {
       await apiFetch(`${API_BASE}/projects/${project.id}/status`, { method: 'PATCH', headers: { 'Content-Type': 'application/json' }, body: JSON.stringify({ status: newStatus }) })
       setSelectedProject(prev => prev ? { ...prev, status: newStatus } : null); loadProjects()
@@ -981,7 +981,7 @@ function App() {
             <div className="obras-list">
               {/* Active Projects rendering */}
               {projects
-                .filter(p => p.status === 'ACTIVE' || p.name === 'Geral')
+                .filter(p => p.status !== 'ARCHIVED' || p.name === 'Geral')
                 .filter(p => !searchQuery || p.name.toLowerCase().includes(searchQuery.toLowerCase()))
                 .sort((a, b) => { if (a.name === 'Geral') return -1; if (b.name === 'Geral') return 1; return 0 })
                 .map(p => (
@@ -1331,7 +1331,7 @@ function App() {
             )}
             
             <button className="info-archive-btn" onClick={() => { toggleProjectStatus(selectedProject); setShowObraInfo(false) }} style={{ marginTop: '20px' }}>
-              {selectedProject.status === 'ACTIVE' ? 'Arquivar Obra' : 'Reativar Obra'}
+              {selectedProject.status !== 'ARCHIVED' ? 'Arquivar Obra' : 'Reativar Obra'}
             </button>
           </div>
         </div>